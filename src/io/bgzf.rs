@@ -0,0 +1,546 @@
+//! Support for the Blocked GNU Zip Format (BGZF).
+//!
+//! BGZF files are a series of concatenated gzip members, each holding at most 64KiB of
+//! uncompressed data and carrying its own compressed size in a gzip extra field.  This makes them
+//! readable by any gzip decoder while allowing tools like `tabix` and `samtools` to index and seek
+//! within them.  See section 4.1 of the [SAM specification](https://samtools.github.io/hts-specs/SAMv1.pdf)
+//! for the details of the format.
+use std::fmt;
+use std::io::{self, BufRead, ErrorKind, Read, Seek, SeekFrom, Write};
+
+use flate2::read::DeflateDecoder;
+use flate2::write::DeflateEncoder;
+use flate2::{Compression, Crc};
+
+use crate::io::FinishWrite;
+
+/// The maximum number of uncompressed bytes placed into a single block; matches htslib.
+pub(crate) const MAX_BLOCK_DATA_SIZE: usize = 0xff00;
+
+/// The maximum total size of a single BGZF block, including header and footer.
+pub(crate) const MAX_BLOCK_SIZE: usize = 0x10000;
+
+/// The length of the gzip header, including the BGZF extra field, at the start of each block.
+pub(crate) const BLOCK_HEADER_SIZE: usize = 18;
+
+/// The length of the gzip footer (CRC32 and uncompressed size) at the end of each block.
+pub(crate) const BLOCK_FOOTER_SIZE: usize = 8;
+
+/// The empty block that terminates every well-formed BGZF file.
+pub const BGZF_EOF: [u8; 28] = [
+    0x1f, 0x8b, 0x08, 0x04, 0x00, 0x00, 0x00, 0x00, 0x00, 0xff, 0x06, 0x00, 0x42, 0x43, 0x02, 0x00,
+    0x1b, 0x00, 0x03, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+];
+
+/// A BGZF virtual file offset.  The upper 48 bits hold the offset in the compressed file of the
+/// start of a block (the `coffset`), and the lower 16 bits hold the offset within the
+/// uncompressed data of that block (the `uoffset`).
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct VirtualOffset(u64);
+
+impl VirtualOffset {
+    /// Creates a virtual offset from a compressed block offset and an offset within the block.
+    ///
+    /// # Panics
+    /// - panics if `coffset` does not fit into 48 bits
+    pub fn new(coffset: u64, uoffset: u16) -> Self {
+        assert!(coffset < (1 << 48), "Compressed offset {coffset} is too large for BGZF");
+        VirtualOffset((coffset << 16) | u64::from(uoffset))
+    }
+
+    /// The offset in the compressed file of the block containing this position.
+    pub fn coffset(&self) -> u64 {
+        self.0 >> 16
+    }
+
+    /// The offset within the uncompressed data of the block containing this position.
+    pub fn uoffset(&self) -> u16 {
+        (self.0 & 0xffff) as u16
+    }
+}
+
+impl From<u64> for VirtualOffset {
+    fn from(value: u64) -> Self {
+        VirtualOffset(value)
+    }
+}
+
+impl From<VirtualOffset> for u64 {
+    fn from(value: VirtualOffset) -> Self {
+        value.0
+    }
+}
+
+impl fmt::Display for VirtualOffset {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}:{}", self.coffset(), self.uoffset())
+    }
+}
+
+/// Reader that decompresses BGZF data block by block while tracking the [`VirtualOffset`] of
+/// the next byte to be read.  When the underlying reader is seekable the reader can be
+/// repositioned to any previously recorded virtual offset with [`BgzfReader::seek_virtual`].
+///
+/// ```rust
+/// use std::io::{BufRead, Cursor, Write};
+/// use fgoxide::io::{BgzfReader, BgzfWriter};
+/// use flate2::Compression;
+///
+/// let mut writer = BgzfWriter::new(vec![], Compression::default());
+/// writer.write_all(b"first\nsecond\n").unwrap();
+/// let bytes = writer.finish().unwrap();
+///
+/// let mut reader = BgzfReader::new(Cursor::new(bytes));
+/// let mut line = String::new();
+/// reader.read_line(&mut line).unwrap();
+/// let second = reader.virtual_offset();
+///
+/// reader.seek_virtual(second).unwrap();
+/// line.clear();
+/// reader.read_line(&mut line).unwrap();
+/// assert_eq!(line, "second\n");
+/// ```
+pub struct BgzfReader<R: Read> {
+    inner: R,
+    /// The uncompressed contents of the current block.
+    block: Vec<u8>,
+    /// The compressed offset of the current block.
+    block_offset: u64,
+    /// The compressed offset of the block following the current block.
+    next_block_offset: u64,
+    /// The offset within the current block of the next byte to be read.
+    position: usize,
+}
+
+impl<R: Read> BgzfReader<R> {
+    /// Creates a new reader positioned at the start of the underlying stream, which is assumed
+    /// to be at compressed offset zero.
+    pub fn new(inner: R) -> Self {
+        Self { inner, block: Vec::new(), block_offset: 0, next_block_offset: 0, position: 0 }
+    }
+
+    /// Returns the virtual offset of the next byte to be read.  When the current block has been
+    /// fully consumed this is the start of the next block.
+    pub fn virtual_offset(&self) -> VirtualOffset {
+        if self.position >= self.block.len() {
+            VirtualOffset::new(self.next_block_offset, 0)
+        } else {
+            VirtualOffset::new(self.block_offset, self.position as u16)
+        }
+    }
+
+    /// Returns a reference to the underlying reader.
+    pub fn get_ref(&self) -> &R {
+        &self.inner
+    }
+
+    /// Consumes the reader and returns the underlying reader.
+    pub fn into_inner(self) -> R {
+        self.inner
+    }
+
+    /// Reads and decompresses the next block from the underlying reader into `self.block`.
+    /// Returns false if the underlying reader was already at EOF.
+    fn read_block(&mut self) -> io::Result<bool> {
+        let mut header = [0u8; 12];
+        if !read_exact_or_eof(&mut self.inner, &mut header)? {
+            self.block.clear();
+            self.position = 0;
+            self.block_offset = self.next_block_offset;
+            return Ok(false);
+        }
+        if header[0..4] != [0x1f, 0x8b, 0x08, 0x04] {
+            return Err(invalid_data("Invalid BGZF block header"));
+        }
+
+        let xlen = u16::from_le_bytes([header[10], header[11]]) as usize;
+        let mut extra = vec![0u8; xlen];
+        self.inner.read_exact(&mut extra)?;
+        let bsize = block_size(&extra).ok_or_else(|| invalid_data("Missing BGZF BC field"))?;
+        if bsize < 12 + xlen + BLOCK_FOOTER_SIZE {
+            return Err(invalid_data("Invalid BGZF block size"));
+        }
+
+        let mut cdata = vec![0u8; bsize - 12 - xlen - BLOCK_FOOTER_SIZE];
+        self.inner.read_exact(&mut cdata)?;
+        let mut footer = [0u8; BLOCK_FOOTER_SIZE];
+        self.inner.read_exact(&mut footer)?;
+        let expected_crc = u32::from_le_bytes([footer[0], footer[1], footer[2], footer[3]]);
+        let expected_len = u32::from_le_bytes([footer[4], footer[5], footer[6], footer[7]]);
+
+        self.block.clear();
+        DeflateDecoder::new(cdata.as_slice()).read_to_end(&mut self.block)?;
+        let mut crc = Crc::new();
+        crc.update(&self.block);
+        if crc.sum() != expected_crc || self.block.len() != expected_len as usize {
+            return Err(invalid_data("BGZF block failed CRC or length check"));
+        }
+
+        self.block_offset = self.next_block_offset;
+        self.next_block_offset += bsize as u64;
+        self.position = 0;
+        Ok(true)
+    }
+}
+
+impl<R: Read + Seek> BgzfReader<R> {
+    /// Repositions the reader so that the next byte read is the one at the given virtual offset.
+    pub fn seek_virtual(&mut self, offset: VirtualOffset) -> io::Result<()> {
+        self.inner.seek(SeekFrom::Start(offset.coffset()))?;
+        self.next_block_offset = offset.coffset();
+        self.read_block()?;
+        if usize::from(offset.uoffset()) > self.block.len() {
+            return Err(io::Error::new(
+                ErrorKind::InvalidInput,
+                format!("Virtual offset {offset} is beyond the end of its block"),
+            ));
+        }
+        self.position = usize::from(offset.uoffset());
+        Ok(())
+    }
+}
+
+impl<R: Read> Read for BgzfReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let available = self.fill_buf()?;
+        let n = available.len().min(buf.len());
+        buf[..n].copy_from_slice(&available[..n]);
+        self.consume(n);
+        Ok(n)
+    }
+}
+
+impl<R: Read> BufRead for BgzfReader<R> {
+    fn fill_buf(&mut self) -> io::Result<&[u8]> {
+        // Loop so that empty blocks (e.g. the EOF marker) are skipped over
+        while self.position >= self.block.len() {
+            if !self.read_block()? {
+                break;
+            }
+        }
+        Ok(&self.block[self.position..])
+    }
+
+    fn consume(&mut self, amt: usize) {
+        self.position = (self.position + amt).min(self.block.len());
+    }
+}
+
+/// Extracts the total block size from the BGZF `BC` subfield of a gzip extra field.
+fn block_size(extra: &[u8]) -> Option<usize> {
+    let mut i = 0;
+    while i + 4 <= extra.len() {
+        let len = u16::from_le_bytes([extra[i + 2], extra[i + 3]]) as usize;
+        if extra[i] == b'B' && extra[i + 1] == b'C' && len == 2 && i + 6 <= extra.len() {
+            return Some(u16::from_le_bytes([extra[i + 4], extra[i + 5]]) as usize + 1);
+        }
+        i += 4 + len;
+    }
+    None
+}
+
+/// Reads the header of the block starting at the reader's position and returns the total size
+/// of the block in bytes, or `None` if the reader is at EOF.  The reader is left positioned
+/// part way through the block.
+pub(crate) fn read_block_size<R: Read>(reader: &mut R) -> io::Result<Option<u64>> {
+    let mut header = [0u8; 12];
+    if !read_exact_or_eof(reader, &mut header)? {
+        return Ok(None);
+    }
+    if header[0..4] != [0x1f, 0x8b, 0x08, 0x04] {
+        return Err(invalid_data("Invalid BGZF block header"));
+    }
+    let xlen = u16::from_le_bytes([header[10], header[11]]) as usize;
+    let mut extra = vec![0u8; xlen];
+    reader.read_exact(&mut extra)?;
+    let bsize = block_size(&extra).ok_or_else(|| invalid_data("Missing BGZF BC field"))?;
+    Ok(Some(bsize as u64))
+}
+
+/// Fills `buf` completely, returning false if the reader was at EOF before any bytes were read.
+fn read_exact_or_eof<R: Read>(reader: &mut R, buf: &mut [u8]) -> io::Result<bool> {
+    let mut filled = 0;
+    while filled < buf.len() {
+        match reader.read(&mut buf[filled..]) {
+            Ok(0) if filled == 0 => return Ok(false),
+            Ok(0) => return Err(ErrorKind::UnexpectedEof.into()),
+            Ok(n) => filled += n,
+            Err(e) if e.kind() == ErrorKind::Interrupted => {}
+            Err(e) => return Err(e),
+        }
+    }
+    Ok(true)
+}
+
+/// Creates an `InvalidData` error with the given message.
+fn invalid_data(msg: &str) -> io::Error {
+    io::Error::new(ErrorKind::InvalidData, msg)
+}
+
+/// Writer that compresses data into BGZF blocks.  Data is buffered until a full block's worth is
+/// available (or the writer is flushed), and the BGZF EOF marker is written when the writer is
+/// finished or dropped.
+pub struct BgzfWriter<W: Write> {
+    inner: Option<W>,
+    compression: Compression,
+    buffer: Vec<u8>,
+    /// Whether the EOF marker has been written
+    finished: bool,
+}
+
+impl<W: Write> BgzfWriter<W> {
+    /// Creates a new writer that will compress blocks at the given compression level.
+    pub fn new(inner: W, compression: Compression) -> Self {
+        let buffer = Vec::with_capacity(MAX_BLOCK_DATA_SIZE);
+        Self { inner: Some(inner), compression, buffer, finished: false }
+    }
+
+    /// Writes any buffered data and the BGZF EOF marker, and returns the underlying writer.
+    pub fn finish(mut self) -> io::Result<W> {
+        self.try_finish()?;
+        Ok(self.inner.take().expect("BGZF writer already finished"))
+    }
+
+    /// Writes any buffered data followed by the EOF marker if that has not already been done.
+    fn try_finish(&mut self) -> io::Result<()> {
+        if self.inner.is_some() && !self.finished {
+            self.write_block()?;
+            let inner = self.inner.as_mut().unwrap();
+            inner.write_all(&BGZF_EOF)?;
+            inner.flush()?;
+            self.finished = true;
+        }
+        Ok(())
+    }
+
+    /// Compresses the contents of the buffer into a single block and writes it out.
+    fn write_block(&mut self) -> io::Result<()> {
+        if self.buffer.is_empty() {
+            return Ok(());
+        }
+
+        let block = compress_block(&self.buffer, self.compression)?;
+        self.inner.as_mut().expect("BGZF writer already finished").write_all(&block)?;
+        self.buffer.clear();
+        Ok(())
+    }
+}
+
+impl<W: Write> Write for BgzfWriter<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let n = buf.len().min(MAX_BLOCK_DATA_SIZE - self.buffer.len());
+        self.buffer.extend_from_slice(&buf[..n]);
+        if self.buffer.len() == MAX_BLOCK_DATA_SIZE {
+            self.write_block()?;
+        }
+        Ok(n)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.write_block()?;
+        match self.inner.as_mut() {
+            Some(inner) => inner.flush(),
+            None => Ok(()),
+        }
+    }
+}
+
+impl<W: FinishWrite> FinishWrite for BgzfWriter<W> {
+    fn finish(&mut self) -> io::Result<()> {
+        self.try_finish()?;
+        match self.inner.as_mut() {
+            Some(inner) => inner.finish(),
+            None => Ok(()),
+        }
+    }
+}
+
+impl<W: Write> Drop for BgzfWriter<W> {
+    fn drop(&mut self) {
+        let _ = self.try_finish();
+    }
+}
+
+/// Compresses `data` (which must be at most [`MAX_BLOCK_DATA_SIZE`] bytes) into a complete BGZF
+/// block.  Falls back to storing the data uncompressed if compression would overflow the block.
+fn compress_block(data: &[u8], compression: Compression) -> io::Result<Vec<u8>> {
+    let mut cdata = deflate(data, compression)?;
+    if cdata.len() + BLOCK_HEADER_SIZE + BLOCK_FOOTER_SIZE > MAX_BLOCK_SIZE {
+        cdata = deflate(data, Compression::none())?;
+    }
+
+    let block_size = cdata.len() + BLOCK_HEADER_SIZE + BLOCK_FOOTER_SIZE;
+    let mut crc = Crc::new();
+    crc.update(data);
+
+    let mut block = Vec::with_capacity(block_size);
+    block.extend_from_slice(&[0x1f, 0x8b, 0x08, 0x04, 0x00, 0x00, 0x00, 0x00, 0x00, 0xff]);
+    block.extend_from_slice(&[0x06, 0x00, b'B', b'C', 0x02, 0x00]);
+    block.extend_from_slice(&((block_size - 1) as u16).to_le_bytes());
+    block.extend_from_slice(&cdata);
+    block.extend_from_slice(&crc.sum().to_le_bytes());
+    block.extend_from_slice(&(data.len() as u32).to_le_bytes());
+    Ok(block)
+}
+
+/// Raw-deflates `data` at the given compression level.
+fn deflate(data: &[u8], compression: Compression) -> io::Result<Vec<u8>> {
+    let mut encoder = DeflateEncoder::new(Vec::with_capacity(data.len()), compression);
+    encoder.write_all(data)?;
+    encoder.finish()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use flate2::read::MultiGzDecoder;
+    use std::io::Cursor;
+
+    /// Splits a BGZF stream into its blocks using the BSIZE field of each block header.
+    fn blocks(bytes: &[u8]) -> Vec<&[u8]> {
+        let mut blocks = vec![];
+        let mut offset = 0;
+        while offset < bytes.len() {
+            assert_eq!(&bytes[offset + 12..offset + 14], b"BC");
+            let bsize = u16::from_le_bytes([bytes[offset + 16], bytes[offset + 17]]) as usize;
+            blocks.push(&bytes[offset..=offset + bsize]);
+            offset += bsize + 1;
+        }
+        blocks
+    }
+
+    #[test]
+    fn test_empty_writer_emits_only_eof_marker() {
+        let writer = BgzfWriter::new(vec![], Compression::default());
+        let bytes = writer.finish().unwrap();
+        assert_eq!(bytes, BGZF_EOF);
+    }
+
+    #[test]
+    fn test_round_trip_through_gzip_decoder() {
+        let data: Vec<u8> = (0..200_000).map(|i| (i % 251) as u8).collect();
+        let mut writer = BgzfWriter::new(vec![], Compression::default());
+        writer.write_all(&data).unwrap();
+        let bytes = writer.finish().unwrap();
+
+        let mut decoded = vec![];
+        MultiGzDecoder::new(bytes.as_slice()).read_to_end(&mut decoded).unwrap();
+        assert_eq!(decoded, data);
+
+        let blocks = blocks(&bytes);
+        assert_eq!(blocks.len(), 5); // four data blocks plus the EOF block
+        assert_eq!(*blocks.last().unwrap(), BGZF_EOF);
+        assert!(blocks.iter().all(|b| b.len() <= MAX_BLOCK_SIZE));
+    }
+
+    #[test]
+    fn test_incompressible_data_fits_in_blocks() {
+        // A simple LCG produces bytes that deflate cannot compress
+        let mut state = 12345u32;
+        let data: Vec<u8> = (0..MAX_BLOCK_DATA_SIZE * 2)
+            .map(|_| {
+                state = state.wrapping_mul(1_103_515_245).wrapping_add(12345);
+                (state >> 16) as u8
+            })
+            .collect();
+
+        let mut writer = BgzfWriter::new(vec![], Compression::best());
+        writer.write_all(&data).unwrap();
+        let bytes = writer.finish().unwrap();
+
+        let mut decoded = vec![];
+        MultiGzDecoder::new(bytes.as_slice()).read_to_end(&mut decoded).unwrap();
+        assert_eq!(decoded, data);
+        assert!(blocks(&bytes).iter().all(|b| b.len() <= MAX_BLOCK_SIZE));
+    }
+
+    #[test]
+    fn test_drop_finishes_stream() {
+        let mut bytes = vec![];
+        {
+            let mut writer = BgzfWriter::new(&mut bytes, Compression::default());
+            writer.write_all(b"hello\n").unwrap();
+        }
+        assert!(bytes.ends_with(&BGZF_EOF));
+        assert_eq!(blocks(&bytes).len(), 2);
+    }
+
+    #[test]
+    fn test_virtual_offset_parts() {
+        let offset = VirtualOffset::new(123_456, 789);
+        assert_eq!(offset.coffset(), 123_456);
+        assert_eq!(offset.uoffset(), 789);
+        assert_eq!(u64::from(offset), (123_456 << 16) | 789);
+        assert_eq!(VirtualOffset::from(u64::from(offset)), offset);
+        assert_eq!(offset.to_string(), "123456:789");
+    }
+
+    #[test]
+    fn test_reader_reads_back_written_data() {
+        let data: Vec<u8> = (0..200_000).map(|i| (i % 251) as u8).collect();
+        let mut writer = BgzfWriter::new(vec![], Compression::default());
+        writer.write_all(&data).unwrap();
+        let bytes = writer.finish().unwrap();
+
+        let mut reader = BgzfReader::new(bytes.as_slice());
+        let mut decoded = vec![];
+        reader.read_to_end(&mut decoded).unwrap();
+        assert_eq!(decoded, data);
+        assert_eq!(reader.virtual_offset(), VirtualOffset::new(bytes.len() as u64, 0));
+    }
+
+    #[test]
+    fn test_seek_virtual_returns_to_recorded_lines() {
+        // Flush after every few lines so that the lines are spread over many blocks
+        let mut writer = BgzfWriter::new(vec![], Compression::default());
+        for i in 0..100 {
+            writeln!(writer, "line-{i}").unwrap();
+            if i % 7 == 0 {
+                writer.flush().unwrap();
+            }
+        }
+        let bytes = writer.finish().unwrap();
+
+        let mut reader = BgzfReader::new(Cursor::new(bytes));
+        let mut offsets = vec![];
+        let mut line = String::new();
+        loop {
+            let offset = reader.virtual_offset();
+            line.clear();
+            if reader.read_line(&mut line).unwrap() == 0 {
+                break;
+            }
+            offsets.push(offset);
+        }
+        assert_eq!(offsets.len(), 100);
+        assert!(offsets.iter().any(|o| o.coffset() > 0));
+
+        for i in [57, 3, 99, 0, 42] {
+            reader.seek_virtual(offsets[i]).unwrap();
+            line.clear();
+            reader.read_line(&mut line).unwrap();
+            assert_eq!(line, format!("line-{i}\n"));
+        }
+    }
+
+    #[test]
+    fn test_seek_virtual_beyond_block_fails() {
+        let mut writer = BgzfWriter::new(vec![], Compression::default());
+        writer.write_all(b"short\n").unwrap();
+        let bytes = writer.finish().unwrap();
+
+        let mut reader = BgzfReader::new(Cursor::new(bytes));
+        assert!(reader.seek_virtual(VirtualOffset::new(0, 1000)).is_err());
+    }
+
+    #[test]
+    fn test_reader_rejects_plain_gzip() {
+        let mut encoder = flate2::write::GzEncoder::new(vec![], Compression::default());
+        encoder.write_all(b"not bgzf\n").unwrap();
+        let bytes = encoder.finish().unwrap();
+
+        let mut reader = BgzfReader::new(bytes.as_slice());
+        let mut decoded = vec![];
+        assert!(reader.read_to_end(&mut decoded).is_err());
+    }
+}
@@ -0,0 +1,195 @@
+//! A reader that detects and removes byte-order marks from text.
+use std::io::{self, ErrorKind, Read};
+
+/// The UTF-8 byte-order mark.
+pub(crate) const UTF8_BOM: [u8; 3] = [0xef, 0xbb, 0xbf];
+
+/// Returns true if data starts with a UTF-16 byte-order mark, in either byte order.
+pub(crate) fn starts_with_utf16_bom(data: &[u8]) -> bool {
+    data.starts_with(&[0xff, 0xfe]) || data.starts_with(&[0xfe, 0xff])
+}
+
+/// The text encoding of a stream, as determined from its byte-order mark.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+enum Bom {
+    /// The start of the stream has not been read yet
+    Unknown,
+    /// UTF-8 or no byte-order mark, in which case data is passed through unchanged
+    None,
+    /// UTF-16, which is converted to UTF-8
+    Utf16 { big_endian: bool },
+}
+
+/// Reader that detects a byte-order mark at the start of its input and removes it, so that e.g.
+/// the first column header of a CSV file written by a Windows tool does not start with
+/// `\u{feff}`.  A UTF-8 byte-order mark is simply dropped.  Input starting with a UTF-16 (little
+/// or big endian) byte-order mark is converted to UTF-8 as it is read.  Input without a
+/// byte-order mark is passed through unchanged.
+pub struct BomStrippingReader<R> {
+    inner: R,
+    bom: Bom,
+    /// Decoded data waiting to be returned to the caller
+    out: Vec<u8>,
+    out_pos: usize,
+    /// Undecoded UTF-16 bytes left over from the previous read (e.g. half a code unit)
+    pending: Vec<u8>,
+}
+
+impl<R> BomStrippingReader<R> {
+    /// Wraps a reader, which must be positioned at the start of its input.
+    pub fn new(inner: R) -> Self {
+        Self { inner, bom: Bom::Unknown, out: Vec::new(), out_pos: 0, pending: Vec::new() }
+    }
+
+    /// Consumes this wrapper, returning the underlying reader.
+    pub fn into_inner(self) -> R {
+        self.inner
+    }
+}
+
+impl<R: Read> BomStrippingReader<R> {
+    /// Reads the first few bytes of the input to detect any byte-order mark.
+    fn detect(&mut self) -> io::Result<()> {
+        let mut head = Vec::with_capacity(UTF8_BOM.len());
+        let mut byte = [0u8; 1];
+        while head.len() < UTF8_BOM.len() {
+            match self.inner.read(&mut byte) {
+                Ok(0) => break,
+                Ok(_) => head.push(byte[0]),
+                Err(e) if e.kind() == ErrorKind::Interrupted => (),
+                Err(e) => return Err(e),
+            }
+            if !UTF8_BOM.starts_with(&head) && head.len() >= 2 {
+                break;
+            }
+        }
+
+        if head == UTF8_BOM {
+            self.bom = Bom::None;
+        } else if starts_with_utf16_bom(&head) {
+            self.bom = Bom::Utf16 { big_endian: head[0] == 0xfe };
+            self.pending.extend_from_slice(&head[2..]);
+        } else {
+            self.bom = Bom::None;
+            self.out = head;
+        }
+        Ok(())
+    }
+
+    /// Reads and decodes more UTF-16 input into `out`, returning false at the end of input.
+    fn decode_utf16(&mut self, big_endian: bool, buf_len: usize) -> io::Result<bool> {
+        let mut raw = vec![0u8; buf_len.max(4)];
+        let n = self.inner.read(&mut raw)?;
+        if n == 0 {
+            if self.pending.is_empty() {
+                return Ok(false);
+            }
+            return Err(io::Error::new(ErrorKind::InvalidData, "Truncated UTF-16 input"));
+        }
+        self.pending.extend_from_slice(&raw[..n]);
+
+        let mut units: Vec<u16> = self
+            .pending
+            .chunks_exact(2)
+            .map(|b| {
+                if big_endian {
+                    u16::from_be_bytes([b[0], b[1]])
+                } else {
+                    u16::from_le_bytes([b[0], b[1]])
+                }
+            })
+            .collect();
+        let mut consumed = units.len() * 2;
+        // Hold back a high surrogate whose pair has not been read yet
+        if matches!(units.last(), Some(u) if (0xd800..0xdc00).contains(u)) {
+            units.pop();
+            consumed -= 2;
+        }
+        self.pending.drain(..consumed);
+
+        self.out.clear();
+        self.out_pos = 0;
+        for c in char::decode_utf16(units) {
+            let c = c.map_err(|e| io::Error::new(ErrorKind::InvalidData, e))?;
+            let mut utf8 = [0u8; 4];
+            self.out.extend_from_slice(c.encode_utf8(&mut utf8).as_bytes());
+        }
+        Ok(true)
+    }
+}
+
+impl<R: Read> Read for BomStrippingReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        if self.bom == Bom::Unknown {
+            self.detect()?;
+        }
+
+        while self.out_pos >= self.out.len() {
+            match self.bom {
+                Bom::Utf16 { big_endian } => {
+                    if !self.decode_utf16(big_endian, buf.len())? {
+                        return Ok(0);
+                    }
+                }
+                _ => return self.inner.read(buf),
+            }
+        }
+
+        let n = buf.len().min(self.out.len() - self.out_pos);
+        buf[..n].copy_from_slice(&self.out[self.out_pos..self.out_pos + n]);
+        self.out_pos += n;
+        Ok(n)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rstest::rstest;
+
+    fn read_all(bytes: &[u8]) -> io::Result<Vec<u8>> {
+        let mut out = Vec::new();
+        BomStrippingReader::new(bytes).read_to_end(&mut out)?;
+        Ok(out)
+    }
+
+    #[rstest]
+    #[case(b"", b"")]
+    #[case(b"a", b"a")]
+    #[case(b"\xef\xbb", b"\xef\xbb")]
+    #[case(b"\xef\xbb\xbf", b"")]
+    #[case(b"\xef\xbb\xbfsample,count", b"sample,count")]
+    #[case(b"sample\xef\xbb\xbf", b"sample\xef\xbb\xbf")]
+    #[case(b"\xff\xfea\x00\xe9\x00", "aé".as_bytes())]
+    #[case(b"\xfe\xff\x00a\x00\xe9", "aé".as_bytes())]
+    #[case(b"\xff\xfe=\xd8\x00\xde", "😀".as_bytes())]
+    fn test_stripping_boms(#[case] input: &[u8], #[case] expected: &[u8]) {
+        assert_eq!(read_all(input).unwrap(), expected);
+    }
+
+    #[test]
+    fn test_utf16_split_across_reads() {
+        let text = "héllo 😀 wörld\n".repeat(100);
+        let mut bytes = vec![0xff, 0xfe];
+        bytes.extend(text.encode_utf16().flat_map(u16::to_le_bytes));
+
+        // Read in tiny pieces so that code units and surrogate pairs are split between reads
+        let mut reader = BomStrippingReader::new(&bytes[..]);
+        let mut out = Vec::new();
+        let mut buf = [0u8; 3];
+        loop {
+            let n = reader.read(&mut buf).unwrap();
+            if n == 0 {
+                break;
+            }
+            out.extend_from_slice(&buf[..n]);
+        }
+        assert_eq!(String::from_utf8(out).unwrap(), text);
+    }
+
+    #[test]
+    fn test_invalid_utf16_fails() {
+        assert!(read_all(b"\xff\xfea\x00b").is_err());
+        assert!(read_all(b"\xff\xfe\x00\xdca\x00").is_err());
+    }
+}
@@ -0,0 +1,190 @@
+//! Writers that compute a checksum over the bytes written and record it in a sidecar file.
+use std::fmt::Write as FmtWrite;
+use std::fs::File;
+use std::io::{self, Read, Write};
+use std::path::{Path, PathBuf};
+
+use md5::Md5;
+use sha2::{Digest, Sha256};
+
+use crate::io::FinishWrite;
+
+/// The checksum algorithms that can be used to produce sidecar files for outputs.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum ChecksumAlgorithm {
+    /// MD5, written to a `.md5` sidecar
+    Md5,
+    /// SHA-256, written to a `.sha256` sidecar
+    Sha256,
+}
+
+impl ChecksumAlgorithm {
+    /// The extension appended to a file's name to generate the name of its sidecar file.
+    pub fn extension(&self) -> &'static str {
+        match self {
+            ChecksumAlgorithm::Md5 => "md5",
+            ChecksumAlgorithm::Sha256 => "sha256",
+        }
+    }
+
+    /// Returns the path of the sidecar file for the given path, e.g. `out.tsv.gz.md5`.
+    pub fn sidecar_path<P: AsRef<Path>>(&self, p: &P) -> PathBuf {
+        let mut name = p.as_ref().as_os_str().to_owned();
+        name.push(".");
+        name.push(self.extension());
+        PathBuf::from(name)
+    }
+}
+
+/// The state of an in-progress checksum.
+enum Hasher {
+    Md5(Md5),
+    Sha256(Sha256),
+}
+
+impl Hasher {
+    fn new(algorithm: ChecksumAlgorithm) -> Self {
+        match algorithm {
+            ChecksumAlgorithm::Md5 => Hasher::Md5(Md5::new()),
+            ChecksumAlgorithm::Sha256 => Hasher::Sha256(Sha256::new()),
+        }
+    }
+
+    fn update(&mut self, data: &[u8]) {
+        match self {
+            Hasher::Md5(h) => h.update(data),
+            Hasher::Sha256(h) => h.update(data),
+        }
+    }
+
+    fn finalize_hex(self) -> String {
+        let bytes = match self {
+            Hasher::Md5(h) => h.finalize().to_vec(),
+            Hasher::Sha256(h) => h.finalize().to_vec(),
+        };
+        bytes.iter().fold(String::with_capacity(bytes.len() * 2), |mut hex, b| {
+            let _ = write!(hex, "{b:02x}");
+            hex
+        })
+    }
+}
+
+/// Writer that passes bytes through to a file while computing their checksum, and writes the
+/// checksum to a sidecar file (in the format used by `md5sum`/`sha256sum`) when finished, or
+/// when dropped if it was not finished explicitly.
+pub(crate) struct ChecksumWriter {
+    inner: File,
+    path: PathBuf,
+    sidecar: File,
+    hasher: Option<Hasher>,
+}
+
+impl ChecksumWriter {
+    /// Wraps a file that was newly created (or truncated) at `path`, writing the checksum to
+    /// `sidecar`, which must be empty.
+    pub(crate) fn new<P: AsRef<Path>>(
+        inner: File,
+        path: &P,
+        sidecar: File,
+        algorithm: ChecksumAlgorithm,
+    ) -> Self {
+        let path = path.as_ref().to_path_buf();
+        Self { inner, path, sidecar, hasher: Some(Hasher::new(algorithm)) }
+    }
+
+    /// Wraps a file opened for appending at `path`, first hashing the file's existing contents
+    /// so that the checksum covers the whole file.
+    pub(crate) fn appending<P: AsRef<Path>>(
+        inner: File,
+        path: &P,
+        sidecar: File,
+        algorithm: ChecksumAlgorithm,
+    ) -> io::Result<Self> {
+        let mut writer = Self::new(inner, path, sidecar, algorithm);
+        let mut existing = File::open(path)?;
+        let mut buffer = vec![0u8; 64 * 1024];
+        loop {
+            let n = existing.read(&mut buffer)?;
+            if n == 0 {
+                break;
+            }
+            writer.hasher.as_mut().unwrap().update(&buffer[..n]);
+        }
+        Ok(writer)
+    }
+}
+
+impl Write for ChecksumWriter {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        if self.hasher.is_none() {
+            let msg = "Cannot write to a writer that has been finished";
+            return Err(io::Error::new(io::ErrorKind::BrokenPipe, msg));
+        }
+        let n = self.inner.write(buf)?;
+        if let Some(hasher) = self.hasher.as_mut() {
+            hasher.update(&buf[..n]);
+        }
+        Ok(n)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+impl FinishWrite for ChecksumWriter {
+    fn finish(&mut self) -> io::Result<()> {
+        if let Some(hasher) = self.hasher.take() {
+            self.inner.flush()?;
+            let name = self.path.file_name().map(|n| n.to_string_lossy()).unwrap_or_default();
+            writeln!(self.sidecar, "{}  {}", hasher.finalize_hex(), name)?;
+            self.sidecar.flush()?;
+        }
+        Ok(())
+    }
+}
+
+impl Drop for ChecksumWriter {
+    fn drop(&mut self) {
+        let _ = self.finish();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    #[test]
+    fn test_sidecar_path() {
+        let path = Path::new("/data/out.tsv.gz");
+        assert_eq!(ChecksumAlgorithm::Md5.sidecar_path(&path), Path::new("/data/out.tsv.gz.md5"));
+        assert_eq!(
+            ChecksumAlgorithm::Sha256.sidecar_path(&path),
+            Path::new("/data/out.tsv.gz.sha256")
+        );
+    }
+
+    #[test]
+    fn test_known_checksums() {
+        let tempdir = TempDir::new().unwrap();
+        let path = tempdir.path().join("hello.txt");
+
+        for (algorithm, expected) in [
+            (ChecksumAlgorithm::Md5, "b1946ac92492d2347c6235b4d2611184"),
+            (
+                ChecksumAlgorithm::Sha256,
+                "5891b5b522d5df086d0ff0b110fbd9d21bb4fc7163af34d08286a2e846f6be03",
+            ),
+        ] {
+            let file = File::create(&path).unwrap();
+            let sidecar = File::create(algorithm.sidecar_path(&path)).unwrap();
+            let mut writer = ChecksumWriter::new(file, &path, sidecar, algorithm);
+            writer.write_all(b"hello\n").unwrap();
+            writer.finish().unwrap();
+
+            let sidecar = std::fs::read_to_string(algorithm.sidecar_path(&path)).unwrap();
+            assert_eq!(sidecar, format!("{expected}  hello.txt\n"));
+        }
+    }
+}
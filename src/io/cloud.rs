@@ -0,0 +1,217 @@
+//! Streaming reads and writes of objects in cloud object stores such as S3 and GCS.
+use std::io::{self, ErrorKind, Read, Write};
+use std::sync::Arc;
+
+use bytes::Bytes;
+use futures::stream::{BoxStream, StreamExt};
+use object_store::aws::AmazonS3Builder;
+use object_store::buffered::BufWriter as ObjectBufWriter;
+use object_store::gcp::GoogleCloudStorageBuilder;
+use object_store::path::Path as ObjectPath;
+use object_store::ObjectStore;
+use tokio::io::AsyncWriteExt;
+use tokio::runtime::Runtime;
+
+use crate::io::FinishWrite;
+
+/// The URL schemes that are read from and written to object stores
+#[cfg(not(test))]
+pub(crate) const OBJECT_STORE_SCHEMES: [&str; 2] = ["s3://", "gs://"];
+
+/// The URL schemes that are read from and written to object stores, plus `memory://` for an
+/// in-memory store shared by all tests
+#[cfg(test)]
+pub(crate) const OBJECT_STORE_SCHEMES: [&str; 3] = ["s3://", "gs://", "memory://"];
+
+/// Opens an object, given as an `s3://` or `gs://` URL, for reading.
+pub(crate) fn open_object_read(url: &str) -> io::Result<Box<dyn Read + Send>> {
+    let (store, path) = parse_url(url)?;
+    Ok(Box::new(ObjectReader::new(store, path)?))
+}
+
+/// Opens an object, given as an `s3://` or `gs://` URL, for writing.
+pub(crate) fn open_object_write(url: &str) -> io::Result<Box<dyn FinishWrite>> {
+    let (store, path) = parse_url(url)?;
+    Ok(Box::new(ObjectWriter::new(store, path)?))
+}
+
+/// Splits a URL into its scheme, bucket and key.
+fn split_url(url: &str) -> io::Result<(&str, &str, &str)> {
+    let invalid = || io::Error::new(ErrorKind::InvalidInput, format!("Invalid object URL: {url}"));
+    let (scheme, rest) = url.split_once("://").ok_or_else(invalid)?;
+    let (bucket, key) = rest.split_once('/').ok_or_else(invalid)?;
+    if bucket.is_empty() || key.is_empty() {
+        return Err(invalid());
+    }
+    Ok((scheme, bucket, key))
+}
+
+/// Builds a store for the bucket in a URL, configured from the environment in the same way as
+/// the AWS and Google Cloud command line tools, and returns it with the path of the object.
+fn parse_url(url: &str) -> io::Result<(Arc<dyn ObjectStore>, ObjectPath)> {
+    let (scheme, bucket, key) = split_url(url)?;
+    let store: Arc<dyn ObjectStore> = match scheme {
+        "s3" => Arc::new(AmazonS3Builder::from_env().with_bucket_name(bucket).build()?),
+        "gs" => Arc::new(GoogleCloudStorageBuilder::from_env().with_bucket_name(bucket).build()?),
+        #[cfg(test)]
+        "memory" => tests::memory_store(),
+        _ => {
+            let msg = format!("Unsupported object store URL: {url}");
+            return Err(io::Error::new(ErrorKind::InvalidInput, msg));
+        }
+    };
+    Ok((store, ObjectPath::from(key)))
+}
+
+/// Creates a single-threaded runtime on which to drive an object store's requests.
+fn new_runtime() -> io::Result<Runtime> {
+    tokio::runtime::Builder::new_current_thread().enable_all().build()
+}
+
+/// Reader that streams the contents of an object, blocking on each chunk of the response.
+pub(crate) struct ObjectReader {
+    runtime: Runtime,
+    stream: BoxStream<'static, object_store::Result<Bytes>>,
+    chunk: Bytes,
+}
+
+impl ObjectReader {
+    /// Requests an object from a store, failing if it does not exist.
+    pub(crate) fn new(store: Arc<dyn ObjectStore>, path: ObjectPath) -> io::Result<Self> {
+        let runtime = new_runtime()?;
+        let stream = runtime.block_on(store.get(&path))?.into_stream();
+        Ok(Self { runtime, stream, chunk: Bytes::new() })
+    }
+}
+
+impl Read for ObjectReader {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        while self.chunk.is_empty() {
+            match self.runtime.block_on(self.stream.next()) {
+                Some(chunk) => self.chunk = chunk?,
+                None => return Ok(0),
+            }
+        }
+
+        let n = buf.len().min(self.chunk.len());
+        buf[..n].copy_from_slice(&self.chunk.split_to(n));
+        Ok(n)
+    }
+}
+
+/// Writer that uploads data to an object, buffering it and using a multipart upload for large
+/// objects.  The object is only created once the writer is finished, or otherwise dropped; since
+/// errors cannot be returned from `drop`, a failure to complete the upload is then logged at
+/// `error` level.
+pub(crate) struct ObjectWriter {
+    runtime: Runtime,
+    inner: ObjectBufWriter,
+    completed: bool,
+}
+
+impl ObjectWriter {
+    /// Creates a writer that uploads to the given path in a store.
+    pub(crate) fn new(store: Arc<dyn ObjectStore>, path: ObjectPath) -> io::Result<Self> {
+        let inner = ObjectBufWriter::new(store, path);
+        Ok(Self { runtime: new_runtime()?, inner, completed: false })
+    }
+}
+
+impl Write for ObjectWriter {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        if self.completed {
+            let msg = "Cannot write to an object whose upload has completed";
+            return Err(io::Error::new(ErrorKind::BrokenPipe, msg));
+        }
+        self.runtime.block_on(self.inner.write(buf))
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        if self.completed {
+            return Ok(());
+        }
+        self.runtime.block_on(self.inner.flush())
+    }
+}
+
+impl FinishWrite for ObjectWriter {
+    /// Completes the upload, creating the object.
+    fn finish(&mut self) -> io::Result<()> {
+        if self.completed {
+            return Ok(());
+        }
+        self.completed = true;
+        self.runtime.block_on(self.inner.shutdown())
+    }
+}
+
+impl Drop for ObjectWriter {
+    fn drop(&mut self) {
+        if let Err(e) = self.finish() {
+            log::error!("Failed to complete upload to object store: {e}");
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::io::Io;
+    use object_store::memory::InMemory;
+    use std::sync::OnceLock;
+
+    /// Returns the store used for `memory://` URLs.
+    pub(super) fn memory_store() -> Arc<dyn ObjectStore> {
+        static STORE: OnceLock<Arc<InMemory>> = OnceLock::new();
+        STORE.get_or_init(|| Arc::new(InMemory::new())).clone()
+    }
+
+    #[test]
+    fn test_split_url() {
+        assert_eq!(
+            split_url("s3://bucket/dir/file.txt").unwrap(),
+            ("s3", "bucket", "dir/file.txt")
+        );
+        assert_eq!(split_url("gs://bucket/file.txt").unwrap(), ("gs", "bucket", "file.txt"));
+        assert!(split_url("s3://bucket").is_err());
+        assert!(split_url("s3:///file.txt").is_err());
+        assert!(split_url("bucket/file.txt").is_err());
+    }
+
+    #[test]
+    fn test_writing_and_reading_objects() {
+        let store: Arc<dyn ObjectStore> = Arc::new(InMemory::new());
+        let path = ObjectPath::from("dir/file.txt");
+        let data: Vec<u8> = (0..100_000u32).flat_map(|i| i.to_le_bytes()).collect();
+
+        let mut writer = ObjectWriter::new(store.clone(), path.clone()).unwrap();
+        for chunk in data.chunks(1000) {
+            writer.write_all(chunk).unwrap();
+        }
+        writer.finish().unwrap();
+        assert_eq!(writer.write(b"more").unwrap_err().kind(), ErrorKind::BrokenPipe);
+
+        let mut read = vec![];
+        ObjectReader::new(store, path).unwrap().read_to_end(&mut read).unwrap();
+        assert_eq!(read, data);
+    }
+
+    #[test]
+    fn test_writing_objects_after_flushing() {
+        let io = Io::default();
+        let url = "memory://bucket/flushed.txt.gz";
+        let mut writer = io.new_writer(&url).unwrap();
+        writer.write_all(b"first\n").unwrap();
+        writer.flush().unwrap();
+        writer.write_all(b"second\n").unwrap();
+        writer.finish().unwrap();
+        assert_eq!(io.read_lines(&url).unwrap(), vec!["first", "second"]);
+    }
+
+    #[test]
+    fn test_reading_missing_object() {
+        let store: Arc<dyn ObjectStore> = Arc::new(InMemory::new());
+        let result = ObjectReader::new(store, ObjectPath::from("missing.txt"));
+        assert_eq!(result.err().unwrap().kind(), ErrorKind::NotFound);
+    }
+}
@@ -0,0 +1,233 @@
+//! Converting tables between delimited formats and, with the `json` feature, JSON Lines.
+use std::io::{Read, Write};
+
+use csv::ByteRecord;
+
+#[cfg(feature = "json")]
+use {
+    crate::io::delim::dedupe_headers,
+    crate::io::DuplicateColumns,
+    serde_json::{Map, Value},
+    std::io::{self, BufRead, ErrorKind},
+};
+
+use crate::{FgError, Result};
+
+/// The format of a table read or written by
+/// [`DelimFile::convert`](crate::io::DelimFile::convert).
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum TableFormat {
+    /// Comma-separated values
+    Csv,
+    /// Tab-separated values
+    Tsv,
+    /// Values separated by any other single byte, such as `|` or `;`
+    Delimited(u8),
+    /// JSON Lines, with one JSON object per line whose keys are the column names.  Available
+    /// with the `json` feature.
+    #[cfg(feature = "json")]
+    Jsonl,
+}
+
+impl TableFormat {
+    /// Returns the format usually stored in files with the given extension, without any
+    /// compression extension: `csv`, `tsv` or `txt` (tab-separated), `psv` (pipe-separated),
+    /// and with the `json` feature `jsonl` or `ndjson`.  The extension is matched ignoring case.
+    pub fn from_extension(extension: &str) -> Option<TableFormat> {
+        match extension.to_ascii_lowercase().as_str() {
+            "csv" => Some(TableFormat::Csv),
+            "tsv" | "txt" => Some(TableFormat::Tsv),
+            "psv" => Some(TableFormat::Delimited(b'|')),
+            #[cfg(feature = "json")]
+            "jsonl" | "ndjson" => Some(TableFormat::Jsonl),
+            _ => None,
+        }
+    }
+
+    /// Returns the byte separating fields, or `None` for JSON Lines.
+    pub fn delimiter(&self) -> Option<u8> {
+        match self {
+            TableFormat::Csv => Some(b','),
+            TableFormat::Tsv => Some(b'\t'),
+            TableFormat::Delimited(delimiter) => Some(*delimiter),
+            #[cfg(feature = "json")]
+            TableFormat::Jsonl => None,
+        }
+    }
+}
+
+/// Copies the header and records of a delimited file to a writer with a different delimiter.
+/// Returns the number of records written.
+pub(crate) fn delim_to_delim<R: Read, W: Write>(
+    reader: &mut csv::Reader<R>,
+    writer: &mut csv::Writer<W>,
+) -> Result<u64> {
+    writer.write_byte_record(reader.byte_headers()?)?;
+    let mut count = 0;
+    let mut record = ByteRecord::new();
+    while reader.read_byte_record(&mut record)? {
+        writer.write_byte_record(&record)?;
+        count += 1;
+    }
+    writer.flush().map_err(FgError::IoError)?;
+    Ok(count)
+}
+
+/// Writes each record of a delimited file as a JSON object keyed by the column names, with
+/// values as strings and empty or null-token values as `null`.  Repeated column names are
+/// handled according to `duplicates`.  Returns the number of records written.
+#[cfg(feature = "json")]
+pub(crate) fn delim_to_jsonl<R: Read, W: Write>(
+    reader: &mut csv::Reader<R>,
+    mut write: W,
+    null_tokens: &[String],
+    duplicates: DuplicateColumns,
+) -> Result<u64> {
+    let mut header = reader.byte_headers()?.clone();
+    dedupe_headers(&mut header, duplicates)?;
+    let columns: Vec<String> =
+        header.iter().map(|name| String::from_utf8_lossy(name).into_owned()).collect();
+
+    let mut count = 0;
+    let mut record = csv::StringRecord::new();
+    while reader.read_record(&mut record)? {
+        let object: Map<String, Value> = columns
+            .iter()
+            .enumerate()
+            .map(|(i, column)| {
+                let value = match record.get(i) {
+                    Some(v) if !v.is_empty() && !null_tokens.iter().any(|t| t == v) => {
+                        Value::String(v.to_string())
+                    }
+                    _ => Value::Null,
+                };
+                (column.clone(), value)
+            })
+            .collect();
+        write_json_line(&mut write, &object)?;
+        count += 1;
+    }
+    write.flush().map_err(FgError::IoError)?;
+    Ok(count)
+}
+
+/// Writes the objects of a JSON Lines file as delimited records, with a header of the keys of
+/// the first object in order.  Missing keys and `null` values are written as empty fields,
+/// strings as their contents, and other values as JSON.  Returns the number of records written,
+/// or an error if a line is not a JSON object or has a key not in the first object.
+#[cfg(feature = "json")]
+pub(crate) fn jsonl_to_delim<R: BufRead, W: Write>(
+    read: R,
+    writer: &mut csv::Writer<W>,
+) -> Result<u64> {
+    let mut columns: Option<Vec<String>> = None;
+    let mut count = 0;
+    let mut record = csv::StringRecord::new();
+    for object in json_objects(read) {
+        let object = object?;
+        if columns.is_none() {
+            writer.write_record(object.keys())?;
+            columns = Some(object.keys().cloned().collect());
+        }
+        let columns = columns.as_ref().expect("set from the first object");
+        let unexpected: Vec<String> =
+            object.keys().filter(|k| !columns.contains(k)).cloned().collect();
+        if !unexpected.is_empty() {
+            return Err(FgError::HeaderMismatch { missing: vec![], unexpected });
+        }
+
+        record.clear();
+        for column in columns.iter() {
+            match object.get(column) {
+                None | Some(Value::Null) => record.push_field(""),
+                Some(Value::String(s)) => record.push_field(s),
+                Some(value) => record.push_field(&value.to_string()),
+            }
+        }
+        writer.write_record(&record)?;
+        count += 1;
+    }
+    writer.flush().map_err(FgError::IoError)?;
+    Ok(count)
+}
+
+/// Rewrites the objects of a JSON Lines file one per line in compact form, checking that each
+/// line is a JSON object.  Returns the number of objects written.
+#[cfg(feature = "json")]
+pub(crate) fn jsonl_to_jsonl<R: BufRead, W: Write>(read: R, mut write: W) -> Result<u64> {
+    let mut count = 0;
+    for object in json_objects(read) {
+        write_json_line(&mut write, &object?)?;
+        count += 1;
+    }
+    write.flush().map_err(FgError::IoError)?;
+    Ok(count)
+}
+
+/// Iterates over the JSON objects on the non-blank lines of a reader.
+#[cfg(feature = "json")]
+fn json_objects<R: BufRead>(read: R) -> impl Iterator<Item = Result<Map<String, Value>>> {
+    read.lines().zip(1..).filter(|(line, _)| !line.as_ref().is_ok_and(|l| l.trim().is_empty())).map(
+        |(line, n)| {
+            let line = line.map_err(FgError::IoError)?;
+            serde_json::from_str(&line).map_err(|e| {
+                let msg = format!("Line {n} is not a JSON object: {e}");
+                FgError::IoError(io::Error::new(ErrorKind::InvalidData, msg))
+            })
+        },
+    )
+}
+
+/// Writes a JSON object followed by a new line.
+#[cfg(feature = "json")]
+fn write_json_line<W: Write>(write: &mut W, object: &Map<String, Value>) -> Result<()> {
+    serde_json::to_writer(&mut *write, object).map_err(|e| FgError::IoError(e.into()))?;
+    write.write_all(b"\n").map_err(FgError::IoError)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rstest::rstest;
+
+    #[rstest]
+    #[case("csv", Some(TableFormat::Csv))]
+    #[case("TSV", Some(TableFormat::Tsv))]
+    #[case("txt", Some(TableFormat::Tsv))]
+    #[case("psv", Some(TableFormat::Delimited(b'|')))]
+    #[case("gz", None)]
+    fn test_table_format_from_extension(#[case] ext: &str, #[case] expected: Option<TableFormat>) {
+        assert_eq!(TableFormat::from_extension(ext), expected);
+    }
+
+    #[cfg(feature = "json")]
+    #[test]
+    fn test_converting_between_delimited_and_jsonl() {
+        let tsv = "name\tcount\tnote\na\t1\t\nb\tNA\t\"x,y\"\n";
+        let mut reader = csv::ReaderBuilder::new().delimiter(b'\t').from_reader(tsv.as_bytes());
+        let mut jsonl = vec![];
+        let nulls = ["NA".to_string()];
+        let count = delim_to_jsonl(&mut reader, &mut jsonl, &nulls, DuplicateColumns::Reject);
+        assert_eq!(count.unwrap(), 2);
+        let expected = "{\"name\":\"a\",\"count\":\"1\",\"note\":null}\n\
+                        {\"name\":\"b\",\"count\":null,\"note\":\"x,y\"}\n";
+        assert_eq!(String::from_utf8(jsonl.clone()).unwrap(), expected);
+
+        let input = "{\"id\":1,\"tags\":[\"x\"],\"ok\":true}\n\n{\"ok\":null,\"id\":2}\n";
+        let mut writer = csv::WriterBuilder::new().from_writer(vec![]);
+        assert_eq!(jsonl_to_delim(input.as_bytes(), &mut writer).unwrap(), 2);
+        let csv = String::from_utf8(writer.get_ref().clone()).unwrap();
+        assert_eq!(csv, "id,tags,ok\n1,\"[\"\"x\"\"]\",true\n2,,\n");
+
+        let mut writer = csv::WriterBuilder::new().from_writer(vec![]);
+        let result = jsonl_to_delim("{\"id\":1}\n{\"id\":2,\"x\":3}\n".as_bytes(), &mut writer);
+        match result {
+            Err(FgError::HeaderMismatch { unexpected, .. }) => assert_eq!(unexpected, vec!["x"]),
+            _ => panic!("expected a header mismatch"),
+        }
+
+        let mut output = vec![];
+        let result = jsonl_to_jsonl("{\"id\":1}\n[1, 2]\n".as_bytes(), &mut output);
+        assert!(matches!(result, Err(FgError::IoError(e)) if e.to_string().starts_with("Line 2")));
+    }
+}
@@ -0,0 +1,172 @@
+//! Reader and writer wrappers that count the bytes passing through them.
+use std::io::{self, BufRead, Read, Write};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+
+use crate::io::FinishWrite;
+
+/// A shareable handle to a count of bytes.  Handles remain valid after the reader or writer that
+/// updates them has been dropped, so counts can be retrieved after a stream is closed.
+#[derive(Clone, Debug, Default)]
+pub struct ByteCounter(Arc<AtomicU64>);
+
+impl ByteCounter {
+    /// Returns the number of bytes counted so far.
+    pub fn get(&self) -> u64 {
+        self.0.load(Ordering::Relaxed)
+    }
+
+    fn add(&self, n: usize) {
+        self.0.fetch_add(n as u64, Ordering::Relaxed);
+    }
+}
+
+/// The compressed and uncompressed byte counts for a stream opened by
+/// [`Io::new_counting_reader`](crate::io::Io::new_counting_reader) or
+/// [`Io::new_counting_writer`](crate::io::Io::new_counting_writer).  For uncompressed files the
+/// two counts are the same.
+#[derive(Clone, Debug, Default)]
+pub struct ByteCounts {
+    compressed: ByteCounter,
+    uncompressed: ByteCounter,
+}
+
+impl ByteCounts {
+    pub(crate) fn new(compressed: ByteCounter, uncompressed: ByteCounter) -> Self {
+        Self { compressed, uncompressed }
+    }
+
+    /// The number of bytes read from or written to the underlying file.
+    pub fn compressed(&self) -> u64 {
+        self.compressed.get()
+    }
+
+    /// The number of bytes read or written by the caller, prior to compression.
+    pub fn uncompressed(&self) -> u64 {
+        self.uncompressed.get()
+    }
+}
+
+/// Reader that counts the bytes read through it.
+pub struct CountingReader<R> {
+    inner: R,
+    counter: ByteCounter,
+}
+
+impl<R> CountingReader<R> {
+    /// Wraps a reader, counting bytes from zero.
+    pub fn new(inner: R) -> Self {
+        Self { inner, counter: ByteCounter::default() }
+    }
+
+    /// Returns a handle to the count of bytes read, which remains valid after this is dropped.
+    pub fn counter(&self) -> ByteCounter {
+        self.counter.clone()
+    }
+
+    /// Returns the number of bytes read so far.
+    pub fn byte_count(&self) -> u64 {
+        self.counter.get()
+    }
+
+    /// Consumes this wrapper, returning the underlying reader.
+    pub fn into_inner(self) -> R {
+        self.inner
+    }
+}
+
+impl<R: Read> Read for CountingReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let n = self.inner.read(buf)?;
+        self.counter.add(n);
+        Ok(n)
+    }
+}
+
+impl<R: BufRead> BufRead for CountingReader<R> {
+    fn fill_buf(&mut self) -> io::Result<&[u8]> {
+        self.inner.fill_buf()
+    }
+
+    fn consume(&mut self, amt: usize) {
+        self.counter.add(amt);
+        self.inner.consume(amt);
+    }
+}
+
+/// Writer that counts the bytes written through it.
+pub struct CountingWriter<W> {
+    inner: W,
+    counter: ByteCounter,
+}
+
+impl<W> CountingWriter<W> {
+    /// Wraps a writer, counting bytes from zero.
+    pub fn new(inner: W) -> Self {
+        Self { inner, counter: ByteCounter::default() }
+    }
+
+    /// Returns a handle to the count of bytes written, which remains valid after this is dropped.
+    pub fn counter(&self) -> ByteCounter {
+        self.counter.clone()
+    }
+
+    /// Returns the number of bytes written so far.
+    pub fn byte_count(&self) -> u64 {
+        self.counter.get()
+    }
+
+    /// Consumes this wrapper, returning the underlying writer.
+    pub fn into_inner(self) -> W {
+        self.inner
+    }
+}
+
+impl<W: Write> Write for CountingWriter<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let n = self.inner.write(buf)?;
+        self.counter.add(n);
+        Ok(n)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+impl<W: FinishWrite> FinishWrite for CountingWriter<W> {
+    fn finish(&mut self) -> io::Result<()> {
+        self.inner.finish()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_counting_reader() {
+        let mut reader = CountingReader::new("hello\nworld\n".as_bytes());
+        let counter = reader.counter();
+
+        let mut line = String::new();
+        reader.read_line(&mut line).unwrap();
+        assert_eq!(reader.byte_count(), 6);
+
+        let mut rest = vec![];
+        reader.read_to_end(&mut rest).unwrap();
+        drop(reader);
+        assert_eq!(counter.get(), 12);
+    }
+
+    #[test]
+    fn test_counting_writer() {
+        let mut writer = CountingWriter::new(vec![]);
+        let counter = writer.counter();
+        writer.write_all(b"hello").unwrap();
+        assert_eq!(writer.byte_count(), 5);
+        writer.write_all(b" world").unwrap();
+        assert_eq!(writer.into_inner(), b"hello world");
+        assert_eq!(counter.get(), 11);
+    }
+}
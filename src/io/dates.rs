@@ -0,0 +1,203 @@
+//! Reading and writing dates and times in delimited files as `chrono` types.
+//!
+//! `chrono`'s own serde support reads and writes ISO 8601 values such as `2024-01-31`.  Columns
+//! in other formats can be read into `chrono` types either by configuring a [`DateFormat`] for
+//! the column with [`DelimFileBuilder::date_format`](crate::io::DelimFileBuilder::date_format),
+//! or by annotating the field with one of the modules here, which also write the same format:
+//!
+//! ```rust
+//! use chrono::NaiveDate;
+//! use serde::{Deserialize, Serialize};
+//!
+//! #[derive(Deserialize, Serialize)]
+//! struct Run {
+//!     #[serde(with = "fgoxide::io::dates::mdy")]
+//!     started: NaiveDate,
+//!     #[serde(with = "fgoxide::io::dates::excel_date")]
+//!     finished: NaiveDate,
+//! }
+//! ```
+use chrono::{Days, NaiveDate, NaiveDateTime, NaiveTime};
+
+/// The format of the `m/d/Y` dates written by US-locale spreadsheets, e.g. `1/31/2024`.
+const MDY: &str = "%m/%d/%Y";
+
+/// The ISO 8601 format in which dates with times are passed on for deserialization.
+const ISO_DATETIME: &str = "%Y-%m-%dT%H:%M:%S%.f";
+
+/// The number of milliseconds in a day.
+const MILLIS_PER_DAY: u64 = 86_400_000;
+
+/// The formats in which dates and times can be read by
+/// [`DelimFileBuilder::date_format`](crate::io::DelimFileBuilder::date_format).  Values are
+/// converted to ISO 8601 before deserialization, as a date if they have no time part and as a
+/// date and time otherwise, so columns of dates should be read into `NaiveDate` fields and
+/// columns with times into `NaiveDateTime` fields.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum DateFormat {
+    /// ISO 8601 dates and times, e.g. `2024-01-31` or `2024-01-31T13:45:00`, also allowing a
+    /// space in place of the `T` as written by many databases
+    Iso8601,
+    /// `m/d/Y` dates, e.g. `1/31/2024` or `01/31/2024`, optionally followed by a space and an
+    /// `H:M:S` time
+    MonthDayYear,
+    /// Excel serial dates, the number of days since 1899-12-30 with any fraction of a day as
+    /// the time, e.g. `45322` or `45322.5`
+    ExcelSerial,
+    /// A custom `strftime`-style format as understood by `chrono`, e.g. `%d.%m.%Y`
+    Custom(String),
+}
+
+impl DateFormat {
+    /// Converts a value in this format to ISO 8601, or returns `None` if it is not valid.
+    pub fn to_iso8601(&self, value: &str) -> Option<String> {
+        match self {
+            DateFormat::Iso8601 => Some(match value.split_once(' ') {
+                Some((date, time)) => format!("{date}T{time}"),
+                None => value.to_string(),
+            }),
+            DateFormat::MonthDayYear => match value.split_once(' ') {
+                Some((date, time)) => {
+                    let date = NaiveDate::parse_from_str(date, MDY).ok()?;
+                    let time = NaiveTime::parse_from_str(time, "%H:%M:%S").ok()?;
+                    Some(date.and_time(time).format(ISO_DATETIME).to_string())
+                }
+                None => NaiveDate::parse_from_str(value, MDY).ok().map(|d| d.to_string()),
+            },
+            DateFormat::ExcelSerial => {
+                let datetime = from_excel_serial(value.parse().ok()?)?;
+                Some(if datetime.time() == NaiveTime::MIN {
+                    datetime.date().to_string()
+                } else {
+                    datetime.format(ISO_DATETIME).to_string()
+                })
+            }
+            DateFormat::Custom(format) => match NaiveDateTime::parse_from_str(value, format) {
+                Ok(datetime) => Some(datetime.format(ISO_DATETIME).to_string()),
+                Err(_) => NaiveDate::parse_from_str(value, format).ok().map(|d| d.to_string()),
+            },
+        }
+    }
+}
+
+/// Returns the day that Excel serial dates count from.  Excel treats 1900 as a leap year, so
+/// counting from the last day of 1899 gives the correct date for serials from 1900-03-01 on.
+fn excel_epoch() -> NaiveDate {
+    NaiveDate::from_ymd_opt(1899, 12, 30).expect("valid date")
+}
+
+/// Converts an Excel serial date to a date and time, rounded to the nearest millisecond.
+fn from_excel_serial(serial: f64) -> Option<NaiveDateTime> {
+    if !serial.is_finite() || serial < 0.0 {
+        return None;
+    }
+    let millis = (serial * MILLIS_PER_DAY as f64).round() as u64;
+    let days = millis / MILLIS_PER_DAY;
+    let millis = (millis % MILLIS_PER_DAY) as u32;
+    let date = excel_epoch().checked_add_days(Days::new(days))?;
+    let time =
+        NaiveTime::from_num_seconds_from_midnight_opt(millis / 1000, (millis % 1000) * 1_000_000)?;
+    Some(date.and_time(time))
+}
+
+/// Converts a date and time to an Excel serial date.
+fn to_excel_serial(datetime: &NaiveDateTime) -> f64 {
+    let elapsed = datetime.signed_duration_since(excel_epoch().and_time(NaiveTime::MIN));
+    elapsed.num_milliseconds() as f64 / MILLIS_PER_DAY as f64
+}
+
+/// Serde helpers for `NaiveDate` fields in `m/d/Y` format, e.g. `1/31/2024`, for use with
+/// `#[serde(with = "fgoxide::io::dates::mdy")]`.  Dates are written zero-padded.
+pub mod mdy {
+    use chrono::NaiveDate;
+    use serde::{de, Deserialize, Deserializer, Serializer};
+
+    /// Writes a date in `m/d/Y` format.
+    pub fn serialize<S: Serializer>(date: &NaiveDate, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_str(&date.format(super::MDY))
+    }
+
+    /// Reads a date in `m/d/Y` format.
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<NaiveDate, D::Error> {
+        let value = String::deserialize(deserializer)?;
+        NaiveDate::parse_from_str(&value, super::MDY).map_err(de::Error::custom)
+    }
+}
+
+/// Serde helpers for `NaiveDate` fields holding Excel serial dates, e.g. `45322`, for use with
+/// `#[serde(with = "fgoxide::io::dates::excel_date")]`.  Any fraction of a day is ignored when
+/// reading.
+pub mod excel_date {
+    use chrono::NaiveDate;
+    use serde::{de, Deserialize, Deserializer, Serializer};
+
+    /// Writes a date as the whole number of days since the Excel epoch.
+    pub fn serialize<S: Serializer>(date: &NaiveDate, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_i64(date.signed_duration_since(super::excel_epoch()).num_days())
+    }
+
+    /// Reads a date from an Excel serial date.
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<NaiveDate, D::Error> {
+        let serial = f64::deserialize(deserializer)?;
+        super::from_excel_serial(serial)
+            .map(|datetime| datetime.date())
+            .ok_or_else(|| de::Error::custom(format!("invalid Excel serial date: {serial}")))
+    }
+}
+
+/// Serde helpers for `NaiveDateTime` fields holding Excel serial dates with times, e.g.
+/// `45322.5`, for use with `#[serde(with = "fgoxide::io::dates::excel_datetime")]`.  Times are
+/// rounded to the nearest millisecond.
+pub mod excel_datetime {
+    use chrono::NaiveDateTime;
+    use serde::{de, Deserialize, Deserializer, Serializer};
+
+    /// Writes a date and time as the fractional number of days since the Excel epoch.
+    pub fn serialize<S: Serializer>(
+        datetime: &NaiveDateTime,
+        serializer: S,
+    ) -> Result<S::Ok, S::Error> {
+        serializer.serialize_f64(super::to_excel_serial(datetime))
+    }
+
+    /// Reads a date and time from an Excel serial date.
+    pub fn deserialize<'de, D: Deserializer<'de>>(
+        deserializer: D,
+    ) -> Result<NaiveDateTime, D::Error> {
+        let serial = f64::deserialize(deserializer)?;
+        super::from_excel_serial(serial)
+            .ok_or_else(|| de::Error::custom(format!("invalid Excel serial date: {serial}")))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rstest::rstest;
+
+    #[rstest]
+    #[case(DateFormat::Iso8601, "2024-01-31", Some("2024-01-31"))]
+    #[case(DateFormat::Iso8601, "2024-01-31 13:45:00", Some("2024-01-31T13:45:00"))]
+    #[case(DateFormat::MonthDayYear, "1/31/2024", Some("2024-01-31"))]
+    #[case(DateFormat::MonthDayYear, "01/31/2024 13:45:10", Some("2024-01-31T13:45:10"))]
+    #[case(DateFormat::MonthDayYear, "31/01/2024", None)]
+    #[case(DateFormat::ExcelSerial, "45322", Some("2024-01-31"))]
+    #[case(DateFormat::ExcelSerial, "45322.75", Some("2024-01-31T18:00:00"))]
+    #[case(DateFormat::ExcelSerial, "-1", None)]
+    #[case(DateFormat::Custom("%d.%m.%Y".to_string()), "31.01.2024", Some("2024-01-31"))]
+    #[case(DateFormat::Custom("%d.%m.%Y %H:%M".to_string()), "31.01.2024 09:05", Some("2024-01-31T09:05:00"))]
+    fn test_converting_dates_to_iso8601(
+        #[case] format: DateFormat,
+        #[case] value: &str,
+        #[case] expected: Option<&str>,
+    ) {
+        assert_eq!(format.to_iso8601(value).as_deref(), expected);
+    }
+
+    #[test]
+    fn test_excel_serial_round_trip() {
+        let datetime = NaiveDate::from_ymd_opt(2024, 1, 31).unwrap().and_hms_opt(6, 0, 0).unwrap();
+        assert_eq!(to_excel_serial(&datetime), 45322.25);
+        assert_eq!(from_excel_serial(45322.25), Some(datetime));
+    }
+}
@@ -0,0 +1,190 @@
+//! Removing records with duplicate keys from delimited files.
+use std::collections::{HashMap, HashSet};
+use std::io::{Read, Write};
+
+use csv::ByteRecord;
+
+use crate::io::DuplicateKeys;
+use crate::{FgError, Result};
+
+/// The values of the key columns of a record.
+pub(crate) type Key = Vec<Vec<u8>>;
+
+/// Returns the values of the key columns of a record, treating missing fields as empty.
+pub(crate) fn key_of(record: &ByteRecord, indices: &[usize]) -> Key {
+    indices.iter().map(|&i| record.get(i).unwrap_or_default().to_vec()).collect()
+}
+
+/// Builds the error returned when a key is repeated and duplicates are rejected.
+fn duplicate_key(record: &ByteRecord, indices: &[usize], delimiter: u8) -> FgError {
+    let fields: Vec<String> =
+        key_of(record, indices).iter().map(|f| String::from_utf8_lossy(f).into_owned()).collect();
+    FgError::DuplicateKey {
+        key: fields.join(&char::from(delimiter).to_string()),
+        line: record.position().map_or(0, |p| p.line()),
+    }
+}
+
+/// Writes the records of a reader whose records with equal keys are adjacent, such as a file
+/// sorted by the keys, keeping one record for each run of equal keys.  Only one record is held
+/// in memory at a time.  Returns the number of records dropped.
+pub(crate) fn dedup_sorted<R: Read, W: Write>(
+    reader: &mut csv::Reader<R>,
+    writer: &mut csv::Writer<W>,
+    indices: &[usize],
+    duplicates: DuplicateKeys,
+    delimiter: u8,
+) -> Result<u64> {
+    let mut dropped = 0;
+    let mut held = ByteRecord::new();
+    let mut record = ByteRecord::new();
+    if !reader.read_byte_record(&mut held)? {
+        return Ok(0);
+    }
+    let mut key = key_of(&held, indices);
+    while reader.read_byte_record(&mut record)? {
+        let next = key_of(&record, indices);
+        if next == key {
+            match duplicates {
+                DuplicateKeys::Reject => return Err(duplicate_key(&record, indices, delimiter)),
+                DuplicateKeys::KeepFirst => {}
+                DuplicateKeys::KeepLast => std::mem::swap(&mut held, &mut record),
+            }
+            dropped += 1;
+        } else {
+            writer.write_byte_record(&held)?;
+            std::mem::swap(&mut held, &mut record);
+            key = next;
+        }
+    }
+    writer.write_byte_record(&held)?;
+    writer.flush().map_err(FgError::IoError)?;
+    Ok(dropped)
+}
+
+/// Writes the records of a reader in any order, keeping the first record with each key, or
+/// rejecting repeated keys.  The keys seen so far are held in memory.  Returns the number of
+/// records dropped.
+pub(crate) fn dedup_unsorted<R: Read, W: Write>(
+    reader: &mut csv::Reader<R>,
+    writer: &mut csv::Writer<W>,
+    indices: &[usize],
+    duplicates: DuplicateKeys,
+    delimiter: u8,
+) -> Result<u64> {
+    let mut dropped = 0;
+    let mut seen = HashSet::new();
+    let mut record = ByteRecord::new();
+    while reader.read_byte_record(&mut record)? {
+        if seen.insert(key_of(&record, indices)) {
+            writer.write_byte_record(&record)?;
+        } else if duplicates == DuplicateKeys::Reject {
+            return Err(duplicate_key(&record, indices, delimiter));
+        } else {
+            dropped += 1;
+        }
+    }
+    writer.flush().map_err(FgError::IoError)?;
+    Ok(dropped)
+}
+
+/// Writes the records of a reader in any order, keeping the last record with each key.  The
+/// last record seen with each key is held in memory with its position, and the records kept are
+/// written in their original order once the reader is exhausted, so the input is only read
+/// once.  Returns the number of records dropped.
+pub(crate) fn dedup_keep_last<R: Read, W: Write>(
+    reader: &mut csv::Reader<R>,
+    writer: &mut csv::Writer<W>,
+    indices: &[usize],
+) -> Result<u64> {
+    let mut last: HashMap<Key, (u64, ByteRecord)> = HashMap::new();
+    let mut record = ByteRecord::new();
+    let mut n = 0;
+    while reader.read_byte_record(&mut record)? {
+        last.insert(key_of(&record, indices), (n, record.clone()));
+        n += 1;
+    }
+
+    let mut kept: Vec<(u64, ByteRecord)> = last.into_values().collect();
+    kept.sort_unstable_by_key(|(n, _)| *n);
+    for (_, record) in &kept {
+        writer.write_byte_record(record)?;
+    }
+    writer.flush().map_err(FgError::IoError)?;
+    Ok(n - kept.len() as u64)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::io::delim::column_indices;
+    use csv::{ReaderBuilder, WriterBuilder};
+    use rstest::rstest;
+
+    const DATA: &str = "id\tvalue\na\t1\na\t2\nb\t3\nc\t4\nc\t5\nc\t6\n";
+    const UNSORTED: &str = "id\tvalue\na\t1\nc\t2\na\t3\nb\t4\nc\t5\n";
+
+    fn tsv_reader(data: &str) -> csv::Reader<&[u8]> {
+        ReaderBuilder::new().delimiter(b'\t').from_reader(data.as_bytes())
+    }
+
+    fn tsv_writer() -> csv::Writer<Vec<u8>> {
+        WriterBuilder::new().delimiter(b'\t').from_writer(vec![])
+    }
+
+    #[rstest]
+    #[case(DuplicateKeys::KeepFirst, "a\t1\nb\t3\nc\t4\n")]
+    #[case(DuplicateKeys::KeepLast, "a\t2\nb\t3\nc\t6\n")]
+    fn test_deduplicating_sorted_records(
+        #[case] duplicates: DuplicateKeys,
+        #[case] expected: &str,
+    ) {
+        let mut reader = tsv_reader(DATA);
+        let indices = column_indices(&["id"], reader.headers().unwrap()).unwrap();
+        let mut writer = tsv_writer();
+        let dropped = dedup_sorted(&mut reader, &mut writer, &indices, duplicates, b'\t').unwrap();
+        assert_eq!(dropped, 3);
+        assert_eq!(String::from_utf8_lossy(writer.get_ref()), expected);
+    }
+
+    #[test]
+    fn test_deduplicating_unsorted_records() {
+        let mut reader = tsv_reader(UNSORTED);
+        let indices = column_indices(&["id"], reader.headers().unwrap()).unwrap();
+        let mut writer = tsv_writer();
+        let first = DuplicateKeys::KeepFirst;
+        assert_eq!(dedup_unsorted(&mut reader, &mut writer, &indices, first, b'\t').unwrap(), 2);
+        assert_eq!(writer.get_ref(), b"a\t1\nc\t2\nb\t4\n");
+
+        let mut writer = tsv_writer();
+        let dropped = dedup_keep_last(&mut tsv_reader(UNSORTED), &mut writer, &indices);
+        assert_eq!(dropped.unwrap(), 2);
+        assert_eq!(writer.get_ref(), b"a\t3\nb\t4\nc\t5\n");
+    }
+
+    #[test]
+    fn test_rejecting_duplicate_keys() {
+        let mut reader = tsv_reader(UNSORTED);
+        let indices = column_indices(&["id", "value"], reader.headers().unwrap()).unwrap();
+        let reject = DuplicateKeys::Reject;
+        assert_eq!(
+            dedup_unsorted(&mut reader, &mut tsv_writer(), &indices, reject, b'\t').unwrap(),
+            0
+        );
+
+        let mut reader = tsv_reader(DATA);
+        let indices = column_indices(&["id"], reader.headers().unwrap()).unwrap();
+        match dedup_sorted(&mut reader, &mut tsv_writer(), &indices, reject, b'\t') {
+            Err(FgError::DuplicateKey { key, line }) => {
+                assert_eq!(key, "a");
+                assert_eq!(line, 3);
+            }
+            _ => panic!("expected a duplicate key"),
+        }
+
+        let result = column_indices(&["id", "name"], reader.headers().unwrap());
+        assert!(
+            matches!(result, Err(FgError::HeaderMismatch { missing, .. }) if missing == ["name"])
+        );
+    }
+}
@@ -0,0 +1,1492 @@
+//! Streaming readers and writers of delimited files of serde-compatible records.
+use std::borrow::Cow;
+use std::cell::RefCell;
+use std::cmp::Ordering;
+use std::collections::hash_map::Entry;
+use std::collections::HashMap;
+use std::hash::Hash;
+use std::io::{BufRead, BufWriter, Cursor, Read, SeekFrom, Write};
+use std::marker::PhantomData;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+use csv::{ByteRecord, DeserializeErrorKind, Position, ReaderBuilder, StringRecord, WriterBuilder};
+use serde::de::{
+    self, DeserializeOwned, DeserializeSeed, Deserializer, IntoDeserializer, MapAccess, Visitor,
+};
+use serde::{forward_to_deserialize_any, Serialize};
+
+#[cfg(feature = "rayon")]
+use rayon::prelude::*;
+
+use crate::io::format::{Formatted, WriteFormat};
+use crate::io::FinishWrite;
+use crate::{DelimError, FgError, Result};
+
+/// How columns in a delimited file that do not correspond to any field of the struct being read
+/// are handled.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub enum ExtraColumns {
+    /// Extra columns are skipped, so that a struct can be read from a subset of a file's columns
+    #[default]
+    Ignore,
+    /// Extra columns cause reading to fail with [`FgError::HeaderMismatch`]
+    Reject,
+}
+
+/// How records with the same key are handled when reading a delimited file into a map with
+/// [`DelimFile::read_map`](crate::io::DelimFile::read_map).
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub enum DuplicateKeys {
+    /// A repeated key causes reading to fail with [`FgError::DuplicateKey`]
+    #[default]
+    Reject,
+    /// The first record with each key is kept and later ones are discarded
+    KeepFirst,
+    /// The last record with each key is kept, replacing earlier ones
+    KeepLast,
+}
+
+/// A function registered with
+/// [`DelimFileBuilder::column_parser`](crate::io::DelimFileBuilder::column_parser) that converts
+/// the raw value of a field into a form that can be deserialized.
+pub(crate) type ColumnParser = Arc<dyn Fn(&str) -> String + Send + Sync>;
+
+/// How columns with the same name in the header of a delimited file are handled when reading
+/// records.  Without either, only one of the columns would be deserialized into a field of the
+/// same name, or deserialization would fail with a confusing error.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub enum DuplicateColumns {
+    /// A repeated column name causes reading to fail with [`FgError::DuplicateColumn`]
+    #[default]
+    Reject,
+    /// Repeated column names are given a suffix with the number of the occurrence, so that
+    /// columns named `name`, `name` and `name` are read as `name`, `name_2` and `name_3`
+    Rename,
+}
+
+/// Iterator over the records of a delimited file, returned by
+/// [`DelimFile::read_iter`](crate::io::DelimFile::read_iter), that deserializes each record as
+/// it is parsed so that files too large to hold in memory can be processed.  For files with a
+/// header line the header is read when the reader is created and used to map fields to the
+/// record's members; records in headerless files are deserialized by position.
+///
+/// When reading structs from files with columns that are not fields of the struct, only the
+/// columns that are fields are passed on for deserialization.
+pub struct DelimFileReader<D, R = Box<dyn BufRead + Send>> {
+    reader: csv::Reader<R>,
+    headers: Option<ByteRecord>,
+    /// The indices of the columns to deserialize, if not all of them
+    projection: Option<Vec<usize>>,
+    /// Field values that are read as empty fields
+    null_tokens: Vec<Vec<u8>>,
+    /// The indices of the deserialized fields that are booleans which may be written as 1 or 0
+    int_bools: Vec<usize>,
+    /// The indices of the deserialized fields that are converted by custom parsers
+    parsers: Vec<(usize, ColumnParser)>,
+    /// Whether records with the wrong number of fields are reported as
+    /// [`FgError::FieldCountMismatch`]
+    strict: bool,
+    /// Whether records with fewer fields than expected are allowed and counted
+    ragged: bool,
+    /// The number of fields expected in each record, if known
+    width: Option<usize>,
+    /// The number of records read with fewer fields than expected
+    padded: u64,
+    /// The path of the file being read, for reporting errors
+    path: Option<PathBuf>,
+    record: ByteRecord,
+    projected: ByteRecord,
+    _marker: PhantomData<fn() -> D>,
+}
+
+impl<D: DeserializeOwned, R: Read> DelimFileReader<D, R> {
+    /// Creates a reader over a csv reader configured to expect a header line.  Columns in the
+    /// header are renamed from the keys of `renames` to the corresponding values before the
+    /// header is used, and columns with the same name are then handled according to
+    /// `duplicates`.  If `strict` is true, the header must contain exactly the fields of the
+    /// record type, otherwise a [`FgError::HeaderMismatch`] listing the differences is returned.
+    /// Columns that are not fields of the record type are handled according to `extra_columns`.
+    pub(crate) fn new(
+        mut reader: csv::Reader<R>,
+        renames: &HashMap<&str, &str>,
+        duplicates: DuplicateColumns,
+        strict: bool,
+        extra_columns: ExtraColumns,
+    ) -> Result<Self> {
+        let mut headers: ByteRecord = reader
+            .byte_headers()
+            .map_err(FgError::ConversionError)?
+            .iter()
+            .map(|h| match std::str::from_utf8(h).ok().and_then(|h| renames.get(h)) {
+                Some(renamed) => renamed.as_bytes(),
+                None => h,
+            })
+            .collect();
+        dedupe_headers(&mut headers, duplicates)?;
+        let width = headers.len();
+        if strict {
+            check_headers::<D>(&headers)?;
+        }
+
+        let mut projection = None;
+        if let Some(fields) = struct_fields::<D>() {
+            let (keep, extra): (Vec<usize>, Vec<usize>) = (0..headers.len())
+                .partition(|&i| fields.iter().any(|f| f.as_bytes() == &headers[i]));
+            if !extra.is_empty() {
+                if extra_columns == ExtraColumns::Reject {
+                    let unexpected = extra
+                        .iter()
+                        .map(|&i| String::from_utf8_lossy(&headers[i]).into_owned())
+                        .collect();
+                    return Err(FgError::HeaderMismatch { missing: vec![], unexpected });
+                }
+                headers = keep.iter().map(|&i| &headers[i]).collect();
+                projection = Some(keep);
+            }
+        }
+
+        Ok(Self {
+            reader,
+            headers: Some(headers),
+            projection,
+            null_tokens: vec![],
+            int_bools: vec![],
+            parsers: vec![],
+            strict: false,
+            ragged: false,
+            width: Some(width),
+            padded: 0,
+            path: None,
+            record: ByteRecord::new(),
+            projected: ByteRecord::new(),
+            _marker: PhantomData,
+        })
+    }
+
+    /// Creates a reader over a csv reader configured for data without a header line.
+    pub(crate) fn headerless(reader: csv::Reader<R>) -> Self {
+        Self {
+            reader,
+            headers: None,
+            projection: None,
+            null_tokens: vec![],
+            int_bools: vec![],
+            parsers: vec![],
+            strict: false,
+            ragged: false,
+            width: None,
+            padded: 0,
+            path: None,
+            record: ByteRecord::new(),
+            projected: ByteRecord::new(),
+            _marker: PhantomData,
+        }
+    }
+}
+
+impl<D: DeserializeOwned, R> DelimFileReader<D, R> {
+    /// Allows boolean fields of the record type to be read from `1` and `0` as well as from
+    /// `true` and `false`.
+    pub(crate) fn with_int_bools(mut self) -> Self {
+        let bools = bool_fields::<D>();
+        self.int_bools = self.field_indices(|name| bools.contains(&name));
+        self
+    }
+
+    /// Sets custom parsers that convert the values of the named columns before they are
+    /// deserialized.  Columns that are not deserialized are ignored.
+    pub(crate) fn with_parsers(mut self, parsers: &[(String, ColumnParser)]) -> Self {
+        self.parsers = parsers
+            .iter()
+            .flat_map(|(column, parser)| {
+                self.field_indices(|name| name == column.as_str())
+                    .into_iter()
+                    .map(|i| (i, parser.clone()))
+            })
+            .collect();
+        self
+    }
+
+    /// Returns the indices of the deserialized fields whose names match a predicate, using the
+    /// header if there is one and otherwise the order of the fields of the record type.
+    fn field_indices(&self, matches: impl Fn(&str) -> bool) -> Vec<usize> {
+        match &self.headers {
+            Some(headers) => (0..headers.len())
+                .filter(|&i| std::str::from_utf8(&headers[i]).is_ok_and(&matches))
+                .collect(),
+            None => struct_fields::<D>()
+                .unwrap_or_default()
+                .iter()
+                .enumerate()
+                .filter(|(_, f)| matches(f))
+                .map(|(i, _)| i)
+                .collect(),
+        }
+    }
+}
+
+impl<D, R> DelimFileReader<D, R> {
+    /// Sets field values that are read as if they were empty.
+    pub(crate) fn with_null_tokens(mut self, null_tokens: Vec<Vec<u8>>) -> Self {
+        self.null_tokens = null_tokens;
+        self
+    }
+
+    /// Reports records with a different number of fields from the header, or from the first
+    /// record in headerless files, as [`FgError::FieldCountMismatch`] errors.  The csv reader
+    /// must not be flexible.
+    pub(crate) fn strict(mut self) -> Self {
+        self.strict = true;
+        self
+    }
+
+    /// Allows records with fewer fields than the header, or than the first record in headerless
+    /// files, counting them, and reports records with more fields as
+    /// [`FgError::FieldCountMismatch`] errors.  The csv reader must be flexible.
+    pub(crate) fn ragged(mut self) -> Self {
+        self.ragged = true;
+        self
+    }
+
+    /// Sets the path of the file being read, which is included in errors.
+    pub(crate) fn with_path(mut self, path: &Path) -> Self {
+        self.path = Some(path.to_path_buf());
+        self
+    }
+
+    /// Returns the number of records read so far that had fewer fields than the header and
+    /// whose missing trailing fields were read as absent, when reading with
+    /// [`DelimFileBuilder::ragged`](crate::io::DelimFileBuilder::ragged).
+    pub fn padded_records(&self) -> u64 {
+        self.padded
+    }
+
+    /// Reads records into the given buffers, reusing their storage.
+    pub(crate) fn with_buffers(mut self, buffers: RecordBuffers) -> Self {
+        self.record = buffers.record;
+        self.projected = buffers.projected;
+        self
+    }
+
+    /// Consumes the reader, returning the buffers into which it read records so that they can
+    /// be reused by another reader.
+    pub fn into_buffers(self) -> RecordBuffers {
+        RecordBuffers { record: self.record, projected: self.projected }
+    }
+
+    /// Returns true if a field is one of the null tokens.
+    fn is_null(&self, field: &[u8]) -> bool {
+        self.null_tokens.iter().any(|t| t == field)
+    }
+}
+
+impl<D: DeserializeOwned, R: Read> DelimFileReader<D, R> {
+    /// Reads all remaining records, appending them to `recs`, so that a vector's storage can be
+    /// reused across files by clearing it between reads.  Returns the number of records read.
+    /// If a record cannot be read the error is returned, leaving the records read before it in
+    /// `recs`.
+    pub fn read_into(&mut self, recs: &mut Vec<D>) -> Result<usize> {
+        let start = recs.len();
+        for rec in self.by_ref() {
+            recs.push(rec?);
+        }
+        Ok(recs.len() - start)
+    }
+
+    /// Reads all remaining records, skipping those that cannot be parsed or deserialized rather
+    /// than failing.  Returns the records read successfully along with a [`BadRecord`]
+    /// describing each record skipped.  Errors reading the underlying data are still returned.
+    pub fn read_lenient(mut self) -> Result<(Vec<D>, Vec<BadRecord>)> {
+        let mut recs = vec![];
+        let mut bad = vec![];
+        while let Some(rec) = self.next() {
+            match rec {
+                Ok(rec) => recs.push(rec),
+                Err(FgError::ConversionError(e)) if !e.is_io_error() => {
+                    let line = e.position().unwrap_or_else(|| self.reader.position()).line();
+                    let message = match e.kind() {
+                        csv::ErrorKind::Deserialize { err, .. } => err.to_string(),
+                        _ => e.to_string(),
+                    };
+                    bad.push(BadRecord { line, message });
+                }
+                Err(FgError::DelimError(e)) => {
+                    bad.push(BadRecord { line: e.line, message: e.to_string() });
+                }
+                Err(e @ FgError::FieldCountMismatch { line, .. }) => {
+                    bad.push(BadRecord { line, message: e.to_string() });
+                }
+                Err(e) => return Err(e),
+            }
+        }
+        Ok((recs, bad))
+    }
+
+    /// Reads all remaining records into a map keyed by the value of the named column, which
+    /// need not be a field of the record type.  Repeated keys are handled according to
+    /// `duplicates`.
+    pub(crate) fn read_map<K>(
+        mut self,
+        key_column: &str,
+        duplicates: DuplicateKeys,
+    ) -> Result<HashMap<K, D>>
+    where
+        K: DeserializeOwned + Eq + Hash,
+    {
+        let headers = self.reader.byte_headers().map_err(FgError::ConversionError)?;
+        let index = match headers.iter().position(|h| h == key_column.as_bytes()) {
+            Some(index) => index,
+            None => {
+                let missing = vec![key_column.to_string()];
+                return Err(FgError::HeaderMismatch { missing, unexpected: vec![] });
+            }
+        };
+
+        let mut map = HashMap::new();
+        let mut key_record = ByteRecord::new();
+        while let Some(rec) = self.next() {
+            let rec = rec?;
+            key_record.clear();
+            key_record.push_field(self.record.get(index).unwrap_or_default());
+            key_record.set_position(self.record.position().cloned());
+            let key: K = key_record.deserialize(None).map_err(FgError::ConversionError)?;
+            match map.entry(key) {
+                Entry::Vacant(entry) => {
+                    entry.insert(rec);
+                }
+                Entry::Occupied(mut entry) => match duplicates {
+                    DuplicateKeys::Reject => {
+                        return Err(FgError::DuplicateKey {
+                            key: String::from_utf8_lossy(&key_record[0]).into_owned(),
+                            line: self.record.position().map_or(0, |p| p.line()),
+                        });
+                    }
+                    DuplicateKeys::KeepFirst => {}
+                    DuplicateKeys::KeepLast => {
+                        entry.insert(rec);
+                    }
+                },
+            }
+        }
+        Ok(map)
+    }
+}
+
+impl<D, R> DelimFileReader<D, R> {
+    /// Converts the reader into an iterator over batches of up to `chunk_size` records.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `chunk_size` is zero.
+    pub fn chunks(self, chunk_size: usize) -> DelimFileChunks<D, R> {
+        assert!(chunk_size > 0, "chunk_size must be greater than zero");
+        DelimFileChunks { reader: self, chunk_size, chunk: Vec::with_capacity(chunk_size) }
+    }
+}
+
+/// Iterator over batches of records from a delimited file, returned by
+/// [`DelimFile::read_chunks`](crate::io::DelimFile::read_chunks), so that fixed-size units of
+/// work can be dispatched while bounding memory use.  Each batch is full except for the last.
+/// If a record cannot be read the error is returned in place of a batch, and the records read
+/// before it are returned at the start of the next batch.
+pub struct DelimFileChunks<D, R = Box<dyn BufRead + Send>> {
+    reader: DelimFileReader<D, R>,
+    chunk_size: usize,
+    chunk: Vec<D>,
+}
+
+impl<D: DeserializeOwned, R: Read> Iterator for DelimFileChunks<D, R> {
+    type Item = Result<Vec<D>>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        while self.chunk.len() < self.chunk_size {
+            match self.reader.next() {
+                Some(Ok(rec)) => self.chunk.push(rec),
+                Some(Err(e)) => return Some(Err(e)),
+                None => break,
+            }
+        }
+        if self.chunk.is_empty() {
+            None
+        } else {
+            Some(Ok(std::mem::replace(&mut self.chunk, Vec::with_capacity(self.chunk_size))))
+        }
+    }
+}
+
+/// The buffers into which a [`DelimFileReader`] reads the fields of each record, taken from a
+/// finished reader with [`DelimFileReader::into_buffers`] and given to a new one with
+/// [`DelimFile::read_iter_with_buffers`](crate::io::DelimFile::read_iter_with_buffers), so
+/// that their storage is reused when many files are read one after another.
+#[derive(Clone, Debug, Default)]
+pub struct RecordBuffers {
+    record: ByteRecord,
+    projected: ByteRecord,
+}
+
+/// A record skipped by [`DelimFileReader::read_lenient`] because it could not be read.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct BadRecord {
+    /// The 1-based number of the line on which the record starts
+    pub line: u64,
+    /// A description of why the record could not be read
+    pub message: String,
+}
+
+impl<D, R: Read> DelimFileReader<D, R> {
+    /// Reads the next record and prepares it for deserialization, returning true if the record
+    /// to be deserialized is in `projected` rather than `record`, or `None` once all records
+    /// have been read.
+    fn read_prepared(&mut self) -> Option<Result<bool>> {
+        match self.reader.read_byte_record(&mut self.record) {
+            Ok(false) => None,
+            Ok(true) => {
+                if self.ragged {
+                    let width = *self.width.get_or_insert(self.record.len());
+                    match self.record.len().cmp(&width) {
+                        Ordering::Less => self.padded += 1,
+                        Ordering::Equal => {}
+                        Ordering::Greater => {
+                            return Some(Err(FgError::FieldCountMismatch {
+                                line: self.record.position().map_or(0, |p| p.line()),
+                                expected: width as u64,
+                                found: self.record.len() as u64,
+                            }))
+                        }
+                    }
+                }
+                let has_nulls =
+                    !self.null_tokens.is_empty() && self.record.iter().any(|f| self.is_null(f));
+                let unchanged = self.int_bools.is_empty() && self.parsers.is_empty();
+                if self.projection.is_none() && !has_nulls && unchanged {
+                    return Some(Ok(false));
+                }
+
+                self.projected.clear();
+                let len = self.projection.as_ref().map_or(self.record.len(), Vec::len);
+                for i in 0..len {
+                    let field = match &self.projection {
+                        // Short records in flexible files stay short, so missing fields are
+                        // still reported as missing
+                        Some(projection) => match self.record.get(projection[i]) {
+                            Some(field) => field,
+                            None => break,
+                        },
+                        None => &self.record[i],
+                    };
+                    let parser = self.parsers.iter().find(|(p, _)| *p == i).map(|(_, p)| p);
+                    let parsed = match (parser, std::str::from_utf8(field)) {
+                        (Some(parse), Ok(value)) if !value.is_empty() && !self.is_null(field) => {
+                            Some(parse(value))
+                        }
+                        _ => None,
+                    };
+                    let field = parsed.as_ref().map_or(field, |p| p.as_bytes());
+                    let int_bool = self.int_bools.contains(&i);
+                    push_field(&mut self.projected, field, &self.null_tokens, int_bool);
+                }
+                self.projected.set_position(self.record.position().cloned());
+                Some(Ok(true))
+            }
+            Err(e) => Some(Err(self.read_error(e))),
+        }
+    }
+
+    /// Converts an error from the csv reader, describing records of the wrong length
+    /// explicitly if strict.
+    fn read_error(&self, e: csv::Error) -> FgError {
+        match e.kind() {
+            csv::ErrorKind::UnequalLengths { pos, expected_len, len } if self.strict => {
+                FgError::FieldCountMismatch {
+                    line: pos.as_ref().map_or_else(|| self.reader.position().line(), |p| p.line()),
+                    expected: *expected_len,
+                    found: *len,
+                }
+            }
+            _ => FgError::ConversionError(e),
+        }
+    }
+}
+
+impl<D: DeserializeOwned, R: Read> Iterator for DelimFileReader<D, R> {
+    type Item = Result<D>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let record = match self.read_prepared()? {
+            Ok(true) => &self.projected,
+            Ok(false) => &self.record,
+            Err(e) => return Some(Err(e)),
+        };
+        let headers = record_headers(self.headers.as_ref(), record, self.ragged);
+        Some(deserialize_record(record, headers.as_deref(), self.path.as_deref()))
+    }
+}
+
+#[cfg(feature = "rayon")]
+impl<D: DeserializeOwned + Send, R: Read + Send> DelimFileReader<D, R> {
+    /// Reads all remaining records, deserializing them on the current rayon thread pool while
+    /// records continue to be read on another thread.  Records are returned in the order they
+    /// are read.  This is faster than iterating when deserialization is the bottleneck, such
+    /// as for records with many fields.  The first error encountered is returned.
+    pub fn read_parallel(mut self) -> Result<Vec<D>> {
+        const BATCH_SIZE: usize = 1024;
+        let headers = self.headers.clone();
+        let path = self.path.clone();
+        let ragged = self.ragged;
+        let (tx, rx) = std::sync::mpsc::sync_channel::<Result<Vec<ByteRecord>>>(4);
+
+        std::thread::scope(|scope| {
+            scope.spawn(move || loop {
+                let mut batch = Vec::with_capacity(BATCH_SIZE);
+                while batch.len() < BATCH_SIZE {
+                    match self.read_prepared() {
+                        Some(Ok(true)) => batch.push(self.projected.clone()),
+                        Some(Ok(false)) => batch.push(self.record.clone()),
+                        Some(Err(e)) => {
+                            let _ = tx.send(Err(e));
+                            return;
+                        }
+                        None => break,
+                    }
+                }
+                let done = batch.len() < BATCH_SIZE;
+                // Stop early if the receiver has failed and gone away
+                if (!batch.is_empty() && tx.send(Ok(batch)).is_err()) || done {
+                    return;
+                }
+            });
+
+            let mut recs = vec![];
+            for batch in rx {
+                let batch: Result<Vec<D>> = batch?
+                    .par_iter()
+                    .map(|record| {
+                        let headers = record_headers(headers.as_ref(), record, ragged);
+                        deserialize_record(record, headers.as_deref(), path.as_deref())
+                    })
+                    .collect();
+                recs.extend(batch?);
+            }
+            Ok(recs)
+        })
+    }
+}
+
+/// Returns the headers with which to deserialize a record.  When reading ragged records, the
+/// headers of a short record are truncated to its length, so that its missing trailing fields
+/// are absent and take their default values rather than failing as the end of the record.
+fn record_headers<'a>(
+    headers: Option<&'a ByteRecord>,
+    record: &ByteRecord,
+    ragged: bool,
+) -> Option<Cow<'a, ByteRecord>> {
+    match headers {
+        Some(headers) if ragged && record.len() < headers.len() => {
+            Some(Cow::Owned(headers.iter().take(record.len()).collect()))
+        }
+        headers => headers.map(Cow::Borrowed),
+    }
+}
+
+/// Deserializes a record, converting any error into a [`DelimError`] describing the offending
+/// field.
+pub(crate) fn deserialize_record<D: DeserializeOwned>(
+    record: &ByteRecord,
+    headers: Option<&ByteRecord>,
+    path: Option<&Path>,
+) -> Result<D> {
+    record.deserialize(headers).map_err(|e| {
+        let (line, field, expected) = match e.kind() {
+            csv::ErrorKind::Deserialize { pos, err } => {
+                let line = pos.as_ref().or(record.position()).map_or(0, |p| p.line());
+                let expected = match err.kind() {
+                    DeserializeErrorKind::ParseBool(_) => Some("boolean"),
+                    DeserializeErrorKind::ParseInt(_) => Some("integer"),
+                    DeserializeErrorKind::ParseFloat(_) => Some("number"),
+                    _ => None,
+                };
+                (line, err.field(), expected)
+            }
+            _ => return FgError::ConversionError(e),
+        };
+        let lossy = |f: &[u8]| String::from_utf8_lossy(f).into_owned();
+        let index = field.and_then(|i| usize::try_from(i).ok());
+        FgError::DelimError(Box::new(DelimError {
+            path: path.map(Path::to_path_buf),
+            line,
+            column: index.and_then(|i| headers?.get(i)).map(lossy),
+            field,
+            value: index.and_then(|i| record.get(i)).map(lossy),
+            expected,
+            error: e,
+        }))
+    })
+}
+
+/// Adds a field to a record, replacing null tokens with empty fields, and `1` and `0` with
+/// `true` and `false` if `int_bool` is true.
+fn push_field(record: &mut ByteRecord, field: &[u8], null_tokens: &[Vec<u8>], int_bool: bool) {
+    if null_tokens.iter().any(|t| t == field) {
+        record.push_field(b"");
+    } else {
+        match field {
+            b"1" if int_bool => record.push_field(b"true"),
+            b"0" if int_bool => record.push_field(b"false"),
+            _ => record.push_field(field),
+        }
+    }
+}
+
+/// Finds the named columns in a header, returning a [`FgError::HeaderMismatch`] listing any that
+/// are missing.
+pub(crate) fn column_indices<S: AsRef<str>>(
+    columns: &[S],
+    headers: &StringRecord,
+) -> Result<Vec<usize>> {
+    let mut indices = Vec::with_capacity(columns.len());
+    let mut missing = vec![];
+    for column in columns {
+        match headers.iter().position(|h| h == column.as_ref()) {
+            Some(index) => indices.push(index),
+            None => missing.push(column.as_ref().to_string()),
+        }
+    }
+    if missing.is_empty() {
+        Ok(indices)
+    } else {
+        Err(FgError::HeaderMismatch { missing, unexpected: vec![] })
+    }
+}
+
+/// Finds columns with the same name in a header, renaming repeats or returning an error for the
+/// first according to `duplicates`.
+pub(crate) fn dedupe_headers(headers: &mut ByteRecord, duplicates: DuplicateColumns) -> Result<()> {
+    let mut seen: HashMap<Vec<u8>, usize> = HashMap::new();
+    let mut deduped = ByteRecord::with_capacity(headers.as_slice().len(), headers.len());
+    for name in headers.iter() {
+        let occurrences = seen.entry(name.to_vec()).or_insert(0);
+        *occurrences += 1;
+        if *occurrences == 1 {
+            deduped.push_field(name);
+            continue;
+        }
+        if duplicates == DuplicateColumns::Reject {
+            let column = String::from_utf8_lossy(name).into_owned();
+            return Err(FgError::DuplicateColumn { column });
+        }
+        // Skip any suffixes that are already used by other columns
+        let mut n = *occurrences;
+        let renamed = loop {
+            let renamed = [name, format!("_{n}").as_bytes()].concat();
+            if !headers.iter().any(|h| h == renamed) && !seen.contains_key(&renamed) {
+                break renamed;
+            }
+            n += 1;
+        };
+        deduped.push_field(&renamed);
+        seen.insert(renamed, 1);
+    }
+    *headers = deduped;
+    Ok(())
+}
+
+/// Checks that a header contains exactly the fields of a struct, returning an error listing the
+/// missing and unexpected columns if not.  Types that are not structs are not checked.
+fn check_headers<D: DeserializeOwned>(headers: &ByteRecord) -> Result<()> {
+    let fields = match struct_fields::<D>() {
+        Some(fields) => fields,
+        None => return Ok(()),
+    };
+
+    let headers: Vec<String> = headers.iter().map(|h| String::from_utf8_lossy(h).into()).collect();
+    let missing: Vec<String> =
+        fields.iter().filter(|f| !headers.iter().any(|h| h == *f)).map(|f| f.to_string()).collect();
+    let unexpected: Vec<String> =
+        headers.iter().filter(|h| !fields.contains(&h.as_str())).cloned().collect();
+
+    if missing.is_empty() && unexpected.is_empty() {
+        Ok(())
+    } else {
+        Err(FgError::HeaderMismatch { missing, unexpected })
+    }
+}
+
+/// Returns the names of the fields of a struct type, as they appear in serialized data, or `None`
+/// if the type does not deserialize as a struct.
+fn struct_fields<D: DeserializeOwned>() -> Option<&'static [&'static str]> {
+    let mut fields = None;
+    let _ = D::deserialize(FieldNames(&mut fields));
+    fields
+}
+
+/// Deserializer that records the field names requested by a struct's `Deserialize`
+/// implementation, and then fails rather than producing a value.
+struct FieldNames<'a>(&'a mut Option<&'static [&'static str]>);
+
+impl<'de> Deserializer<'de> for FieldNames<'_> {
+    type Error = de::value::Error;
+
+    fn deserialize_any<V: Visitor<'de>>(
+        self,
+        _visitor: V,
+    ) -> std::result::Result<V::Value, Self::Error> {
+        Err(de::Error::custom("not a struct"))
+    }
+
+    fn deserialize_struct<V: Visitor<'de>>(
+        self,
+        _name: &'static str,
+        fields: &'static [&'static str],
+        _visitor: V,
+    ) -> std::result::Result<V::Value, Self::Error> {
+        *self.0 = Some(fields);
+        Err(de::Error::custom("field names recorded"))
+    }
+
+    forward_to_deserialize_any! {
+        bool i8 i16 i32 i64 i128 u8 u16 u32 u64 u128 f32 f64 char str string bytes byte_buf
+        option unit unit_struct newtype_struct seq tuple tuple_struct map enum identifier
+        ignored_any
+    }
+}
+
+/// Returns the names of the fields of a struct type that deserialize as booleans, including
+/// optional booleans.
+fn bool_fields<D: DeserializeOwned>() -> Vec<&'static str> {
+    let fields = struct_fields::<D>().unwrap_or_default();
+    fields
+        .iter()
+        .copied()
+        .filter(|field| {
+            let mut is_bool = false;
+            let _ = D::deserialize(FieldProbe { field: Some(field), is_bool: &mut is_bool });
+            is_bool
+        })
+        .collect()
+}
+
+/// Deserializer for a struct containing only a single field, which records whether the field's
+/// value is requested as a boolean and then fails rather than producing a value.
+struct FieldProbe<'a> {
+    field: Option<&'static str>,
+    is_bool: &'a mut bool,
+}
+
+impl<'de> Deserializer<'de> for FieldProbe<'_> {
+    type Error = de::value::Error;
+
+    fn deserialize_any<V: Visitor<'de>>(
+        self,
+        _visitor: V,
+    ) -> std::result::Result<V::Value, Self::Error> {
+        Err(de::Error::custom("not a struct"))
+    }
+
+    fn deserialize_struct<V: Visitor<'de>>(
+        self,
+        _name: &'static str,
+        _fields: &'static [&'static str],
+        visitor: V,
+    ) -> std::result::Result<V::Value, Self::Error> {
+        visitor.visit_map(self)
+    }
+
+    forward_to_deserialize_any! {
+        bool i8 i16 i32 i64 i128 u8 u16 u32 u64 u128 f32 f64 char str string bytes byte_buf
+        option unit unit_struct newtype_struct seq tuple tuple_struct map enum identifier
+        ignored_any
+    }
+}
+
+impl<'de> MapAccess<'de> for FieldProbe<'_> {
+    type Error = de::value::Error;
+
+    fn next_key_seed<K: DeserializeSeed<'de>>(
+        &mut self,
+        seed: K,
+    ) -> std::result::Result<Option<K::Value>, Self::Error> {
+        match self.field.take() {
+            Some(field) => seed.deserialize(field.into_deserializer()).map(Some),
+            None => Ok(None),
+        }
+    }
+
+    fn next_value_seed<V: DeserializeSeed<'de>>(
+        &mut self,
+        seed: V,
+    ) -> std::result::Result<V::Value, Self::Error> {
+        seed.deserialize(ValueProbe(self.is_bool))
+    }
+}
+
+/// Deserializer that records whether a value is requested as a boolean, looking through
+/// options, and then fails rather than producing a value.
+struct ValueProbe<'a>(&'a mut bool);
+
+impl<'de> Deserializer<'de> for ValueProbe<'_> {
+    type Error = de::value::Error;
+
+    fn deserialize_any<V: Visitor<'de>>(
+        self,
+        _visitor: V,
+    ) -> std::result::Result<V::Value, Self::Error> {
+        Err(de::Error::custom("not a boolean"))
+    }
+
+    fn deserialize_bool<V: Visitor<'de>>(
+        self,
+        _visitor: V,
+    ) -> std::result::Result<V::Value, Self::Error> {
+        *self.0 = true;
+        Err(de::Error::custom("boolean recorded"))
+    }
+
+    fn deserialize_option<V: Visitor<'de>>(
+        self,
+        visitor: V,
+    ) -> std::result::Result<V::Value, Self::Error> {
+        visitor.visit_some(self)
+    }
+
+    forward_to_deserialize_any! {
+        i8 i16 i32 i64 i128 u8 u16 u32 u64 u128 f32 f64 char str string bytes byte_buf
+        unit unit_struct newtype_struct seq tuple tuple_struct map struct enum identifier
+        ignored_any
+    }
+}
+
+/// Iterator over the records of a delimited file as [`StringRecord`]s, returned by
+/// [`DelimFile::read_raw`](crate::io::DelimFile::read_raw), for reading files whose columns are
+/// not known in advance.  The header is read when the reader is created.
+///
+/// For performance-critical code, [`RawDelimReader::read_byte_record`] reads records into a
+/// reusable [`ByteRecord`] without allocating or validating UTF-8 for each record:
+///
+/// ```rust
+/// use fgoxide::io::{ByteRecord, DelimFile};
+/// # let tempdir = tempfile::TempDir::new().unwrap();
+/// # let path = tempdir.path().join("metrics.tsv");
+/// # std::fs::write(&path, "name\tcount\na\t1\nb\t2\n").unwrap();
+///
+/// let mut reader = DelimFile::default().read_raw(&path, b'\t', false).unwrap();
+/// let column = reader.column_index("count").unwrap();
+/// let mut record = ByteRecord::new();
+/// let mut total = 0;
+/// while reader.read_byte_record(&mut record).unwrap() {
+///     total += std::str::from_utf8(&record[column]).unwrap().parse::<u64>().unwrap();
+/// }
+/// assert_eq!(total, 3);
+/// ```
+pub struct RawDelimReader<R = Box<dyn BufRead + Send>> {
+    reader: csv::Reader<R>,
+    headers: StringRecord,
+}
+
+impl<R: Read> RawDelimReader<R> {
+    /// Creates a reader over a csv reader configured to expect a header line.
+    pub(crate) fn new(mut reader: csv::Reader<R>) -> Result<Self> {
+        let headers = reader.headers().map_err(FgError::ConversionError)?.clone();
+        Ok(Self { reader, headers })
+    }
+
+    /// Returns the header of the file, which is empty for an empty file.
+    pub fn headers(&self) -> &StringRecord {
+        &self.headers
+    }
+
+    /// Returns the index of the first column with the given name, if any.
+    pub fn column_index(&self, name: &str) -> Option<usize> {
+        self.headers.iter().position(|h| h == name)
+    }
+
+    /// Reads the next record into `record`, replacing its contents and reusing its storage.
+    /// Returns false, leaving `record` empty, once all records have been read.
+    pub fn read_byte_record(&mut self, record: &mut ByteRecord) -> Result<bool> {
+        self.reader.read_byte_record(record).map_err(FgError::ConversionError)
+    }
+
+    /// Reads the next record into `record` as for [`RawDelimReader::read_byte_record`], also
+    /// checking that its fields are valid UTF-8.
+    pub fn read_record(&mut self, record: &mut StringRecord) -> Result<bool> {
+        self.reader.read_record(record).map_err(FgError::ConversionError)
+    }
+}
+
+impl<R: Read> Iterator for RawDelimReader<R> {
+    type Item = Result<StringRecord>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let mut record = StringRecord::new();
+        match self.read_record(&mut record) {
+            Ok(true) => Some(Ok(record)),
+            Ok(false) => None,
+            Err(e) => Some(Err(e)),
+        }
+    }
+}
+
+/// Handle for writing records to a delimited file one at a time, returned by
+/// [`DelimFile::new_writer`](crate::io::DelimFile::new_writer), so that records can be written
+/// as they are produced.  A header line is written before the first record.  Use
+/// [`DelimFileWriter::finish`] to flush all records and finish the output, detecting any errors
+/// in doing so.
+pub struct DelimFileWriter<S, W: Write = BufWriter<Box<dyn FinishWrite>>> {
+    writer: csv::Writer<W>,
+    order: Option<ColumnOrder>,
+    format: Option<WriteFormat>,
+    _marker: PhantomData<fn(&S)>,
+}
+
+impl<S: Serialize, W: Write> DelimFileWriter<S, W> {
+    /// Creates a writer over a configured csv writer.
+    pub(crate) fn new(writer: csv::Writer<W>) -> Self {
+        Self { writer, order: None, format: None, _marker: PhantomData }
+    }
+
+    /// Creates a writer that writes the given columns, in order, rather than the fields of each
+    /// record in the order they are declared.  Columns that are not fields of the record are
+    /// written as empty fields, and fields that are not columns are not written.  The csv
+    /// writer must be configured not to write headers; a header line listing the columns is
+    /// written before the first record if `headers` is true.
+    pub(crate) fn with_column_order(
+        writer: csv::Writer<W>,
+        columns: Vec<String>,
+        headers: bool,
+    ) -> Self {
+        let order = ColumnOrder::new(columns, headers);
+        Self { writer, order: Some(order), format: None, _marker: PhantomData }
+    }
+
+    /// Sets the options used to format the values of fields as they are written.
+    pub(crate) fn with_format(mut self, format: WriteFormat) -> Self {
+        self.format = if format.is_default() { None } else { Some(format) };
+        self
+    }
+
+    /// Writes a single record.
+    pub fn write_record(&mut self, rec: &S) -> Result<()> {
+        match &self.format {
+            None => write_formatted(&mut self.writer, &mut self.order, rec),
+            Some(format) => {
+                let rec = Formatted { value: rec, format };
+                write_formatted(&mut self.writer, &mut self.order, &rec)
+            }
+        }
+    }
+
+    /// Writes all the records from an iterable.
+    pub fn write_records(&mut self, recs: impl IntoIterator<Item = S>) -> Result<()> {
+        for rec in recs {
+            self.write_record(&rec)?;
+        }
+        Ok(())
+    }
+
+    /// Flushes all records written so far to the underlying writer.
+    pub fn flush(&mut self) -> Result<()> {
+        self.writer.flush().map_err(FgError::IoError)
+    }
+
+    /// Flushes all records and closes the writer.  Any compression of the output is finished
+    /// when the underlying writer is dropped; use [`DelimFileWriter::finish`] to detect errors
+    /// in doing so.
+    pub fn close(mut self) -> Result<()> {
+        self.flush()
+    }
+}
+
+impl<S: Serialize, W: FinishWrite> DelimFileWriter<S, W> {
+    /// Flushes all records and finishes the output, such as writing the end of a compressed
+    /// stream or a checksum sidecar file, returning any error in doing so.
+    pub fn finish(self) -> Result<()> {
+        let mut inner = self.writer.into_inner().map_err(|e| FgError::IoError(e.into_error()))?;
+        inner.finish().map_err(FgError::IoError)
+    }
+}
+
+/// Writes a single record, which has been wrapped if necessary to format its values.
+fn write_formatted<T: Serialize + ?Sized, W: Write>(
+    writer: &mut csv::Writer<W>,
+    order: &mut Option<ColumnOrder>,
+    rec: &T,
+) -> Result<()> {
+    match order {
+        None => writer.serialize(rec).map_err(FgError::ConversionError),
+        Some(order) => {
+            if order.indices.is_none() && order.headers {
+                writer.write_record(&order.columns).map_err(FgError::ConversionError)?;
+            }
+            let record = order.reorder(rec)?;
+            writer.write_byte_record(record).map_err(FgError::ConversionError)
+        }
+    }
+}
+
+/// Rearranges the fields of serialized records into a fixed order of columns.
+struct ColumnOrder {
+    columns: Vec<String>,
+    headers: bool,
+    /// For each column, the index of the record field written to it, determined from the first
+    /// record
+    indices: Option<Vec<Option<usize>>>,
+    /// Serializes each record, with csv itself so that values are formatted exactly as they
+    /// otherwise would be, and the names of the fields before the first record
+    serializer: csv::Writer<RecordBuffer>,
+    /// Parses each serialized record back into its fields
+    parser: csv::Reader<Cursor<Vec<u8>>>,
+    record: ByteRecord,
+    reordered: ByteRecord,
+}
+
+impl ColumnOrder {
+    fn new(columns: Vec<String>, headers: bool) -> Self {
+        let serializer = WriterBuilder::new().flexible(true).from_writer(RecordBuffer::default());
+        let parser = ReaderBuilder::new()
+            .has_headers(false)
+            .flexible(true)
+            .from_reader(Cursor::new(Vec::new()));
+        Self {
+            columns,
+            headers,
+            indices: None,
+            serializer,
+            parser,
+            record: ByteRecord::new(),
+            reordered: ByteRecord::new(),
+        }
+    }
+
+    /// Serializes a record and returns its fields arranged into the configured columns.
+    fn reorder<S: Serialize + ?Sized>(&mut self, rec: &S) -> Result<&ByteRecord> {
+        self.serializer.serialize(rec).map_err(FgError::ConversionError)?;
+        self.serializer.flush().map_err(FgError::IoError)?;
+        {
+            // Hand the serialized bytes to the parser, and reuse its previous buffer
+            let mut serialized = self.serializer.get_ref().0.borrow_mut();
+            std::mem::swap(&mut *serialized, self.parser.get_mut().get_mut());
+            serialized.clear();
+        }
+        let start = Position::new();
+        self.parser.seek_raw(SeekFrom::Start(0), start).map_err(FgError::ConversionError)?;
+
+        if self.indices.is_none() {
+            let mut fields = ByteRecord::new();
+            self.parser.read_byte_record(&mut fields).map_err(FgError::ConversionError)?;
+            let indices =
+                self.columns.iter().map(|c| fields.iter().position(|f| f == c.as_bytes()));
+            self.indices = Some(indices.collect());
+        }
+        if !self.parser.read_byte_record(&mut self.record).map_err(FgError::ConversionError)? {
+            self.record.clear();
+        }
+
+        self.reordered.clear();
+        for index in self.indices.iter().flatten() {
+            self.reordered.push_field(index.and_then(|i| self.record.get(i)).unwrap_or_default());
+        }
+        Ok(&self.reordered)
+    }
+}
+
+/// The buffer a [`ColumnOrder`] serializes records into.  A csv writer only gives shared access
+/// to the writer it wraps, so the buffer is emptied through a `RefCell`.
+#[derive(Default)]
+struct RecordBuffer(RefCell<Vec<u8>>);
+
+impl Write for RecordBuffer {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        self.0.get_mut().extend_from_slice(buf);
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde::Deserialize;
+
+    #[derive(Debug, Deserialize, Serialize, PartialEq)]
+    struct Rec {
+        name: String,
+        n: u32,
+    }
+
+    fn reader(data: &'static str) -> DelimFileReader<Rec, &'static [u8]> {
+        DelimFileReader::new(
+            ReaderBuilder::new().delimiter(b'\t').from_reader(data.as_bytes()),
+            &HashMap::new(),
+            DuplicateColumns::Reject,
+            false,
+            ExtraColumns::Ignore,
+        )
+        .unwrap()
+    }
+
+    #[test]
+    fn test_reading_records_by_header() {
+        let recs: Vec<Rec> = reader("n\tname\n1\ta\n2\tb\n").map(|r| r.unwrap()).collect();
+        assert_eq!(recs, vec![Rec { name: "a".into(), n: 1 }, Rec { name: "b".into(), n: 2 }]);
+    }
+
+    #[test]
+    fn test_errors_are_returned_per_record() {
+        let mut recs = reader("name\tn\na\t1\nb\tx\nc\t3\n");
+        assert_eq!(recs.next().unwrap().unwrap(), Rec { name: "a".into(), n: 1 });
+        assert!(recs.next().unwrap().is_err());
+        assert_eq!(recs.next().unwrap().unwrap(), Rec { name: "c".into(), n: 3 });
+        assert!(recs.next().is_none());
+    }
+
+    #[test]
+    fn test_writing_records_incrementally() {
+        let mut buf = vec![];
+        let mut writer = DelimFileWriter::new(WriterBuilder::new().from_writer(&mut buf));
+        writer.write_record(&Rec { name: "a".into(), n: 1 }).unwrap();
+        writer.flush().unwrap();
+        writer.write_records(vec![Rec { name: "b".into(), n: 2 }]).unwrap();
+        writer.close().unwrap();
+        assert_eq!(String::from_utf8(buf).unwrap(), "name,n\na,1\nb,2\n");
+    }
+
+    #[test]
+    fn test_reading_headerless_records_by_position() {
+        let csv = ReaderBuilder::new().has_headers(false).from_reader("a,1\nb,2\n".as_bytes());
+        let recs: Vec<Rec> = DelimFileReader::headerless(csv).map(|r| r.unwrap()).collect();
+        assert_eq!(recs, vec![Rec { name: "a".into(), n: 1 }, Rec { name: "b".into(), n: 2 }]);
+    }
+
+    #[test]
+    fn test_struct_fields() {
+        assert_eq!(struct_fields::<Rec>(), Some(&["name", "n"][..]));
+        assert_eq!(struct_fields::<(String, u32)>(), None);
+    }
+
+    #[test]
+    fn test_reading_leniently() {
+        let (recs, bad) = reader("name\tn\na\t1\nb\tx\nc\t3\nd\n").read_lenient().unwrap();
+        assert_eq!(recs, vec![Rec { name: "a".into(), n: 1 }, Rec { name: "c".into(), n: 3 }]);
+        assert_eq!(bad.len(), 2);
+        assert_eq!(bad[0].line, 3);
+        assert_eq!(
+            bad[0].message,
+            "Failed to deserialize record on line 3 in column 'n'; value \"x\" is not a valid \
+             integer: invalid digit found in string"
+        );
+        assert_eq!(bad[1].line, 5);
+        assert!(bad[1].message.contains("found record with 1 fields"), "{}", bad[1].message);
+    }
+
+    #[test]
+    fn test_strict_field_counts() {
+        let mut recs = reader("name\tn\na\t1\nb\t2\t3\n");
+        assert!(matches!(recs.nth(1).unwrap(), Err(FgError::ConversionError(_))));
+
+        let mut recs = reader("name\tn\na\t1\nb\t2\t3\nc\n").strict();
+        assert_eq!(recs.next().unwrap().unwrap(), Rec { name: "a".into(), n: 1 });
+        for (line, found) in [(3, 3), (4, 1)] {
+            match recs.next().unwrap().unwrap_err() {
+                FgError::FieldCountMismatch { line: l, expected: 2, found: f } => {
+                    assert_eq!((l, f), (line, found));
+                }
+                e => panic!("unexpected error: {e}"),
+            }
+        }
+    }
+
+    #[test]
+    fn test_reading_raw_records() {
+        let csv =
+            ReaderBuilder::new().delimiter(b'\t').from_reader("a\tb\n1\tx\n2\ty\n".as_bytes());
+        let reader = RawDelimReader::new(csv).unwrap();
+        assert_eq!(reader.headers(), vec!["a", "b"]);
+        let recs: Vec<StringRecord> = reader.map(|r| r.unwrap()).collect();
+        assert_eq!(recs, vec![vec!["1", "x"], vec!["2", "y"]]);
+
+        let reader = RawDelimReader::new(ReaderBuilder::new().from_reader("".as_bytes())).unwrap();
+        assert!(reader.headers().is_empty());
+        assert_eq!(reader.count(), 0);
+    }
+
+    #[test]
+    fn test_reading_byte_records_into_reused_buffer() {
+        let data = b"a,b,c\n1,x,\"q,r\"\n2,\xff,s\n";
+        let mut reader = RawDelimReader::new(ReaderBuilder::new().from_reader(&data[..])).unwrap();
+        assert_eq!(reader.column_index("c"), Some(2));
+        assert_eq!(reader.column_index("d"), None);
+
+        let mut record = ByteRecord::new();
+        assert!(reader.read_byte_record(&mut record).unwrap());
+        assert_eq!(record, vec!["1", "x", "q,r"]);
+        assert!(reader.read_byte_record(&mut record).unwrap());
+        assert_eq!(&record[1], b"\xff");
+        assert!(!reader.read_byte_record(&mut record).unwrap());
+        assert!(record.is_empty());
+    }
+
+    #[test]
+    fn test_reading_records_into_map() {
+        let data = "id\tname\tn\n7\ta\t1\n3\tb\t2\n7\tc\t3\n";
+        let map: HashMap<u32, Rec> = reader(data).read_map("id", DuplicateKeys::KeepFirst).unwrap();
+        assert_eq!(map.len(), 2);
+        assert_eq!(map[&7], Rec { name: "a".into(), n: 1 });
+        assert_eq!(map[&3], Rec { name: "b".into(), n: 2 });
+
+        let map: HashMap<u32, Rec> = reader(data).read_map("id", DuplicateKeys::KeepLast).unwrap();
+        assert_eq!(map[&7], Rec { name: "c".into(), n: 3 });
+
+        // Keys may also be fields of the record
+        let map: HashMap<String, Rec> =
+            reader(data).read_map("name", DuplicateKeys::Reject).unwrap();
+        assert_eq!(map["b"], Rec { name: "b".into(), n: 2 });
+
+        match reader(data).read_map::<u32>("id", DuplicateKeys::Reject).unwrap_err() {
+            FgError::DuplicateKey { key, line } => assert_eq!((key.as_str(), line), ("7", 4)),
+            e => panic!("unexpected error: {e}"),
+        }
+        let result = reader(data).read_map::<u32>("sample", DuplicateKeys::Reject);
+        assert!(matches!(result, Err(FgError::HeaderMismatch { .. })));
+        let result = reader(data).read_map::<u32>("name", DuplicateKeys::Reject);
+        assert!(matches!(result, Err(FgError::ConversionError(_))));
+    }
+
+    #[test]
+    fn test_reading_records_in_chunks() {
+        let chunks: Vec<Vec<Rec>> =
+            reader("name\tn\na\t1\nb\t2\nc\t3\n").chunks(2).map(|c| c.unwrap()).collect();
+        assert_eq!(chunks.iter().map(Vec::len).collect::<Vec<_>>(), vec![2, 1]);
+        assert_eq!(chunks[1], vec![Rec { name: "c".into(), n: 3 }]);
+        assert_eq!(reader("name\tn\n").chunks(2).count(), 0);
+
+        let mut chunks = reader("name\tn\na\t1\nb\tx\nc\t3\nd\t4\n").chunks(2);
+        assert!(chunks.next().unwrap().is_err());
+        let names: Vec<String> =
+            chunks.next().unwrap().unwrap().into_iter().map(|r| r.name).collect();
+        assert_eq!(names, vec!["a", "c"]);
+        assert_eq!(chunks.next().unwrap().unwrap().len(), 1);
+        assert!(chunks.next().is_none());
+    }
+
+    #[test]
+    #[should_panic(expected = "chunk_size must be greater than zero")]
+    fn test_reading_records_in_empty_chunks() {
+        reader("name\tn\n").chunks(0);
+    }
+
+    #[cfg(feature = "rayon")]
+    #[test]
+    fn test_reading_records_in_parallel() {
+        let data: String = std::iter::once("name\tn\textra\n".to_string())
+            .chain((0..5000).map(|n| format!("r{n}\t{n}\te\n")))
+            .collect();
+        let csv = ReaderBuilder::new().delimiter(b'\t').from_reader(data.as_bytes());
+        let reader: DelimFileReader<Rec, _> = DelimFileReader::new(
+            csv,
+            &HashMap::new(),
+            DuplicateColumns::Reject,
+            false,
+            ExtraColumns::Ignore,
+        )
+        .unwrap();
+        let recs = reader.read_parallel().unwrap();
+        assert_eq!(recs.len(), 5000);
+        assert!(recs
+            .iter()
+            .enumerate()
+            .all(|(i, r)| r.n as usize == i && r.name == format!("r{i}")));
+
+        let result = reader_parallel("name\tn\na\t1\nb\tx\nc\t3\n");
+        match result.unwrap_err() {
+            FgError::DelimError(e) => assert_eq!((e.line, e.value.as_deref()), (3, Some("x"))),
+            e => panic!("unexpected error: {e}"),
+        }
+        assert!(reader_parallel("name\tn\n").unwrap().is_empty());
+    }
+
+    #[cfg(feature = "rayon")]
+    fn reader_parallel(data: &'static str) -> Result<Vec<Rec>> {
+        reader(data).read_parallel()
+    }
+
+    #[test]
+    fn test_bool_fields() {
+        #[derive(Deserialize)]
+        #[allow(dead_code)]
+        struct Flags {
+            name: String,
+            pass: bool,
+            n: u8,
+            #[serde(rename = "dup")]
+            duplicate: Option<bool>,
+        }
+        assert_eq!(bool_fields::<Flags>(), vec!["pass", "dup"]);
+        assert!(bool_fields::<Rec>().is_empty());
+        assert!(bool_fields::<(bool, u32)>().is_empty());
+    }
+
+    #[test]
+    fn test_reading_headerless_bools_as_ints() {
+        #[derive(Debug, Deserialize, PartialEq)]
+        struct Flag {
+            n: u8,
+            pass: bool,
+        }
+        let reader =
+            ReaderBuilder::new().has_headers(false).from_reader("1,1\n0,true\n".as_bytes());
+        let recs: Vec<Flag> =
+            DelimFileReader::headerless(reader).with_int_bools().map(|r| r.unwrap()).collect();
+        assert_eq!(recs, vec![Flag { n: 1, pass: true }, Flag { n: 0, pass: true }]);
+    }
+
+    #[test]
+    fn test_strict_header_validation() {
+        let csv = |data: &'static str| ReaderBuilder::new().from_reader(data.as_bytes());
+        let (none, ignore) = (HashMap::new(), ExtraColumns::Ignore);
+        let reject = DuplicateColumns::Reject;
+        assert!(DelimFileReader::<Rec, _>::new(csv("n,name\n1,a\n"), &none, reject, true, ignore)
+            .is_ok());
+
+        let result = DelimFileReader::<Rec, _>::new(
+            csv("name,count,extra\na,1,x\n"),
+            &none,
+            reject,
+            true,
+            ignore,
+        );
+        match result.err().unwrap() {
+            FgError::HeaderMismatch { missing, unexpected } => {
+                assert_eq!(missing, vec!["n"]);
+                assert_eq!(unexpected, vec!["count", "extra"]);
+            }
+            e => panic!("unexpected error: {e}"),
+        }
+
+        // Types that are not structs are not validated
+        assert!(DelimFileReader::<(String, u32), _>::new(
+            csv("a,b,c\n"),
+            &none,
+            reject,
+            true,
+            ignore
+        )
+        .is_ok());
+    }
+
+    #[test]
+    fn test_renaming_headers() {
+        let csv = ReaderBuilder::new().from_reader("Sample Name,Count\na,1\n".as_bytes());
+        let renames = HashMap::from([("Sample Name", "name"), ("Count", "n")]);
+        let recs: Vec<Rec> = DelimFileReader::new(
+            csv,
+            &renames,
+            DuplicateColumns::Reject,
+            true,
+            ExtraColumns::Ignore,
+        )
+        .unwrap()
+        .map(|r| r.unwrap())
+        .collect();
+        assert_eq!(recs, vec![Rec { name: "a".into(), n: 1 }]);
+    }
+
+    #[test]
+    fn test_duplicate_header_columns() {
+        let csv = |data: &'static str| ReaderBuilder::new().from_reader(data.as_bytes());
+        let data = "name,n,name,name_2,name\na,1,b,c,d\n";
+        let result = DelimFileReader::<HashMap<String, String>, _>::new(
+            csv(data),
+            &HashMap::new(),
+            DuplicateColumns::Reject,
+            false,
+            ExtraColumns::Ignore,
+        );
+        match result.err().unwrap() {
+            FgError::DuplicateColumn { column } => assert_eq!(column, "name"),
+            e => panic!("unexpected error: {e}"),
+        }
+
+        let mut reader = DelimFileReader::<HashMap<String, String>, _>::new(
+            csv(data),
+            &HashMap::new(),
+            DuplicateColumns::Rename,
+            false,
+            ExtraColumns::Ignore,
+        )
+        .unwrap();
+        let rec = reader.next().unwrap().unwrap();
+        let expected =
+            [("name", "a"), ("n", "1"), ("name_3", "b"), ("name_2", "c"), ("name_4", "d")];
+        let expected: HashMap<String, String> =
+            expected.iter().map(|(k, v)| (k.to_string(), v.to_string())).collect();
+        assert_eq!(rec, expected);
+    }
+
+    #[test]
+    fn test_projecting_columns() {
+        let csv = |data: &'static str| ReaderBuilder::new().from_reader(data.as_bytes());
+        let data = "x,n,y,name,z\n1,2,3,a,4\n5,6,7,b,8\n";
+        let recs: Vec<Rec> = DelimFileReader::new(
+            csv(data),
+            &HashMap::new(),
+            DuplicateColumns::Reject,
+            false,
+            ExtraColumns::Ignore,
+        )
+        .unwrap()
+        .map(|r| r.unwrap())
+        .collect();
+        assert_eq!(recs, vec![Rec { name: "a".into(), n: 2 }, Rec { name: "b".into(), n: 6 }]);
+
+        let result = DelimFileReader::<Rec, _>::new(
+            csv(data),
+            &HashMap::new(),
+            DuplicateColumns::Reject,
+            false,
+            ExtraColumns::Reject,
+        );
+        match result.err().unwrap() {
+            FgError::HeaderMismatch { missing, unexpected } => {
+                assert!(missing.is_empty());
+                assert_eq!(unexpected, vec!["x", "y", "z"]);
+            }
+            e => panic!("unexpected error: {e}"),
+        }
+    }
+
+    #[test]
+    fn test_writing_columns_in_explicit_order() {
+        let mut buf = vec![];
+        let columns = vec!["n".to_string(), "missing".to_string(), "name".to_string()];
+        let csv = WriterBuilder::new().has_headers(false).from_writer(&mut buf);
+        let mut writer = DelimFileWriter::with_column_order(csv, columns, true);
+        writer.write_record(&Rec { name: "a,b".into(), n: 1 }).unwrap();
+        writer.write_record(&Rec { name: "c".into(), n: 2 }).unwrap();
+        writer.close().unwrap();
+        assert_eq!(String::from_utf8(buf).unwrap(), "n,missing,name\n1,,\"a,b\"\n2,,c\n");
+    }
+
+    #[test]
+    fn test_writing_many_records_in_explicit_order() {
+        let mut buf = vec![];
+        let columns = vec!["name".to_string(), "n".to_string()];
+        let csv = WriterBuilder::new().has_headers(false).from_writer(&mut buf);
+        let mut writer = DelimFileWriter::with_column_order(csv, columns, false);
+        let names = ["two\nlines", "say \"hi\"", "", "plain"];
+        for n in 0..1000 {
+            let name = names[n % names.len()].to_string();
+            writer.write_record(&Rec { name, n: n as u32 }).unwrap();
+        }
+        writer.close().unwrap();
+
+        let mut reader = ReaderBuilder::new().has_headers(false).from_reader(buf.as_slice());
+        let recs: Vec<StringRecord> = reader.records().map(|r| r.unwrap()).collect();
+        assert_eq!(recs.len(), 1000);
+        for (n, rec) in recs.iter().enumerate() {
+            assert_eq!(rec, &StringRecord::from(vec![names[n % 4], &n.to_string()]));
+        }
+    }
+}
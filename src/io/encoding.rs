@@ -0,0 +1,122 @@
+//! Writers that encode UTF-8 text into other character encodings.
+use std::io::{self, ErrorKind, Write};
+
+use encoding_rs::{Encoder, EncoderResult, Encoding, UTF_16BE, UTF_16LE};
+
+use crate::io::FinishWrite;
+
+/// Writer that accepts UTF-8 text and writes it to an underlying writer in another encoding.
+/// Multi-byte characters split across writes are handled, but writing bytes that are not valid
+/// UTF-8, or characters that cannot be represented in the target encoding, is an error.
+pub(crate) struct EncodingWriter<W> {
+    inner: W,
+    encoding: &'static Encoding,
+    encoder: Encoder,
+    /// The bytes of an incomplete UTF-8 character at the end of the last write
+    pending: Vec<u8>,
+}
+
+impl<W: Write> EncodingWriter<W> {
+    pub(crate) fn new(inner: W, encoding: &'static Encoding) -> Self {
+        Self { inner, encoding, encoder: encoding.new_encoder(), pending: Vec::new() }
+    }
+
+    /// Encodes a string into bytes in the target encoding.
+    fn encode_str(&mut self, text: &str) -> io::Result<Vec<u8>> {
+        // encoding_rs only decodes UTF-16, so it is encoded here directly
+        if self.encoding == UTF_16LE {
+            return Ok(text.encode_utf16().flat_map(u16::to_le_bytes).collect());
+        } else if self.encoding == UTF_16BE {
+            return Ok(text.encode_utf16().flat_map(u16::to_be_bytes).collect());
+        }
+
+        let mut out = Vec::with_capacity(text.len());
+        let mut remaining = text;
+        loop {
+            let needed = self
+                .encoder
+                .max_buffer_length_from_utf8_without_replacement(remaining.len())
+                .unwrap_or(remaining.len() * 4);
+            out.reserve(needed);
+            let (result, read) = self
+                .encoder
+                .encode_from_utf8_to_vec_without_replacement(remaining, &mut out, false);
+            remaining = &remaining[read..];
+            match result {
+                EncoderResult::InputEmpty => return Ok(out),
+                EncoderResult::OutputFull => (),
+                EncoderResult::Unmappable(c) => {
+                    let msg =
+                        format!("Character {c:?} cannot be encoded as {}", self.encoding.name());
+                    return Err(io::Error::new(ErrorKind::InvalidData, msg));
+                }
+            }
+        }
+    }
+}
+
+impl<W: Write> Write for EncodingWriter<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let mut bytes = std::mem::take(&mut self.pending);
+        bytes.extend_from_slice(buf);
+
+        let valid = match std::str::from_utf8(&bytes) {
+            Ok(_) => bytes.len(),
+            Err(e) if e.error_len().is_none() => e.valid_up_to(),
+            Err(e) => return Err(io::Error::new(ErrorKind::InvalidData, e)),
+        };
+        let text = std::str::from_utf8(&bytes[..valid]).expect("validated above");
+        let encoded = self.encode_str(text)?;
+        self.inner.write_all(&encoded)?;
+        self.pending = bytes[valid..].to_vec();
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+impl<W: FinishWrite> FinishWrite for EncodingWriter<W> {
+    fn finish(&mut self) -> io::Result<()> {
+        if !self.pending.is_empty() {
+            let msg = "Incomplete UTF-8 character at the end of the output";
+            return Err(io::Error::new(ErrorKind::InvalidData, msg));
+        }
+        self.inner.finish()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use encoding_rs::{SHIFT_JIS, WINDOWS_1252};
+    use rstest::rstest;
+
+    #[rstest]
+    #[case(UTF_16LE, vec![0x63, 0x00, 0x61, 0x00, 0x66, 0x00, 0xe9, 0x00])]
+    #[case(UTF_16BE, vec![0x00, 0x63, 0x00, 0x61, 0x00, 0x66, 0x00, 0xe9])]
+    #[case(WINDOWS_1252, vec![0x63, 0x61, 0x66, 0xe9])]
+    fn test_encoding_text(#[case] encoding: &'static Encoding, #[case] expected: Vec<u8>) {
+        let mut writer = EncodingWriter::new(Vec::new(), encoding);
+        // Write the two bytes of "é" separately to check split characters are handled
+        let bytes = "café".as_bytes();
+        writer.write_all(&bytes[..4]).unwrap();
+        writer.write_all(&bytes[4..]).unwrap();
+        assert_eq!(writer.inner, expected);
+    }
+
+    #[test]
+    fn test_unmappable_characters_fail() {
+        let mut writer = EncodingWriter::new(Vec::new(), WINDOWS_1252);
+        assert!(writer.write_all("日本".as_bytes()).is_err());
+        let mut writer = EncodingWriter::new(Vec::new(), SHIFT_JIS);
+        writer.write_all("日本".as_bytes()).unwrap();
+    }
+
+    #[test]
+    fn test_invalid_utf8_fails() {
+        let mut writer = EncodingWriter::new(Vec::new(), WINDOWS_1252);
+        assert!(writer.write_all(&[0x61, 0xff, 0x62]).is_err());
+    }
+}
@@ -0,0 +1,296 @@
+//! Writers that can be finished explicitly, so that errors writing the end of an output are
+//! returned rather than lost when the writer is dropped.
+use std::fs::File;
+use std::io::{self, BufWriter, Stdout, Write};
+#[cfg(feature = "gzp")]
+use std::sync::{Arc, Mutex, MutexGuard};
+
+#[cfg(feature = "bzip2")]
+use bzip2::write::BzEncoder;
+use flate2::write::GzEncoder;
+#[cfg(feature = "xz")]
+use xz2::write::XzEncoder;
+use zstd::stream::Encoder as ZstdEncoder;
+
+/// A writer that holds back data until the end of its output, or must otherwise complete the
+/// output once everything has been written, such as a compression encoder that writes the end of
+/// its stream or an upload that creates an object.  Writers that are dropped without being
+/// finished still finish themselves where possible, but any error doing so is lost, so callers
+/// should finish writers whose output matters.
+///
+/// The writers returned by [`Io`](crate::io::Io) implement this trait, and finishing one
+/// finishes every writer that its data passes through, down to the file itself:
+///
+/// ```rust
+/// use std::io::Write;
+/// use fgoxide::io::{FinishWrite, Io};
+/// # use tempfile::TempDir;
+/// # let dir = TempDir::new().unwrap();
+/// # let path = dir.path().join("out.txt.gz");
+///
+/// let mut writer = Io::default().new_writer(&path).unwrap();
+/// writer.write_all(b"hello\n").unwrap();
+/// writer.finish().unwrap();
+/// ```
+pub trait FinishWrite: Write + Send {
+    /// Flushes all buffered data, writes anything held back until the end of the output and
+    /// completes the output, returning any error doing so.  Nothing may be written once a writer
+    /// is finished, and finishing it again does nothing.
+    fn finish(&mut self) -> io::Result<()>;
+}
+
+impl<W: FinishWrite + ?Sized> FinishWrite for Box<W> {
+    fn finish(&mut self) -> io::Result<()> {
+        (**self).finish()
+    }
+}
+
+impl<W: FinishWrite> FinishWrite for BufWriter<W> {
+    fn finish(&mut self) -> io::Result<()> {
+        self.flush()?;
+        self.get_mut().finish()
+    }
+}
+
+impl FinishWrite for File {
+    fn finish(&mut self) -> io::Result<()> {
+        self.flush()
+    }
+}
+
+impl FinishWrite for Stdout {
+    fn finish(&mut self) -> io::Result<()> {
+        self.flush()
+    }
+}
+
+impl FinishWrite for io::Sink {
+    fn finish(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+impl FinishWrite for Vec<u8> {
+    fn finish(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+impl<W: FinishWrite> FinishWrite for GzEncoder<W> {
+    fn finish(&mut self) -> io::Result<()> {
+        self.try_finish()?;
+        self.get_mut().finish()
+    }
+}
+
+#[cfg(feature = "bzip2")]
+impl<W: FinishWrite> FinishWrite for BzEncoder<W> {
+    fn finish(&mut self) -> io::Result<()> {
+        self.try_finish()?;
+        self.get_mut().finish()
+    }
+}
+
+#[cfg(feature = "xz")]
+impl<W: FinishWrite> FinishWrite for XzEncoder<W> {
+    fn finish(&mut self) -> io::Result<()> {
+        self.try_finish()?;
+        self.get_mut().finish()
+    }
+}
+
+impl<W: FinishWrite> FinishWrite for ZstdEncoder<'static, W> {
+    fn finish(&mut self) -> io::Result<()> {
+        self.do_finish()?;
+        self.get_mut().finish()
+    }
+}
+
+#[cfg(feature = "lz4")]
+impl<W: FinishWrite> FinishWrite for lz4_flex::frame::FrameEncoder<W> {
+    fn finish(&mut self) -> io::Result<()> {
+        self.try_finish()?;
+        self.get_mut().finish()
+    }
+}
+
+#[cfg(feature = "snappy")]
+impl<W: FinishWrite> FinishWrite for snap::write::FrameEncoder<W> {
+    fn finish(&mut self) -> io::Result<()> {
+        self.flush()?;
+        self.get_mut().finish()
+    }
+}
+
+/// Adapts a writer, such as a compression encoder, so that it is finished when dropped unless it
+/// was finished explicitly, and so that once it is finished writing fails and flushing or
+/// finishing again does nothing, which not all encoders guarantee themselves.
+pub(crate) struct Finishing<W: FinishWrite> {
+    inner: W,
+    finished: bool,
+}
+
+impl<W: FinishWrite> Finishing<W> {
+    pub(crate) fn new(inner: W) -> Self {
+        Self { inner, finished: false }
+    }
+}
+
+impl<W: FinishWrite> Write for Finishing<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        if self.finished {
+            let msg = "Cannot write to a writer that has been finished";
+            return Err(io::Error::new(io::ErrorKind::BrokenPipe, msg));
+        }
+        self.inner.write(buf)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        if self.finished {
+            return Ok(());
+        }
+        self.inner.flush()
+    }
+}
+
+impl<W: FinishWrite> FinishWrite for Finishing<W> {
+    fn finish(&mut self) -> io::Result<()> {
+        if self.finished {
+            return Ok(());
+        }
+        // A writer that failed to finish is not finished again, e.g. when dropped
+        self.finished = true;
+        self.inner.finish()
+    }
+}
+
+impl<W: FinishWrite> Drop for Finishing<W> {
+    fn drop(&mut self) {
+        let _ = self.finish();
+    }
+}
+
+/// Writer that advances a progress bar by the number of bytes written through it.
+#[cfg(feature = "indicatif")]
+pub(crate) struct ProgressBarWriter<W> {
+    inner: W,
+    bar: indicatif::ProgressBar,
+}
+
+#[cfg(feature = "indicatif")]
+impl<W> ProgressBarWriter<W> {
+    pub(crate) fn new(inner: W, bar: indicatif::ProgressBar) -> Self {
+        Self { inner, bar }
+    }
+}
+
+#[cfg(feature = "indicatif")]
+impl<W: Write> Write for ProgressBarWriter<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let n = self.inner.write(buf)?;
+        self.bar.inc(n as u64);
+        Ok(n)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+#[cfg(feature = "indicatif")]
+impl<W: FinishWrite> FinishWrite for ProgressBarWriter<W> {
+    fn finish(&mut self) -> io::Result<()> {
+        self.inner.finish()
+    }
+}
+
+/// A writer shared between a parallel encoder's background thread, which writes to it, and the
+/// [`ParEncoder`] that finishes it.
+#[cfg(feature = "gzp")]
+#[derive(Clone)]
+struct SharedWriter(Arc<Mutex<Box<dyn FinishWrite>>>);
+
+#[cfg(feature = "gzp")]
+impl SharedWriter {
+    fn lock(&self) -> MutexGuard<'_, Box<dyn FinishWrite>> {
+        self.0.lock().unwrap_or_else(|e| e.into_inner())
+    }
+}
+
+#[cfg(feature = "gzp")]
+impl Write for SharedWriter {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.lock().write(buf)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.lock().flush()
+    }
+}
+
+/// A gzip or BGZF encoder that compresses on multiple threads using `gzp`.  The encoder writes
+/// to its destination from a background thread, so the destination is shared with it, to be
+/// finished once the encoder has written the end of the stream.  It must only be finished once,
+/// so is used through [`Finishing`].
+#[cfg(feature = "gzp")]
+pub(crate) struct ParEncoder<F: gzp::FormatSpec> {
+    encoder: gzp::par::compress::ParCompress<F>,
+    destination: SharedWriter,
+}
+
+#[cfg(feature = "gzp")]
+impl<F: gzp::FormatSpec> ParEncoder<F> {
+    /// Creates an encoder built by `builder` that writes to `destination`.
+    pub(crate) fn new(
+        builder: gzp::par::compress::ParCompressBuilder<F>,
+        destination: Box<dyn FinishWrite>,
+    ) -> Self {
+        let destination = SharedWriter(Arc::new(Mutex::new(destination)));
+        let encoder = builder.from_writer(destination.clone());
+        Self { encoder, destination }
+    }
+}
+
+#[cfg(feature = "gzp")]
+impl<F: gzp::FormatSpec> Write for ParEncoder<F> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.encoder.write(buf)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.encoder.flush()
+    }
+}
+
+#[cfg(feature = "gzp")]
+impl<F: gzp::FormatSpec> FinishWrite for ParEncoder<F> {
+    fn finish(&mut self) -> io::Result<()> {
+        gzp::ZWriter::finish(&mut self.encoder)
+            .map_err(|e| io::Error::new(io::ErrorKind::Other, e))?;
+        self.destination.lock().finish()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use flate2::read::MultiGzDecoder;
+    use flate2::Compression;
+    use std::io::Read;
+
+    #[test]
+    fn test_finishing_writes_the_end_of_the_stream() {
+        let encoder = GzEncoder::new(vec![], Compression::default());
+        let mut writer = BufWriter::new(Finishing::new(encoder));
+        writer.write_all(b"hello\n").unwrap();
+        writer.finish().unwrap();
+        writer.finish().unwrap();
+        writer.flush().unwrap();
+        assert_eq!(writer.get_mut().write(b"more").unwrap_err().kind(), io::ErrorKind::BrokenPipe);
+
+        let mut text = String::new();
+        let compressed = &writer.get_ref().inner.get_ref()[..];
+        MultiGzDecoder::new(compressed).read_to_string(&mut text).unwrap();
+        assert_eq!(text, "hello\n");
+    }
+}
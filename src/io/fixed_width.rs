@@ -0,0 +1,414 @@
+//! Reading and writing files of serde-compatible records in fixed-width columns.
+use std::fmt::Write;
+use std::io::{BufRead, ErrorKind};
+use std::marker::PhantomData;
+use std::path::{Path, PathBuf};
+
+use csv::{ByteRecord, Position, ReaderBuilder, StringRecord, WriterBuilder};
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+
+use crate::io::delim::deserialize_record;
+use crate::io::Io;
+use crate::{FgError, Result};
+
+/// Struct that contains associated functions for reading and writing structs to and from files
+/// in which each field occupies a fixed number of characters, such as mainframe-style exports
+/// and the reports of older instruments.  The widths of the columns are either configured with
+/// [`FixedWidthFileBuilder::widths`] or inferred from the header line, in which each column
+/// starts at the start of its name.  Fields are trimmed of surrounding spaces when read and
+/// padded with spaces when written.  Widths are counted in characters, not bytes.
+///
+/// ```rust
+/// use fgoxide::io::FixedWidthFile;
+///
+/// let file = FixedWidthFile::builder().widths([10, 6, 8]).build();
+/// ```
+pub struct FixedWidthFile {
+    io: Io,
+    widths: Option<Vec<usize>>,
+    header: bool,
+}
+
+/// Generates a default implementation that uses the default Io instance and infers widths
+impl Default for FixedWidthFile {
+    fn default() -> Self {
+        FixedWidthFile::builder().build()
+    }
+}
+
+/// Builder for [`FixedWidthFile`] instances.
+pub struct FixedWidthFileBuilder {
+    io: Io,
+    widths: Option<Vec<usize>>,
+    header: bool,
+}
+
+impl Default for FixedWidthFileBuilder {
+    fn default() -> Self {
+        FixedWidthFileBuilder { io: Io::default(), widths: None, header: true }
+    }
+}
+
+impl FixedWidthFileBuilder {
+    /// Sets the [`Io`] instance used to open files for reading and writing.
+    pub fn io(mut self, io: Io) -> FixedWidthFileBuilder {
+        self.io = io;
+        self
+    }
+
+    /// Sets the width of each column in characters.  By default widths are inferred from the
+    /// header line when reading, and from the longest value in each column when writing.
+    pub fn widths(mut self, widths: impl IntoIterator<Item = usize>) -> FixedWidthFileBuilder {
+        self.widths = Some(widths.into_iter().collect());
+        self
+    }
+
+    /// Sets whether files start with a header line naming the columns.  Defaults to true.
+    /// Records in files without a header are deserialized by position, and widths must be set.
+    pub fn header(mut self, header: bool) -> FixedWidthFileBuilder {
+        self.header = header;
+        self
+    }
+
+    /// Builds the configured [`FixedWidthFile`] instance.
+    pub fn build(self) -> FixedWidthFile {
+        FixedWidthFile { io: self.io, widths: self.widths, header: self.header }
+    }
+}
+
+impl FixedWidthFile {
+    /// Creates a new FixedWidthFile that reads and writes files using the given [`Io`]
+    /// instance, with widths inferred from the header line.
+    pub fn new(io: Io) -> FixedWidthFile {
+        FixedWidthFile::builder().io(io).build()
+    }
+
+    /// Returns a [`FixedWidthFileBuilder`] for configuring a new FixedWidthFile.
+    pub fn builder() -> FixedWidthFileBuilder {
+        FixedWidthFileBuilder::default()
+    }
+
+    /// Reads structs implementing `[Deserialize]` from a fixed-width file.
+    pub fn read<D, P>(&self, path: &P) -> Result<Vec<D>>
+    where
+        D: DeserializeOwned,
+        P: AsRef<Path>,
+    {
+        self.read_iter(path)?.collect()
+    }
+
+    /// Opens a fixed-width file for reading, returning a [`FixedWidthReader`] that deserializes
+    /// records one at a time as they are read.  The header line, if any, is read immediately.
+    pub fn read_iter<D, P>(&self, path: &P) -> Result<FixedWidthReader<D>>
+    where
+        D: DeserializeOwned,
+        P: AsRef<Path>,
+    {
+        let read = self.io.new_reader(path)?;
+        Ok(self.reader(read)?.with_path(path.as_ref()))
+    }
+
+    /// Reads structs implementing `[Deserialize]` from fixed-width data in any reader.  The
+    /// data is not decompressed.
+    pub fn read_from<D, R>(&self, read: R) -> Result<Vec<D>>
+    where
+        D: DeserializeOwned,
+        R: BufRead,
+    {
+        self.reader(read)?.collect()
+    }
+
+    /// Writes structs implementing `[Serialize]` to a fixed-width file, preceded by a header
+    /// line if configured.  If widths are not set, each column is made one character wider than
+    /// its longest value or name, so that the file can be read back with inferred widths; all
+    /// records are held in memory to find the widths.  Writing fails if a value or name is
+    /// wider than its column.
+    pub fn write<S, P>(&self, path: &P, recs: impl IntoIterator<Item = S>) -> Result<()>
+    where
+        S: Serialize,
+        P: AsRef<Path>,
+    {
+        let (names, rows) = serialize_records(recs, self.header)?;
+        let widths = match &self.widths {
+            Some(widths) => widths.clone(),
+            None => inferred_widths(names.iter().chain(&rows)),
+        };
+        let lines: Result<Vec<String>> =
+            names.iter().chain(&rows).map(|record| format_line(record, &widths)).collect();
+        self.io.write_lines(path, lines?)
+    }
+
+    /// Builds a [`FixedWidthReader`] over the given reader, reading the header line if any.
+    fn reader<D, R: BufRead>(&self, mut read: R) -> Result<FixedWidthReader<D, R>> {
+        let mut line = String::new();
+        let mut line_number = 0;
+        let mut headers = None;
+        let widths = if self.header {
+            read.read_line(&mut line).map_err(FgError::IoError)?;
+            line_number += 1;
+            let header = trim_terminator(&line);
+            let widths = match &self.widths {
+                Some(widths) => widths.clone(),
+                None => header_widths(header),
+            };
+            let mut names = ByteRecord::new();
+            split_line(header, &widths, &mut names);
+            headers = Some(names);
+            widths
+        } else {
+            self.widths.clone().ok_or_else(|| {
+                let msg = "Widths must be set to read fixed-width files without a header";
+                FgError::IoError(std::io::Error::new(ErrorKind::InvalidInput, msg))
+            })?
+        };
+
+        Ok(FixedWidthReader {
+            read,
+            widths,
+            headers,
+            path: None,
+            line,
+            line_number,
+            record: ByteRecord::new(),
+            _marker: PhantomData,
+        })
+    }
+}
+
+/// Iterator over the records of a fixed-width file, returned by [`FixedWidthFile::read_iter`],
+/// that deserializes each record as it is read.  Blank lines are skipped.
+pub struct FixedWidthReader<D, R = Box<dyn BufRead + Send>> {
+    read: R,
+    /// The width of each column in characters, the last of which may be unbounded
+    widths: Vec<usize>,
+    headers: Option<ByteRecord>,
+    /// The path of the file being read, for reporting errors
+    path: Option<PathBuf>,
+    line: String,
+    /// The number of lines read so far
+    line_number: u64,
+    record: ByteRecord,
+    _marker: PhantomData<fn() -> D>,
+}
+
+impl<D, R> FixedWidthReader<D, R> {
+    /// Sets the path of the file being read, which is included in errors.
+    fn with_path(mut self, path: &Path) -> Self {
+        self.path = Some(path.to_path_buf());
+        self
+    }
+
+    /// Returns the names of the columns from the header line, if the file has one.
+    pub fn headers(&self) -> Option<&ByteRecord> {
+        self.headers.as_ref()
+    }
+}
+
+impl<D: DeserializeOwned, R: BufRead> Iterator for FixedWidthReader<D, R> {
+    type Item = Result<D>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            self.line.clear();
+            match self.read.read_line(&mut self.line) {
+                Ok(0) => return None,
+                Ok(_) => self.line_number += 1,
+                Err(e) => return Some(Err(FgError::IoError(e))),
+            }
+            let line = trim_terminator(&self.line);
+            if line.trim().is_empty() {
+                continue;
+            }
+            split_line(line, &self.widths, &mut self.record);
+            let mut position = Position::new();
+            position.set_line(self.line_number);
+            self.record.set_position(Some(position));
+            return Some(deserialize_record(
+                &self.record,
+                self.headers.as_ref(),
+                self.path.as_deref(),
+            ));
+        }
+    }
+}
+
+/// Removes the line terminator from the end of a line.
+fn trim_terminator(line: &str) -> &str {
+    line.strip_suffix('\n').map_or(line, |l| l.strip_suffix('\r').unwrap_or(l))
+}
+
+/// Infers the widths of the columns of a header line, in which each column starts with its
+/// name.  The last column extends to the end of the line.
+fn header_widths(header: &str) -> Vec<usize> {
+    let mut starts = vec![];
+    let mut previous = ' ';
+    for (i, c) in header.chars().enumerate() {
+        if c != ' ' && previous == ' ' {
+            starts.push(i);
+        }
+        previous = c;
+    }
+    // Any leading spaces belong to the first column
+    if let Some(first) = starts.first_mut() {
+        *first = 0;
+    }
+    let mut widths: Vec<usize> = starts.windows(2).map(|w| w[1] - w[0]).collect();
+    if !starts.is_empty() {
+        widths.push(usize::MAX);
+    }
+    widths
+}
+
+/// Splits a line into fields of the given widths, trimming spaces from each, and replaces the
+/// contents of `record` with them.  Fields beyond the end of the line are empty.
+fn split_line(line: &str, widths: &[usize], record: &mut ByteRecord) {
+    record.clear();
+    let bounds: Vec<usize> = line.char_indices().map(|(i, _)| i).chain([line.len()]).collect();
+    let chars = bounds.len() - 1;
+    let mut start = 0usize;
+    for &width in widths {
+        let end = start.saturating_add(width);
+        let field = &line[bounds[start.min(chars)]..bounds[end.min(chars)]];
+        record.push_field(field.trim_matches(' ').as_bytes());
+        start = end;
+    }
+}
+
+/// Serializes records to their fields, returning the names of the columns if `header` is true
+/// along with the fields of each record.
+fn serialize_records<S: Serialize>(
+    recs: impl IntoIterator<Item = S>,
+    header: bool,
+) -> Result<(Option<StringRecord>, Vec<StringRecord>)> {
+    let mut names = None;
+    let mut rows = vec![];
+    for (i, rec) in recs.into_iter().enumerate() {
+        let mut writer = WriterBuilder::new().has_headers(header && i == 0).from_writer(vec![]);
+        writer.serialize(rec)?;
+        writer.flush().map_err(FgError::IoError)?;
+        let mut reader =
+            ReaderBuilder::new().has_headers(false).from_reader(writer.get_ref().as_slice());
+        let mut records = reader.records().collect::<csv::Result<Vec<_>>>()?;
+        // Only records of named fields, such as structs, have a header written before them
+        if records.len() > 1 {
+            names = Some(records.remove(0));
+        }
+        rows.extend(records);
+    }
+    Ok((names, rows))
+}
+
+/// Returns widths one character wider than the longest value in each column, except for the
+/// last column which is as wide as its longest value.
+fn inferred_widths<'a>(records: impl Iterator<Item = &'a StringRecord>) -> Vec<usize> {
+    let mut widths: Vec<usize> = vec![];
+    for record in records {
+        for (i, field) in record.iter().enumerate() {
+            let width = field.chars().count() + 1;
+            match widths.get_mut(i) {
+                Some(w) => *w = (*w).max(width),
+                None => widths.push(width),
+            }
+        }
+    }
+    if let Some(last) = widths.last_mut() {
+        *last -= 1;
+    }
+    widths
+}
+
+/// Formats the fields of a record into a line, padding each to the width of its column.
+fn format_line(record: &StringRecord, widths: &[usize]) -> Result<String> {
+    if record.len() != widths.len() {
+        let msg =
+            format!("Record has {} fields but {} widths were set", record.len(), widths.len());
+        return Err(FgError::IoError(std::io::Error::new(ErrorKind::InvalidInput, msg)));
+    }
+    let mut line = String::new();
+    for (field, &width) in record.iter().zip(widths) {
+        let len = field.chars().count();
+        if len > width {
+            let msg = format!("Value {field:?} is wider than its column of width {width}");
+            return Err(FgError::IoError(std::io::Error::new(ErrorKind::InvalidData, msg)));
+        }
+        // Padding is counted in characters, as are the widths
+        let _ = write!(line, "{field:<width$}");
+    }
+    Ok(line)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rstest::rstest;
+    use serde::Deserialize;
+    use tempfile::TempDir;
+
+    #[derive(Debug, Serialize, Deserialize, PartialEq)]
+    struct Sample {
+        name: String,
+        count: u32,
+        value: Option<f64>,
+    }
+
+    #[rstest]
+    #[case("name      count value", vec![10, 6, usize::MAX])]
+    #[case("  name count", vec![7, usize::MAX])]
+    #[case("name", vec![usize::MAX])]
+    #[case("", vec![])]
+    fn test_inferring_widths_from_header(#[case] header: &str, #[case] expected: Vec<usize>) {
+        assert_eq!(header_widths(header), expected);
+    }
+
+    #[test]
+    fn test_splitting_lines_into_fields() {
+        let mut record = ByteRecord::new();
+        split_line("  né   42 ab", &[5, 5, usize::MAX], &mut record);
+        assert_eq!(record, vec!["né", "42", "ab"]);
+        split_line("x", &[3, 3], &mut record);
+        assert_eq!(record, vec!["x", ""]);
+    }
+
+    #[test]
+    fn test_fixed_width_round_trip() {
+        let tempdir = TempDir::new().unwrap();
+        let path = tempdir.path().join("samples.txt");
+        let samples = vec![
+            Sample { name: "sample1".to_string(), count: 12, value: Some(0.5) },
+            Sample { name: "s2".to_string(), count: 1234567, value: None },
+        ];
+        FixedWidthFile::default().write(&path, &samples).unwrap();
+        let lines = Io::default().read_lines(&path).unwrap();
+        assert_eq!(
+            lines,
+            vec!["name    count   value", "sample1 12      0.5  ", "s2      1234567      "]
+        );
+        let read: Vec<Sample> = FixedWidthFile::default().read(&path).unwrap();
+        assert_eq!(read, samples);
+
+        // Widths can be set explicitly, in which case values must fit
+        let file = FixedWidthFile::builder().widths([8, 8, 4]).header(false).build();
+        file.write(&path, &samples).unwrap();
+        let lines = Io::default().read_lines(&path).unwrap();
+        assert_eq!(lines, vec!["sample1 12      0.5 ", "s2      1234567     "]);
+        let read: Vec<Sample> = file.read(&path).unwrap();
+        assert_eq!(read, samples);
+        let narrow = FixedWidthFile::builder().widths([4, 8, 4]).build();
+        assert!(narrow.write(&path, &samples).is_err());
+    }
+
+    #[test]
+    fn test_reading_fixed_width_errors() {
+        let text = "name  count value\nx     12    0.5\ny     many  \n";
+        let err = FixedWidthFile::default().read_from::<Sample, _>(text.as_bytes()).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "Failed to deserialize record on line 3 in column 'count'; value \"many\" is not a \
+             valid integer: invalid digit found in string"
+        );
+
+        let headerless = FixedWidthFile::builder().header(false).build();
+        assert!(headerless.read_from::<Sample, _>(text.as_bytes()).is_err());
+    }
+}
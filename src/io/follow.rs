@@ -0,0 +1,95 @@
+//! A reader that follows a file as it grows, in the manner of `tail -f`.
+use std::io::{self, Read};
+use std::thread;
+use std::time::{Duration, Instant};
+
+/// Reader that, on reaching the end of its input, polls for newly appended data instead of
+/// returning end of file.  Polling happens every `interval` until either data arrives or, if an
+/// idle timeout is set, no data has been read for that long, at which point end of file is
+/// returned.  Without an idle timeout the reader follows its input indefinitely.
+///
+/// Since reads block while waiting for data, partially written lines are returned in full once
+/// their newline is written.
+pub struct FollowReader<R> {
+    inner: R,
+    interval: Duration,
+    idle_timeout: Option<Duration>,
+}
+
+impl<R> FollowReader<R> {
+    /// Wraps a reader, polling for new data every `interval` and giving up after `idle_timeout`
+    /// without new data, if given.
+    pub fn new(inner: R, interval: Duration, idle_timeout: Option<Duration>) -> Self {
+        Self { inner, interval, idle_timeout }
+    }
+
+    /// Consumes this wrapper, returning the underlying reader.
+    pub fn into_inner(self) -> R {
+        self.inner
+    }
+}
+
+impl<R: Read> Read for FollowReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        if buf.is_empty() {
+            return Ok(0);
+        }
+
+        let idle_since = Instant::now();
+        loop {
+            let n = self.inner.read(buf)?;
+            if n > 0 {
+                return Ok(n);
+            }
+            match self.idle_timeout {
+                Some(timeout) if idle_since.elapsed() >= timeout => return Ok(0),
+                _ => thread::sleep(self.interval),
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs::{File, OpenOptions};
+    use std::io::{BufRead, BufReader, Write};
+    use tempfile::TempDir;
+
+    #[test]
+    fn test_following_appended_data() {
+        let tempdir = TempDir::new().unwrap();
+        let path = tempdir.path().join("log.txt");
+        std::fs::write(&path, "first\n").unwrap();
+
+        let appender_path = path.clone();
+        let appender = thread::spawn(move || {
+            let mut file = OpenOptions::new().append(true).open(appender_path).unwrap();
+            for line in ["second\n", "thi", "rd\n"] {
+                thread::sleep(Duration::from_millis(50));
+                file.write_all(line.as_bytes()).unwrap();
+                file.flush().unwrap();
+            }
+        });
+
+        let file = File::open(&path).unwrap();
+        let timeout = Some(Duration::from_secs(2));
+        let reader = BufReader::new(FollowReader::new(file, Duration::from_millis(5), timeout));
+        let mut lines = reader.lines();
+        assert_eq!(lines.next().unwrap().unwrap(), "first");
+        assert_eq!(lines.next().unwrap().unwrap(), "second");
+        assert_eq!(lines.next().unwrap().unwrap(), "third");
+        appender.join().unwrap();
+    }
+
+    #[test]
+    fn test_idle_timeout_ends_input() {
+        let timeout = Duration::from_millis(50);
+        let mut reader = FollowReader::new(&b"data"[..], Duration::from_millis(5), Some(timeout));
+        let start = Instant::now();
+        let mut out = String::new();
+        reader.read_to_string(&mut out).unwrap();
+        assert_eq!(out, "data");
+        assert!(start.elapsed() >= timeout);
+    }
+}
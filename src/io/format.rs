@@ -0,0 +1,334 @@
+//! Customization of how values are formatted when records are serialized.
+use serde::ser::{
+    Serialize, SerializeSeq, SerializeStruct, SerializeTuple, SerializeTupleStruct, Serializer,
+};
+
+/// How floating point values are formatted when written.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum FloatFormat {
+    /// The shortest representation that reads back as the same value, e.g. `0.1` or `1234.5678`
+    #[default]
+    Shortest,
+    /// A fixed number of digits after the decimal point, e.g. `0.1000` or `1234.5678` for 4
+    Decimals(usize),
+    /// A fixed number of significant digits, e.g. `0.1000` or `1235` for 4.  Digits before the
+    /// decimal point beyond the number of significant digits are written as zeros.  The number
+    /// of digits must be at least one.
+    SignificantDigits(usize),
+}
+
+impl FloatFormat {
+    /// Formats a value, or returns `None` if it should be written in the default format.  Values
+    /// that are written as zero, such as `-0.0001` to fewer than four decimals, are written
+    /// without a sign.
+    fn format(self, value: f64) -> Option<String> {
+        if !value.is_finite() {
+            return None;
+        }
+        let formatted = match self {
+            FloatFormat::Shortest => return None,
+            FloatFormat::Decimals(decimals) => format!("{value:.decimals$}"),
+            FloatFormat::SignificantDigits(digits) => {
+                // Round in scientific notation first, so that the exponent reflects any rounding
+                // up to the next power of ten
+                let precision = digits.max(1) - 1;
+                let rounded = format!("{value:.precision$e}");
+                let exponent: i64 = rounded.rsplit('e').next()?.parse().ok()?;
+                let value: f64 = rounded.parse().ok()?;
+                let decimals = (precision as i64 - exponent).max(0) as usize;
+                format!("{value:.decimals$}")
+            }
+        };
+        match formatted.strip_prefix('-') {
+            Some(unsigned) if unsigned.bytes().all(|b| matches!(b, b'0' | b'.')) => {
+                Some(unsigned.to_string())
+            }
+            _ => Some(formatted),
+        }
+    }
+}
+
+/// Options for formatting the values of fields when writing delimited files.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub(crate) struct WriteFormat {
+    /// The token written for `None` values, in place of an empty field
+    pub(crate) null_token: Option<String>,
+    /// The format of `f32` and `f64` values
+    pub(crate) float_format: FloatFormat,
+    /// Whether booleans are written as `1` and `0` rather than `true` and `false`
+    pub(crate) bools_as_ints: bool,
+}
+
+impl WriteFormat {
+    /// Returns true if values are formatted as they would be without any options.
+    pub(crate) fn is_default(&self) -> bool {
+        *self == WriteFormat::default()
+    }
+}
+
+/// Wraps a value so that it, and any values nested within it, are serialized according to a
+/// [`WriteFormat`].
+pub(crate) struct Formatted<'a, T: ?Sized> {
+    pub(crate) value: &'a T,
+    pub(crate) format: &'a WriteFormat,
+}
+
+impl<T: Serialize + ?Sized> Serialize for Formatted<'_, T> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        self.value.serialize(FormattingSerializer { inner: serializer, format: self.format })
+    }
+}
+
+/// Serializer that applies a [`WriteFormat`] to values before passing them to another serializer.
+struct FormattingSerializer<'a, S> {
+    inner: S,
+    format: &'a WriteFormat,
+}
+
+/// Generates serializer methods that pass values straight through to the inner serializer.
+macro_rules! forward {
+    ($($method:ident($($arg:ident: $ty:ty),*) -> $ret:ty;)*) => {
+        $(
+            fn $method(self, $($arg: $ty),*) -> Result<$ret, Self::Error> {
+                self.inner.$method($($arg),*)
+            }
+        )*
+    };
+}
+
+impl<'a, S: Serializer> Serializer for FormattingSerializer<'a, S> {
+    type Ok = S::Ok;
+    type Error = S::Error;
+    type SerializeSeq = Compound<'a, S::SerializeSeq>;
+    type SerializeTuple = Compound<'a, S::SerializeTuple>;
+    type SerializeTupleStruct = Compound<'a, S::SerializeTupleStruct>;
+    type SerializeTupleVariant = S::SerializeTupleVariant;
+    type SerializeMap = S::SerializeMap;
+    type SerializeStruct = Compound<'a, S::SerializeStruct>;
+    type SerializeStructVariant = S::SerializeStructVariant;
+
+    forward! {
+        serialize_i8(v: i8) -> S::Ok;
+        serialize_i16(v: i16) -> S::Ok;
+        serialize_i32(v: i32) -> S::Ok;
+        serialize_i64(v: i64) -> S::Ok;
+        serialize_i128(v: i128) -> S::Ok;
+        serialize_u8(v: u8) -> S::Ok;
+        serialize_u16(v: u16) -> S::Ok;
+        serialize_u32(v: u32) -> S::Ok;
+        serialize_u64(v: u64) -> S::Ok;
+        serialize_u128(v: u128) -> S::Ok;
+        serialize_char(v: char) -> S::Ok;
+        serialize_str(v: &str) -> S::Ok;
+        serialize_bytes(v: &[u8]) -> S::Ok;
+        serialize_unit() -> S::Ok;
+        serialize_unit_struct(name: &'static str) -> S::Ok;
+        serialize_unit_variant(name: &'static str, index: u32, variant: &'static str) -> S::Ok;
+        serialize_tuple_variant(
+            name: &'static str, index: u32, variant: &'static str, len: usize
+        ) -> S::SerializeTupleVariant;
+        serialize_map(len: Option<usize>) -> S::SerializeMap;
+        serialize_struct_variant(
+            name: &'static str, index: u32, variant: &'static str, len: usize
+        ) -> S::SerializeStructVariant;
+    }
+
+    fn serialize_bool(self, v: bool) -> Result<S::Ok, S::Error> {
+        if self.format.bools_as_ints {
+            self.inner.serialize_u8(u8::from(v))
+        } else {
+            self.inner.serialize_bool(v)
+        }
+    }
+
+    fn serialize_f32(self, v: f32) -> Result<S::Ok, S::Error> {
+        match self.format.float_format.format(f64::from(v)) {
+            Some(formatted) => self.inner.serialize_str(&formatted),
+            None => self.inner.serialize_f32(v),
+        }
+    }
+
+    fn serialize_f64(self, v: f64) -> Result<S::Ok, S::Error> {
+        match self.format.float_format.format(v) {
+            Some(formatted) => self.inner.serialize_str(&formatted),
+            None => self.inner.serialize_f64(v),
+        }
+    }
+
+    fn serialize_none(self) -> Result<S::Ok, S::Error> {
+        match &self.format.null_token {
+            Some(token) => self.inner.serialize_str(token),
+            None => self.inner.serialize_none(),
+        }
+    }
+
+    fn serialize_some<T: Serialize + ?Sized>(self, value: &T) -> Result<S::Ok, S::Error> {
+        let format = self.format;
+        self.inner.serialize_some(&Formatted { value, format })
+    }
+
+    fn serialize_newtype_struct<T: Serialize + ?Sized>(
+        self,
+        name: &'static str,
+        value: &T,
+    ) -> Result<S::Ok, S::Error> {
+        let format = self.format;
+        self.inner.serialize_newtype_struct(name, &Formatted { value, format })
+    }
+
+    fn serialize_newtype_variant<T: Serialize + ?Sized>(
+        self,
+        name: &'static str,
+        index: u32,
+        variant: &'static str,
+        value: &T,
+    ) -> Result<S::Ok, S::Error> {
+        let format = self.format;
+        self.inner.serialize_newtype_variant(name, index, variant, &Formatted { value, format })
+    }
+
+    fn serialize_seq(self, len: Option<usize>) -> Result<Self::SerializeSeq, S::Error> {
+        Ok(Compound { inner: self.inner.serialize_seq(len)?, format: self.format })
+    }
+
+    fn serialize_tuple(self, len: usize) -> Result<Self::SerializeTuple, S::Error> {
+        Ok(Compound { inner: self.inner.serialize_tuple(len)?, format: self.format })
+    }
+
+    fn serialize_tuple_struct(
+        self,
+        name: &'static str,
+        len: usize,
+    ) -> Result<Self::SerializeTupleStruct, S::Error> {
+        Ok(Compound { inner: self.inner.serialize_tuple_struct(name, len)?, format: self.format })
+    }
+
+    fn serialize_struct(
+        self,
+        name: &'static str,
+        len: usize,
+    ) -> Result<Self::SerializeStruct, S::Error> {
+        Ok(Compound { inner: self.inner.serialize_struct(name, len)?, format: self.format })
+    }
+}
+
+/// Wraps the serializer of a compound value so that its elements are formatted.
+struct Compound<'a, C> {
+    inner: C,
+    format: &'a WriteFormat,
+}
+
+impl<C: SerializeSeq> SerializeSeq for Compound<'_, C> {
+    type Ok = C::Ok;
+    type Error = C::Error;
+
+    fn serialize_element<T: Serialize + ?Sized>(&mut self, value: &T) -> Result<(), C::Error> {
+        self.inner.serialize_element(&Formatted { value, format: self.format })
+    }
+
+    fn end(self) -> Result<C::Ok, C::Error> {
+        self.inner.end()
+    }
+}
+
+impl<C: SerializeTuple> SerializeTuple for Compound<'_, C> {
+    type Ok = C::Ok;
+    type Error = C::Error;
+
+    fn serialize_element<T: Serialize + ?Sized>(&mut self, value: &T) -> Result<(), C::Error> {
+        self.inner.serialize_element(&Formatted { value, format: self.format })
+    }
+
+    fn end(self) -> Result<C::Ok, C::Error> {
+        self.inner.end()
+    }
+}
+
+impl<C: SerializeTupleStruct> SerializeTupleStruct for Compound<'_, C> {
+    type Ok = C::Ok;
+    type Error = C::Error;
+
+    fn serialize_field<T: Serialize + ?Sized>(&mut self, value: &T) -> Result<(), C::Error> {
+        self.inner.serialize_field(&Formatted { value, format: self.format })
+    }
+
+    fn end(self) -> Result<C::Ok, C::Error> {
+        self.inner.end()
+    }
+}
+
+impl<C: SerializeStruct> SerializeStruct for Compound<'_, C> {
+    type Ok = C::Ok;
+    type Error = C::Error;
+
+    fn serialize_field<T: Serialize + ?Sized>(
+        &mut self,
+        key: &'static str,
+        value: &T,
+    ) -> Result<(), C::Error> {
+        self.inner.serialize_field(key, &Formatted { value, format: self.format })
+    }
+
+    fn skip_field(&mut self, key: &'static str) -> Result<(), C::Error> {
+        self.inner.skip_field(key)
+    }
+
+    fn end(self) -> Result<C::Ok, C::Error> {
+        self.inner.end()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde::Serialize;
+
+    #[derive(Serialize)]
+    struct Rec {
+        name: Option<String>,
+        values: (Option<u32>, Option<u32>),
+    }
+
+    fn to_csv<T: Serialize>(value: &T, format: &WriteFormat) -> String {
+        let mut writer = csv::WriterBuilder::new().has_headers(false).from_writer(vec![]);
+        writer.serialize(Formatted { value, format }).unwrap();
+        String::from_utf8(writer.into_inner().unwrap()).unwrap()
+    }
+
+    #[test]
+    fn test_null_token() {
+        let rec = Rec { name: None, values: (Some(1), None) };
+        assert_eq!(to_csv(&rec, &WriteFormat::default()), ",1,\n");
+        let format = WriteFormat { null_token: Some("NA".to_string()), ..Default::default() };
+        assert_eq!(to_csv(&rec, &format), "NA,1,NA\n");
+    }
+
+    #[test]
+    fn test_float_formats() {
+        let values = (0.1f64, 1234.5678f64, -0.000123456f64, 9.99996f32, f64::NAN);
+        let write =
+            |float_format| to_csv(&values, &WriteFormat { float_format, ..Default::default() });
+        assert_eq!(write(FloatFormat::Shortest), "0.1,1234.5678,-0.000123456,9.99996,NaN\n");
+        assert_eq!(write(FloatFormat::Decimals(4)), "0.1000,1234.5678,-0.0001,10.0000,NaN\n");
+        assert_eq!(write(FloatFormat::Decimals(0)), "0,1235,0,10,NaN\n");
+        assert_eq!(write(FloatFormat::SignificantDigits(4)), "0.1000,1235,-0.0001235,10.00,NaN\n");
+        assert_eq!(write(FloatFormat::SignificantDigits(1)), "0.1,1000,-0.0001,10,NaN\n");
+    }
+
+    #[test]
+    fn test_bools_as_ints() {
+        let values = (true, false, Some(true), None::<bool>);
+        assert_eq!(to_csv(&values, &WriteFormat::default()), "true,false,true,\n");
+        let format = WriteFormat { bools_as_ints: true, ..Default::default() };
+        assert_eq!(to_csv(&values, &format), "1,0,1,\n");
+    }
+
+    #[test]
+    fn test_float_format_of_zero() {
+        assert_eq!(FloatFormat::SignificantDigits(3).format(0.0), Some("0.00".to_string()));
+        assert_eq!(FloatFormat::Decimals(2).format(f64::INFINITY), None);
+        assert_eq!(FloatFormat::Decimals(4).format(-0.00001), Some("0.0000".to_string()));
+        assert_eq!(FloatFormat::Decimals(0).format(-0.0), Some("0".to_string()));
+        assert_eq!(FloatFormat::SignificantDigits(2).format(-0.0), Some("0.0".to_string()));
+    }
+}
@@ -0,0 +1,82 @@
+//! Reading the bodies of HTTP(S) responses as input streams.
+use std::io::{self, ErrorKind, Read};
+
+/// Issues a GET request for the given URL and returns a reader over the body of the response.
+/// Responses with an error status are reported as errors, with a status of 404 reported as
+/// [`ErrorKind::NotFound`] and 401 or 403 as [`ErrorKind::PermissionDenied`].  Failures to
+/// connect to the server are reported as [`ErrorKind::NotConnected`].
+pub(crate) fn open_url(url: &str) -> io::Result<Box<dyn Read + Send>> {
+    let (kind, msg) = match ureq::get(url).call() {
+        Ok(response) => return Ok(Box::new(response.into_reader())),
+        Err(ureq::Error::Status(404, _)) => (ErrorKind::NotFound, format!("Not found: {url}")),
+        Err(ureq::Error::Status(code @ (401 | 403), _)) => {
+            (ErrorKind::PermissionDenied, format!("Access denied ({code}): {url}"))
+        }
+        Err(ureq::Error::Status(code, _)) => {
+            (ErrorKind::InvalidData, format!("Unexpected HTTP status {code}: {url}"))
+        }
+        Err(e @ ureq::Error::Transport(_)) => {
+            (ErrorKind::NotConnected, format!("Failed to read {url}: {e}"))
+        }
+    };
+    Err(io::Error::new(kind, msg))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::io::Io;
+    use std::io::{BufRead, BufReader, Write};
+    use std::net::TcpListener;
+
+    /// Serves a single HTTP request on a local port with the given status and body, returning
+    /// the URL of the server.
+    fn serve_once(status: &'static str, body: Vec<u8>) -> String {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        std::thread::spawn(move || {
+            let (stream, _) = listener.accept().unwrap();
+            let mut reader = BufReader::new(stream);
+            let mut line = String::new();
+            while reader.read_line(&mut line).unwrap() > 0 && line != "\r\n" {
+                line.clear();
+            }
+            let mut stream = reader.into_inner();
+            let header = format!(
+                "HTTP/1.1 {status}\r\nContent-Length: {}\r\nConnection: close\r\n\r\n",
+                body.len()
+            );
+            stream.write_all(header.as_bytes()).unwrap();
+            stream.write_all(&body).unwrap();
+        });
+        format!("http://{addr}")
+    }
+
+    #[test]
+    fn test_open_url() {
+        let url = serve_once("200 OK", b"hello\nworld\n".to_vec());
+        let mut body = String::new();
+        open_url(&format!("{url}/hello.txt")).unwrap().read_to_string(&mut body).unwrap();
+        assert_eq!(body, "hello\nworld\n");
+    }
+
+    #[test]
+    fn test_open_url_not_found() {
+        let url = serve_once("404 Not Found", vec![]);
+        let result = open_url(&format!("{url}/missing.txt"));
+        assert_eq!(result.err().unwrap().kind(), ErrorKind::NotFound);
+    }
+
+    #[test]
+    fn test_reading_compressed_lines_from_url() {
+        let tempdir = tempfile::TempDir::new().unwrap();
+        let path = tempdir.path().join("lines.txt.gz");
+        let io = Io::default();
+        io.write_lines(&path, ["a", "b", "c"]).unwrap();
+
+        let url = serve_once("200 OK", std::fs::read(&path).unwrap());
+        let url = format!("{url}/lines.txt.gz");
+        assert!(Io::is_url_path(&url));
+        assert_eq!(io.read_lines(&url).unwrap(), vec!["a", "b", "c"]);
+    }
+}
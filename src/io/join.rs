@@ -0,0 +1,130 @@
+//! Joining the records of two delimited files on the values of key columns.
+use std::collections::HashMap;
+use std::io::{Read, Write};
+
+use csv::ByteRecord;
+
+use crate::io::dedup::{key_of, Key};
+use crate::io::delim::{column_indices, dedupe_headers};
+use crate::io::DuplicateColumns;
+use crate::{FgError, Result};
+
+/// Which records are written by [`DelimFile::join`](crate::io::DelimFile::join).
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub enum JoinKind {
+    /// Only records of the left file with a matching record in the right file are written
+    #[default]
+    Inner,
+    /// Every record of the left file is written, with empty values in place of the right
+    /// file's columns when there is no matching record
+    Left,
+}
+
+/// Joins the records of `left` with those of `right` that have the same values in the key
+/// columns, writing a header and then, for each record of `left` in order, one record for each
+/// match made up of the left record followed by the right record's non-key fields.  The right
+/// records are held in memory and the left records are streamed.  Columns of the output with
+/// the same name are handled according to `duplicates`.  Returns the number of records written.
+pub(crate) fn join<L: Read, R: Read, W: Write, S: AsRef<str>>(
+    left: &mut csv::Reader<L>,
+    right: &mut csv::Reader<R>,
+    writer: &mut csv::Writer<W>,
+    key_columns: &[S],
+    kind: JoinKind,
+    duplicates: DuplicateColumns,
+) -> Result<u64> {
+    let left_indices = column_indices(key_columns, left.headers()?)?;
+    let right_indices = column_indices(key_columns, right.headers()?)?;
+    // The columns of the right file that are written, which are all but the keys
+    let right_columns: Vec<usize> =
+        (0..right.headers()?.len()).filter(|i| !right_indices.contains(i)).collect();
+
+    let mut header = left.byte_headers()?.clone();
+    let right_header = right.byte_headers()?;
+    for &i in &right_columns {
+        header.push_field(&right_header[i]);
+    }
+    dedupe_headers(&mut header, duplicates)?;
+    writer.write_byte_record(&header)?;
+
+    let mut matches: HashMap<Key, Vec<ByteRecord>> = HashMap::new();
+    let mut record = ByteRecord::new();
+    while right.read_byte_record(&mut record)? {
+        let mut values = ByteRecord::with_capacity(record.as_slice().len(), right_columns.len());
+        for &i in &right_columns {
+            values.push_field(record.get(i).unwrap_or_default());
+        }
+        matches.entry(key_of(&record, &right_indices)).or_default().push(values);
+    }
+
+    let mut count = 0;
+    let mut output = ByteRecord::new();
+    while left.read_byte_record(&mut record)? {
+        match matches.get(&key_of(&record, &left_indices)) {
+            Some(matched) => {
+                for values in matched {
+                    output.clone_from(&record);
+                    output.extend(values);
+                    writer.write_byte_record(&output)?;
+                    count += 1;
+                }
+            }
+            None if kind == JoinKind::Left => {
+                output.clone_from(&record);
+                output.extend(right_columns.iter().map(|_| b""));
+                writer.write_byte_record(&output)?;
+                count += 1;
+            }
+            None => {}
+        }
+    }
+    writer.flush().map_err(FgError::IoError)?;
+    Ok(count)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use csv::{ReaderBuilder, WriterBuilder};
+    use rstest::rstest;
+
+    const LEFT: &str = "sample\tlane\treads\ns1\t1\t10\ns2\t1\t20\ns1\t2\t30\ns3\t1\t40\n";
+    const RIGHT: &str = "project\tsample\tlane\na\ts1\t1\nb\ts1\t2\nc\ts1\t2\nd\ts2\t1\n";
+
+    fn tsv_reader(data: &str) -> csv::Reader<&[u8]> {
+        ReaderBuilder::new().delimiter(b'\t').from_reader(data.as_bytes())
+    }
+
+    fn join_tsv(keys: &[&str], kind: JoinKind, duplicates: DuplicateColumns) -> Result<String> {
+        let mut writer = WriterBuilder::new().delimiter(b'\t').from_writer(vec![]);
+        join(&mut tsv_reader(LEFT), &mut tsv_reader(RIGHT), &mut writer, keys, kind, duplicates)?;
+        Ok(String::from_utf8(writer.get_ref().clone()).unwrap())
+    }
+
+    #[rstest]
+    #[case(
+        JoinKind::Inner,
+        "sample\tlane\treads\tproject\ns1\t1\t10\ta\ns2\t1\t20\td\ns1\t2\t30\tb\ns1\t2\t30\tc\n"
+    )]
+    #[case(
+        JoinKind::Left,
+        "sample\tlane\treads\tproject\ns1\t1\t10\ta\ns2\t1\t20\td\ns1\t2\t30\tb\ns1\t2\t30\tc\n\
+         s3\t1\t40\t\n"
+    )]
+    fn test_joining_records(#[case] kind: JoinKind, #[case] expected: &str) {
+        let joined = join_tsv(&["sample", "lane"], kind, DuplicateColumns::Reject).unwrap();
+        assert_eq!(joined, expected);
+    }
+
+    #[test]
+    fn test_joining_records_with_same_column_names() {
+        let joined = join_tsv(&["sample"], JoinKind::Inner, DuplicateColumns::Rename).unwrap();
+        assert!(joined.starts_with("sample\tlane\treads\tproject\tlane_2\ns1\t1\t10\ta\t1\n"));
+
+        let result = join_tsv(&["sample"], JoinKind::Inner, DuplicateColumns::Reject);
+        assert!(matches!(result, Err(FgError::DuplicateColumn { column }) if column == "lane"));
+
+        let result = join_tsv(&["reads"], JoinKind::Inner, DuplicateColumns::Reject);
+        assert!(matches!(result, Err(FgError::HeaderMismatch { .. })));
+    }
+}
@@ -0,0 +1,128 @@
+//! Reader wrappers that guard against malformed or malicious inputs.
+use std::io::{self, ErrorKind, Read};
+
+/// Reader that fails with a descriptive error if it encounters a line longer than a maximum
+/// length, rather than allowing a consumer reading by lines to buffer an arbitrarily large
+/// "line", e.g. when a binary file is read as text by mistake.  Line lengths are measured in
+/// bytes and exclude the trailing newline.
+pub struct MaxLineLengthReader<R> {
+    inner: R,
+    max_line_length: usize,
+    /// The length of the line currently being read
+    current_length: usize,
+    /// The number of complete lines read so far
+    lines: u64,
+}
+
+impl<R> MaxLineLengthReader<R> {
+    /// Wraps a reader, allowing lines of at most `max_line_length` bytes.
+    pub fn new(inner: R, max_line_length: usize) -> Self {
+        Self { inner, max_line_length, current_length: 0, lines: 0 }
+    }
+
+    /// Consumes this wrapper, returning the underlying reader.
+    pub fn into_inner(self) -> R {
+        self.inner
+    }
+}
+
+impl<R: Read> Read for MaxLineLengthReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let n = self.inner.read(buf)?;
+        for line in buf[..n].split_inclusive(|b| *b == b'\n') {
+            let terminated = line.last() == Some(&b'\n');
+            self.current_length += line.len() - usize::from(terminated);
+            if self.current_length > self.max_line_length {
+                let msg = format!(
+                    "Line {} exceeds the maximum line length of {} bytes; is this a binary file?",
+                    self.lines + 1,
+                    self.max_line_length
+                );
+                return Err(io::Error::new(ErrorKind::InvalidData, msg));
+            }
+            if terminated {
+                self.current_length = 0;
+                self.lines += 1;
+            }
+        }
+        Ok(n)
+    }
+}
+
+/// Reader that fails with an error once more than a maximum number of bytes have been read
+/// through it.  When wrapped around a decoder this caps the total decompressed size of a file,
+/// protecting against "decompression bombs" that expand to vastly more data than expected.
+pub struct MaxBytesReader<R> {
+    inner: R,
+    max_bytes: u64,
+    bytes: u64,
+}
+
+impl<R> MaxBytesReader<R> {
+    /// Wraps a reader, allowing at most `max_bytes` bytes to be read.
+    pub fn new(inner: R, max_bytes: u64) -> Self {
+        Self { inner, max_bytes, bytes: 0 }
+    }
+
+    /// Consumes this wrapper, returning the underlying reader.
+    pub fn into_inner(self) -> R {
+        self.inner
+    }
+}
+
+impl<R: Read> Read for MaxBytesReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        // Read at most one byte beyond the limit, so that exceeding it is detected promptly
+        let allowed = (self.max_bytes - self.bytes).saturating_add(1);
+        let len = buf.len().min(usize::try_from(allowed).unwrap_or(usize::MAX));
+        let n = self.inner.read(&mut buf[..len])?;
+        self.bytes += n as u64;
+        if self.bytes > self.max_bytes {
+            return Err(max_bytes_error(self.max_bytes));
+        }
+        Ok(n)
+    }
+}
+
+/// Returns the error for input of more than `max_bytes` decompressed bytes.
+pub(crate) fn max_bytes_error(max_bytes: u64) -> io::Error {
+    let msg = format!(
+        "Input exceeds the maximum of {max_bytes} decompressed bytes; is it a decompression bomb?"
+    );
+    io::Error::new(ErrorKind::InvalidData, msg)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::BufRead;
+    use std::io::BufReader;
+
+    #[test]
+    fn test_reading_up_to_max_bytes() {
+        let mut out = Vec::new();
+        MaxBytesReader::new(&b"12345"[..], 5).read_to_end(&mut out).unwrap();
+        assert_eq!(out, b"12345");
+
+        let mut reader = MaxBytesReader::new(&b"123456"[..], 5);
+        let err = reader.read_to_end(&mut Vec::new()).unwrap_err();
+        assert_eq!(err.kind(), ErrorKind::InvalidData);
+    }
+
+    #[test]
+    fn test_lines_within_limit_are_read() {
+        let data = b"1234\n12345\n\n123";
+        let reader = BufReader::with_capacity(3, MaxLineLengthReader::new(&data[..], 5));
+        let lines: Vec<String> = reader.lines().map(|l| l.unwrap()).collect();
+        assert_eq!(lines, vec!["1234", "12345", "", "123"]);
+    }
+
+    #[test]
+    fn test_long_lines_fail() {
+        let data = b"1234\n123456\n";
+        let reader = BufReader::with_capacity(3, MaxLineLengthReader::new(&data[..], 5));
+        let err = reader.lines().find_map(|l| l.err()).unwrap();
+        assert_eq!(err.kind(), ErrorKind::InvalidData);
+        assert!(err.to_string().contains("Line 2 exceeds the maximum line length of 5 bytes"));
+    }
+}
@@ -0,0 +1,392 @@
+//! An index of line offsets that allows random access to the lines of a file.
+use std::fs::File;
+use std::io::{BufRead, BufReader, ErrorKind, Read, Seek, SeekFrom, Write};
+use std::ops::Range;
+use std::path::{Path, PathBuf};
+
+use crate::io::{BgzfReader, CompressionFormat, Io, VirtualOffset};
+use crate::{FgError, Result};
+
+/// The bytes at the start of every line index file, including the version of the format
+const INDEX_MAGIC: &[u8; 8] = b"FGLIDX1\n";
+
+/// The kinds of file that can be indexed, which determine how offsets are interpreted.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+enum IndexedFormat {
+    /// Offsets are byte offsets into an uncompressed file
+    Plain,
+    /// Offsets are BGZF virtual offsets
+    Bgzf,
+}
+
+/// A reader over an indexed file that can report and seek to offsets.
+enum Source {
+    Plain(BufReader<File>, u64),
+    Bgzf(BgzfReader<BufReader<File>>),
+}
+
+impl Source {
+    fn open(path: &Path, format: IndexedFormat, buffer_size: usize) -> Result<Self> {
+        let file = File::open(path).map_err(FgError::IoError)?;
+        let buf = BufReader::with_capacity(buffer_size, file);
+        Ok(match format {
+            IndexedFormat::Plain => Source::Plain(buf, 0),
+            IndexedFormat::Bgzf => Source::Bgzf(BgzfReader::new(buf)),
+        })
+    }
+
+    /// The offset of the next byte to be read.
+    fn offset(&self) -> u64 {
+        match self {
+            Source::Plain(_, offset) => *offset,
+            Source::Bgzf(reader) => reader.virtual_offset().into(),
+        }
+    }
+
+    fn seek(&mut self, offset: u64) -> Result<()> {
+        match self {
+            Source::Plain(reader, current) => {
+                reader.seek(SeekFrom::Start(offset)).map_err(FgError::IoError)?;
+                *current = offset;
+            }
+            Source::Bgzf(reader) => {
+                reader.seek_virtual(VirtualOffset::from(offset)).map_err(FgError::IoError)?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Reads the next line into `line`, without its line terminator, returning false at EOF.
+    fn read_line(&mut self, line: &mut String) -> Result<bool> {
+        let mut bytes = Vec::new();
+        let n = match self {
+            Source::Plain(reader, offset) => {
+                let n = reader.read_until(b'\n', &mut bytes).map_err(FgError::IoError)?;
+                *offset += n as u64;
+                n
+            }
+            Source::Bgzf(reader) => {
+                reader.read_until(b'\n', &mut bytes).map_err(FgError::IoError)?
+            }
+        };
+
+        if bytes.last() == Some(&b'\n') {
+            bytes.pop();
+            if bytes.last() == Some(&b'\r') {
+                bytes.pop();
+            }
+        }
+        line.clear();
+        line.push_str(&String::from_utf8(bytes).map_err(|e| {
+            FgError::IoError(std::io::Error::new(ErrorKind::InvalidData, e.utf8_error()))
+        })?);
+        Ok(n > 0)
+    }
+}
+
+/// An in-memory index of the offsets of lines within an uncompressed or BGZF-compressed file,
+/// built by scanning the file once.  Once built, individual lines or ranges of lines can be read
+/// without reading the file from the start.
+///
+/// To limit memory use on very large files, the offset of only every Nth line may be stored, in
+/// which case reads seek to the nearest preceding indexed line and skip forward from there.
+///
+/// ```rust
+/// use fgoxide::io::{Io, LineIndex};
+/// use tempfile::TempDir;
+///
+/// let dir = TempDir::new().unwrap();
+/// let path = dir.path().join("lines.txt");
+/// let io = Io::default();
+/// io.write_lines(&path, (0..100).map(|i| format!("line {i}"))).unwrap();
+///
+/// let index = LineIndex::build(&io, &path, 10).unwrap();
+/// assert_eq!(index.len(), 100);
+/// assert_eq!(index.read_line(42).unwrap(), "line 42");
+/// assert_eq!(index.read_range(98..100).unwrap(), vec!["line 98", "line 99"]);
+///
+/// // Indexes can be saved and reloaded to avoid rebuilding them
+/// let index_path = dir.path().join("lines.txt.idx");
+/// index.write(&io, &index_path).unwrap();
+/// assert_eq!(LineIndex::load(&io, &index_path).unwrap(), index);
+/// ```
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct LineIndex {
+    path: PathBuf,
+    format: IndexedFormat,
+    every: u64,
+    offsets: Vec<u64>,
+    line_count: u64,
+    file_len: u64,
+    buffer_size: usize,
+}
+
+impl LineIndex {
+    /// Builds an index of the file at `p`, recording the offset of every `every`th line.  The
+    /// file must be uncompressed, or compressed with BGZF (which may use either a `.bgz` or `.gz`
+    /// extension).
+    ///
+    /// # Panics
+    /// - panics if `every` is zero
+    pub fn build<P: AsRef<Path>>(io: &Io, p: &P, every: u64) -> Result<LineIndex> {
+        assert!(every > 0, "Must index at least every line!");
+        let format = match io.compression_format(p) {
+            CompressionFormat::None => IndexedFormat::Plain,
+            CompressionFormat::Gzip | CompressionFormat::Bgzf => IndexedFormat::Bgzf,
+            _ => {
+                let msg = "Only uncompressed and BGZF files can be indexed";
+                return Err(FgError::IoError(std::io::Error::new(ErrorKind::InvalidInput, msg)));
+            }
+        };
+
+        let path = p.as_ref().to_path_buf();
+        let file_len = std::fs::metadata(&path).map_err(FgError::IoError)?.len();
+        let mut source = Source::open(&path, format, io.buffer_size)?;
+        let mut offsets = Vec::new();
+        let mut line_count = 0;
+        let mut line = String::new();
+        loop {
+            let offset = source.offset();
+            if !source.read_line(&mut line)? {
+                break;
+            }
+            if line_count % every == 0 {
+                offsets.push(offset);
+            }
+            line_count += 1;
+        }
+
+        let buffer_size = io.buffer_size;
+        Ok(LineIndex { path, format, every, offsets, line_count, file_len, buffer_size })
+    }
+
+    /// Writes the index to a file, so that it can be loaded with [`LineIndex::load`] rather
+    /// than rebuilt.  The index is written in a compact binary format, compressed according to
+    /// the extension of `p`, and records the path of the indexed file, so the indexed file
+    /// should not be moved afterwards.
+    pub fn write<P: AsRef<Path>>(&self, io: &Io, p: &P) -> Result<()> {
+        let path = self.path.to_str().ok_or_else(|| {
+            let msg = format!("Cannot store non UTF-8 path in index: {}", self.path.display());
+            FgError::IoError(std::io::Error::new(ErrorKind::InvalidInput, msg))
+        })?;
+
+        let mut out = io.new_writer(p)?;
+        out.write_all(INDEX_MAGIC).map_err(FgError::IoError)?;
+        let format = match self.format {
+            IndexedFormat::Plain => 0u8,
+            IndexedFormat::Bgzf => 1u8,
+        };
+        out.write_all(&[format]).map_err(FgError::IoError)?;
+        let header = [self.every, self.line_count, self.file_len, path.len() as u64];
+        for value in header.iter().chain(&[self.offsets.len() as u64]).chain(&self.offsets) {
+            out.write_all(&value.to_le_bytes()).map_err(FgError::IoError)?;
+        }
+        out.write_all(path.as_bytes()).map_err(FgError::IoError)?;
+        out.flush().map_err(FgError::IoError)
+    }
+
+    /// Loads an index previously written with [`LineIndex::write`].  Fails if the indexed file
+    /// no longer exists or its size has changed since the index was built.
+    pub fn load<P: AsRef<Path>>(io: &Io, p: &P) -> Result<LineIndex> {
+        let invalid = |msg: &str| {
+            let msg = format!("Invalid line index {}: {}", p.as_ref().display(), msg);
+            FgError::IoError(std::io::Error::new(ErrorKind::InvalidData, msg))
+        };
+
+        let mut r = io.new_reader(p)?;
+        let mut magic = [0u8; 8];
+        r.read_exact(&mut magic).map_err(|_| invalid("file is truncated"))?;
+        if &magic != INDEX_MAGIC {
+            return Err(invalid("unrecognized file format"));
+        }
+
+        let read_u64 = |r: &mut dyn Read| -> Result<u64> {
+            let mut bytes = [0u8; 8];
+            r.read_exact(&mut bytes).map_err(|_| invalid("file is truncated"))?;
+            Ok(u64::from_le_bytes(bytes))
+        };
+
+        let mut format = [0u8; 1];
+        r.read_exact(&mut format).map_err(FgError::IoError)?;
+        let format = match format[0] {
+            0 => IndexedFormat::Plain,
+            1 => IndexedFormat::Bgzf,
+            _ => return Err(invalid("unrecognized indexed file format")),
+        };
+        let every = read_u64(&mut r)?;
+        let line_count = read_u64(&mut r)?;
+        let file_len = read_u64(&mut r)?;
+        let path_len = read_u64(&mut r)?;
+        let offset_count = read_u64(&mut r)?;
+        if every == 0 || offset_count != line_count / every + u64::from(line_count % every != 0) {
+            return Err(invalid("inconsistent line and offset counts"));
+        }
+        let offsets = (0..offset_count).map(|_| read_u64(&mut r)).collect::<Result<Vec<_>>>()?;
+
+        let mut path = String::new();
+        r.take(path_len).read_to_string(&mut path).map_err(FgError::IoError)?;
+        if path.len() as u64 != path_len {
+            return Err(invalid("file is truncated"));
+        }
+        let path = PathBuf::from(path);
+
+        if std::fs::metadata(&path).map_err(FgError::IoError)?.len() != file_len {
+            let msg = format!("Indexed file has changed since it was indexed: {}", path.display());
+            return Err(FgError::IoError(std::io::Error::new(ErrorKind::InvalidData, msg)));
+        }
+
+        let buffer_size = io.buffer_size;
+        Ok(LineIndex { path, format, every, offsets, line_count, file_len, buffer_size })
+    }
+
+    /// The path of the indexed file.
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// The number of lines in the indexed file.
+    pub fn len(&self) -> u64 {
+        self.line_count
+    }
+
+    /// Returns true if the indexed file contains no lines.
+    pub fn is_empty(&self) -> bool {
+        self.line_count == 0
+    }
+
+    /// Reads the line with the given zero-based index.
+    pub fn read_line(&self, i: u64) -> Result<String> {
+        Ok(self.read_range(i..i + 1)?.remove(0))
+    }
+
+    /// Reads the lines within the given range of zero-based line indices.
+    pub fn read_range(&self, range: Range<u64>) -> Result<Vec<String>> {
+        if range.end > self.line_count || range.start > range.end {
+            let msg = format!(
+                "Line range {}..{} is out of bounds for file with {} lines",
+                range.start, range.end, self.line_count
+            );
+            return Err(FgError::IoError(std::io::Error::new(ErrorKind::InvalidInput, msg)));
+        } else if range.is_empty() {
+            return Ok(Vec::new());
+        }
+
+        let nearest = range.start / self.every;
+        let mut source = Source::open(&self.path, self.format, self.buffer_size)?;
+        source.seek(self.offsets[nearest as usize])?;
+
+        let mut line = String::new();
+        for _ in nearest * self.every..range.start {
+            source.read_line(&mut line)?;
+        }
+
+        let mut lines = Vec::with_capacity((range.end - range.start) as usize);
+        for _ in range {
+            if !source.read_line(&mut line)? {
+                let msg = "Indexed file is shorter than expected; has it been modified?";
+                return Err(FgError::IoError(std::io::Error::new(ErrorKind::UnexpectedEof, msg)));
+            }
+            lines.push(line.clone());
+        }
+        Ok(lines)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rstest::rstest;
+    use tempfile::TempDir;
+
+    #[rstest]
+    #[case("lines.txt", 1)]
+    #[case("lines.txt", 7)]
+    #[case("lines.bgz", 1)]
+    #[case("lines.bgz", 100)]
+    fn test_reading_lines_via_index(#[case] file_name: &str, #[case] every: u64) {
+        let tempdir = TempDir::new().unwrap();
+        let path = tempdir.path().join(file_name);
+        // Long enough lines to span several BGZF blocks
+        let lines: Vec<String> =
+            (0..5000).map(|i| format!("{i}\t{}", "x".repeat(i % 50))).collect();
+
+        let io = Io::default();
+        io.write_lines(&path, &lines).unwrap();
+        let index = LineIndex::build(&io, &path, every).unwrap();
+        assert_eq!(index.len(), 5000);
+
+        for i in [0, 1, 6, 7, 99, 100, 2500, 4999] {
+            assert_eq!(index.read_line(i).unwrap(), lines[i as usize]);
+        }
+        assert_eq!(index.read_range(95..205).unwrap(), lines[95..205]);
+        assert!(index.read_range(10..10).unwrap().is_empty());
+        assert!(index.read_line(5000).is_err());
+    }
+
+    #[rstest]
+    #[case("lines.txt.idx")]
+    #[case("lines.txt.idx.gz")]
+    fn test_writing_and_loading_index(#[case] index_name: &str) {
+        let tempdir = TempDir::new().unwrap();
+        let path = tempdir.path().join("lines.txt");
+        let index_path = tempdir.path().join(index_name);
+
+        let io = Io::default();
+        io.write_lines(&path, (0..1000).map(|i| format!("line {i}"))).unwrap();
+        let index = LineIndex::build(&io, &path, 3).unwrap();
+        index.write(&io, &index_path).unwrap();
+
+        let loaded = LineIndex::load(&io, &index_path).unwrap();
+        assert_eq!(loaded, index);
+        assert_eq!(loaded.read_line(500).unwrap(), "line 500");
+
+        // Changes to the indexed file are detected
+        io.append_lines(&path, ["more"]).unwrap();
+        assert!(LineIndex::load(&io, &index_path).is_err());
+    }
+
+    #[test]
+    fn test_loading_invalid_index_fails() {
+        let tempdir = TempDir::new().unwrap();
+        let index_path = tempdir.path().join("lines.txt.idx");
+        let io = Io::default();
+
+        std::fs::write(&index_path, "not an index").unwrap();
+        assert!(LineIndex::load(&io, &index_path).is_err());
+        std::fs::write(&index_path, &INDEX_MAGIC[..]).unwrap();
+        assert!(LineIndex::load(&io, &index_path).is_err());
+    }
+
+    #[test]
+    fn test_line_terminators_and_empty_files() {
+        let tempdir = TempDir::new().unwrap();
+        let path = tempdir.path().join("lines.txt");
+
+        let io = Io::default();
+        std::fs::write(&path, "a\r\n\nlast").unwrap();
+        let index = LineIndex::build(&io, &path, 1).unwrap();
+        assert_eq!(index.read_range(0..3).unwrap(), vec!["a", "", "last"]);
+
+        std::fs::write(&path, "").unwrap();
+        let index = LineIndex::build(&io, &path, 1).unwrap();
+        assert!(index.is_empty());
+        assert!(index.read_line(0).is_err());
+    }
+
+    #[test]
+    fn test_indexing_unsupported_files_fails() {
+        let tempdir = TempDir::new().unwrap();
+        let io = Io::default();
+
+        let zstd = tempdir.path().join("lines.txt.zst");
+        io.write_lines(&zstd, ["a"]).unwrap();
+        assert!(LineIndex::build(&io, &zstd, 1).is_err());
+
+        // Plain gzip, as opposed to BGZF, cannot be indexed
+        let gzip = tempdir.path().join("lines.txt.gz");
+        let mut encoder = flate2::write::GzEncoder::new(Vec::new(), flate2::Compression::fast());
+        std::io::Write::write_all(&mut encoder, b"a\n").unwrap();
+        std::fs::write(&gzip, encoder.finish().unwrap()).unwrap();
+        assert!(LineIndex::build(&io, &gzip, 1).is_err());
+    }
+}
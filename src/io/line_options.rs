@@ -0,0 +1,90 @@
+//! Options controlling how lines are post-processed when read.
+
+/// Options for [`Io::read_lines_with_opts`](crate::io::Io::read_lines_with_opts), which control
+/// trimming and skipping of lines as they are read.  By default lines are returned unchanged.
+///
+/// Trimming is applied first, so lines containing only whitespace are considered empty, and
+/// prefixes are matched against trimmed lines, when trimming is enabled.
+///
+/// ```rust
+/// use fgoxide::io::LineOptions;
+///
+/// let opts = LineOptions::default().trim_whitespace(true).skip_empty(true).skip_prefix("#");
+/// assert_eq!(opts.apply("  value  ".to_string()), Some("value".to_string()));
+/// assert_eq!(opts.apply("  # comment".to_string()), None);
+/// assert_eq!(opts.apply("   ".to_string()), None);
+/// ```
+#[derive(Clone, Debug, Default)]
+pub struct LineOptions {
+    trim_whitespace: bool,
+    skip_empty: bool,
+    skip_prefixes: Vec<String>,
+}
+
+impl LineOptions {
+    /// If true, leading and trailing whitespace is removed from each line.
+    pub fn trim_whitespace(mut self, trim: bool) -> LineOptions {
+        self.trim_whitespace = trim;
+        self
+    }
+
+    /// If true, empty lines are skipped.
+    pub fn skip_empty(mut self, skip: bool) -> LineOptions {
+        self.skip_empty = skip;
+        self
+    }
+
+    /// Adds a prefix, such as `#`, that marks lines to be skipped.  May be called more than
+    /// once to skip lines starting with any of several prefixes.
+    pub fn skip_prefix(mut self, prefix: &str) -> LineOptions {
+        self.skip_prefixes.push(prefix.to_string());
+        self
+    }
+
+    /// Applies the options to a line, returning the processed line or `None` if it should be
+    /// skipped.
+    pub fn apply(&self, line: String) -> Option<String> {
+        let line = if self.trim_whitespace {
+            let trimmed = line.trim();
+            if trimmed.len() == line.len() {
+                line
+            } else {
+                trimmed.to_string()
+            }
+        } else {
+            line
+        };
+
+        if (self.skip_empty && line.is_empty())
+            || self.skip_prefixes.iter().any(|p| line.starts_with(p.as_str()))
+        {
+            None
+        } else {
+            Some(line)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rstest::rstest;
+
+    #[rstest]
+    #[case(LineOptions::default(), vec!["  a ", "", "#b", "  ", "//c", "d"])]
+    #[case(LineOptions::default().trim_whitespace(true), vec!["a", "", "#b", "", "//c", "d"])]
+    #[case(LineOptions::default().skip_empty(true), vec!["  a ", "#b", "  ", "//c", "d"])]
+    #[case(
+        LineOptions::default().trim_whitespace(true).skip_empty(true),
+        vec!["a", "#b", "//c", "d"]
+    )]
+    #[case(
+        LineOptions::default().skip_prefix("#").skip_prefix("//"),
+        vec!["  a ", "", "  ", "d"]
+    )]
+    fn test_applying_options(#[case] opts: LineOptions, #[case] expected: Vec<&str>) {
+        let lines = ["  a ", "", "#b", "  ", "//c", "d"];
+        let actual: Vec<String> = lines.iter().filter_map(|l| opts.apply(l.to_string())).collect();
+        assert_eq!(actual, expected);
+    }
+}
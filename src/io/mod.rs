@@ -5,8 +5,25 @@
 //! objects to a path.
 //!
 //! The two core parts of this module are the [`Io`] and [`DelimFile`] structs. These structs provide
-//! methods for reading and writing to files that transparently handle compression based on the
-//! file extension of the path given to the methods.
+//! methods for reading and writing to files that transparently handle compression (gzip and
+//! zstd) based on the file extension of the path given to the methods.  Support for bzip2 and xz
+//! is available by enabling the `bzip2` and `xz` features, for the lz4 frame format by enabling
+//! the `lz4` feature, and for the snappy framing format (`.sz`) by enabling the `snappy`
+//! feature.  Enabling the `gzp` feature allows gzip and
+//! BGZF output to be compressed on multiple threads (see [`IoBuilder::threads`]), and enabling
+//! the `mmap` feature adds `Io::new_mmap_reader` for reading files via memory mapping.  The
+//! `encoding` feature allows text in encodings other than UTF-8 to be read and written (see
+//! `IoBuilder::encoding`), and the `indicatif` feature adds readers and writers that report
+//! their progress on an `indicatif` progress bar.  With the `http` feature, `http://` and
+//! `https://` URLs can be read like files, and with the `cloud` feature `s3://` and `gs://`
+//! URLs can be both read and written.  The `rayon` feature adds [`DelimFile`] methods that
+//! deserialize records on multiple threads, and the `chrono` feature adds the `dates` module
+//! for reading dates and times in common formats into `chrono` types.  The `json` feature
+//! allows [`DelimFile::convert`] to read and write JSON Lines, and the `indexmap` feature adds
+//! [`DelimFile`] methods that read and write records as ordered maps from column names to
+//! values.  The `rand` feature adds seeded random sampling of lines, the `log` feature adds
+//! `ProgressReader` for logging the progress of long-running reads, and the `checksum` feature
+//! allows checksum sidecar files to be written alongside outputs (see `IoBuilder::checksum`).
 //!
 //! ## Example
 //!
@@ -41,364 +58,6265 @@
 //!     Ok(())
 //! }
 //! ```
-use std::fs::File;
-use std::io::{BufRead, BufReader, BufWriter, Write};
-use std::path::Path;
+mod bgzf;
+mod bom;
+#[cfg(feature = "checksum")]
+mod checksum;
+#[cfg(feature = "cloud")]
+mod cloud;
+mod convert;
+mod counting;
+#[cfg(feature = "chrono")]
+pub mod dates;
+mod dedup;
+mod delim;
+#[cfg(feature = "encoding")]
+mod encoding;
+mod finish;
+mod fixed_width;
+mod follow;
+mod format;
+#[cfg(feature = "http")]
+mod http;
+mod join;
+mod limits;
+mod line_index;
+mod line_options;
+mod multi;
+mod multi_writer;
+#[cfg(feature = "log")]
+mod progress;
+mod rolling;
+#[cfg(feature = "indexmap")]
+mod rows;
+mod schema;
+mod sharded;
+mod sniff;
+mod sort;
+mod split;
+mod tee;
+mod threaded;
+mod whitespace;
+
+pub use bgzf::{BgzfReader, BgzfWriter, VirtualOffset, BGZF_EOF};
+pub use bom::BomStrippingReader;
+#[cfg(feature = "checksum")]
+pub use checksum::ChecksumAlgorithm;
+pub use convert::TableFormat;
+pub use counting::{ByteCounter, ByteCounts, CountingReader, CountingWriter};
+pub use csv::{ByteRecord, QuoteStyle, StringRecord, Trim};
+#[cfg(feature = "chrono")]
+pub use dates::DateFormat;
+pub use delim::{
+    BadRecord, DelimFileChunks, DelimFileReader, DelimFileWriter, DuplicateColumns, DuplicateKeys,
+    ExtraColumns, RawDelimReader, RecordBuffers,
+};
+#[cfg(feature = "encoding")]
+pub use encoding_rs::Encoding;
+pub use finish::FinishWrite;
+pub use fixed_width::{FixedWidthFile, FixedWidthFileBuilder, FixedWidthReader};
+pub use follow::FollowReader;
+pub use format::FloatFormat;
+#[cfg(feature = "indexmap")]
+pub use indexmap::IndexMap;
+#[cfg(feature = "indicatif")]
+pub use indicatif::ProgressBar;
+pub use join::JoinKind;
+pub use limits::{MaxBytesReader, MaxLineLengthReader};
+pub use line_index::LineIndex;
+pub use line_options::LineOptions;
+pub use multi::MultiReader;
+pub use multi_writer::MultiWriter;
+#[cfg(feature = "log")]
+pub use progress::{ProgressInterval, ProgressReader};
+pub use rolling::{RollingLimit, RollingWriter};
+#[cfg(feature = "indexmap")]
+pub use rows::{Row, RowReader};
+pub use schema::{
+    ColumnSpec, ColumnType, InferredColumn, Schema, ValidationReport, Violation, ViolationKind,
+};
+pub use sharded::ShardedWriter;
+pub use sniff::SniffedFormat;
+pub use sort::SortKey;
+pub use tee::TeeWriter;
+pub use threaded::{ThreadedReader, ThreadedWriter};
+pub use whitespace::WhitespaceReader;
+
+use std::collections::{HashMap, VecDeque};
+use std::fs::{File, OpenOptions};
+use std::io::{BufRead, BufReader, BufWriter, ErrorKind, Read, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU64, AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
 
 use crate::{FgError, Result};
-use csv::{QuoteStyle, ReaderBuilder, WriterBuilder};
+#[cfg(feature = "bzip2")]
+use bzip2::bufread::MultiBzDecoder;
+#[cfg(feature = "bzip2")]
+use bzip2::write::BzEncoder;
+#[cfg(feature = "checksum")]
+use checksum::ChecksumWriter;
+use csv::{ReaderBuilder, WriterBuilder};
+use delim::ColumnParser;
+use finish::Finishing;
 use flate2::bufread::MultiGzDecoder;
 use flate2::write::GzEncoder;
 use flate2::Compression;
+use format::WriteFormat;
+#[cfg(feature = "rand")]
+use rand::{Rng, SeedableRng};
+#[cfg(feature = "rand")]
+use rand_chacha::ChaCha8Rng;
 use serde::{de::DeserializeOwned, Serialize};
+use split::{Layout, Quoting, SplitOptions};
+#[cfg(feature = "xz")]
+use xz2::bufread::XzDecoder;
+#[cfg(feature = "xz")]
+use xz2::write::XzEncoder;
 use zstd::stream::{Decoder as ZstdDecoder, Encoder as ZstdEncoder};
 
 /// The default buffer size when creating buffered readers/writers
 const BUFFER_SIZE: usize = 64 * 1024;
 
-/// The set of file extensions to treat as FASTQ, GZIPPED, or ZSTD
+/// The default compression level used for gzip (and bzip2) outputs
+const COMPRESSION_LEVEL: u32 = 5;
+
+/// The default preset used when writing xz files; matches the `xz` command line default
+#[cfg(feature = "xz")]
+const XZ_PRESET: u32 = 6;
+
+/// The default level used when writing zstd files; zero selects zstd's own default level
+const ZSTD_LEVEL: i32 = 0;
+
+/// The number of chunks that may be queued between a threaded reader or writer and its thread
+const THREADED_QUEUE_SIZE: usize = 4;
+
+/// The paths that are treated as standard input and standard output respectively
+const STDIN_PATHS: [&str; 2] = ["-", "/dev/stdin"];
+const STDOUT_PATHS: [&str; 2] = ["-", "/dev/stdout"];
+
+/// The paths that are treated as a null device, which discards writes and is empty when read, on
+/// all platforms
+const NULL_PATHS: [&str; 1] = ["/dev/null"];
+
+/// The URL schemes that are read over HTTP when the `http` feature is enabled
+const URL_SCHEMES: [&str; 2] = ["http://", "https://"];
+
+/// The set of file extensions to treat as FASTQ, GZIPPED, BGZF, ZSTD, BZIP2 or XZ.  BGZF files are
+/// valid gzip files and are read as such, but are written with a true BGZF block structure.
 const FASTQ_EXTENSIONS: [&str; 2] = ["fastq", "fq"];
 const GZIP_EXTENSIONS: [&str; 2] = ["gz", "bgz"];
+const BGZF_EXTENSIONS: [&str; 1] = ["bgz"];
 const ZSTD_EXTENSIONS: [&str; 1] = ["zst"];
+#[cfg(feature = "bzip2")]
+const BZIP2_EXTENSIONS: [&str; 1] = ["bz2"];
+#[cfg(feature = "xz")]
+const XZ_EXTENSIONS: [&str; 1] = ["xz"];
+#[cfg(feature = "lz4")]
+const LZ4_EXTENSIONS: [&str; 1] = ["lz4"];
+#[cfg(feature = "snappy")]
+const SNAPPY_EXTENSIONS: [&str; 1] = ["sz"];
+
+/// The compression formats that can be read and written by [`Io`].
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum CompressionFormat {
+    /// Uncompressed data
+    None,
+    /// Gzip, read with support for multiple members
+    Gzip,
+    /// Blocked gzip; read as gzip but written with a BGZF block structure
+    Bgzf,
+    /// Zstandard
+    Zstd,
+    /// Bzip2, read with support for multiple streams
+    #[cfg(feature = "bzip2")]
+    Bzip2,
+    /// Xz (LZMA2), read with support for multiple streams
+    #[cfg(feature = "xz")]
+    Xz,
+    /// The lz4 frame format
+    #[cfg(feature = "lz4")]
+    Lz4,
+    /// The snappy framing format
+    #[cfg(feature = "snappy")]
+    Snappy,
+}
+
+impl CompressionFormat {
+    /// Returns the default mapping of file extensions to compression formats.
+    fn default_extensions() -> Vec<(String, CompressionFormat)> {
+        let mut extensions = vec![];
+        let mut add = |exts: &[&str], format| {
+            for ext in exts {
+                extensions.push((ext.to_string(), format));
+            }
+        };
+
+        add(&BGZF_EXTENSIONS, CompressionFormat::Bgzf);
+        let gzip: Vec<&str> =
+            GZIP_EXTENSIONS.into_iter().filter(|e| !BGZF_EXTENSIONS.contains(e)).collect();
+        add(&gzip, CompressionFormat::Gzip);
+        add(&ZSTD_EXTENSIONS, CompressionFormat::Zstd);
+        #[cfg(feature = "bzip2")]
+        add(&BZIP2_EXTENSIONS, CompressionFormat::Bzip2);
+        #[cfg(feature = "xz")]
+        add(&XZ_EXTENSIONS, CompressionFormat::Xz);
+        #[cfg(feature = "lz4")]
+        add(&LZ4_EXTENSIONS, CompressionFormat::Lz4);
+        #[cfg(feature = "snappy")]
+        add(&SNAPPY_EXTENSIONS, CompressionFormat::Snappy);
+        extensions
+    }
+
+    /// Returns the canonical file extension (without a leading `.`) used for files in this
+    /// format, or `None` for uncompressed data.
+    pub fn extension(&self) -> Option<&'static str> {
+        match self {
+            CompressionFormat::None => None,
+            CompressionFormat::Gzip => Some(GZIP_EXTENSIONS[0]),
+            CompressionFormat::Bgzf => Some(BGZF_EXTENSIONS[0]),
+            CompressionFormat::Zstd => Some(ZSTD_EXTENSIONS[0]),
+            #[cfg(feature = "bzip2")]
+            CompressionFormat::Bzip2 => Some(BZIP2_EXTENSIONS[0]),
+            #[cfg(feature = "xz")]
+            CompressionFormat::Xz => Some(XZ_EXTENSIONS[0]),
+            #[cfg(feature = "lz4")]
+            CompressionFormat::Lz4 => Some(LZ4_EXTENSIONS[0]),
+            #[cfg(feature = "snappy")]
+            CompressionFormat::Snappy => Some(SNAPPY_EXTENSIONS[0]),
+        }
+    }
+
+    /// Determines the compression format from the leading bytes of a file, returning `None`
+    /// if the bytes do not match the magic number of any supported format.
+    fn from_magic(bytes: &[u8]) -> CompressionFormat {
+        #[cfg(feature = "lz4")]
+        if bytes.starts_with(&[0x04, 0x22, 0x4d, 0x18]) {
+            return CompressionFormat::Lz4;
+        }
+        #[cfg(feature = "snappy")]
+        if bytes.starts_with(b"\xff\x06\x00\x00sNaPpY") {
+            return CompressionFormat::Snappy;
+        }
+        #[cfg(feature = "bzip2")]
+        if bytes.starts_with(b"BZh") {
+            return CompressionFormat::Bzip2;
+        }
+        #[cfg(feature = "xz")]
+        if bytes.starts_with(b"\xfd7zXZ\x00") {
+            return CompressionFormat::Xz;
+        }
+
+        if bytes.starts_with(&[0x1f, 0x8b]) {
+            CompressionFormat::Gzip
+        } else if bytes.starts_with(&[0x28, 0xb5, 0x2f, 0xfd]) {
+            CompressionFormat::Zstd
+        } else {
+            CompressionFormat::None
+        }
+    }
+}
+
+/// The terminators that can be written at the end of each line by [`Io`] and [`DelimFile`].
+/// Readers always accept both `\n` and `\r\n`, and strip them from the lines returned.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub enum LineTerminator {
+    /// Unix-style `\n` line endings
+    #[default]
+    Lf,
+    /// Windows-style `\r\n` line endings
+    CrLf,
+    /// Lines are terminated with an arbitrary byte
+    Custom(u8),
+}
+
+impl LineTerminator {
+    /// Returns the bytes written at the end of each line.
+    pub fn as_bytes(&self) -> &[u8] {
+        match self {
+            LineTerminator::Lf => b"\n",
+            LineTerminator::CrLf => b"\r\n",
+            LineTerminator::Custom(b) => std::slice::from_ref(b),
+        }
+    }
+
+    /// Returns the equivalent terminator for writing delimited files.
+    fn to_csv(self) -> csv::Terminator {
+        match self {
+            LineTerminator::Lf => csv::Terminator::Any(b'\n'),
+            LineTerminator::CrLf => csv::Terminator::CRLF,
+            LineTerminator::Custom(b) => csv::Terminator::Any(b),
+        }
+    }
+}
+
+/// The ways in which [`Io::read_lines_sampled`] can select lines from a file.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum LineSampling {
+    /// Keeps every nth line, starting with the first
+    EveryNth(u64),
+    /// Keeps each line independently with the given probability, using a random number
+    /// generator seeded with `seed` so that samples are reproducible, including across platforms
+    #[cfg(feature = "rand")]
+    Probability { p: f64, seed: u64 },
+}
 
 /// Unit-struct that contains associated functions for reading and writing Structs to/from
 /// unstructured files.
 pub struct Io {
     compression: Compression,
     buffer_size: usize,
+    #[cfg(feature = "xz")]
+    xz_preset: u32,
+    zstd_level: i32,
+    zstd_long_distance_matching: bool,
+    threads: usize,
+    detect_compression: bool,
+    create_parent_dirs: bool,
+    overwrite: bool,
+    #[cfg(feature = "checksum")]
+    checksum: Option<ChecksumAlgorithm>,
+    max_line_length: Option<usize>,
+    max_decompressed_bytes: Option<u64>,
+    strip_bom: bool,
+    line_terminator: LineTerminator,
+    #[cfg(feature = "encoding")]
+    encoding: Option<&'static Encoding>,
+    extensions: Vec<(String, CompressionFormat)>,
 }
 
 /// Returns a Default implementation that will compress to gzip level 5.
 impl Default for Io {
     fn default() -> Self {
-        Io::new(5, BUFFER_SIZE)
+        Io::builder().build()
+    }
+}
+
+/// Builder for [`Io`] instances that need settings beyond those accepted by [`Io::new`].
+///
+/// ```rust
+/// use fgoxide::io::Io;
+///
+/// let io = Io::builder().compression(9).buffer_size(128 * 1024).build();
+/// ```
+pub struct IoBuilder {
+    compression: u32,
+    buffer_size: usize,
+    #[cfg(feature = "xz")]
+    xz_preset: u32,
+    zstd_level: i32,
+    zstd_long_distance_matching: bool,
+    threads: usize,
+    detect_compression: bool,
+    create_parent_dirs: bool,
+    overwrite: bool,
+    #[cfg(feature = "checksum")]
+    checksum: Option<ChecksumAlgorithm>,
+    max_line_length: Option<usize>,
+    max_decompressed_bytes: Option<u64>,
+    strip_bom: bool,
+    line_terminator: LineTerminator,
+    #[cfg(feature = "encoding")]
+    encoding: Option<&'static Encoding>,
+    extensions: Vec<(String, CompressionFormat)>,
+}
+
+impl Default for IoBuilder {
+    fn default() -> Self {
+        IoBuilder {
+            compression: COMPRESSION_LEVEL,
+            buffer_size: BUFFER_SIZE,
+            #[cfg(feature = "xz")]
+            xz_preset: XZ_PRESET,
+            zstd_level: ZSTD_LEVEL,
+            zstd_long_distance_matching: false,
+            threads: 1,
+            detect_compression: false,
+            create_parent_dirs: false,
+            overwrite: true,
+            #[cfg(feature = "checksum")]
+            checksum: None,
+            max_line_length: None,
+            max_decompressed_bytes: None,
+            strip_bom: true,
+            line_terminator: LineTerminator::Lf,
+            #[cfg(feature = "encoding")]
+            encoding: None,
+            extensions: CompressionFormat::default_extensions(),
+        }
+    }
+}
+
+impl IoBuilder {
+    /// Sets the compression level used when writing gzip (and bzip2) files.
+    pub fn compression(mut self, compression: u32) -> IoBuilder {
+        self.compression = compression;
+        self
+    }
+
+    /// Sets the size of the buffers used when creating buffered readers/writers.
+    pub fn buffer_size(mut self, buffer_size: usize) -> IoBuilder {
+        self.buffer_size = buffer_size;
+        self
+    }
+
+    /// Sets the preset (0-9) used when writing xz files.  Higher presets compress better but
+    /// are slower and use considerably more memory.
+    ///
+    /// # Panics
+    /// - panics if `preset` is greater than 9
+    #[cfg(feature = "xz")]
+    pub fn xz_preset(mut self, preset: u32) -> IoBuilder {
+        assert!(preset <= 9, "xz preset must be between 0 and 9, found {preset}");
+        self.xz_preset = preset;
+        self
+    }
+
+    /// Sets the level used when writing zstd files.  Zero selects zstd's default level, and
+    /// negative levels trade compression ratio for speed.
+    ///
+    /// # Panics
+    /// - panics if `level` is outside the range supported by zstd
+    pub fn zstd_level(mut self, level: i32) -> IoBuilder {
+        let range = zstd::compression_level_range();
+        assert!(
+            level == 0 || range.contains(&level),
+            "zstd level must be between {} and {}, found {level}",
+            range.start(),
+            range.end()
+        );
+        self.zstd_level = level;
+        self
+    }
+
+    /// If true, zstd files are written with long-distance matching enabled, which can improve
+    /// compression of large files containing repeats far apart, at the cost of memory.
+    pub fn zstd_long_distance_matching(mut self, enabled: bool) -> IoBuilder {
+        self.zstd_long_distance_matching = enabled;
+        self
+    }
+
+    /// Sets the number of threads used to compress output.  Zstd output is always compressed
+    /// using zstd's native multi-threading, and gzip and BGZF output is compressed in parallel
+    /// when the `gzp` feature is enabled.  Other formats are always compressed on one thread.
+    ///
+    /// # Panics
+    /// - panics if `threads` is zero
+    pub fn threads(mut self, threads: usize) -> IoBuilder {
+        assert_ne!(threads, 0, "Number of threads cannot be zero!");
+        self.threads = threads;
+        self
+    }
+
+    /// If true, readers determine the compression format of a file by inspecting its leading
+    /// bytes rather than trusting its extension, so that misnamed files (e.g. gzipped data in a
+    /// `.txt` file) are still decoded correctly.  Writers always use the path's extension.
+    pub fn detect_compression(mut self, detect: bool) -> IoBuilder {
+        self.detect_compression = detect;
+        self
+    }
+
+    /// If true, writers create any missing parent directories of the path being written to,
+    /// rather than failing.
+    pub fn create_parent_dirs(mut self, create: bool) -> IoBuilder {
+        self.create_parent_dirs = create;
+        self
+    }
+
+    /// If false, writers refuse to replace existing files, returning [`FgError::FileExists`]
+    /// instead.  The check is made atomically as the file is created.  Appending to existing
+    /// files is unaffected.  Defaults to true.
+    pub fn overwrite(mut self, overwrite: bool) -> IoBuilder {
+        self.overwrite = overwrite;
+        self
+    }
+
+    /// Sets the algorithm used to write a checksum sidecar file alongside every file written,
+    /// e.g. `out.tsv.gz.md5` for `out.tsv.gz`.  The checksum is computed over the bytes written
+    /// to disk (i.e. after compression) and is written, in the format produced by `md5sum` and
+    /// `sha256sum`, when the writer is finished.  Nothing is written for standard output.
+    #[cfg(feature = "checksum")]
+    pub fn checksum(mut self, algorithm: ChecksumAlgorithm) -> IoBuilder {
+        self.checksum = Some(algorithm);
+        self
+    }
+
+    /// Sets the maximum length, in bytes, of lines in files read.  Readers fail with an error
+    /// on encountering a longer line, rather than exhausting memory when e.g. a binary file is
+    /// read as text.  Applies to all readers opened by [`Io`] other than [`BgzfReader`]s.
+    ///
+    /// # Panics
+    /// - panics if `max_line_length` is zero
+    pub fn max_line_length(mut self, max_line_length: usize) -> IoBuilder {
+        assert_ne!(max_line_length, 0, "Maximum line length cannot be zero!");
+        self.max_line_length = Some(max_line_length);
+        self
+    }
+
+    /// Sets the maximum number of bytes that may be read from any one file after decompression.
+    /// Readers fail with an error once the limit is exceeded, protecting services that read
+    /// untrusted inputs from small compressed files that expand to enormous sizes.  Combine with
+    /// [`IoBuilder::max_line_length`] to also limit the size of individual lines.  Applies to all
+    /// readers opened by [`Io`] other than [`BgzfReader`]s.
+    pub fn max_decompressed_bytes(mut self, max_bytes: u64) -> IoBuilder {
+        self.max_decompressed_bytes = Some(max_bytes);
+        self
+    }
+
+    /// If true, readers detect and remove a byte-order mark at the start of a file, as written
+    /// by many Windows tools, and convert UTF-16 files that start with a byte-order mark to
+    /// UTF-8.  Defaults to true; disable it when reading binary data that may happen to start
+    /// with the same bytes as a byte-order mark.
+    pub fn strip_bom(mut self, strip: bool) -> IoBuilder {
+        self.strip_bom = strip;
+        self
+    }
+
+    /// Sets the terminator written at the end of each line by [`Io::write_lines`],
+    /// [`Io::append_lines`], [`ShardedWriter`] and [`DelimFile`].  Defaults to `\n`; use
+    /// [`LineTerminator::CrLf`] for files consumed by Windows tools.
+    pub fn line_terminator(mut self, terminator: LineTerminator) -> IoBuilder {
+        self.line_terminator = terminator;
+        self
+    }
+
+    /// Sets the character encoding of text files read and written, e.g. UTF-16 or Windows-1252.
+    /// Readers decode files from the encoding into UTF-8, except that a byte-order mark at the
+    /// start of a file takes precedence over the configured encoding.  Writers encode the UTF-8
+    /// data written to them into the encoding, failing on characters the encoding cannot
+    /// represent.  Byte-order marks are not written.  Applies to all readers and writers opened by
+    /// [`Io`] other than BGZF readers and zstd dictionary writers.
+    ///
+    /// ```rust
+    /// use fgoxide::io::{Encoding, Io};
+    ///
+    /// let latin1 = Encoding::for_label(b"latin1").unwrap();
+    /// let io = Io::builder().encoding(latin1).build();
+    /// ```
+    #[cfg(feature = "encoding")]
+    pub fn encoding(mut self, encoding: &'static Encoding) -> IoBuilder {
+        self.encoding = Some(encoding);
+        self
+    }
+
+    /// Registers a file extension to be read and written using the given compression format,
+    /// replacing any existing mapping for the same extension.  Extensions may contain multiple
+    /// parts (e.g. `txt.gz2`), and may be given with or without a leading `.`.  When more than one
+    /// registered extension matches a path, the longest one is used.
+    ///
+    /// ```rust
+    /// use fgoxide::io::{CompressionFormat, Io};
+    ///
+    /// let io = Io::builder()
+    ///     .register_extension("bgzf", CompressionFormat::Bgzf)
+    ///     .register_extension(".txt.gz2", CompressionFormat::Gzip)
+    ///     .build();
+    /// assert_eq!(io.compression_format(&"reads.bgzf"), CompressionFormat::Bgzf);
+    /// assert_eq!(io.compression_format(&"notes.txt.gz2"), CompressionFormat::Gzip);
+    /// ```
+    pub fn register_extension(mut self, extension: &str, format: CompressionFormat) -> IoBuilder {
+        let extension = extension.trim_start_matches('.').to_string();
+        self.extensions.retain(|(ext, _)| *ext != extension);
+        self.extensions.push((extension, format));
+        self
+    }
+
+    /// Removes all registered extensions, including the defaults, so that only extensions
+    /// subsequently registered with [`IoBuilder::register_extension`] are recognized.
+    pub fn clear_extensions(mut self) -> IoBuilder {
+        self.extensions.clear();
+        self
+    }
+
+    /// Builds the configured [`Io`] instance.
+    pub fn build(self) -> Io {
+        Io {
+            compression: Compression::new(self.compression),
+            buffer_size: self.buffer_size,
+            #[cfg(feature = "xz")]
+            xz_preset: self.xz_preset,
+            zstd_level: self.zstd_level,
+            zstd_long_distance_matching: self.zstd_long_distance_matching,
+            threads: self.threads,
+            detect_compression: self.detect_compression,
+            create_parent_dirs: self.create_parent_dirs,
+            overwrite: self.overwrite,
+            #[cfg(feature = "checksum")]
+            checksum: self.checksum,
+            max_line_length: self.max_line_length,
+            max_decompressed_bytes: self.max_decompressed_bytes,
+            strip_bom: self.strip_bom,
+            line_terminator: self.line_terminator,
+            #[cfg(feature = "encoding")]
+            encoding: self.encoding,
+            extensions: self.extensions,
+        }
     }
 }
 
 impl Io {
     /// Creates a new Io instance with the given compression level.
     pub fn new(compression: u32, buffer_size: usize) -> Io {
-        Io { compression: flate2::Compression::new(compression), buffer_size }
+        Io::builder().compression(compression).buffer_size(buffer_size).build()
+    }
+
+    /// Returns a builder for configuring a new Io instance.
+    pub fn builder() -> IoBuilder {
+        IoBuilder::default()
     }
 
-    /// Opens a file for reading. Transparently handles decoding gzip and zstd files.
+    /// Returns a builder initialized with this instance's settings, for configuring a new Io
+    /// instance that differs from this one in a few settings.
+    pub fn to_builder(&self) -> IoBuilder {
+        IoBuilder {
+            compression: self.compression.level(),
+            buffer_size: self.buffer_size,
+            #[cfg(feature = "xz")]
+            xz_preset: self.xz_preset,
+            zstd_level: self.zstd_level,
+            zstd_long_distance_matching: self.zstd_long_distance_matching,
+            threads: self.threads,
+            detect_compression: self.detect_compression,
+            create_parent_dirs: self.create_parent_dirs,
+            overwrite: self.overwrite,
+            #[cfg(feature = "checksum")]
+            checksum: self.checksum,
+            max_line_length: self.max_line_length,
+            max_decompressed_bytes: self.max_decompressed_bytes,
+            strip_bom: self.strip_bom,
+            line_terminator: self.line_terminator,
+            #[cfg(feature = "encoding")]
+            encoding: self.encoding,
+            extensions: self.extensions.clone(),
+        }
+    }
+
+    /// Opens a file for reading. Transparently handles decoding gzip, zstd, bzip2 and xz files.
+    /// The compression format is chosen based on the file extension, or by inspecting the
+    /// leading bytes of the file if the `Io` was built with [`IoBuilder::detect_compression`].
+    /// The paths `-` and `/dev/stdin` read from standard input, which is treated as uncompressed
+    /// unless compression detection is enabled.  With the `http` feature, `http://` and
+    /// `https://` URLs are streamed from the web and decompressed in the same way as files, and
+    /// with the `cloud` feature so are `s3://` and `gs://` URLs.
     pub fn new_reader<P>(&self, p: &P) -> Result<Box<dyn BufRead + Send>>
     where
         P: AsRef<Path>,
     {
-        let file = File::open(p).map_err(FgError::IoError)?;
-        let buf = BufReader::with_capacity(self.buffer_size, file);
+        let mut buf = self.open_read(p)?;
+        let format = self.read_format(p, &mut buf)?;
+        self.decode(buf, format)
+    }
 
-        if Self::is_gzip_path(p) {
-            Ok(Box::new(BufReader::with_capacity(self.buffer_size, MultiGzDecoder::new(buf))))
-        } else if Self::is_zstd_path(p) {
-            Ok(Box::new(BufReader::with_capacity(
-                self.buffer_size,
-                ZstdDecoder::new(buf).map_err(FgError::IoError)?,
-            )))
+    /// Determines the compression format of a file being read, either from its leading bytes
+    /// or from its extension depending on how this `Io` was configured.
+    fn read_format<P, R>(&self, p: &P, buf: &mut R) -> Result<CompressionFormat>
+    where
+        P: AsRef<Path>,
+        R: BufRead,
+    {
+        if self.detect_compression {
+            Ok(CompressionFormat::from_magic(buf.fill_buf().map_err(FgError::IoError)?))
         } else {
-            Ok(Box::new(buf))
+            Ok(self.compression_format(p))
         }
     }
 
-    /// Opens a file for writing. Transparently handles encoding data in gzip and zstd formats.
-    pub fn new_writer<P>(&self, p: &P) -> Result<BufWriter<Box<dyn Write + Send>>>
+    /// Opens a file (or standard input) for reading, decoding it with the given compression
+    /// format regardless of the path's extension.
+    pub fn new_reader_with_format<P>(
+        &self,
+        p: &P,
+        format: CompressionFormat,
+    ) -> Result<Box<dyn BufRead + Send>>
     where
         P: AsRef<Path>,
     {
-        let file = File::create(p).map_err(FgError::IoError)?;
-        let write: Box<dyn Write + Send> = if Io::is_gzip_path(p) {
-            Box::new(GzEncoder::new(file, self.compression))
-        } else if Io::is_zstd_path(p) {
-            Box::new(ZstdEncoder::new(file, 0).map_err(FgError::IoError)?.auto_finish())
-        } else {
-            Box::new(file)
-        };
-
-        Ok(BufWriter::with_capacity(self.buffer_size, write))
+        let buf = self.open_read(p)?;
+        self.decode(buf, format)
     }
 
-    /// Reads lines from a file into a Vec
-    pub fn read_lines<P>(&self, p: &P) -> Result<Vec<String>>
+    /// Opens a file for reading as with [`Io::new_reader`], along with a [`ByteCounts`] that
+    /// tracks the number of bytes read both before and after decompression.
+    pub fn new_counting_reader<P>(&self, p: &P) -> Result<(Box<dyn BufRead + Send>, ByteCounts)>
     where
         P: AsRef<Path>,
     {
-        let r = self.new_reader(p)?;
-        let mut v = Vec::new();
-        for result in r.lines() {
-            v.push(result.map_err(FgError::IoError)?);
-        }
+        let read = CountingReader::new(self.open_read(p)?.into_inner());
+        let compressed = read.counter();
+        let mut buf = BufReader::with_capacity(self.buffer_size, read);
+        let format = self.read_format(p, &mut buf)?;
 
-        Ok(v)
+        let reader = CountingReader::new(self.decode(buf, format)?);
+        let counts = ByteCounts::new(compressed, reader.counter());
+        Ok((Box::new(reader), counts))
     }
 
-    /// Writes all the lines from an iterable of string-like values to a file, separated by new lines.
-    pub fn write_lines<P, S>(&self, p: &P, lines: impl IntoIterator<Item = S>) -> Result<()>
+    /// Opens a file for reading as with [`Io::new_reader`], but performs reading and
+    /// decompression on a background thread using a [`ThreadedReader`].  A few chunks of the
+    /// configured buffer size are decoded ahead of the caller.
+    pub fn new_threaded_reader<P>(&self, p: &P) -> Result<Box<dyn BufRead + Send>>
     where
         P: AsRef<Path>,
-        S: AsRef<str>,
     {
-        let mut out = self.new_writer(p)?;
-        for line in lines {
-            out.write_all(line.as_ref().as_bytes()).map_err(FgError::IoError)?;
-            out.write_all(&[b'\n']).map_err(FgError::IoError)?;
-        }
-
-        out.flush().map_err(FgError::IoError)
+        let reader = self.new_reader(p)?;
+        Ok(Box::new(ThreadedReader::new(reader, self.buffer_size, THREADED_QUEUE_SIZE)))
     }
 
-    /// Returns true if the path ends with a recognized file extension
-    fn is_path_with_extension<P: AsRef<Path>, const N: usize>(
+    /// Opens an uncompressed file for reading with a [`FollowReader`], which waits for more data
+    /// to be appended to the file when it reaches the end, polling every `interval`.  End of file
+    /// is only reported once no data has been appended for `idle_timeout`, or never if no
+    /// timeout is given.  Compressed files cannot be followed and are rejected.
+    pub fn new_follow_reader<P>(
+        &self,
         p: &P,
-        extensions: [&str; N],
-    ) -> bool {
-        if let Some(ext) = p.as_ref().extension() {
-            match ext.to_str() {
-                Some(x) => extensions.contains(&x),
-                None => false,
-            }
-        } else {
-            false
+        interval: Duration,
+        idle_timeout: Option<Duration>,
+    ) -> Result<Box<dyn BufRead + Send>>
+    where
+        P: AsRef<Path>,
+    {
+        if self.compression_format(p) != CompressionFormat::None {
+            let msg = format!("Cannot follow compressed file: {}", p.as_ref().display());
+            return Err(FgError::IoError(std::io::Error::new(ErrorKind::InvalidInput, msg)));
         }
-    }
 
-    /// Returns true if the path ends with a recognized FASTQ file extension
-    pub fn is_fastq_path<P: AsRef<Path>>(p: &P) -> bool {
-        Self::is_path_with_extension(p, FASTQ_EXTENSIONS)
+        let read = self.open_read(p)?.into_inner();
+        let follow = FollowReader::new(read, interval, idle_timeout);
+        Ok(Box::new(BufReader::with_capacity(self.buffer_size, follow)))
     }
 
-    /// Returns true if the path ends with a recognized GZIP file extension
-    pub fn is_gzip_path<P: AsRef<Path>>(p: &P) -> bool {
-        Self::is_path_with_extension(p, GZIP_EXTENSIONS)
+    /// Opens a file for reading as with [`Io::new_reader`], logging progress every `interval`
+    /// lines or bytes (measured after decompression) using a [`ProgressReader`].
+    #[cfg(feature = "log")]
+    pub fn new_progress_reader<P>(
+        &self,
+        p: &P,
+        interval: ProgressInterval,
+    ) -> Result<Box<dyn BufRead + Send>>
+    where
+        P: AsRef<Path>,
+    {
+        let name = p.as_ref().display().to_string();
+        let progress = ProgressReader::new(self.new_reader(p)?, &name, interval);
+        Ok(Box::new(BufReader::with_capacity(self.buffer_size, progress)))
     }
 
-    /// Returns true if the path ends with a recognized ZSTD file extension
-    pub fn is_zstd_path<P: AsRef<Path>>(p: &P) -> bool {
-        Self::is_path_with_extension(p, ZSTD_EXTENSIONS)
-    }
-}
+    /// Opens a file for reading as with [`Io::new_reader`], along with a [`ProgressBar`] that
+    /// tracks the number of bytes read after decompression.  For uncompressed files the bar's
+    /// length is taken from the file's size; otherwise the bar is a spinner showing the number
+    /// of bytes read so far.
+    #[cfg(feature = "indicatif")]
+    pub fn new_reader_with_progress_bar<P>(
+        &self,
+        p: &P,
+    ) -> Result<(Box<dyn BufRead + Send>, ProgressBar)>
+    where
+        P: AsRef<Path>,
+    {
+        let mut buf = self.open_read(p)?;
+        let format = self.read_format(p, &mut buf)?;
+        let len = if format == CompressionFormat::None
+            && !Self::is_stdin_path(p)
+            && !Self::is_null_path(p)
+        {
+            std::fs::metadata(p).ok().map(|m| m.len())
+        } else {
+            None
+        };
 
-/// Unit-struct that contains associated functions for reading and writing Structs to/from
-/// delimited files.  Structs should use serde's Serialize/Deserialize derive macros in
-/// order to be used with these functions.
-pub struct DelimFile {
-    io: Io,
-}
+        let bar = Self::new_progress_bar(len);
+        let reader = bar.wrap_read(self.decode(buf, format)?);
+        Ok((Box::new(BufReader::with_capacity(self.buffer_size, reader)), bar))
+    }
 
-/// Generates a default implementation that uses the default Io instance
-impl Default for DelimFile {
-    fn default() -> Self {
-        DelimFile { io: Io::default() }
+    /// Creates a progress bar of the given length in bytes, or a spinner if the length is
+    /// unknown.
+    #[cfg(feature = "indicatif")]
+    fn new_progress_bar(len: Option<u64>) -> ProgressBar {
+        use indicatif::ProgressStyle;
+        match len {
+            Some(len) => ProgressBar::new(len).with_style(
+                ProgressStyle::with_template(
+                    "{bytes}/{total_bytes} [{wide_bar}] {bytes_per_sec}, eta {eta}",
+                )
+                .expect("valid progress bar template"),
+            ),
+            None => ProgressBar::new_spinner().with_style(
+                ProgressStyle::with_template("{spinner} {bytes} ({bytes_per_sec})")
+                    .expect("valid progress bar template"),
+            ),
+        }
     }
-}
 
-impl DelimFile {
-    /// Writes a series of one or more structs to a delimited file.  If `quote` is true then fields
-    /// will be quoted as necessary, otherwise they will never be quoted.
-    pub fn write<S, P>(
-        &self,
-        path: &P,
-        recs: impl IntoIterator<Item = S>,
-        delimiter: u8,
-        quote: bool,
-    ) -> Result<()>
+    /// Opens several files for reading as one continuous stream, decoding each according to its
+    /// own compression format.  Files are concatenated as-is, so a file that does not end with a
+    /// newline will have its last line joined with the first line of the following file.  All
+    /// files are opened up front, so missing files are reported immediately.
+    pub fn new_multi_reader<P>(&self, paths: &[P]) -> Result<Box<dyn BufRead + Send>>
     where
-        S: Serialize,
         P: AsRef<Path>,
     {
-        let write = self.io.new_writer(path)?;
+        let readers = paths.iter().map(|p| self.new_reader(p)).collect::<Result<Vec<_>>>()?;
+        Ok(Box::new(MultiReader::new(readers)))
+    }
 
-        let mut writer = WriterBuilder::new()
-            .delimiter(delimiter)
-            .has_headers(true)
-            .quote_style(if quote { QuoteStyle::Necessary } else { QuoteStyle::Never })
-            .from_writer(write);
+    /// Opens a buffered reader over the raw bytes of a file, or of standard input if the path
+    /// is `-` or `/dev/stdin`.
+    fn open_read<P>(&self, p: &P) -> Result<BufReader<Box<dyn Read + Send>>>
+    where
+        P: AsRef<Path>,
+    {
+        #[cfg(feature = "cloud")]
+        if Self::is_object_store_path(p) {
+            let url = p.as_ref().to_string_lossy();
+            let read = cloud::open_object_read(&url).map_err(FgError::IoError)?;
+            return Ok(BufReader::with_capacity(self.buffer_size, read));
+        }
 
-        for rec in recs {
-            writer.serialize(rec).map_err(FgError::ConversionError)?;
+        #[cfg(feature = "http")]
+        if Self::is_url_path(p) {
+            let url = p.as_ref().to_string_lossy();
+            let read = http::open_url(&url).map_err(FgError::IoError)?;
+            return Ok(BufReader::with_capacity(self.buffer_size, read));
         }
 
-        writer.flush().map_err(FgError::IoError)
+        let read: Box<dyn Read + Send> = if Self::is_stdin_path(p) {
+            Box::new(std::io::stdin())
+        } else if Self::is_null_path(p) {
+            Box::new(std::io::empty())
+        } else {
+            Box::new(File::open(p).map_err(FgError::IoError)?)
+        };
+        Ok(BufReader::with_capacity(self.buffer_size, read))
     }
 
-    /// Writes structs implementing `[Serialize]` to a file with tab separators between fields.
-    pub fn write_tsv<S, P>(&self, path: &P, recs: impl IntoIterator<Item = S>) -> Result<()>
+    /// Wraps a buffered reader in the decoder for the given compression format, and applies any
+    /// text encoding, byte-order mark stripping and limits configured on this `Io`.
+    fn decode<R>(&self, buf: R, format: CompressionFormat) -> Result<Box<dyn BufRead + Send>>
     where
-        S: Serialize,
-        P: AsRef<Path>,
+        R: BufRead + Send + 'static,
     {
-        self.write(path, recs, b'\t', true)
+        Ok(self.wrap_reader(self.decompress(buf, format)?))
     }
 
-    /// Writes structs implementing `[Serialize]` to a file with comma separators between fields.
-    pub fn write_csv<S, P>(&self, path: &P, recs: impl IntoIterator<Item = S>) -> Result<()>
+    /// Wraps a buffered reader in the decoder for the given compression format, without
+    /// applying any of the text handling performed by [`Io::decode`].
+    fn decompress<R>(&self, buf: R, format: CompressionFormat) -> Result<Box<dyn BufRead + Send>>
     where
-        S: Serialize,
-        P: AsRef<Path>,
+        R: BufRead + Send + 'static,
     {
-        self.write(path, recs, b',', true)
+        let decoder: Box<dyn Read + Send> = match format {
+            CompressionFormat::None => return Ok(Box::new(buf)),
+            CompressionFormat::Gzip | CompressionFormat::Bgzf => Box::new(MultiGzDecoder::new(buf)),
+            CompressionFormat::Zstd => {
+                Box::new(ZstdDecoder::with_buffer(buf).map_err(FgError::IoError)?)
+            }
+            #[cfg(feature = "bzip2")]
+            CompressionFormat::Bzip2 => Box::new(MultiBzDecoder::new(buf)),
+            #[cfg(feature = "xz")]
+            CompressionFormat::Xz => Box::new(XzDecoder::new_multi_decoder(buf)),
+            #[cfg(feature = "lz4")]
+            CompressionFormat::Lz4 => Box::new(lz4_flex::frame::FrameDecoder::new(buf)),
+            #[cfg(feature = "snappy")]
+            CompressionFormat::Snappy => Box::new(snap::read::FrameDecoder::new(buf)),
+        };
+
+        Ok(Box::new(BufReader::with_capacity(self.buffer_size, decoder)))
     }
 
-    /// Reads structs implementing `[Deserialize]` from a file with the given separators between fields.
-    /// If `quote` is true then fields surrounded by quotes are parsed, otherwise quotes are not
-    /// considered.
-    pub fn read<D, P>(&self, path: &P, delimiter: u8, quote: bool) -> Result<Vec<D>>
+    /// Opens a file for reading via a read-only memory mapping, which avoids the per-read system
+    /// call overhead of buffered file reads when scanning very large uncompressed files.  Compressed
+    /// files are decoded from the mapping as usual.  Standard input cannot be memory-mapped.
+    ///
+    /// # Safety
+    ///
+    /// The file must not be modified or truncated, by this or any other process, while the
+    /// returned reader is in use.  Doing so is undefined behavior, and on many platforms
+    /// truncating the file kills the process with `SIGBUS` when the missing data is read.
+    #[cfg(feature = "mmap")]
+    #[allow(unsafe_code)]
+    pub unsafe fn new_mmap_reader<P>(&self, p: &P) -> Result<Box<dyn BufRead + Send>>
     where
-        D: DeserializeOwned,
         P: AsRef<Path>,
     {
-        let read = self.io.new_reader(path)?;
+        if Self::is_stdin_path(p) {
+            let msg = "Cannot memory-map standard input";
+            return Err(FgError::IoError(std::io::Error::new(ErrorKind::InvalidInput, msg)));
+        }
 
-        let mut reader = ReaderBuilder::new()
-            .delimiter(delimiter)
-            .has_headers(true)
-            .quoting(quote)
-            .from_reader(read);
+        let file = File::open(p).map_err(FgError::IoError)?;
+        // SAFETY: the mapping is read-only, and the caller ensures that the file is not modified
+        // while it is mapped
+        let mmap = unsafe { memmap2::Mmap::map(&file) }.map_err(FgError::IoError)?;
+        let mut buf = std::io::Cursor::new(mmap);
+        let format = self.read_format(p, &mut buf)?;
+        self.decode(buf, format)
+    }
 
-        let mut results = vec![];
+    /// Returns true if a text encoding other than UTF-8 has been configured.
+    fn has_encoding(&self) -> bool {
+        #[cfg(feature = "encoding")]
+        return self.encoding.is_some();
+        #[cfg(not(feature = "encoding"))]
+        false
+    }
 
-        for result in reader.deserialize::<D>() {
-            let rec = result.map_err(FgError::ConversionError)?;
-            results.push(rec);
+    /// Wraps a decoded reader to apply any text encoding, byte-order mark stripping and limits
+    /// configured on this `Io`.
+    fn wrap_reader(&self, mut reader: Box<dyn BufRead + Send>) -> Box<dyn BufRead + Send> {
+        // Decoding from another encoding handles byte-order marks itself
+        #[cfg(feature = "encoding")]
+        if let Some(encoding) = self.encoding {
+            let decoder = encoding_rs_io::DecodeReaderBytesBuilder::new()
+                .encoding(Some(encoding))
+                .bom_sniffing(self.strip_bom)
+                .build(reader);
+            reader = Box::new(BufReader::with_capacity(self.buffer_size, decoder));
         }
+        if self.strip_bom && !self.has_encoding() {
+            let stripped = BomStrippingReader::new(reader);
+            reader = Box::new(BufReader::with_capacity(self.buffer_size, stripped));
+        }
+        if let Some(max) = self.max_decompressed_bytes {
+            let limited = MaxBytesReader::new(reader, max);
+            reader = Box::new(BufReader::with_capacity(self.buffer_size, limited));
+        }
+        if let Some(max) = self.max_line_length {
+            let limited = MaxLineLengthReader::new(reader, max);
+            reader = Box::new(BufReader::with_capacity(self.buffer_size, limited));
+        }
+        reader
+    }
 
-        Ok(results)
+    /// Opens a BGZF file for reading with a [`BgzfReader`], which exposes the virtual offset of
+    /// the data being read and allows seeking back to previously recorded virtual offsets.
+    pub fn new_bgzf_reader<P>(&self, p: &P) -> Result<BgzfReader<BufReader<File>>>
+    where
+        P: AsRef<Path>,
+    {
+        let file = File::open(p).map_err(FgError::IoError)?;
+        Ok(BgzfReader::new(BufReader::with_capacity(self.buffer_size, file)))
     }
 
-    /// Reads structs implementing `[Deserialize]` from a file with tab separators between fields.
-    pub fn read_tsv<D, P>(&self, path: &P) -> Result<Vec<D>>
+    /// Opens a file for writing. Transparently handles encoding data in gzip, zstd, bzip2 and xz
+    /// formats.  Paths ending in `.bgz` are written as BGZF so that they may be indexed.  Bzip2
+    /// output uses the same compression level as gzip, clamped to bzip2's valid range of 1-9,
+    /// while xz and zstd output use their own configured levels.  The end of a compressed stream
+    /// is written when the writer is dropped, but any error doing so is lost; call
+    /// [`FinishWrite::finish`] once everything has been written to have it returned instead.
+    ///
+    /// The paths `-` and `/dev/stdout` write uncompressed data to standard output.  With the
+    /// `cloud` feature, `s3://` and `gs://` URLs are uploaded to as the data is written, with the
+    /// object created when the writer is finished or dropped.
+    pub fn new_writer<P>(&self, p: &P) -> Result<BufWriter<Box<dyn FinishWrite>>>
     where
-        D: DeserializeOwned,
         P: AsRef<Path>,
     {
-        self.read(path, b'\t', true)
+        self.new_writer_with_format(p, self.compression_format(p))
     }
 
-    /// Reads structs implementing `[Deserialize]` from a file with tab separators between fields.
-    pub fn read_csv<D, P>(&self, path: &P) -> Result<Vec<D>>
+    /// Opens a file (or standard output) for writing, encoding data with the given compression
+    /// format regardless of the path's extension.
+    pub fn new_writer_with_format<P>(
+        &self,
+        p: &P,
+        format: CompressionFormat,
+    ) -> Result<BufWriter<Box<dyn FinishWrite>>>
     where
-        D: DeserializeOwned,
         P: AsRef<Path>,
     {
-        self.read(path, b',', true)
+        let write = self.open_write(p, false, |w| self.encode(w, format))?;
+        Ok(BufWriter::with_capacity(self.buffer_size, write))
     }
-}
 
-#[cfg(test)]
-mod tests {
-    use crate::io::{DelimFile, Io};
-    use rstest::rstest;
-    use serde::{Deserialize, Serialize};
-    use tempfile::TempDir;
+    /// Opens a file for appending, creating it if it does not exist.  Data is encoded based on
+    /// the file extension as with [`Io::new_writer`]; for compressed formats this appends a new
+    /// gzip member (or zstd frame, bzip2 stream, etc.) to the end of the file, which the readers
+    /// returned by [`Io::new_reader`] read seamlessly as part of a single stream.
+    pub fn new_appender<P>(&self, p: &P) -> Result<BufWriter<Box<dyn FinishWrite>>>
+    where
+        P: AsRef<Path>,
+    {
+        let write = self.open_write(p, true, |w| self.encode(w, self.compression_format(p)))?;
+        Ok(BufWriter::with_capacity(self.buffer_size, write))
+    }
 
-    /// Record type used in testing DelimFile
-    #[derive(Debug, Serialize, Deserialize, PartialEq)]
-    struct Rec {
-        s: String,
-        i: usize,
-        b: bool,
-        o: Option<f64>,
+    /// Opens a file for writing as with [`Io::new_writer`], along with a [`ByteCounts`] that
+    /// tracks the number of bytes written both before and after compression.  The final counts
+    /// are available once the writer has been flushed and dropped.
+    pub fn new_counting_writer<P>(
+        &self,
+        p: &P,
+    ) -> Result<(BufWriter<Box<dyn FinishWrite>>, ByteCounts)>
+    where
+        P: AsRef<Path>,
+    {
+        let mut counts = None;
+        let write = self.open_write(p, false, |w| {
+            let compressed = CountingWriter::new(w);
+            let compressed_counter = compressed.counter();
+            let uncompressed =
+                CountingWriter::new(self.encode(compressed, self.compression_format(p))?);
+            counts = Some(ByteCounts::new(compressed_counter, uncompressed.counter()));
+            Ok(Box::new(uncompressed))
+        })?;
+        let counts = counts.expect("set when the writer is opened");
+        Ok((BufWriter::with_capacity(self.buffer_size, write), counts))
     }
 
-    #[test]
-    fn test_reading_and_writing_lines_to_file() {
+    /// Opens a file for writing as with [`Io::new_writer`], but performs compression and writing
+    /// on a background thread using a [`ThreadedWriter`].  Use [`ThreadedWriter::finish`] to
+    /// wait for all data to be written and detect any errors.
+    pub fn new_threaded_writer<P>(
+        &self,
+        p: &P,
+    ) -> Result<ThreadedWriter<BufWriter<Box<dyn FinishWrite>>>>
+    where
+        P: AsRef<Path>,
+    {
+        let writer = self.new_writer(p)?;
+        Ok(ThreadedWriter::new(writer, self.buffer_size, THREADED_QUEUE_SIZE))
+    }
+
+    /// Opens a file for writing as with [`Io::new_writer`], along with a spinner-style
+    /// [`ProgressBar`] that tracks the number of bytes written before compression.
+    #[cfg(feature = "indicatif")]
+    pub fn new_writer_with_progress_bar<P>(
+        &self,
+        p: &P,
+    ) -> Result<(BufWriter<Box<dyn FinishWrite>>, ProgressBar)>
+    where
+        P: AsRef<Path>,
+    {
+        let bar = Self::new_progress_bar(None);
+        let write = self.open_write(p, false, |w| {
+            let write = self.encode(w, self.compression_format(p))?;
+            Ok(Box::new(finish::ProgressBarWriter::new(write, bar.clone())))
+        })?;
+        Ok((BufWriter::with_capacity(self.buffer_size, write), bar))
+    }
+
+    /// Opens several files for writing, returning a single writer that writes everything it
+    /// receives to all of them.  Each file is compressed according to its own extension, so
+    /// that e.g. a plain-text and a gzipped copy of the same output can be written in one pass.
+    pub fn new_tee_writer<P>(&self, paths: &[P]) -> Result<BufWriter<Box<dyn FinishWrite>>>
+    where
+        P: AsRef<Path>,
+    {
+        let mut writers = Vec::with_capacity(paths.len());
+        for p in paths {
+            writers
+                .push(self.open_write(p, false, |w| self.encode(w, self.compression_format(p)))?);
+        }
+
+        let write: Box<dyn FinishWrite> = Box::new(TeeWriter::new(writers));
+        Ok(BufWriter::with_capacity(self.buffer_size, write))
+    }
+
+    /// Creates a [`RollingWriter`] that splits output across numbered part files named after
+    /// the given path, rolling over to a new part whenever the current one reaches `limit`.
+    pub fn new_rolling_writer<P>(&self, p: &P, limit: RollingLimit) -> RollingWriter<'_>
+    where
+        P: AsRef<Path>,
+    {
+        RollingWriter::new(self, p, limit)
+    }
+
+    /// Opens the raw (pre-compression) destination for a path: standard output, or a file that
+    /// is created or appended to, wrapped to compute a checksum if so configured.  `wrap` builds
+    /// the writers that data passes through before reaching the destination, such as a
+    /// compression encoder.
+    fn open_write<P, F>(&self, p: &P, append: bool, wrap: F) -> Result<Box<dyn FinishWrite>>
+    where
+        P: AsRef<Path>,
+        F: FnOnce(Box<dyn FinishWrite>) -> Result<Box<dyn FinishWrite>>,
+    {
+        if Self::is_stdout_path(p) {
+            return wrap(Box::new(std::io::stdout()));
+        } else if Self::is_null_path(p) {
+            return wrap(Box::new(std::io::sink()));
+        }
+
+        #[cfg(feature = "cloud")]
+        if Self::is_object_store_path(p) {
+            if append {
+                let msg = format!("Cannot append to object: {}", p.as_ref().display());
+                return Err(FgError::IoError(std::io::Error::new(ErrorKind::Unsupported, msg)));
+            }
+            let url = p.as_ref().to_string_lossy();
+            return wrap(cloud::open_object_write(&url).map_err(FgError::IoError)?);
+        }
+
+        let file = self.create_file(p, append)?;
+        match self.checksummed(file, p, append) {
+            Ok(write) => wrap(write),
+            Err(e) => {
+                // Don't leave behind a new file that has no sidecar
+                if !append {
+                    let _ = std::fs::remove_file(p);
+                }
+                Err(e)
+            }
+        }
+    }
+
+    /// Wraps a file written to at `p` to compute its checksum if so configured, first hashing
+    /// any existing contents if `append` is true.  The sidecar file is created as for `p`
+    /// itself, so fails with [`FgError::FileExists`] if it exists and overwriting is disabled,
+    /// except when appending, in which case any existing sidecar is replaced.
+    #[cfg(feature = "checksum")]
+    fn checksummed<P>(&self, file: File, p: &P, append: bool) -> Result<Box<dyn FinishWrite>>
+    where
+        P: AsRef<Path>,
+    {
+        let algorithm = match self.checksum {
+            Some(algorithm) => algorithm,
+            None => return Ok(Box::new(file)),
+        };
+
+        let sidecar_path = algorithm.sidecar_path(p);
+        if append {
+            let sidecar = self.open_file(
+                &sidecar_path,
+                OpenOptions::new().write(true).create(true).truncate(true),
+            )?;
+            let writer = ChecksumWriter::appending(file, p, sidecar, algorithm);
+            Ok(Box::new(writer.map_err(FgError::IoError)?))
+        } else {
+            let sidecar = self.create_file(&sidecar_path, false)?;
+            Ok(Box::new(ChecksumWriter::new(file, p, sidecar, algorithm)))
+        }
+    }
+
+    /// Returns a file written to at `p` unchanged, as checksums require the `checksum` feature.
+    #[cfg(not(feature = "checksum"))]
+    fn checksummed<P>(&self, file: File, _p: &P, _append: bool) -> Result<Box<dyn FinishWrite>> {
+        Ok(Box::new(file))
+    }
+
+    /// Appends all the lines from an iterable of string-like values to a file, creating it if
+    /// it does not exist.
+    pub fn append_lines<P, S>(&self, p: &P, lines: impl IntoIterator<Item = S>) -> Result<()>
+    where
+        P: AsRef<Path>,
+        S: AsRef<str>,
+    {
+        let mut out = self.new_appender(p)?;
+        for line in lines {
+            out.write_all(line.as_ref().as_bytes()).map_err(FgError::IoError)?;
+            out.write_all(self.line_terminator.as_bytes()).map_err(FgError::IoError)?;
+        }
+
+        out.finish().map_err(FgError::IoError)
+    }
+
+    /// Creates (or truncates) a file for writing, or opens it for appending if `append` is true,
+    /// first creating any missing parent directories if so configured.  Fails with
+    /// [`FgError::FileExists`] if overwriting is disabled and the file already exists.
+    fn create_file<P>(&self, p: &P, append: bool) -> Result<File>
+    where
+        P: AsRef<Path>,
+    {
+        let mut options = OpenOptions::new();
+        if append {
+            options.append(true).create(true);
+        } else if self.overwrite {
+            options.write(true).create(true).truncate(true);
+        } else {
+            options.write(true).create_new(true);
+        }
+        self.open_file(p, &options)
+    }
+
+    /// Opens a file with the given options, first creating any missing parent directories if so
+    /// configured.  Fails with [`FgError::FileExists`] if the options require a new file and the
+    /// file already exists.
+    fn open_file<P>(&self, p: &P, options: &OpenOptions) -> Result<File>
+    where
+        P: AsRef<Path>,
+    {
+        if self.create_parent_dirs {
+            if let Some(parent) = p.as_ref().parent() {
+                std::fs::create_dir_all(parent).map_err(FgError::IoError)?;
+            }
+        }
+
+        options.open(p).map_err(|e| match e.kind() {
+            ErrorKind::AlreadyExists => FgError::FileExists(p.as_ref().to_path_buf()),
+            _ => FgError::IoError(e),
+        })
+    }
+
+    /// Wraps a writer in the encoder for the given compression format.
+    fn encode<W>(&self, write: W, format: CompressionFormat) -> Result<Box<dyn FinishWrite>>
+    where
+        W: FinishWrite + 'static,
+    {
+        let write = self.compress(write, format)?;
+        #[cfg(feature = "encoding")]
+        if let Some(encoding) = self.encoding {
+            return Ok(Box::new(encoding::EncodingWriter::new(write, encoding)));
+        }
+        Ok(write)
+    }
+
+    /// Wraps a writer in the encoder for the given compression format.
+    fn compress<W>(&self, write: W, format: CompressionFormat) -> Result<Box<dyn FinishWrite>>
+    where
+        W: FinishWrite + 'static,
+    {
+        #[cfg(feature = "gzp")]
+        if self.threads > 1 {
+            use gzp::deflate::{Bgzf, Gzip};
+            use gzp::par::compress::ParCompressBuilder;
+            match format {
+                CompressionFormat::Gzip => {
+                    let builder = ParCompressBuilder::<Gzip>::new()
+                        .num_threads(self.threads)
+                        .expect("IoBuilder ensures threads is non-zero");
+                    let builder = builder.compression_level(self.compression);
+                    let encoder = finish::ParEncoder::new(builder, Box::new(write));
+                    return Ok(Box::new(Finishing::new(encoder)));
+                }
+                CompressionFormat::Bgzf => {
+                    let builder = ParCompressBuilder::<Bgzf>::new()
+                        .num_threads(self.threads)
+                        .expect("IoBuilder ensures threads is non-zero");
+                    let builder = builder.compression_level(self.compression);
+                    let encoder = finish::ParEncoder::new(builder, Box::new(write));
+                    return Ok(Box::new(Finishing::new(encoder)));
+                }
+                _ => (),
+            }
+        }
+
+        Ok(match format {
+            CompressionFormat::None => Box::new(write),
+            CompressionFormat::Gzip => {
+                Box::new(Finishing::new(GzEncoder::new(write, self.compression)))
+            }
+            CompressionFormat::Bgzf => {
+                Box::new(Finishing::new(BgzfWriter::new(write, self.compression)))
+            }
+            CompressionFormat::Zstd => Box::new(Finishing::new(self.zstd_encoder(write, &[])?)),
+            #[cfg(feature = "bzip2")]
+            CompressionFormat::Bzip2 => {
+                let level = bzip2::Compression::new(self.compression.level().clamp(1, 9));
+                Box::new(Finishing::new(BzEncoder::new(write, level)))
+            }
+            #[cfg(feature = "xz")]
+            CompressionFormat::Xz => {
+                Box::new(Finishing::new(XzEncoder::new(write, self.xz_preset)))
+            }
+            #[cfg(feature = "lz4")]
+            CompressionFormat::Lz4 => {
+                Box::new(Finishing::new(lz4_flex::frame::FrameEncoder::new(write)))
+            }
+            #[cfg(feature = "snappy")]
+            CompressionFormat::Snappy => {
+                Box::new(Finishing::new(snap::write::FrameEncoder::new(write)))
+            }
+        })
+    }
+
+    /// Creates a zstd encoder using the configured level, threads and long-distance matching,
+    /// and the given dictionary (which may be empty to compress without a dictionary).
+    fn zstd_encoder<W>(&self, write: W, dictionary: &[u8]) -> Result<ZstdEncoder<'static, W>>
+    where
+        W: Write,
+    {
+        let mut encoder = ZstdEncoder::with_dictionary(write, self.zstd_level, dictionary)
+            .map_err(FgError::IoError)?;
+        if self.zstd_long_distance_matching {
+            encoder.long_distance_matching(true).map_err(FgError::IoError)?;
+        }
+        if self.threads > 1 {
+            encoder.multithread(self.threads as u32).map_err(FgError::IoError)?;
+        }
+        Ok(encoder)
+    }
+
+    /// Trains a zstd dictionary of at most `max_size` bytes from the contents of the given
+    /// sample files, which are decompressed as necessary.  Dictionaries greatly improve the
+    /// compression of many small, similar files, and are used with
+    /// [`Io::new_zstd_writer_with_dictionary`] and [`Io::new_zstd_reader_with_dictionary`].
+    pub fn train_zstd_dictionary<P>(&self, paths: &[P], max_size: usize) -> Result<Vec<u8>>
+    where
+        P: AsRef<Path>,
+    {
+        let mut samples = Vec::with_capacity(paths.len());
+        for path in paths {
+            let mut sample = Vec::new();
+            self.new_reader(path)?.read_to_end(&mut sample).map_err(FgError::IoError)?;
+            samples.push(sample);
+        }
+
+        zstd::dict::from_samples(&samples, max_size).map_err(FgError::IoError)
+    }
+
+    /// Opens a zstd file, compressed using the given dictionary, for reading.  The file is
+    /// always decoded as zstd, regardless of its extension.
+    pub fn new_zstd_reader_with_dictionary<P>(
+        &self,
+        p: &P,
+        dictionary: &[u8],
+    ) -> Result<Box<dyn BufRead + Send>>
+    where
+        P: AsRef<Path>,
+    {
+        let file = File::open(p).map_err(FgError::IoError)?;
+        let buf = BufReader::with_capacity(self.buffer_size, file);
+        let decoder = ZstdDecoder::with_dictionary(buf, dictionary).map_err(FgError::IoError)?;
+        Ok(self.wrap_reader(Box::new(BufReader::with_capacity(self.buffer_size, decoder))))
+    }
+
+    /// Opens a file for writing, compressing with zstd using the given dictionary.  The file is
+    /// always encoded as zstd, regardless of its extension.
+    pub fn new_zstd_writer_with_dictionary<P>(
+        &self,
+        p: &P,
+        dictionary: &[u8],
+    ) -> Result<BufWriter<Box<dyn FinishWrite>>>
+    where
+        P: AsRef<Path>,
+    {
+        let write = self.open_write(p, false, |w| {
+            Ok(Box::new(Finishing::new(self.zstd_encoder(w, dictionary)?)))
+        })?;
+        Ok(BufWriter::with_capacity(self.buffer_size, write))
+    }
+
+    /// Reads lines from a file into a Vec
+    pub fn read_lines<P>(&self, p: &P) -> Result<Vec<String>>
+    where
+        P: AsRef<Path>,
+    {
+        self.read_lines_iter(p)?.collect()
+    }
+
+    /// Reads lines from a file into a Vec, trimming and skipping lines according to the given
+    /// [`LineOptions`].
+    pub fn read_lines_with_opts<P>(&self, p: &P, opts: &LineOptions) -> Result<Vec<String>>
+    where
+        P: AsRef<Path>,
+    {
+        let mut v = Vec::new();
+        for line in self.read_lines_iter(p)? {
+            v.extend(opts.apply(line?));
+        }
+
+        Ok(v)
+    }
+
+    /// Opens a file and returns an iterator that lazily reads its lines one at a time, so that
+    /// files too large to hold in memory can be processed line by line.
+    pub fn read_lines_iter<P>(&self, p: &P) -> Result<impl Iterator<Item = Result<String>> + Send>
+    where
+        P: AsRef<Path>,
+    {
+        let r = self.new_reader(p)?;
+        Ok(r.lines().map(|result| result.map_err(FgError::IoError)))
+    }
+
+    /// Reads the lines of a file that match a predicate into a Vec.  Lines are read into a
+    /// reused buffer and only lines that match are allocated, so large files can be searched
+    /// cheaply.
+    pub fn read_lines_filtered<P, F>(&self, p: &P, predicate: F) -> Result<Vec<String>>
+    where
+        P: AsRef<Path>,
+        F: FnMut(&str) -> bool + Send,
+    {
+        self.read_lines_filtered_iter(p, predicate)?.collect()
+    }
+
+    /// Opens a file and returns an iterator that lazily reads the lines that match a predicate,
+    /// as with [`Io::read_lines_filtered`].
+    pub fn read_lines_filtered_iter<P, F>(
+        &self,
+        p: &P,
+        mut predicate: F,
+    ) -> Result<impl Iterator<Item = Result<String>> + Send>
+    where
+        P: AsRef<Path>,
+        F: FnMut(&str) -> bool + Send,
+    {
+        let mut r = self.new_reader(p)?;
+        let mut buffer = String::new();
+        Ok(std::iter::from_fn(move || loop {
+            buffer.clear();
+            match r.read_line(&mut buffer) {
+                Ok(0) => return None,
+                Ok(_) => {
+                    let line = buffer.strip_suffix('\n').unwrap_or(&buffer);
+                    let line = line.strip_suffix('\r').unwrap_or(line);
+                    if predicate(line) {
+                        return Some(Ok(line.to_string()));
+                    }
+                }
+                Err(e) => return Some(Err(FgError::IoError(e))),
+            }
+        }))
+    }
+
+    /// Reads a sample of the lines in a file, selected as the file is streamed so that large
+    /// compressed files can be sampled without first being decompressed.  Lines not sampled
+    /// are never allocated.
+    ///
+    /// # Panics
+    /// - panics if sampling every nth line with `n` of zero, or with a probability outside the
+    ///   range zero to one
+    pub fn read_lines_sampled<P>(&self, p: &P, sampling: LineSampling) -> Result<Vec<String>>
+    where
+        P: AsRef<Path>,
+    {
+        match sampling {
+            LineSampling::EveryNth(n) => {
+                assert_ne!(n, 0, "Cannot sample every zeroth line!");
+                let mut i = 0;
+                self.read_lines_filtered(p, |_| {
+                    i += 1;
+                    (i - 1) % n == 0
+                })
+            }
+            #[cfg(feature = "rand")]
+            LineSampling::Probability { p: prob, seed } => {
+                assert!((0.0..=1.0).contains(&prob), "Probability must be between 0 and 1!");
+                let mut rng = ChaCha8Rng::seed_from_u64(seed);
+                self.read_lines_filtered(p, |_| rng.gen_bool(prob))
+            }
+        }
+    }
+
+    /// Selects `k` lines uniformly at random from a file in a single streaming pass, using
+    /// [`reservoir_sample`](crate::iter::reservoir_sample).  Lines are returned in file order,
+    /// and the same `seed` always produces the same sample.
+    #[cfg(feature = "rand")]
+    pub fn reservoir_sample_lines<P>(&self, p: &P, k: usize, seed: u64) -> Result<Vec<String>>
+    where
+        P: AsRef<Path>,
+    {
+        crate::iter::try_reservoir_sample(self.read_lines_iter(p)?, k, seed)
+    }
+
+    /// Opens a file and returns an iterator that lazily reads its lines in batches of
+    /// `chunk_size` lines (the last batch may be smaller), e.g. for handing to worker threads.
+    /// If an error occurs the lines read so far in the batch are discarded, the error is
+    /// returned and iteration ends.
+    ///
+    /// # Panics
+    /// - panics if `chunk_size` is zero
+    pub fn read_lines_chunked<P>(
+        &self,
+        p: &P,
+        chunk_size: usize,
+    ) -> Result<impl Iterator<Item = Result<Vec<String>>> + Send>
+    where
+        P: AsRef<Path>,
+    {
+        assert_ne!(chunk_size, 0, "Chunk size cannot be zero!");
+        let mut lines = self.read_lines_iter(p)?;
+        let mut failed = false;
+        Ok(std::iter::from_fn(move || {
+            if failed {
+                return None;
+            }
+
+            let mut chunk = Vec::with_capacity(chunk_size);
+            for line in lines.by_ref().take(chunk_size) {
+                match line {
+                    Ok(line) => chunk.push(line),
+                    Err(e) => {
+                        failed = true;
+                        return Some(Err(e));
+                    }
+                }
+            }
+            (!chunk.is_empty()).then_some(Ok(chunk))
+        }))
+    }
+
+    /// Opens a file and returns an iterator that lazily reads its lines as bytes, without
+    /// requiring them to be valid UTF-8.  As with [`Io::read_lines`] the trailing `\n` or
+    /// `\r\n` is removed from each line.
+    pub fn read_byte_lines<P>(&self, p: &P) -> Result<impl Iterator<Item = Result<Vec<u8>>> + Send>
+    where
+        P: AsRef<Path>,
+    {
+        let r = self.new_reader(p)?;
+        Ok(r.split(b'\n').map(|result| {
+            let mut line = result.map_err(FgError::IoError)?;
+            if line.last() == Some(&b'\r') {
+                line.pop();
+            }
+            Ok(line)
+        }))
+    }
+
+    /// Counts the lines in a file, consistent with the number of lines returned by
+    /// [`Io::read_lines`], without decoding lines into Strings.  A final line without a trailing
+    /// newline is counted.
+    pub fn count_lines<P>(&self, p: &P) -> Result<u64>
+    where
+        P: AsRef<Path>,
+    {
+        let mut r = self.new_reader(p)?;
+        let mut count = 0;
+        let mut last = None;
+        loop {
+            let buf = r.fill_buf().map_err(FgError::IoError)?;
+            if buf.is_empty() {
+                break;
+            }
+            count += memchr::memchr_iter(b'\n', buf).count() as u64;
+            last = buf.last().copied();
+            let n = buf.len();
+            r.consume(n);
+        }
+
+        match last {
+            Some(b) if b != b'\n' => Ok(count + 1),
+            _ => Ok(count),
+        }
+    }
+
+    /// Reads up to the first `n` lines of a file, without reading the remainder of the file.
+    pub fn read_first_n_lines<P>(&self, p: &P, n: usize) -> Result<Vec<String>>
+    where
+        P: AsRef<Path>,
+    {
+        self.read_lines_iter(p)?.take(n).collect()
+    }
+
+    /// Reads up to the last `n` lines of a file.  Uncompressed files are scanned backwards from
+    /// the end, so only the blocks containing the last lines are read, unless an encoding or a
+    /// limit on line length or bytes read is configured.  Compressed files and streams must be
+    /// read in full, but only the last `n` lines are held in memory.
+    pub fn read_last_n_lines<P>(&self, p: &P, n: usize) -> Result<Vec<String>>
+    where
+        P: AsRef<Path>,
+    {
+        if n == 0 {
+            return Ok(Vec::new());
+        }
+
+        let mut buf = self.open_read(p)?;
+        let format = self.read_format(p, &mut buf)?;
+        // Only UTF-8 byte-order marks are handled when scanning backwards; other text handling
+        // needs the file to be read from the start
+        let utf16 = self.strip_bom && bom::starts_with_utf16_bom(buf.fill_buf()?);
+        let plain = !self.has_encoding()
+            && !utf16
+            && self.max_line_length.is_none()
+            && self.max_decompressed_bytes.is_none();
+        let seekable = !Self::is_stdin_path(p) && !Self::is_null_path(p);
+        if plain && seekable && format == CompressionFormat::None {
+            let file = File::open(p).map_err(FgError::IoError)?;
+            if file.metadata().map_err(FgError::IoError)?.is_file() {
+                return self.read_last_n_lines_of_file(file, n);
+            }
+        }
+
+        let mut lines = VecDeque::with_capacity(n);
+        for line in self.decode(buf, format)?.lines() {
+            if lines.len() == n {
+                lines.pop_front();
+            }
+            lines.push_back(line.map_err(FgError::IoError)?);
+        }
+        Ok(lines.into())
+    }
+
+    /// Reads the last `n` lines of an uncompressed file by reading blocks backwards from the end
+    /// of the file until enough lines have been found, removing any UTF-8 byte-order mark if
+    /// the start of the file is reached.
+    fn read_last_n_lines_of_file(&self, mut file: File, n: usize) -> Result<Vec<String>> {
+        let len = file.metadata().map_err(FgError::IoError)?.len();
+        let mut pos = len;
+        let mut blocks: Vec<Vec<u8>> = Vec::new();
+        let mut newlines = 0;
+
+        // A trailing newline terminates the last line rather than starting a new one, so n
+        // newlines must be found before it to be sure of having n complete lines.
+        while pos > 0 && newlines < n {
+            let size = (self.buffer_size as u64).min(pos);
+            pos -= size;
+            let mut block = vec![0u8; size as usize];
+            file.seek(SeekFrom::Start(pos)).map_err(FgError::IoError)?;
+            file.read_exact(&mut block).map_err(FgError::IoError)?;
+
+            let body = if blocks.is_empty() && block.last() == Some(&b'\n') {
+                &block[..block.len() - 1]
+            } else {
+                &block[..]
+            };
+            newlines += body.iter().filter(|b| **b == b'\n').count();
+            blocks.push(block);
+        }
+
+        let mut bytes: Vec<u8> = blocks.into_iter().rev().flatten().collect();
+        if pos == 0 && self.strip_bom && bytes.starts_with(&bom::UTF8_BOM) {
+            bytes.drain(..bom::UTF8_BOM.len());
+        }
+        if bytes.is_empty() {
+            return Ok(Vec::new());
+        } else if bytes.last() == Some(&b'\n') {
+            bytes.pop();
+        }
+        let start = bytes
+            .iter()
+            .enumerate()
+            .rev()
+            .filter(|(_, b)| **b == b'\n')
+            .nth(n - 1)
+            .map_or(0, |(i, _)| i + 1);
+        let text = std::str::from_utf8(&bytes[start..])
+            .map_err(|e| FgError::IoError(std::io::Error::new(ErrorKind::InvalidData, e)))?;
+        Ok(text.split('\n').map(|l| l.strip_suffix('\r').unwrap_or(l).to_string()).collect())
+    }
+
+    /// Reads lines from several files, in order, into a single Vec.  Unlike
+    /// [`Io::new_multi_reader`] lines are never joined across files, even if a file does not end
+    /// with a newline.
+    pub fn read_lines_multi<P>(&self, paths: &[P]) -> Result<Vec<String>>
+    where
+        P: AsRef<Path>,
+    {
+        let mut v = Vec::new();
+        for p in paths {
+            v.extend(self.read_lines(p)?);
+        }
+
+        Ok(v)
+    }
+
+    /// Writes all the lines from an iterable of string-like values to a file, separated by new lines.
+    pub fn write_lines<P, S>(&self, p: &P, lines: impl IntoIterator<Item = S>) -> Result<()>
+    where
+        P: AsRef<Path>,
+        S: AsRef<str>,
+    {
+        let mut out = self.new_writer(p)?;
+        for line in lines {
+            out.write_all(line.as_ref().as_bytes()).map_err(FgError::IoError)?;
+            out.write_all(self.line_terminator.as_bytes()).map_err(FgError::IoError)?;
+        }
+
+        out.finish().map_err(FgError::IoError)
+    }
+
+    /// Reads the entire (decompressed) contents of a file into a Vec of bytes.  Unlike the
+    /// text-oriented readers, no byte-order mark stripping, encoding or line length limits are
+    /// applied.
+    pub fn read_bytes<P>(&self, p: &P) -> Result<Vec<u8>>
+    where
+        P: AsRef<Path>,
+    {
+        let mut bytes = Vec::new();
+        self.open_binary_read(p)?.read_to_end(&mut bytes).map_err(FgError::IoError)?;
+        Ok(bytes)
+    }
+
+    /// Opens a file for reading its decompressed bytes without any of the text handling
+    /// performed by [`Io::new_reader`], though limits on its size still apply.
+    fn open_binary_read<P>(&self, p: &P) -> Result<Box<dyn Read + Send>>
+    where
+        P: AsRef<Path>,
+    {
+        let mut buf = self.open_read(p)?;
+        let format = self.read_format(p, &mut buf)?;
+        let read = self.decompress(buf, format)?;
+        Ok(match self.max_decompressed_bytes {
+            Some(max) => Box::new(MaxBytesReader::new(read, max)),
+            None => Box::new(read),
+        })
+    }
+
+    /// Copies a file to another path in a single streaming pass, decompressing the source and
+    /// recompressing the output according to the extension of the destination, e.g. to convert
+    /// `data.tsv.gz` to `data.tsv.zst`.  The data is copied byte-for-byte without any text
+    /// handling.  Returns the number of (decompressed) bytes copied.
+    pub fn copy<P, Q>(&self, src: &P, dst: &Q) -> Result<u64>
+    where
+        P: AsRef<Path>,
+        Q: AsRef<Path>,
+    {
+        let mut read = self.open_binary_read(src)?;
+        self.copy_to(&mut read, dst)
+    }
+
+    /// Copies a file as with [`Io::copy`], logging progress every `interval` lines or bytes
+    /// using a [`ProgressReader`].
+    #[cfg(feature = "log")]
+    pub fn copy_with_progress<P, Q>(
+        &self,
+        src: &P,
+        dst: &Q,
+        interval: ProgressInterval,
+    ) -> Result<u64>
+    where
+        P: AsRef<Path>,
+        Q: AsRef<Path>,
+    {
+        let name = src.as_ref().display().to_string();
+        let mut read = ProgressReader::new(self.open_binary_read(src)?, &name, interval);
+        self.copy_to(&mut read, dst)
+    }
+
+    /// Compresses an existing uncompressed file, writing it alongside the original with the
+    /// extension for the given format appended, e.g. `data.tsv` to `data.tsv.gz`, and
+    /// optionally removing the original.  Returns the path of the compressed file.
+    ///
+    /// The compressed file is written to a temporary file in the same directory and renamed
+    /// into place once complete, so a partially compressed file is never left at the final
+    /// path.  The original is only removed once the rename has succeeded.
+    pub fn compress_file<P>(
+        &self,
+        p: &P,
+        format: CompressionFormat,
+        remove_original: bool,
+    ) -> Result<PathBuf>
+    where
+        P: AsRef<Path>,
+    {
+        let p = p.as_ref();
+        let ext = format.extension().ok_or_else(|| {
+            let msg = "Cannot compress a file without a compression format";
+            FgError::IoError(std::io::Error::new(ErrorKind::InvalidInput, msg))
+        })?;
+
+        let mut name = p.as_os_str().to_os_string();
+        name.push(".");
+        name.push(ext);
+        let dst = PathBuf::from(name);
+
+        let mut read = File::open(p).map_err(FgError::IoError)?;
+        self.write_file_atomically(&dst, format, &mut read)?;
+        if remove_original {
+            std::fs::remove_file(p).map_err(FgError::IoError)?;
+        }
+        Ok(dst)
+    }
+
+    /// Decompresses an existing compressed file, writing it alongside the original with the
+    /// compression extension removed, e.g. `data.tsv.gz` to `data.tsv`, and optionally removing
+    /// the original.  Returns the path of the decompressed file.  The file is written atomically
+    /// as with [`Io::compress_file`].
+    pub fn decompress_file<P>(&self, p: &P, remove_original: bool) -> Result<PathBuf>
+    where
+        P: AsRef<Path>,
+    {
+        let p = p.as_ref();
+        let (ext, _) = self.matching_extension(&p).ok_or_else(|| {
+            let msg = format!("Not a compressed file: {}", p.display());
+            FgError::IoError(std::io::Error::new(ErrorKind::InvalidInput, msg))
+        })?;
+
+        let name = p.file_name().and_then(|n| n.to_str()).expect("matched extension");
+        let dst = p.with_file_name(&name[..name.len() - ext.len() - 1]);
+
+        let mut read = self.open_binary_read(&p)?;
+        self.write_file_atomically(&dst, CompressionFormat::None, &mut read)?;
+        if remove_original {
+            std::fs::remove_file(p).map_err(FgError::IoError)?;
+        }
+        Ok(dst)
+    }
+
+    /// Concatenates gzip (or BGZF) files into a single output file by copying their compressed
+    /// bytes directly, producing a valid multi-member gzip file without decompressing and
+    /// recompressing the data.  Returns the number of bytes written.
+    ///
+    /// BGZF end-of-file markers are removed from the inputs and a single marker is written at
+    /// the end of the output if any input had one, or if the output has a BGZF extension, so
+    /// that concatenating BGZF files yields a valid BGZF file.  Inputs that are not gzip
+    /// compressed are rejected before anything is written.
+    pub fn concat_gzip_files<P, Q>(&self, inputs: &[P], output: &Q) -> Result<u64>
+    where
+        P: AsRef<Path>,
+        Q: AsRef<Path>,
+    {
+        // Check all the inputs up front, noting how much of each to copy
+        let mut sources = Vec::with_capacity(inputs.len());
+        let mut any_bgzf_eof = false;
+        for p in inputs {
+            let mut file = File::open(p).map_err(FgError::IoError)?;
+            let len = file.metadata().map_err(FgError::IoError)?.len();
+            if len == 0 {
+                continue;
+            }
+
+            let mut magic = [0u8; 2];
+            file.read_exact(&mut magic).map_err(FgError::IoError)?;
+            if CompressionFormat::from_magic(&magic) != CompressionFormat::Gzip {
+                let msg = format!("Not a gzip file: {}", p.as_ref().display());
+                return Err(FgError::IoError(std::io::Error::new(ErrorKind::InvalidData, msg)));
+            }
+
+            let eof_len = BGZF_EOF.len() as u64;
+            let mut tail = [0u8; BGZF_EOF.len()];
+            let has_eof = len >= eof_len
+                && file.seek(SeekFrom::End(-(eof_len as i64))).is_ok()
+                && file.read_exact(&mut tail).is_ok()
+                && tail == BGZF_EOF;
+            any_bgzf_eof |= has_eof;
+            sources.push((p, if has_eof { len - eof_len } else { len }));
+        }
+
+        let out = self.open_write(output, false, Ok)?;
+        let mut out = BufWriter::with_capacity(self.buffer_size, out);
+        let mut written = 0;
+        for (p, len) in sources {
+            let file = File::open(p).map_err(FgError::IoError)?;
+            written += std::io::copy(&mut file.take(len), &mut out).map_err(FgError::IoError)?;
+        }
+        if any_bgzf_eof || self.compression_format(output) == CompressionFormat::Bgzf {
+            out.write_all(&BGZF_EOF).map_err(FgError::IoError)?;
+            written += BGZF_EOF.len() as u64;
+        }
+        out.finish().map_err(FgError::IoError)?;
+        Ok(written)
+    }
+
+    /// Writes everything from a reader to a uniquely named temporary file next to `dst`,
+    /// compressed with the given format, and then moves it to `dst`.  If overwriting is disabled
+    /// the temporary file is published with a hard link, which fails rather than replacing a
+    /// `dst` created while it was being written.  The temporary file is removed on failure.
+    fn write_file_atomically(
+        &self,
+        dst: &Path,
+        format: CompressionFormat,
+        read: &mut dyn Read,
+    ) -> Result<()> {
+        // Checked up front only to fail before doing any work; publishing is what is safe
+        if !self.overwrite && dst.exists() {
+            return Err(FgError::FileExists(dst.to_path_buf()));
+        }
+
+        static COUNTER: AtomicUsize = AtomicUsize::new(0);
+        let name = dst.file_name().map(|n| n.to_string_lossy()).unwrap_or_default();
+        let id = COUNTER.fetch_add(1, Ordering::Relaxed);
+        let tmp = dst.with_file_name(format!(".{name}.{}.{id}.tmp", std::process::id()));
+        let result = self.write_file(&tmp, dst, format, read).and_then(|()| {
+            if self.overwrite {
+                std::fs::rename(&tmp, dst).map_err(FgError::IoError)
+            } else {
+                std::fs::hard_link(&tmp, dst).map_err(|e| match e.kind() {
+                    ErrorKind::AlreadyExists => FgError::FileExists(dst.to_path_buf()),
+                    _ => FgError::IoError(e),
+                })?;
+                std::fs::remove_file(&tmp).map_err(FgError::IoError)
+            }
+        });
+        if result.is_err() {
+            let _ = std::fs::remove_file(&tmp);
+        }
+        result
+    }
+
+    /// Writes everything from a reader to the new file `tmp`, compressed with the given format,
+    /// with any checksum sidecar named after `dst`.  The file is finished and synced to disk
+    /// before returning, so that it is complete once moved to `dst`.
+    fn write_file(
+        &self,
+        tmp: &Path,
+        dst: &Path,
+        format: CompressionFormat,
+        read: &mut dyn Read,
+    ) -> Result<()> {
+        let file = self.open_file(&tmp, OpenOptions::new().write(true).create_new(true))?;
+        let handle = file.try_clone().map_err(FgError::IoError)?;
+        let write = self.compress(self.checksummed(file, &dst, false)?, format)?;
+        let mut out = BufWriter::with_capacity(self.buffer_size, write);
+        std::io::copy(read, &mut out).map_err(FgError::IoError)?;
+        out.finish().map_err(FgError::IoError)?;
+        handle.sync_all().map_err(FgError::IoError)
+    }
+
+    /// Copies everything from a reader to a file, compressing it according to the file's
+    /// extension.
+    fn copy_to<R, Q>(&self, read: &mut R, dst: &Q) -> Result<u64>
+    where
+        R: Read + ?Sized,
+        Q: AsRef<Path>,
+    {
+        let write =
+            self.open_write(dst, false, |w| self.compress(w, self.compression_format(dst)))?;
+        let mut out = BufWriter::with_capacity(self.buffer_size, write);
+        let n = std::io::copy(read, &mut out).map_err(FgError::IoError)?;
+        out.finish().map_err(FgError::IoError)?;
+        Ok(n)
+    }
+
+    /// Reads the entire (decompressed) contents of a file into a String.  Fails if the
+    /// contents are not valid UTF-8.
+    pub fn read_to_string<P>(&self, p: &P) -> Result<String>
+    where
+        P: AsRef<Path>,
+    {
+        let mut r = self.new_reader(p)?;
+        let mut s = String::new();
+        r.read_to_string(&mut s).map_err(FgError::IoError)?;
+        Ok(s)
+    }
+
+    /// Writes a slice of bytes to a file, compressing it if required by the file's extension.
+    pub fn write_bytes<P>(&self, p: &P, bytes: &[u8]) -> Result<()>
+    where
+        P: AsRef<Path>,
+    {
+        let mut out = self.new_writer(p)?;
+        out.write_all(bytes).map_err(FgError::IoError)?;
+        out.finish().map_err(FgError::IoError)
+    }
+
+    /// Returns the compression format used to read and write the given path, based on the
+    /// extensions registered with this `Io`.
+    pub fn compression_format<P: AsRef<Path>>(&self, p: &P) -> CompressionFormat {
+        self.matching_extension(p).map_or(CompressionFormat::None, |(_, format)| *format)
+    }
+
+    /// Returns the longest registered extension, and its format, that the path ends with.
+    fn matching_extension<P: AsRef<Path>>(&self, p: &P) -> Option<&(String, CompressionFormat)> {
+        let name = p.as_ref().file_name().and_then(|n| n.to_str())?;
+        self.extensions
+            .iter()
+            .filter(|(ext, _)| {
+                name.len() > ext.len() + 1
+                    && name.ends_with(ext.as_str())
+                    && name.as_bytes()[name.len() - ext.len() - 1] == b'.'
+            })
+            .max_by_key(|(ext, _)| ext.len())
+    }
+
+    /// Returns true if the path ends with a recognized file extension
+    fn is_path_with_extension<P: AsRef<Path>, const N: usize>(
+        p: &P,
+        extensions: [&str; N],
+    ) -> bool {
+        if let Some(ext) = p.as_ref().extension() {
+            match ext.to_str() {
+                Some(x) => extensions.contains(&x),
+                None => false,
+            }
+        } else {
+            false
+        }
+    }
+
+    /// Returns true if the path is `-` or `/dev/stdin`, which are read from standard input
+    pub fn is_stdin_path<P: AsRef<Path>>(p: &P) -> bool {
+        STDIN_PATHS.iter().any(|s| p.as_ref() == Path::new(s))
+    }
+
+    /// Returns true if the path is `-` or `/dev/stdout`, which are written to standard output
+    pub fn is_stdout_path<P: AsRef<Path>>(p: &P) -> bool {
+        STDOUT_PATHS.iter().any(|s| p.as_ref() == Path::new(s))
+    }
+
+    /// Returns true if the path is `/dev/null`, which discards all data written to it and is
+    /// read as empty, regardless of platform
+    pub fn is_null_path<P: AsRef<Path>>(p: &P) -> bool {
+        NULL_PATHS.iter().any(|s| p.as_ref() == Path::new(s))
+    }
+
+    /// Returns true if the path is an `http://` or `https://` URL, which can be read from when
+    /// the `http` feature is enabled
+    pub fn is_url_path<P: AsRef<Path>>(p: &P) -> bool {
+        let p = p.as_ref().to_string_lossy();
+        URL_SCHEMES.iter().any(|s| p.starts_with(s))
+    }
+
+    /// Returns true if the path is an `s3://` or `gs://` URL, which can be read from and written
+    /// to when the `cloud` feature is enabled
+    #[cfg(feature = "cloud")]
+    pub fn is_object_store_path<P: AsRef<Path>>(p: &P) -> bool {
+        let p = p.as_ref().to_string_lossy();
+        cloud::OBJECT_STORE_SCHEMES.iter().any(|s| p.starts_with(s))
+    }
+
+    /// Returns a writer that discards all data written to it, of the same type as returned by
+    /// [`Io::new_writer`], so that optional outputs can be disabled without special-casing.
+    pub fn sink(&self) -> BufWriter<Box<dyn FinishWrite>> {
+        let write: Box<dyn FinishWrite> = Box::new(std::io::sink());
+        BufWriter::with_capacity(self.buffer_size, write)
+    }
+
+    /// Returns true if the path ends with a recognized FASTQ file extension
+    pub fn is_fastq_path<P: AsRef<Path>>(p: &P) -> bool {
+        Self::is_path_with_extension(p, FASTQ_EXTENSIONS)
+    }
+
+    /// Returns true if the path ends with a recognized GZIP file extension
+    pub fn is_gzip_path<P: AsRef<Path>>(p: &P) -> bool {
+        Self::is_path_with_extension(p, GZIP_EXTENSIONS)
+    }
+
+    /// Returns true if the path ends with a recognized BGZF file extension
+    pub fn is_bgzf_path<P: AsRef<Path>>(p: &P) -> bool {
+        Self::is_path_with_extension(p, BGZF_EXTENSIONS)
+    }
+
+    /// Returns true if the path ends with a recognized ZSTD file extension
+    pub fn is_zstd_path<P: AsRef<Path>>(p: &P) -> bool {
+        Self::is_path_with_extension(p, ZSTD_EXTENSIONS)
+    }
+
+    /// Returns true if the path ends with a recognized BZIP2 file extension
+    #[cfg(feature = "bzip2")]
+    pub fn is_bzip2_path<P: AsRef<Path>>(p: &P) -> bool {
+        Self::is_path_with_extension(p, BZIP2_EXTENSIONS)
+    }
+
+    /// Returns true if the path ends with a recognized XZ file extension
+    #[cfg(feature = "xz")]
+    pub fn is_xz_path<P: AsRef<Path>>(p: &P) -> bool {
+        Self::is_path_with_extension(p, XZ_EXTENSIONS)
+    }
+
+    /// Returns true if the path ends with a recognized LZ4 file extension
+    #[cfg(feature = "lz4")]
+    pub fn is_lz4_path<P: AsRef<Path>>(p: &P) -> bool {
+        Self::is_path_with_extension(p, LZ4_EXTENSIONS)
+    }
+
+    /// Returns true if the path ends with a recognized snappy (framed) file extension
+    #[cfg(feature = "snappy")]
+    pub fn is_snappy_path<P: AsRef<Path>>(p: &P) -> bool {
+        Self::is_path_with_extension(p, SNAPPY_EXTENSIONS)
+    }
+}
+
+/// Struct that contains associated functions for reading and writing Structs to/from
+/// delimited files.  Structs should use serde's Serialize/Deserialize derive macros in
+/// order to be used with these functions.  The handling of quotes, comments, whitespace and
+/// line terminators can be configured with a [`DelimFileBuilder`].
+pub struct DelimFile {
+    io: Io,
+    quote: u8,
+    quote_style: QuoteStyle,
+    escape: Option<u8>,
+    comment: Option<u8>,
+    flexible: bool,
+    ragged: bool,
+    trim: Trim,
+    terminator: LineTerminator,
+    write_header: bool,
+    strict_headers: bool,
+    strict: bool,
+    extra_columns: ExtraColumns,
+    duplicate_columns: DuplicateColumns,
+    column_order: Option<Vec<String>>,
+    preamble: Vec<String>,
+    null_tokens: Vec<String>,
+    parsers: Vec<(String, ColumnParser)>,
+    sort_buffer_size: usize,
+    write_format: WriteFormat,
+}
+
+/// The default number of bytes of records held in memory by [`DelimFile::sort`].
+const DEFAULT_SORT_BUFFER_SIZE: usize = 256 * 1024 * 1024;
+
+/// Generates a default implementation that uses the default Io instance
+impl Default for DelimFile {
+    fn default() -> Self {
+        DelimFile::builder().build()
+    }
+}
+
+/// Builder for [`DelimFile`] instances that exposes the configuration of the underlying csv
+/// readers and writers.
+///
+/// ```rust
+/// use fgoxide::io::{DelimFile, Trim};
+///
+/// let delim = DelimFile::builder().comment(Some(b'#')).trim(Trim::All).build();
+/// ```
+pub struct DelimFileBuilder {
+    io: Io,
+    quote: u8,
+    quote_style: QuoteStyle,
+    escape: Option<u8>,
+    comment: Option<u8>,
+    flexible: bool,
+    ragged: bool,
+    trim: Trim,
+    terminator: Option<LineTerminator>,
+    write_header: bool,
+    strict_headers: bool,
+    strict: bool,
+    extra_columns: ExtraColumns,
+    duplicate_columns: DuplicateColumns,
+    column_order: Option<Vec<String>>,
+    preamble: Vec<String>,
+    null_tokens: Vec<String>,
+    parsers: Vec<(String, ColumnParser)>,
+    sort_buffer_size: usize,
+    write_format: WriteFormat,
+}
+
+impl Default for DelimFileBuilder {
+    fn default() -> Self {
+        DelimFileBuilder {
+            io: Io::default(),
+            quote: b'"',
+            quote_style: QuoteStyle::Necessary,
+            escape: None,
+            comment: None,
+            flexible: false,
+            ragged: false,
+            trim: Trim::None,
+            terminator: None,
+            write_header: true,
+            strict_headers: false,
+            strict: false,
+            extra_columns: ExtraColumns::Ignore,
+            duplicate_columns: DuplicateColumns::Reject,
+            column_order: None,
+            preamble: vec![],
+            null_tokens: vec![],
+            parsers: vec![],
+            sort_buffer_size: DEFAULT_SORT_BUFFER_SIZE,
+            write_format: WriteFormat::default(),
+        }
+    }
+}
+
+impl DelimFileBuilder {
+    /// Sets the [`Io`] instance used to open files for reading and writing.
+    pub fn io(mut self, io: Io) -> DelimFileBuilder {
+        self.io = io;
+        self
+    }
+
+    /// Sets the character used to quote fields.  Defaults to `"`.
+    pub fn quote(mut self, quote: u8) -> DelimFileBuilder {
+        self.quote = quote;
+        self
+    }
+
+    /// Sets which fields are quoted when writing with quoting enabled, e.g.
+    /// [`QuoteStyle::Always`] for downstream parsers that require every field to be quoted, or
+    /// [`QuoteStyle::NonNumeric`] to quote all fields that are not numbers.  Defaults to
+    /// [`QuoteStyle::Necessary`], which quotes only fields containing the delimiter, the quote
+    /// character or a line break.  Writes with quoting disabled never quote fields.
+    pub fn quote_style(mut self, style: QuoteStyle) -> DelimFileBuilder {
+        self.quote_style = style;
+        self
+    }
+
+    /// Sets the character used to escape quotes within quoted fields.  By default quotes are
+    /// escaped by doubling them, e.g. `"a ""quoted"" word"`.
+    pub fn escape(mut self, escape: Option<u8>) -> DelimFileBuilder {
+        self.escape = escape;
+        self
+    }
+
+    /// Sets the character that marks a line as a comment when it appears at the start of a line.
+    /// Comment lines are skipped when reading.  Defaults to no comment character.
+    pub fn comment(mut self, comment: Option<u8>) -> DelimFileBuilder {
+        self.comment = comment;
+        self
+    }
+
+    /// Sets whether records may have differing numbers of fields.  When false (the default),
+    /// reading or writing a record with a different number of fields from the first is an error.
+    pub fn flexible(mut self, flexible: bool) -> DelimFileBuilder {
+        self.flexible = flexible;
+        self
+    }
+
+    /// Sets whether records with fewer fields than the header can be read, for files whose
+    /// writers drop empty trailing fields.  Missing fields are read as absent, so that they
+    /// deserialize as `None` for `Option` fields or as the default for fields marked
+    /// `#[serde(default)]`, and the number of such records is counted by
+    /// [`DelimFileReader::padded_records`].  Unlike [`flexible`](DelimFileBuilder::flexible)
+    /// reading, records with more fields than the header fail with a
+    /// [`FgError::FieldCountMismatch`].  Has no effect when reading is
+    /// [`strict`](DelimFileBuilder::strict).  Defaults to false.
+    pub fn ragged(mut self, ragged: bool) -> DelimFileBuilder {
+        self.ragged = ragged;
+        self
+    }
+
+    /// Sets whether leading and trailing whitespace is trimmed from headers and/or fields when
+    /// reading.  Defaults to no trimming.
+    pub fn trim(mut self, trim: Trim) -> DelimFileBuilder {
+        self.trim = trim;
+        self
+    }
+
+    /// Sets the terminator written at the end of each record, overriding the
+    /// [`IoBuilder::line_terminator`] of the [`Io`] instance.  When reading, a custom terminator
+    /// byte ends records in place of newlines.
+    pub fn terminator(mut self, terminator: LineTerminator) -> DelimFileBuilder {
+        self.terminator = Some(terminator);
+        self
+    }
+
+    /// Sets whether a header line is written before the records of delimited files.  Defaults to
+    /// true; disabling it is useful when writing fragments that will be concatenated with other
+    /// output.
+    pub fn write_header(mut self, write_header: bool) -> DelimFileBuilder {
+        self.write_header = write_header;
+        self
+    }
+
+    /// Sets whether the header of a file being read must contain exactly the fields of the
+    /// struct being deserialized.  When true, reading fails before any records are parsed with a
+    /// [`FgError::HeaderMismatch`] listing the missing and unexpected columns, including missing
+    /// columns for optional fields.  Defaults to false.
+    pub fn strict_headers(mut self, strict: bool) -> DelimFileBuilder {
+        self.strict_headers = strict;
+        self
+    }
+
+    /// Sets whether files being read must match the struct being deserialized exactly.  When
+    /// true, the header is checked as for [`DelimFileBuilder::strict_headers`], records are read
+    /// as if not [`flexible`](DelimFileBuilder::flexible), and a record with more or fewer
+    /// fields than the header fails with a [`FgError::FieldCountMismatch`] giving its line
+    /// number, rather than fields being silently ignored or filled with defaults.  Defaults to
+    /// false.
+    pub fn strict(mut self, strict: bool) -> DelimFileBuilder {
+        self.strict = strict;
+        self
+    }
+
+    /// Sets how columns that do not correspond to any field of the struct being read are
+    /// handled.  By default they are ignored, so that a small struct can be read from a subset
+    /// of the columns of a wide file.
+    pub fn extra_columns(mut self, extra_columns: ExtraColumns) -> DelimFileBuilder {
+        self.extra_columns = extra_columns;
+        self
+    }
+
+    /// Sets how columns with the same name in the header of a file being read are handled.  By
+    /// default reading fails with a [`FgError::DuplicateColumn`] naming the column, rather than
+    /// records being read from only one of the columns.
+    pub fn duplicate_columns(mut self, duplicates: DuplicateColumns) -> DelimFileBuilder {
+        self.duplicate_columns = duplicates;
+        self
+    }
+
+    /// Sets the columns written to delimited files, in order, in place of the fields of each
+    /// record in the order they are declared.  Columns may be a subset of the record's fields,
+    /// in which case other fields are not written, or include columns that are not fields, which
+    /// are written as empty fields.  Has no effect on headerless files written by position.
+    pub fn column_order<I, C>(mut self, columns: I) -> DelimFileBuilder
+    where
+        I: IntoIterator<Item = C>,
+        C: Into<String>,
+    {
+        self.column_order = Some(columns.into_iter().map(Into::into).collect());
+        self
+    }
+
+    /// Sets lines of metadata, such as `# generated-by=tool v1.2`, that are written verbatim
+    /// before the header line of delimited files.  Lines should start with the comment
+    /// character so that readers can skip them, or capture them with
+    /// [`DelimFile::read_with_comments`].  The preamble is not written when no header line is.
+    pub fn preamble<I, L>(mut self, lines: I) -> DelimFileBuilder
+    where
+        I: IntoIterator<Item = L>,
+        L: Into<String>,
+    {
+        self.preamble = lines.into_iter().map(Into::into).collect();
+        self
+    }
+
+    /// Sets tokens, such as `NA`, `NaN` or `.`, that are read as missing values.  Fields that
+    /// exactly match any of the tokens are read as if they were empty, so that they deserialize
+    /// as `None` for `Option` fields.  Note that this also applies to other fields, so that a
+    /// `String` field containing a token is read as an empty string.  Empty fields are always
+    /// read as `None` for `Option` fields.
+    pub fn null_tokens<I, T>(mut self, tokens: I) -> DelimFileBuilder
+    where
+        I: IntoIterator<Item = T>,
+        T: Into<String>,
+    {
+        self.null_tokens = tokens.into_iter().map(Into::into).collect();
+        self
+    }
+
+    /// Registers a function that converts the values of a column before they are deserialized,
+    /// so that values such as `1,234` or `yes` can be read into numeric or boolean fields.  The
+    /// column is matched by name after any renames, or by the name of the field in headerless
+    /// files.  The function is not called for empty fields or fields matching the
+    /// [`null_tokens`](DelimFileBuilder::null_tokens).  Registering a second function for the
+    /// same column replaces the first.
+    ///
+    /// ```rust
+    /// use fgoxide::io::DelimFile;
+    ///
+    /// let delim = DelimFile::builder().column_parser("count", |v| v.replace(',', "")).build();
+    /// ```
+    pub fn column_parser<F>(mut self, column: impl Into<String>, parser: F) -> DelimFileBuilder
+    where
+        F: Fn(&str) -> String + Send + Sync + 'static,
+    {
+        let column = column.into();
+        self.parsers.retain(|(c, _)| *c != column);
+        self.parsers.push((column, Arc::new(parser)));
+        self
+    }
+
+    /// Sets the format of dates and times in a column, so that values such as `1/31/2024` or
+    /// Excel serial dates can be read into `chrono` fields.  Values are converted to ISO 8601
+    /// with a [`column_parser`](DelimFileBuilder::column_parser), replacing any parser already
+    /// registered for the column; values not in the format are passed on unchanged, so fail
+    /// to deserialize.  Only affects reading; use the serde helpers in [`dates`] to also write
+    /// values in a format other than ISO 8601.
+    #[cfg(feature = "chrono")]
+    pub fn date_format(self, column: impl Into<String>, format: DateFormat) -> DelimFileBuilder {
+        self.column_parser(column, move |v| format.to_iso8601(v).unwrap_or_else(|| v.to_string()))
+    }
+
+    /// Sets a token, such as `NA` or `.`, that is written for `None` values in place of an
+    /// empty field, so that missing values can be told apart from empty strings by tools such
+    /// as R.  The token is written as-is, quoted only if it contains special characters.
+    pub fn none_token(mut self, token: impl Into<String>) -> DelimFileBuilder {
+        self.write_format.null_token = Some(token.into());
+        self
+    }
+
+    /// Sets the format of `f32` and `f64` fields when writing, e.g. to a fixed number of decimal
+    /// places so that output is stable and readable.  Defaults to [`FloatFormat::Shortest`].
+    /// Non-finite values are always written as `NaN`, `inf` and `-inf`.
+    ///
+    /// # Panics
+    /// - panics if `format` is [`FloatFormat::SignificantDigits`] with zero digits
+    pub fn float_format(mut self, format: FloatFormat) -> DelimFileBuilder {
+        if let FloatFormat::SignificantDigits(digits) = format {
+            assert_ne!(digits, 0, "Number of significant digits cannot be zero!");
+        }
+        self.write_format.float_format = format;
+        self
+    }
+
+    /// Sets whether booleans are written as `1` and `0` rather than `true` and `false`, as
+    /// expected by many statistical tools.  When set, boolean fields are read from either form.
+    pub fn bools_as_ints(mut self, bools_as_ints: bool) -> DelimFileBuilder {
+        self.write_format.bools_as_ints = bools_as_ints;
+        self
+    }
+
+    /// Sets the approximate number of bytes of records held in memory by [`DelimFile::sort`]
+    /// before sorted chunks are written to temporary files.  Defaults to 256MiB.
+    pub fn sort_buffer_size(mut self, bytes: usize) -> DelimFileBuilder {
+        self.sort_buffer_size = bytes.max(1);
+        self
+    }
+
+    /// Builds the configured [`DelimFile`] instance.
+    pub fn build(self) -> DelimFile {
+        DelimFile {
+            terminator: self.terminator.unwrap_or(self.io.line_terminator),
+            io: self.io,
+            quote: self.quote,
+            quote_style: self.quote_style,
+            escape: self.escape,
+            comment: self.comment,
+            flexible: self.flexible,
+            ragged: self.ragged,
+            trim: self.trim,
+            write_header: self.write_header,
+            strict_headers: self.strict_headers,
+            strict: self.strict,
+            extra_columns: self.extra_columns,
+            duplicate_columns: self.duplicate_columns,
+            column_order: self.column_order,
+            preamble: self.preamble,
+            null_tokens: self.null_tokens,
+            parsers: self.parsers,
+            sort_buffer_size: self.sort_buffer_size,
+            write_format: self.write_format,
+        }
+    }
+}
+
+impl DelimFile {
+    /// Creates a new DelimFile that reads and writes files using the given [`Io`] instance.
+    pub fn new(io: Io) -> DelimFile {
+        DelimFile::builder().io(io).build()
+    }
+
+    /// Returns a [`DelimFileBuilder`] for configuring a new DelimFile.
+    pub fn builder() -> DelimFileBuilder {
+        DelimFileBuilder::default()
+    }
+
+    /// Returns a copy of this DelimFile whose [`Io`] settings have been changed by `configure`,
+    /// which is passed a builder holding the current settings.  This allows the compression
+    /// level or number of threads to be chosen per call, e.g. fast compression for temporary
+    /// files and high compression for final outputs, while sharing the rest of the configuration.
+    ///
+    /// ```rust
+    /// use fgoxide::io::DelimFile;
+    ///
+    /// let delim = DelimFile::default();
+    /// let fast = delim.with_io(|io| io.compression(1).zstd_level(1));
+    /// let small = delim.with_io(|io| io.compression(9).threads(4));
+    /// ```
+    pub fn with_io<F>(&self, configure: F) -> DelimFile
+    where
+        F: FnOnce(IoBuilder) -> IoBuilder,
+    {
+        DelimFile {
+            io: configure(self.io.to_builder()).build(),
+            quote: self.quote,
+            quote_style: self.quote_style,
+            escape: self.escape,
+            comment: self.comment,
+            flexible: self.flexible,
+            ragged: self.ragged,
+            trim: self.trim,
+            terminator: self.terminator,
+            write_header: self.write_header,
+            strict_headers: self.strict_headers,
+            strict: self.strict,
+            extra_columns: self.extra_columns,
+            duplicate_columns: self.duplicate_columns,
+            column_order: self.column_order.clone(),
+            preamble: self.preamble.clone(),
+            null_tokens: self.null_tokens.clone(),
+            parsers: self.parsers.clone(),
+            sort_buffer_size: self.sort_buffer_size,
+            write_format: self.write_format.clone(),
+        }
+    }
+
+    /// Writes a series of one or more structs to a delimited file.  If `quote` is true then fields
+    /// will be quoted as necessary, or as set by [`DelimFileBuilder::quote_style`], otherwise
+    /// they will never be quoted.
+    pub fn write<S, P>(
+        &self,
+        path: &P,
+        recs: impl IntoIterator<Item = S>,
+        delimiter: u8,
+        quote: bool,
+    ) -> Result<()>
+    where
+        S: Serialize,
+        P: AsRef<Path>,
+    {
+        let mut writer = self.new_writer(path, delimiter, quote)?;
+        writer.write_records(recs)?;
+        writer.finish()
+    }
+
+    /// Writes a series of records to a delimited file without a header line, with fields in the
+    /// order the record's members are declared.  The separator and quoting are as for
+    /// [`DelimFile::write`].
+    pub fn write_headerless<S, P>(
+        &self,
+        path: &P,
+        recs: impl IntoIterator<Item = S>,
+        delimiter: u8,
+        quote: bool,
+    ) -> Result<()>
+    where
+        S: Serialize,
+        P: AsRef<Path>,
+    {
+        let write = self.io.new_writer(path)?;
+        let writer = DelimFileWriter::new(self.csv_writer(write, delimiter, quote, false));
+        let mut writer = writer.with_format(self.write_format.clone());
+        writer.write_records(recs)?;
+        writer.finish()
+    }
+
+    /// Opens a delimited file for writing, returning a [`DelimFileWriter`] to which records can
+    /// be written one at a time as they are produced.  The separator and quoting are as for
+    /// [`DelimFile::write`].
+    pub fn new_writer<S, P>(
+        &self,
+        path: &P,
+        delimiter: u8,
+        quote: bool,
+    ) -> Result<DelimFileWriter<S>>
+    where
+        S: Serialize,
+        P: AsRef<Path>,
+    {
+        let write = self.io.new_writer(path)?;
+        self.delim_writer(write, delimiter, quote, true)
+    }
+
+    /// Streams the records of a delimited file through a function, writing the records it returns
+    /// to `output`, e.g. to derive a new column from existing ones or to filter and reshape a
+    /// file in one pass.  Each record is deserialized as an `In`, and records for which `f`
+    /// returns `None` are dropped.  Input and output are compressed or decompressed according to
+    /// their paths, as for [`Io::new_reader`] and [`Io::new_writer`].  The same separator is used
+    /// for both files, and quoting is as for [`DelimFile::read`] and [`DelimFile::write`].
+    /// Returns the number of records written.
+    ///
+    /// ```no_run
+    /// use fgoxide::io::DelimFile;
+    /// use serde::{Deserialize, Serialize};
+    ///
+    /// #[derive(Deserialize)]
+    /// struct Count { sample: String, reads: u64, duplicates: u64 }
+    ///
+    /// #[derive(Serialize)]
+    /// struct Rate { sample: String, duplicate_rate: f64 }
+    ///
+    /// let written = DelimFile::default().process(
+    ///     &"counts.tsv.gz",
+    ///     &"rates.tsv",
+    ///     b'\t',
+    ///     |c: Count| {
+    ///         let duplicate_rate = c.duplicates as f64 / c.reads as f64;
+    ///         (c.reads > 0).then(|| Rate { sample: c.sample, duplicate_rate })
+    ///     },
+    /// );
+    /// ```
+    pub fn process<In, Out, P, Q, F>(
+        &self,
+        input: &P,
+        output: &Q,
+        delimiter: u8,
+        mut f: F,
+    ) -> Result<u64>
+    where
+        In: DeserializeOwned,
+        Out: Serialize,
+        P: AsRef<Path>,
+        Q: AsRef<Path>,
+        F: FnMut(In) -> Option<Out>,
+    {
+        let reader = self.read_iter::<In, _>(input, delimiter, true)?;
+        let mut writer = self.new_writer(output, delimiter, true)?;
+        let mut count = 0;
+        for rec in reader {
+            if let Some(rec) = f(rec?) {
+                writer.write_record(&rec)?;
+                count += 1;
+            }
+        }
+        writer.finish()?;
+        Ok(count)
+    }
+
+    /// Opens several files for writing, each in the given [`TableFormat`], returning a
+    /// [`MultiWriter`] that writes each record to all of them, e.g. to write the same records
+    /// to both a `.tsv.gz` and, with the `json` feature, a `.jsonl.zst`.  Delimited outputs are
+    /// written as with [`DelimFile::new_writer`], with quoting, and JSON Lines outputs have one
+    /// JSON object per record.  Compression is chosen by each output's path.
+    pub fn new_multi_writer<S, P>(&self, outputs: &[(P, TableFormat)]) -> Result<MultiWriter<S>>
+    where
+        S: Serialize,
+        P: AsRef<Path>,
+    {
+        let mut writer = MultiWriter::new();
+        for (path, format) in outputs {
+            match format.delimiter() {
+                Some(delimiter) => writer.push_delim(self.new_writer(path, delimiter, true)?),
+                #[cfg(feature = "json")]
+                None => writer.push_jsonl(self.io.new_writer(path)?),
+                #[cfg(not(feature = "json"))]
+                None => unreachable!("only JSON Lines has no delimiter"),
+            }
+        }
+        Ok(writer)
+    }
+
+    /// Writes a series of records to several files in one pass over the records, as with
+    /// [`DelimFile::new_multi_writer`], so that records from an iterator that cannot be cloned
+    /// or restarted can be written in more than one format.  Returns the number of records.
+    pub fn write_multi<S, P>(
+        &self,
+        outputs: &[(P, TableFormat)],
+        recs: impl IntoIterator<Item = S>,
+    ) -> Result<u64>
+    where
+        S: Serialize,
+        P: AsRef<Path>,
+    {
+        let mut writer = self.new_multi_writer(outputs)?;
+        let count = writer.write_records(recs)?;
+        writer.close()?;
+        Ok(count)
+    }
+
+    /// Writes a series of structs as delimited data to any writer, such as a socket or an
+    /// in-memory buffer, with a header line.  No compression is applied.  The separator and
+    /// quoting are as for [`DelimFile::write`].
+    pub fn write_to<S, W>(
+        &self,
+        write: W,
+        recs: impl IntoIterator<Item = S>,
+        delimiter: u8,
+        quote: bool,
+    ) -> Result<()>
+    where
+        S: Serialize,
+        W: Write,
+    {
+        let mut writer = self.delim_writer(write, delimiter, quote, true)?;
+        writer.write_records(recs)?;
+        writer.close()
+    }
+
+    /// Writes a series of structs as delimited text, with a header line, to a new String.  The
+    /// separator and quoting are as for [`DelimFile::write`].
+    pub fn write_to_string<S>(
+        &self,
+        recs: impl IntoIterator<Item = S>,
+        delimiter: u8,
+        quote: bool,
+    ) -> Result<String>
+    where
+        S: Serialize,
+    {
+        let mut buf = vec![];
+        self.write_to(&mut buf, recs, delimiter, quote)?;
+        String::from_utf8(buf)
+            .map_err(|e| FgError::IoError(std::io::Error::new(ErrorKind::InvalidData, e)))
+    }
+
+    /// Appends a series of structs to a delimited file, creating the file if it does not exist.
+    /// A header line is written only if the file is new or empty.  If `quote` is true then fields
+    /// will be quoted as necessary, otherwise they will never be quoted.  See [`Io::new_appender`]
+    /// for how compressed files are appended to.
+    pub fn append<S, P>(
+        &self,
+        path: &P,
+        recs: impl IntoIterator<Item = S>,
+        delimiter: u8,
+        quote: bool,
+    ) -> Result<()>
+    where
+        S: Serialize,
+        P: AsRef<Path>,
+    {
+        let has_data = path.as_ref().metadata().map(|m| m.len() > 0).unwrap_or(false);
+        let write = self.io.new_appender(path)?;
+        let mut writer = self.delim_writer(write, delimiter, quote, !has_data)?;
+        writer.write_records(recs)?;
+        writer.finish()
+    }
+
+    /// Builds a [`DelimFileWriter`] over the given writer, writing a header line if `headers`
+    /// is true and header lines have not been disabled with [`DelimFileBuilder::write_header`].
+    /// Any preamble is written immediately, if a header line is to be written.
+    fn delim_writer<S: Serialize, W: Write>(
+        &self,
+        mut write: W,
+        delimiter: u8,
+        quote: bool,
+        headers: bool,
+    ) -> Result<DelimFileWriter<S, W>> {
+        let headers = headers && self.write_header;
+        if headers {
+            self.write_preamble(&mut write)?;
+        }
+
+        let writer = match &self.column_order {
+            Some(columns) => {
+                let writer = self.csv_writer(write, delimiter, quote, false);
+                DelimFileWriter::with_column_order(writer, columns.clone(), headers)
+            }
+            None => DelimFileWriter::new(self.csv_writer(write, delimiter, quote, headers)),
+        };
+        Ok(writer.with_format(self.write_format.clone()))
+    }
+
+    /// Writes the preamble lines, each followed by the line terminator.
+    fn write_preamble<W: Write>(&self, write: &mut W) -> Result<()> {
+        for line in &self.preamble {
+            write.write_all(line.as_bytes()).map_err(FgError::IoError)?;
+            write.write_all(self.terminator.as_bytes()).map_err(FgError::IoError)?;
+        }
+        Ok(())
+    }
+
+    /// Builds a csv writer over the given writer, optionally writing a header line.
+    fn csv_writer<W: Write>(
+        &self,
+        write: W,
+        delimiter: u8,
+        quote: bool,
+        headers: bool,
+    ) -> csv::Writer<W> {
+        let mut builder = WriterBuilder::new();
+        builder
+            .delimiter(delimiter)
+            .has_headers(headers)
+            .terminator(self.terminator.to_csv())
+            .quote(self.quote)
+            .flexible(self.flexible)
+            .quote_style(if quote { self.quote_style } else { QuoteStyle::Never });
+        if let Some(escape) = self.escape {
+            builder.double_quote(false).escape(escape);
+        }
+        builder.from_writer(write)
+    }
+
+    /// Writes structs implementing `[Serialize]` to a file with tab separators between fields.
+    pub fn write_tsv<S, P>(&self, path: &P, recs: impl IntoIterator<Item = S>) -> Result<()>
+    where
+        S: Serialize,
+        P: AsRef<Path>,
+    {
+        self.write(path, recs, b'\t', true)
+    }
+
+    /// Writes structs implementing `[Serialize]` to a file with comma separators between fields.
+    pub fn write_csv<S, P>(&self, path: &P, recs: impl IntoIterator<Item = S>) -> Result<()>
+    where
+        S: Serialize,
+        P: AsRef<Path>,
+    {
+        self.write(path, recs, b',', true)
+    }
+
+    /// Reads structs implementing `[Deserialize]` from a file with the given separators between fields.
+    /// If `quote` is true then fields surrounded by quotes are parsed, otherwise quotes are not
+    /// considered.
+    pub fn read<D, P>(&self, path: &P, delimiter: u8, quote: bool) -> Result<Vec<D>>
+    where
+        D: DeserializeOwned,
+        P: AsRef<Path>,
+    {
+        self.read_iter(path, delimiter, quote)?.collect()
+    }
+
+    /// Reads structs implementing `[Deserialize]` from a delimited file as with
+    /// [`DelimFile::read`], appending them to `recs` rather than returning a new vector, so that
+    /// a service reading many files can reuse the vector's storage by clearing it between
+    /// reads.  Returns the number of records read.  If a record cannot be read the error is
+    /// returned, leaving the records read before it in `recs`.
+    pub fn read_into<D, P>(
+        &self,
+        path: &P,
+        delimiter: u8,
+        quote: bool,
+        recs: &mut Vec<D>,
+    ) -> Result<usize>
+    where
+        D: DeserializeOwned,
+        P: AsRef<Path>,
+    {
+        self.read_iter(path, delimiter, quote)?.read_into(recs)
+    }
+
+    /// Reads structs implementing `[Deserialize]` from a delimited file as with
+    /// [`DelimFile::read`], but skips records that cannot be parsed or deserialized rather than
+    /// failing, so that a few malformed lines do not prevent a large file from being processed.
+    /// Returns the records read along with a [`BadRecord`] giving the line number and error for
+    /// each record skipped.
+    pub fn read_lenient<D, P>(
+        &self,
+        path: &P,
+        delimiter: u8,
+        quote: bool,
+    ) -> Result<(Vec<D>, Vec<BadRecord>)>
+    where
+        D: DeserializeOwned,
+        P: AsRef<Path>,
+    {
+        self.read_iter(path, delimiter, quote)?.read_lenient()
+    }
+
+    /// Opens a delimited file for reading, returning a [`DelimFileReader`] that deserializes
+    /// records one at a time as they are read rather than collecting them all into memory.  The
+    /// separator and quoting are as for [`DelimFile::read`].
+    pub fn read_iter<D, P>(
+        &self,
+        path: &P,
+        delimiter: u8,
+        quote: bool,
+    ) -> Result<DelimFileReader<D>>
+    where
+        D: DeserializeOwned,
+        P: AsRef<Path>,
+    {
+        let read = self.io.new_reader(path)?;
+        Ok(self.delim_reader(read, delimiter, quote, &HashMap::new())?.with_path(path.as_ref()))
+    }
+
+    /// Opens a delimited file for reading as with [`DelimFile::read_iter`], reading records
+    /// into buffers taken from an earlier reader with [`DelimFileReader::into_buffers`] rather
+    /// than allocating new ones.  Together with [`DelimFileReader::read_into`] this reduces
+    /// allocations when files with the same shape are read repeatedly.
+    ///
+    /// ```no_run
+    /// use fgoxide::io::{DelimFile, RecordBuffers};
+    /// use serde::Deserialize;
+    ///
+    /// #[derive(Deserialize)]
+    /// struct Count { sample: String, reads: u64 }
+    ///
+    /// let delim = DelimFile::default();
+    /// let mut buffers = RecordBuffers::default();
+    /// let mut counts: Vec<Count> = Vec::new();
+    /// for path in ["a.tsv", "b.tsv"] {
+    ///     counts.clear();
+    ///     let mut reader = delim.read_iter_with_buffers(&path, b'\t', true, buffers)?;
+    ///     reader.read_into(&mut counts)?;
+    ///     buffers = reader.into_buffers();
+    /// }
+    /// # Ok::<(), fgoxide::FgError>(())
+    /// ```
+    pub fn read_iter_with_buffers<D, P>(
+        &self,
+        path: &P,
+        delimiter: u8,
+        quote: bool,
+        buffers: RecordBuffers,
+    ) -> Result<DelimFileReader<D>>
+    where
+        D: DeserializeOwned,
+        P: AsRef<Path>,
+    {
+        Ok(self.read_iter(path, delimiter, quote)?.with_buffers(buffers))
+    }
+
+    /// Reads a delimited file as with [`DelimFile::read`], also returning the comment lines that
+    /// precede the header, such as the metadata preamble of a metrics file.  Comment lines are
+    /// those starting with the character set with [`DelimFileBuilder::comment`], and are returned
+    /// in full without their line terminators.  If no comment character is set, no lines are
+    /// treated as comments.  Comment lines after the header are skipped.
+    pub fn read_with_comments<D, P>(
+        &self,
+        path: &P,
+        delimiter: u8,
+        quote: bool,
+    ) -> Result<(Vec<String>, Vec<D>)>
+    where
+        D: DeserializeOwned,
+        P: AsRef<Path>,
+    {
+        let (comments, reader) = self.read_iter_with_comments(path, delimiter, quote)?;
+        Ok((comments, reader.collect::<Result<_>>()?))
+    }
+
+    /// Opens a delimited file for reading as with [`DelimFile::read_iter`], first reading the
+    /// comment lines that precede the header as for [`DelimFile::read_with_comments`].
+    pub fn read_iter_with_comments<D, P>(
+        &self,
+        path: &P,
+        delimiter: u8,
+        quote: bool,
+    ) -> Result<(Vec<String>, DelimFileReader<D>)>
+    where
+        D: DeserializeOwned,
+        P: AsRef<Path>,
+    {
+        let mut read = self.io.new_reader(path)?;
+        let comments = self.read_comments(&mut read)?;
+        let reader = self.delim_reader(read, delimiter, quote, &HashMap::new())?;
+        Ok((comments, reader.with_path(path.as_ref())))
+    }
+
+    /// Reads the consecutive comment lines at the current position of a reader.
+    fn read_comments<R: BufRead + ?Sized>(&self, read: &mut R) -> Result<Vec<String>> {
+        let mut comments = vec![];
+        let comment = match self.comment {
+            Some(comment) => comment,
+            None => return Ok(comments),
+        };
+
+        while read.fill_buf().map_err(FgError::IoError)?.first() == Some(&comment) {
+            let mut line = String::new();
+            read.read_line(&mut line).map_err(FgError::IoError)?;
+            let len = line.trim_end_matches(['\r', '\n']).len();
+            line.truncate(len);
+            comments.push(line);
+        }
+        Ok(comments)
+    }
+
+    /// Reads just the header line of a delimited file, returning the column names without
+    /// deserializing any records.  Returns an empty list for an empty file.
+    pub fn read_header<P>(&self, path: &P, delimiter: u8) -> Result<Vec<String>>
+    where
+        P: AsRef<Path>,
+    {
+        let read = self.io.new_reader(path)?;
+        let mut reader = self.csv_reader(read, delimiter, true, true);
+        let headers = reader.headers().map_err(FgError::ConversionError)?;
+        Ok(headers.iter().map(String::from).collect())
+    }
+
+    /// Guesses the format of a delimited file from its first lines, so that files can be read
+    /// without the user specifying the format.  Returns a [`SniffedFormat`] giving the most
+    /// likely delimiter of tab, comma, semicolon and pipe, and whether the first line looks like
+    /// a header, judged by whether its values differ in type or length from the values below
+    /// them.  Blank lines and comment lines are ignored.  The result is only a guess, and may
+    /// be wrong for files with few lines or columns.
+    pub fn sniff<P>(&self, path: &P) -> Result<SniffedFormat>
+    where
+        P: AsRef<Path>,
+    {
+        let lines = self.io.read_first_n_lines(path, sniff::SAMPLE_LINES)?;
+        let lines: Vec<&str> = lines
+            .iter()
+            .map(String::as_str)
+            .filter(|l| !l.trim().is_empty())
+            .filter(|l| self.comment.map_or(true, |c| !l.as_bytes().starts_with(&[c])))
+            .collect();
+        Ok(SniffedFormat::from_lines(&lines, self.quote))
+    }
+
+    /// Reads structs implementing `[Deserialize]` from a delimited file as with
+    /// [`DelimFile::read`], deserializing records on the current rayon thread pool while the
+    /// file is read and parsed on another thread.  Records are returned in the order they appear
+    /// in the file.  This can be much faster than [`DelimFile::read`] for records with many
+    /// fields, for which deserialization rather than reading is the bottleneck.
+    #[cfg(feature = "rayon")]
+    pub fn read_parallel<D, P>(&self, path: &P, delimiter: u8, quote: bool) -> Result<Vec<D>>
+    where
+        D: DeserializeOwned + Send,
+        P: AsRef<Path>,
+    {
+        self.read_iter(path, delimiter, quote)?.read_parallel()
+    }
+
+    /// Reads structs implementing `[Deserialize]` from a delimited file as with
+    /// [`DelimFile::read`], dividing the file into ranges that are parsed concurrently on up to
+    /// `threads` threads, so that all cores can be used to read very large files.  Records are
+    /// returned in the order they appear in the file.
+    ///
+    /// Only uncompressed and BGZF compressed files can be divided; other compressed files fail
+    /// with an error of kind [`ErrorKind::Unsupported`], as do files in UTF-16 or another
+    /// configured [`encoding`](IoBuilder::encoding).  A UTF-8 byte-order mark is removed and the
+    /// [`max_decompressed_bytes`](IoBuilder::max_decompressed_bytes) limit is applied as when
+    /// reading in order.  Because ranges are divided at line terminators, quoted fields must not
+    /// contain line terminators; files in which they do fail with an error of kind
+    /// [`ErrorKind::InvalidData`].
+    pub fn read_split<D, P>(
+        &self,
+        path: &P,
+        delimiter: u8,
+        quote: bool,
+        threads: usize,
+    ) -> Result<Vec<D>>
+    where
+        D: DeserializeOwned + Send,
+        P: AsRef<Path>,
+    {
+        let path = path.as_ref();
+        if self.io.has_encoding() {
+            let msg = format!("Cannot divide {} as an encoding is configured", path.display());
+            return Err(FgError::IoError(std::io::Error::new(ErrorKind::Unsupported, msg)));
+        }
+        let mut file = BufReader::new(File::open(path).map_err(FgError::IoError)?);
+        let compressed = self.io.read_format(&path, &mut file)? != CompressionFormat::None;
+        let layout =
+            Layout::detect(path, compressed).map_err(FgError::IoError)?.ok_or_else(|| {
+                FgError::IoError(std::io::Error::new(
+                    ErrorKind::Unsupported,
+                    format!("Cannot divide {} as it is not uncompressed or BGZF", path.display()),
+                ))
+            })?;
+
+        // Read through any comments and blank lines to the header to find the first record
+        let terminator = match self.terminator {
+            LineTerminator::Custom(b) => b,
+            LineTerminator::Lf | LineTerminator::CrLf => b'\n',
+        };
+        let max_bytes = self.io.max_decompressed_bytes;
+        let bytes_read = AtomicU64::new(0);
+        let count_bytes = |n: usize| match max_bytes {
+            Some(max) if bytes_read.fetch_add(n as u64, Ordering::Relaxed) + n as u64 > max => {
+                Err(FgError::IoError(limits::max_bytes_error(max)))
+            }
+            _ => Ok(()),
+        };
+        let mut reader = layout.open(path).map_err(FgError::IoError)?;
+        let mut header = vec![];
+        let mut lines = 0;
+        loop {
+            header.clear();
+            let n = reader.read_until(terminator, &mut header).map_err(FgError::IoError)?;
+            if n == 0 {
+                return Ok(vec![]);
+            }
+            count_bytes(n)?;
+            lines += 1;
+            if lines == 1 && self.io.strip_bom {
+                if bom::starts_with_utf16_bom(&header) {
+                    let msg = format!("Cannot divide {} as it is UTF-16", path.display());
+                    return Err(FgError::IoError(std::io::Error::new(ErrorKind::Unsupported, msg)));
+                } else if header.starts_with(&bom::UTF8_BOM) {
+                    header.drain(..bom::UTF8_BOM.len());
+                }
+            }
+            let is_comment = self.comment.is_some() && header.first() == self.comment.as_ref();
+            if !is_comment && header.iter().any(|&b| !matches!(b, b'\r' | b'\n')) {
+                break;
+            }
+        }
+
+        let len = file.get_ref().metadata().map_err(FgError::IoError)?.len();
+        let threads = threads.max(1);
+        let range_size = (len / (threads as u64 * 4)).clamp(1 << 16, 1 << 26);
+        let options = SplitOptions { threads, range_size, terminator };
+        let quoting = Quoting {
+            delimiter,
+            quote: self.quote,
+            escape: self.escape,
+            comment: self.comment,
+            terminator,
+        };
+        let check_quoting = |bytes: &[u8]| {
+            if quote && quoting.spans_lines(bytes) {
+                let msg = format!(
+                    "Cannot divide {} as a quoted field contains a line terminator",
+                    path.display()
+                );
+                return Err(FgError::IoError(std::io::Error::new(ErrorKind::InvalidData, msg)));
+            }
+            Ok(())
+        };
+        check_quoting(&header)?;
+        split::read_split(layout, path, reader.position(), lines + 1, &options, |bytes| {
+            count_bytes(bytes.len())?;
+            check_quoting(bytes)?;
+            let read = header.as_slice().chain(bytes);
+            self.delim_reader(read, delimiter, quote, &HashMap::new())?.with_path(path).collect()
+        })
+    }
+
+    /// Opens a delimited file for reading as with [`DelimFile::read_iter`], returning a
+    /// [`DelimFileChunks`] that yields records in batches of `chunk_size`, for example to be
+    /// dispatched to a thread pool, without holding the whole file in memory.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `chunk_size` is zero.
+    pub fn read_chunks<D, P>(
+        &self,
+        path: &P,
+        delimiter: u8,
+        quote: bool,
+        chunk_size: usize,
+    ) -> Result<DelimFileChunks<D>>
+    where
+        D: DeserializeOwned,
+        P: AsRef<Path>,
+    {
+        Ok(self.read_iter(path, delimiter, quote)?.chunks(chunk_size))
+    }
+
+    /// Reads structs implementing `[Deserialize]` from a delimited file into a map keyed by the
+    /// values in the column named `key_column`, such as a sample name.  The key column need not
+    /// be a field of the struct.  Records with keys that have already been read are handled
+    /// according to `duplicates`.  The separator and quoting are as for [`DelimFile::read`].
+    pub fn read_map<K, D, P>(
+        &self,
+        path: &P,
+        delimiter: u8,
+        quote: bool,
+        key_column: &str,
+        duplicates: DuplicateKeys,
+    ) -> Result<HashMap<K, D>>
+    where
+        K: DeserializeOwned + Eq + std::hash::Hash,
+        D: DeserializeOwned,
+        P: AsRef<Path>,
+    {
+        self.read_iter(path, delimiter, quote)?.read_map(key_column, duplicates)
+    }
+
+    /// Reads the records of a delimited file as [`Row`]s, ordered maps from the column names
+    /// to the values, for tables whose columns are only known at runtime.  Values are kept as
+    /// text, and fields missing from short records are empty.  Columns with the same name are
+    /// renamed or rejected as configured by
+    /// [`duplicate_columns`](DelimFileBuilder::duplicate_columns).  The separator and quoting
+    /// are as for [`DelimFile::read`].
+    #[cfg(feature = "indexmap")]
+    pub fn read_rows<P>(&self, path: &P, delimiter: u8, quote: bool) -> Result<Vec<Row>>
+    where
+        P: AsRef<Path>,
+    {
+        self.read_rows_iter(path, delimiter, quote)?.collect()
+    }
+
+    /// Opens a delimited file for reading its records one at a time as [`Row`]s, as with
+    /// [`DelimFile::read_rows`], returning a [`RowReader`] that also provides the columns.
+    #[cfg(feature = "indexmap")]
+    pub fn read_rows_iter<P>(&self, path: &P, delimiter: u8, quote: bool) -> Result<RowReader>
+    where
+        P: AsRef<Path>,
+    {
+        let read = self.io.new_reader(path)?;
+        RowReader::new(self.csv_reader(read, delimiter, quote, true), self.duplicate_columns)
+    }
+
+    /// Writes [`Row`]s to a delimited file, with a header of the first row's keys in order, or
+    /// of the columns set with [`DelimFileBuilder::column_order`].  Keys missing from a row are
+    /// written as empty fields.  Without a column order every row may only have keys from the
+    /// first row, while with a column order keys that are not columns are not written.  Nothing
+    /// but the preamble is written if there are no rows.  The separator and quoting are as for
+    /// [`DelimFile::write`].  Returns the number of rows written.
+    #[cfg(feature = "indexmap")]
+    pub fn write_rows<P, B>(
+        &self,
+        path: &P,
+        rows: impl IntoIterator<Item = B>,
+        delimiter: u8,
+        quote: bool,
+    ) -> Result<u64>
+    where
+        P: AsRef<Path>,
+        B: std::borrow::Borrow<Row>,
+    {
+        let mut write = self.io.new_writer(path)?;
+        if self.write_header {
+            self.write_preamble(&mut write)?;
+        }
+        let mut writer = self.csv_writer(write, delimiter, quote, false);
+        rows::write_rows(&mut writer, rows, self.column_order.as_deref(), self.write_header)
+    }
+
+    /// Opens a delimited file for reading without deserializing its records, returning a
+    /// [`RawDelimReader`] that provides the file's header and yields each record as a
+    /// [`StringRecord`].  This allows files whose columns are not known in advance to be read.
+    /// The separator and quoting are as for [`DelimFile::read`].
+    pub fn read_raw<P>(&self, path: &P, delimiter: u8, quote: bool) -> Result<RawDelimReader>
+    where
+        P: AsRef<Path>,
+    {
+        let read = self.io.new_reader(path)?;
+        RawDelimReader::new(self.csv_reader(read, delimiter, quote, true))
+    }
+
+    /// Infers the type of each column of a delimited file from its first `sample_size` records,
+    /// for example to help write a struct for reading the file or to check that a new file
+    /// looks as expected.  Returns an [`InferredColumn`] for each column in the header, giving
+    /// its [`ColumnType`], whether any values were missing, and some example values.  Empty
+    /// fields and fields matching the [`null_tokens`](DelimFileBuilder::null_tokens) are
+    /// treated as missing.  The separator is as for [`DelimFile::read`] and quotes are parsed.
+    pub fn infer_schema<P>(
+        &self,
+        path: &P,
+        delimiter: u8,
+        sample_size: usize,
+    ) -> Result<Vec<InferredColumn>>
+    where
+        P: AsRef<Path>,
+    {
+        let mut reader = self.read_raw(path, delimiter, true)?;
+        let mut columns: Vec<InferredColumn> =
+            reader.headers().iter().map(|h| InferredColumn::new(h.to_string())).collect();
+        let mut record = StringRecord::new();
+        for _ in 0..sample_size {
+            if !reader.read_record(&mut record)? {
+                break;
+            }
+            for (i, column) in columns.iter_mut().enumerate() {
+                let value = record.get(i).filter(|v| !self.is_missing(v));
+                column.observe(value);
+            }
+        }
+        Ok(columns)
+    }
+
+    /// Validates a delimited file against a [`Schema`], e.g. as a quality control gate before
+    /// data is used, returning a [`ValidationReport`] of every value that does not match its
+    /// [`ColumnSpec`] with its line number.  Empty fields and fields matching the
+    /// [`null_tokens`](DelimFileBuilder::null_tokens) are treated as missing and are only
+    /// violations in required columns.  Errors are returned only if the file cannot be read.
+    /// The separator is as for [`DelimFile::read`] and quotes are parsed.
+    pub fn validate<P>(&self, path: &P, delimiter: u8, schema: &Schema) -> Result<ValidationReport>
+    where
+        P: AsRef<Path>,
+    {
+        let mut reader = self.read_raw(path, delimiter, true)?;
+        let mut report = ValidationReport::default();
+        let header_line = reader.headers().position().map_or(1, |p| p.line());
+        let mut columns = Vec::with_capacity(schema.columns.len());
+        for spec in &schema.columns {
+            match reader.column_index(&spec.name) {
+                Some(index) => columns.push((index, spec)),
+                None if spec.required => report.violations.push(Violation {
+                    line: header_line,
+                    column: spec.name.clone(),
+                    value: None,
+                    kind: ViolationKind::MissingColumn,
+                }),
+                None => {}
+            }
+        }
+
+        let mut record = StringRecord::new();
+        while reader.read_record(&mut record)? {
+            report.records += 1;
+            let line = record.position().map_or(0, |p| p.line());
+            for &(index, spec) in &columns {
+                let value = record.get(index).filter(|v| !self.is_missing(v));
+                let kind = match value {
+                    Some(value) => spec.check(value),
+                    None if spec.required => Some(ViolationKind::MissingValue),
+                    None => None,
+                };
+                if let Some(kind) = kind {
+                    report.violations.push(Violation {
+                        line,
+                        column: spec.name.clone(),
+                        value: value.map(str::to_string),
+                        kind,
+                    });
+                }
+            }
+        }
+        Ok(report)
+    }
+
+    /// Counts the records in a delimited file, not including the header line, without
+    /// deserializing them.  Fields are parsed only enough to find the ends of records, so that
+    /// quoted fields containing newlines are counted correctly, making this much faster than
+    /// reading the records.  Comment lines are not counted.
+    pub fn count<P>(&self, path: &P, delimiter: u8) -> Result<u64>
+    where
+        P: AsRef<Path>,
+    {
+        let read = self.io.new_reader(path)?;
+        let mut reader = self.csv_reader(read, delimiter, true, true);
+        let mut record = ByteRecord::new();
+        let mut count = 0;
+        while reader.read_byte_record(&mut record).map_err(FgError::ConversionError)? {
+            count += 1;
+        }
+        Ok(count)
+    }
+
+    /// Sorts the records of a delimited file by the values of one or more columns, writing them
+    /// with the header to `output`, e.g. to sort a large TSV by contig and position.  Files
+    /// larger than the [`sort_buffer_size`](DelimFileBuilder::sort_buffer_size) are sorted with
+    /// an external merge sort: sorted chunks are written to compressed files in the system's
+    /// temporary directory, which are then merged, so that files much larger than memory can be
+    /// sorted.  At most 64 chunks are merged at once, with more merged in several passes, so that
+    /// the number of open files stays bounded however small the buffer.  The sort is stable, so
+    /// records with equal keys stay in their original order.  Quotes are parsed and fields are
+    /// re-quoted as needed when written.  Returns the number of records sorted.
+    pub fn sort<P, Q>(&self, input: &P, output: &Q, delimiter: u8, keys: &[SortKey]) -> Result<u64>
+    where
+        P: AsRef<Path>,
+        Q: AsRef<Path>,
+    {
+        let mut reader = self.read_raw(input, delimiter, true)?;
+        let keys = sort::resolve_keys(keys, reader.headers())?;
+        let mut spill: Option<sort::SpillDir> = None;
+        let mut records = vec![];
+        let mut buffered = 0;
+        let mut record = ByteRecord::new();
+        while reader.read_byte_record(&mut record)? {
+            // Count some overhead for each record as well as its contents
+            buffered += record.as_slice().len() + 64;
+            records.push(record.clone());
+            if buffered >= self.sort_buffer_size {
+                records.sort_by(|a, b| sort::compare(&keys, a, b));
+                if spill.is_none() {
+                    spill = Some(sort::SpillDir::new(delimiter)?);
+                }
+                if let Some(spill) = &mut spill {
+                    spill.spill(&records)?;
+                }
+                records.clear();
+                buffered = 0;
+            }
+        }
+        records.sort_by(|a, b| sort::compare(&keys, a, b));
+
+        let mut writer = self.csv_writer(self.io.new_writer(output)?, delimiter, true, false);
+        writer.write_record(reader.headers())?;
+        match spill {
+            Some(mut spill) => {
+                spill.spill(&records)?;
+                drop(records);
+                spill.merge_into(&keys, &mut writer, sort::MAX_MERGE_FAN_IN)
+            }
+            None => {
+                for record in &records {
+                    writer.write_byte_record(record)?;
+                }
+                writer.flush().map_err(FgError::IoError)?;
+                Ok(records.len() as u64)
+            }
+        }
+    }
+
+    /// Merges delimited files that are each already sorted by the given keys into one sorted
+    /// file, such as the per-shard outputs of a scattered step.  Records are streamed, so only
+    /// one record from each input is held in memory at a time.  All inputs must have the same
+    /// header, which is written once to `output`, and records with equal keys are written in the
+    /// order of the inputs.  Returns the number of records written, or an error if the headers
+    /// differ or an input is found not to be sorted.
+    pub fn merge_sorted<P, Q>(
+        &self,
+        inputs: &[P],
+        output: &Q,
+        delimiter: u8,
+        keys: &[SortKey],
+    ) -> Result<u64>
+    where
+        P: AsRef<Path>,
+        Q: AsRef<Path>,
+    {
+        let mut readers = Vec::with_capacity(inputs.len());
+        let mut header: Option<StringRecord> = None;
+        for input in inputs {
+            let mut reader = self.csv_reader(self.io.new_reader(input)?, delimiter, true, true);
+            let headers = reader.headers()?.clone();
+            match &header {
+                Some(header) if *header != headers => {
+                    let missing = header.iter().filter(|h| !headers.iter().any(|x| x == *h));
+                    let unexpected = headers.iter().filter(|h| !header.iter().any(|x| x == *h));
+                    return Err(FgError::HeaderMismatch {
+                        missing: missing.map(String::from).collect(),
+                        unexpected: unexpected.map(String::from).collect(),
+                    });
+                }
+                Some(_) => {}
+                None => header = Some(headers),
+            }
+            readers.push(reader);
+        }
+
+        let header = match header {
+            Some(header) => header,
+            None => {
+                let msg = "At least one input is required to merge";
+                return Err(FgError::IoError(std::io::Error::new(ErrorKind::InvalidInput, msg)));
+            }
+        };
+        let keys = sort::resolve_keys(keys, &header)?;
+        let mut writer = self.csv_writer(self.io.new_writer(output)?, delimiter, true, false);
+        writer.write_record(&header)?;
+        sort::merge(&mut readers, &keys, &mut writer)
+    }
+
+    /// Copies a delimited file to `output`, dropping records whose values in the `key_columns`
+    /// repeat those of an earlier record.  Which of the records with the same key is kept, or
+    /// whether a repeated key is an error, is set by `duplicates`.  When the input is `sorted`,
+    /// or at least has records with equal keys next to each other, records are streamed holding
+    /// only one record in memory.  Otherwise the keys seen are held in memory, and keeping the
+    /// last record with each key also holds those records until the input has been read, so the
+    /// input is only ever read once and may be a stream such as `-`.  Records are written in
+    /// their original order.  Returns the number of duplicate records dropped.
+    pub fn dedup<P, Q, S>(
+        &self,
+        input: &P,
+        output: &Q,
+        delimiter: u8,
+        key_columns: &[S],
+        duplicates: DuplicateKeys,
+        sorted: bool,
+    ) -> Result<u64>
+    where
+        P: AsRef<Path>,
+        Q: AsRef<Path>,
+        S: AsRef<str>,
+    {
+        let mut reader = self.csv_reader(self.io.new_reader(input)?, delimiter, true, true);
+        let headers = reader.headers()?.clone();
+        let indices = delim::column_indices(key_columns, &headers)?;
+        let mut writer = self.csv_writer(self.io.new_writer(output)?, delimiter, true, false);
+        writer.write_record(&headers)?;
+        if sorted {
+            dedup::dedup_sorted(&mut reader, &mut writer, &indices, duplicates, delimiter)
+        } else if duplicates == DuplicateKeys::KeepLast {
+            dedup::dedup_keep_last(&mut reader, &mut writer, &indices)
+        } else {
+            dedup::dedup_unsorted(&mut reader, &mut writer, &indices, duplicates, delimiter)
+        }
+    }
+
+    /// Joins two delimited files on the values of one or more key columns present in both, e.g.
+    /// to annotate a table of samples with metadata from a sample sheet.  For each record of
+    /// `left`, in order, a record is written to `output` for each record of `right` with the same
+    /// keys, made up of the left record's fields followed by the right record's non-key fields.
+    /// With [`JoinKind::Left`], left records without a match are also written, with empty values
+    /// for the right file's columns.  The right file is held in memory while the left file is
+    /// streamed, so the right file should be the smaller of the two.  Output columns with the
+    /// same name are renamed or rejected as configured by
+    /// [`duplicate_columns`](DelimFileBuilder::duplicate_columns).  Both files use the same
+    /// separator and quotes are parsed.  Returns the number of records written.
+    pub fn join<P, Q, O, S>(
+        &self,
+        left: &P,
+        right: &Q,
+        output: &O,
+        delimiter: u8,
+        key_columns: &[S],
+        kind: JoinKind,
+    ) -> Result<u64>
+    where
+        P: AsRef<Path>,
+        Q: AsRef<Path>,
+        O: AsRef<Path>,
+        S: AsRef<str>,
+    {
+        let mut left = self.csv_reader(self.io.new_reader(left)?, delimiter, true, true);
+        let mut right = self.csv_reader(self.io.new_reader(right)?, delimiter, true, true);
+        let mut writer = self.csv_writer(self.io.new_writer(output)?, delimiter, true, false);
+        join::join(&mut left, &mut right, &mut writer, key_columns, kind, self.duplicate_columns)
+    }
+
+    /// Returns the [`TableFormat`] of a file based on its extension, ignoring any compression
+    /// extension, e.g. [`TableFormat::Csv`] for `data.csv.gz`, or `None` if the extension is
+    /// not recognized.  See [`TableFormat::from_extension`] for the extensions recognized.
+    pub fn table_format<P: AsRef<Path>>(&self, path: &P) -> Option<TableFormat> {
+        let path = path.as_ref();
+        let name = path.file_name()?.to_str()?;
+        let name = match self.io.matching_extension(&path) {
+            Some((ext, _)) => &name[..name.len() - ext.len() - 1],
+            None => name,
+        };
+        let (_, extension) = name.rsplit_once('.')?;
+        TableFormat::from_extension(extension)
+    }
+
+    /// Converts a table from one format to another, e.g. from CSV to TSV or, with the `json`
+    /// feature, between delimited files and JSON Lines, handling compression of both files based
+    /// on their paths.  The formats can be found from the paths with
+    /// [`DelimFile::table_format`].  The header is kept, and the records are streamed.  Quotes
+    /// are parsed when reading delimited files and added where needed when writing them.
+    ///
+    /// Records are written to JSON Lines as objects keyed by the column names, whose values are
+    /// strings, or `null` for empty values and those matching the
+    /// [`null_tokens`](DelimFileBuilder::null_tokens).  When JSON Lines are read, the columns
+    /// are the keys of the first object, `null` values are written as empty fields and values
+    /// other than strings as JSON text.  Returns the number of records written.
+    pub fn convert<P, Q>(
+        &self,
+        input: &P,
+        from: TableFormat,
+        output: &Q,
+        to: TableFormat,
+    ) -> Result<u64>
+    where
+        P: AsRef<Path>,
+        Q: AsRef<Path>,
+    {
+        let read = self.io.new_reader(input)?;
+        let write = self.io.new_writer(output)?;
+        match (from.delimiter(), to.delimiter()) {
+            (Some(from), Some(to)) => {
+                let mut reader = self.csv_reader(read, from, true, true);
+                convert::delim_to_delim(&mut reader, &mut self.csv_writer(write, to, true, false))
+            }
+            #[cfg(feature = "json")]
+            (Some(from), None) => {
+                let mut reader = self.csv_reader(read, from, true, true);
+                let duplicates = self.duplicate_columns;
+                convert::delim_to_jsonl(&mut reader, write, &self.null_tokens, duplicates)
+            }
+            #[cfg(feature = "json")]
+            (None, Some(to)) => {
+                convert::jsonl_to_delim(read, &mut self.csv_writer(write, to, true, false))
+            }
+            #[cfg(feature = "json")]
+            (None, None) => convert::jsonl_to_jsonl(read, write),
+            #[cfg(not(feature = "json"))]
+            _ => unreachable!("only JSON Lines has no delimiter"),
+        }
+    }
+
+    /// Reads records from a delimited file that has no header line, such as a BED file.  Fields
+    /// are deserialized by position, so records are typically tuples or structs whose members
+    /// are declared in the same order as the columns.  The separator and quoting are as for
+    /// [`DelimFile::read`].
+    pub fn read_headerless<D, P>(&self, path: &P, delimiter: u8, quote: bool) -> Result<Vec<D>>
+    where
+        D: DeserializeOwned,
+        P: AsRef<Path>,
+    {
+        self.read_iter_headerless(path, delimiter, quote)?.collect()
+    }
+
+    /// Opens a delimited file that has no header line for reading, returning a
+    /// [`DelimFileReader`] that deserializes records by position as they are read.
+    pub fn read_iter_headerless<D, P>(
+        &self,
+        path: &P,
+        delimiter: u8,
+        quote: bool,
+    ) -> Result<DelimFileReader<D>>
+    where
+        D: DeserializeOwned,
+        P: AsRef<Path>,
+    {
+        let read = self.io.new_reader(path)?;
+        let reader = self.csv_reader(read, delimiter, quote, false);
+        Ok(self.configure_reader(DelimFileReader::headerless(reader)).with_path(path.as_ref()))
+    }
+
+    /// Reads structs implementing `[Deserialize]` from a delimited file as with
+    /// [`DelimFile::read`], first renaming columns in the file's header from the keys of
+    /// `renames` to the corresponding values.  This allows files whose column names differ from
+    /// a struct's fields to be read without changing the struct.
+    pub fn read_with_renames<D, P>(
+        &self,
+        path: &P,
+        delimiter: u8,
+        quote: bool,
+        renames: &HashMap<&str, &str>,
+    ) -> Result<Vec<D>>
+    where
+        D: DeserializeOwned,
+        P: AsRef<Path>,
+    {
+        self.read_iter_with_renames(path, delimiter, quote, renames)?.collect()
+    }
+
+    /// Opens a delimited file for reading as with [`DelimFile::read_iter`], first renaming
+    /// columns in the file's header as for [`DelimFile::read_with_renames`].
+    pub fn read_iter_with_renames<D, P>(
+        &self,
+        path: &P,
+        delimiter: u8,
+        quote: bool,
+        renames: &HashMap<&str, &str>,
+    ) -> Result<DelimFileReader<D>>
+    where
+        D: DeserializeOwned,
+        P: AsRef<Path>,
+    {
+        let read = self.io.new_reader(path)?;
+        Ok(self.delim_reader(read, delimiter, quote, renames)?.with_path(path.as_ref()))
+    }
+
+    /// Reads structs implementing `[Deserialize]` from delimited data in any reader, such as a
+    /// socket or an in-memory buffer, which must start with a header line.  The data is not
+    /// decompressed.  The separator and quoting are as for [`DelimFile::read`].
+    pub fn read_from<D, R>(&self, read: R, delimiter: u8, quote: bool) -> Result<Vec<D>>
+    where
+        D: DeserializeOwned,
+        R: BufRead,
+    {
+        self.delim_reader(read, delimiter, quote, &HashMap::new())?.collect()
+    }
+
+    /// Reads structs implementing `[Deserialize]` from delimited text, which must start with a
+    /// header line.  The separator and quoting are as for [`DelimFile::read`].
+    pub fn read_from_str<D>(&self, s: &str, delimiter: u8, quote: bool) -> Result<Vec<D>>
+    where
+        D: DeserializeOwned,
+    {
+        self.read_from(s.as_bytes(), delimiter, quote)
+    }
+
+    /// Builds a [`DelimFileReader`] over the given reader, which must start with a header line,
+    /// applying the header renames given and the header checks and field handling configured.
+    fn delim_reader<D: DeserializeOwned, R: Read>(
+        &self,
+        read: R,
+        delimiter: u8,
+        quote: bool,
+        renames: &HashMap<&str, &str>,
+    ) -> Result<DelimFileReader<D, R>> {
+        let reader = self.csv_reader(read, delimiter, quote, true);
+        let strict_headers = self.strict_headers || self.strict;
+        let reader = DelimFileReader::new(
+            reader,
+            renames,
+            self.duplicate_columns,
+            strict_headers,
+            self.extra_columns,
+        )?;
+        Ok(self.configure_reader(reader))
+    }
+
+    /// Applies the configured null tokens, column parsers, field count checks and boolean
+    /// handling to a reader.
+    fn configure_reader<D: DeserializeOwned, R>(
+        &self,
+        reader: DelimFileReader<D, R>,
+    ) -> DelimFileReader<D, R> {
+        let mut reader = reader
+            .with_null_tokens(self.null_tokens.iter().map(|t| t.as_bytes().to_vec()).collect());
+        if self.strict {
+            reader = reader.strict();
+        } else if self.ragged {
+            reader = reader.ragged();
+        }
+        if !self.parsers.is_empty() {
+            reader = reader.with_parsers(&self.parsers);
+        }
+        if self.write_format.bools_as_ints {
+            reader.with_int_bools()
+        } else {
+            reader
+        }
+    }
+
+    /// Returns true if a field is empty or matches one of the
+    /// [`null_tokens`](DelimFileBuilder::null_tokens), so its value is missing.
+    fn is_missing(&self, value: &str) -> bool {
+        value.is_empty() || self.null_tokens.iter().any(|t| t == value)
+    }
+
+    /// Builds a csv reader over the given reader, optionally expecting a header line.
+    fn csv_reader<R: Read>(
+        &self,
+        read: R,
+        delimiter: u8,
+        quote: bool,
+        headers: bool,
+    ) -> csv::Reader<R> {
+        let terminator = match self.terminator {
+            LineTerminator::Lf | LineTerminator::CrLf => csv::Terminator::CRLF,
+            LineTerminator::Custom(b) => csv::Terminator::Any(b),
+        };
+
+        ReaderBuilder::new()
+            .delimiter(delimiter)
+            .has_headers(headers)
+            .quoting(quote)
+            .quote(self.quote)
+            .escape(self.escape)
+            .comment(self.comment)
+            .flexible((self.flexible || self.ragged) && !self.strict)
+            .trim(self.trim)
+            .terminator(terminator)
+            .from_reader(read)
+    }
+
+    /// Reads structs implementing `[Deserialize]` from a file with tab separators between fields.
+    pub fn read_tsv<D, P>(&self, path: &P) -> Result<Vec<D>>
+    where
+        D: DeserializeOwned,
+        P: AsRef<Path>,
+    {
+        self.read(path, b'\t', true)
+    }
+
+    /// Reads structs implementing `[Deserialize]` from a file whose fields are separated by runs
+    /// of spaces and tabs, such as the space-aligned tables written by many older tools, in the
+    /// way that `awk` splits lines.  Leading and trailing whitespace on each line is ignored,
+    /// fields cannot contain whitespace and quotes are not parsed.  Lines are always separated
+    /// by newlines.
+    pub fn read_whitespace<D, P>(&self, path: &P) -> Result<Vec<D>>
+    where
+        D: DeserializeOwned,
+        P: AsRef<Path>,
+    {
+        self.read_iter_whitespace(path)?.collect()
+    }
+
+    /// Opens a whitespace-delimited file for reading as with [`DelimFile::read_whitespace`],
+    /// returning a [`DelimFileReader`] that deserializes records one at a time as they are read.
+    pub fn read_iter_whitespace<D, P>(&self, path: &P) -> Result<DelimFileReader<D>>
+    where
+        D: DeserializeOwned,
+        P: AsRef<Path>,
+    {
+        let read: Box<dyn BufRead + Send> =
+            Box::new(WhitespaceReader::new(self.io.new_reader(path)?));
+        let reader = ReaderBuilder::new()
+            .delimiter(b'\t')
+            .quoting(false)
+            .comment(self.comment)
+            .flexible((self.flexible || self.ragged) && !self.strict)
+            .from_reader(read);
+        let strict_headers = self.strict_headers || self.strict;
+        let reader = DelimFileReader::new(
+            reader,
+            &HashMap::new(),
+            self.duplicate_columns,
+            strict_headers,
+            self.extra_columns,
+        )?;
+        Ok(self.configure_reader(reader).with_path(path.as_ref()))
+    }
+
+    /// Reads structs implementing `[Deserialize]` from a file with tab separators between fields.
+    pub fn read_csv<D, P>(&self, path: &P) -> Result<Vec<D>>
+    where
+        D: DeserializeOwned,
+        P: AsRef<Path>,
+    {
+        self.read(path, b',', true)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    #[cfg(feature = "checksum")]
+    use crate::io::ChecksumAlgorithm;
+    #[cfg(feature = "log")]
+    use crate::io::ProgressInterval;
+    use crate::io::{
+        ColumnSpec, ColumnType, CompressionFormat, DelimFile, DuplicateColumns, DuplicateKeys,
+        ExtraColumns, FinishWrite, FloatFormat, Io, JoinKind, LineOptions, LineSampling,
+        LineTerminator, QuoteStyle, RecordBuffers, Schema, SniffedFormat, SortKey, TableFormat,
+        Trim, ViolationKind, BGZF_EOF,
+    };
+    use crate::FgError;
+    use rstest::rstest;
+    use serde::{Deserialize, Serialize};
+    use std::collections::HashMap;
+    use std::io::{BufRead, Write};
+    use std::time::Duration;
+    use tempfile::TempDir;
+
+    /// Record type used in testing DelimFile
+    #[derive(Debug, Serialize, Deserialize, PartialEq)]
+    struct Rec {
+        s: String,
+        i: usize,
+        b: bool,
+        o: Option<f64>,
+    }
+
+    #[test]
+    fn test_reading_and_writing_lines_to_file() {
         let lines = vec!["foo", "bar,splat,whee", "baz\twhoopsie"];
         let tempdir = TempDir::new().unwrap();
-        let f1 = tempdir.path().join("strs.txt");
-        let f2 = tempdir.path().join("Strings.txt");
+        let f1 = tempdir.path().join("strs.txt");
+        let f2 = tempdir.path().join("Strings.txt");
+
+        let io = Io::default();
+        io.write_lines(&f1, &lines).unwrap();
+        let strings: Vec<String> = lines.iter().map(|l| l.to_string()).collect();
+        io.write_lines(&f2, &strings).unwrap();
+
+        let r1 = io.read_lines(&f1).unwrap();
+        let r2 = io.read_lines(&f2).unwrap();
+
+        assert_eq!(r1, lines);
+        assert_eq!(r2, lines);
+    }
+
+    #[test]
+    fn test_reading_and_writing_gzip_files() {
+        let lines = vec!["foo", "bar", "baz"];
+        let tempdir = TempDir::new().unwrap();
+        let text = tempdir.path().join("text.txt");
+        let gzipped = tempdir.path().join("gzipped.txt.gz");
+
+        let io = Io::default();
+        io.write_lines(&text, &mut lines.iter()).unwrap();
+        io.write_lines(&gzipped, &mut lines.iter()).unwrap();
+
+        let r1 = io.read_lines(&text).unwrap();
+        let r2 = io.read_lines(&gzipped).unwrap();
+
+        assert_eq!(r1, lines);
+        assert_eq!(r2, lines);
+
+        // Also check that we actually wrote gzipped data to the gzip file!
+        assert_ne!(text.metadata().unwrap().len(), gzipped.metadata().unwrap().len());
+    }
+
+    #[test]
+    fn test_reading_and_writing_zstd_files() {
+        let lines = vec!["foo", "bar", "baz"];
+        let tempdir = TempDir::new().unwrap();
+        let text = tempdir.path().join("text.txt");
+        let zstd_compressed = tempdir.path().join("zstd_compressed.txt.zst");
+
+        assert_eq!(Io::is_zstd_path(&text), false);
+        assert_eq!(Io::is_zstd_path(&zstd_compressed), true);
+
+        let io = Io::default();
+        io.write_lines(&text, &mut lines.iter()).unwrap();
+        io.write_lines(&zstd_compressed, &mut lines.iter()).unwrap();
+
+        let r1 = io.read_lines(&text).unwrap();
+        let r2 = io.read_lines(&zstd_compressed).unwrap();
+
+        assert_eq!(r1, lines);
+        assert_eq!(r2, lines);
+
+        // Check whether the two files are different
+        assert_ne!(text.metadata().unwrap().len(), zstd_compressed.metadata().unwrap().len());
+    }
+
+    #[cfg(feature = "bzip2")]
+    #[test]
+    fn test_reading_and_writing_bzip2_files() {
+        let lines = vec!["foo", "bar", "baz"];
+        let tempdir = TempDir::new().unwrap();
+        let text = tempdir.path().join("text.txt");
+        let bzipped = tempdir.path().join("bzipped.txt.bz2");
+
+        assert!(!Io::is_bzip2_path(&text));
+        assert!(Io::is_bzip2_path(&bzipped));
+
+        let io = Io::default();
+        io.write_lines(&text, lines.iter()).unwrap();
+        io.write_lines(&bzipped, lines.iter()).unwrap();
+
+        let r1 = io.read_lines(&text).unwrap();
+        let r2 = io.read_lines(&bzipped).unwrap();
+
+        assert_eq!(r1, lines);
+        assert_eq!(r2, lines);
+
+        // Check that the bzip2 file starts with the bzip2 magic bytes
+        let bytes = std::fs::read(&bzipped).unwrap();
+        assert_eq!(&bytes[0..3], b"BZh");
+    }
+
+    #[test]
+    fn test_writing_bgzf_files() {
+        let lines = vec!["foo", "bar", "baz"];
+        let tempdir = TempDir::new().unwrap();
+        let gz = tempdir.path().join("compressed.txt.gz");
+        let bgz = tempdir.path().join("compressed.txt.bgz");
+
+        let io = Io::default();
+        io.write_lines(&gz, lines.iter()).unwrap();
+        io.write_lines(&bgz, lines.iter()).unwrap();
+        assert_eq!(io.read_lines(&bgz).unwrap(), lines);
+
+        // Plain gzip files have no BGZF extra field or EOF marker, BGZF files have both
+        let gz_bytes = std::fs::read(&gz).unwrap();
+        let bgz_bytes = std::fs::read(&bgz).unwrap();
+        assert_ne!(&gz_bytes[12..14], b"BC");
+        assert!(!gz_bytes.ends_with(&BGZF_EOF));
+        assert_eq!(&bgz_bytes[12..14], b"BC");
+        assert!(bgz_bytes.ends_with(&BGZF_EOF));
+    }
+
+    #[test]
+    fn test_seeking_within_bgzf_file() {
+        let lines: Vec<String> = (0..50_000).map(|i| format!("record\t{i}")).collect();
+        let tempdir = TempDir::new().unwrap();
+        let bgz = tempdir.path().join("records.txt.bgz");
+
+        let io = Io::default();
+        io.write_lines(&bgz, &lines).unwrap();
+
+        let mut reader = io.new_bgzf_reader(&bgz).unwrap();
+        let mut offsets = vec![];
+        let mut line = String::new();
+        while {
+            offsets.push(reader.virtual_offset());
+            line.clear();
+            reader.read_line(&mut line).unwrap() > 0
+        } {}
+        assert!(offsets.last().unwrap().coffset() > 0);
+
+        for i in [40_000, 12, 25_000] {
+            reader.seek_virtual(offsets[i]).unwrap();
+            line.clear();
+            reader.read_line(&mut line).unwrap();
+            assert_eq!(line.trim_end(), lines[i]);
+        }
+    }
+
+    #[cfg(feature = "xz")]
+    #[rstest]
+    #[case(0)]
+    #[case(6)]
+    #[case(9)]
+    fn test_reading_and_writing_xz_files(#[case] preset: u32) {
+        let lines = vec!["foo", "bar", "baz"];
+        let tempdir = TempDir::new().unwrap();
+        let xz = tempdir.path().join("compressed.txt.xz");
+
+        assert!(Io::is_xz_path(&xz));
+
+        let io = Io::builder().xz_preset(preset).build();
+        io.write_lines(&xz, lines.iter()).unwrap();
+        assert_eq!(io.read_lines(&xz).unwrap(), lines);
+
+        // Check that the xz file starts with the xz magic bytes
+        let bytes = std::fs::read(&xz).unwrap();
+        assert_eq!(&bytes[0..6], b"\xFD7zXZ\x00");
+    }
+
+    #[cfg(feature = "lz4")]
+    #[test]
+    fn test_reading_and_writing_lz4_files() {
+        let lines = vec!["foo", "bar", "baz"];
+        let tempdir = TempDir::new().unwrap();
+        let lz4 = tempdir.path().join("compressed.txt.lz4");
+
+        assert!(Io::is_lz4_path(&lz4));
+
+        let io = Io::default();
+        io.write_lines(&lz4, lines.iter()).unwrap();
+        assert_eq!(io.read_lines(&lz4).unwrap(), lines);
+
+        // Check that the lz4 file starts with the lz4 frame magic bytes
+        let bytes = std::fs::read(&lz4).unwrap();
+        assert_eq!(&bytes[0..4], &[0x04, 0x22, 0x4D, 0x18]);
+    }
+
+    #[cfg(feature = "snappy")]
+    #[test]
+    fn test_reading_and_writing_snappy_files() {
+        let lines = vec!["foo", "bar", "baz"];
+        let tempdir = TempDir::new().unwrap();
+        let sz = tempdir.path().join("compressed.txt.sz");
+
+        assert!(Io::is_snappy_path(&sz));
+
+        let io = Io::default();
+        io.write_lines(&sz, lines.iter()).unwrap();
+        assert_eq!(io.read_lines(&sz).unwrap(), lines);
+
+        // Check that the file starts with the snappy framing format's stream identifier
+        let bytes = std::fs::read(&sz).unwrap();
+        assert_eq!(&bytes[0..10], b"\xff\x06\x00\x00sNaPpY");
+    }
+
+    #[rstest]
+    #[case("data.txt.gz")]
+    #[case("data.txt.bgz")]
+    #[case("data.txt.zst")]
+    #[cfg_attr(feature = "bzip2", case("data.txt.bz2"))]
+    #[cfg_attr(feature = "xz", case("data.txt.xz"))]
+    #[case("data.txt")]
+    fn test_detecting_compression_from_magic_bytes(#[case] file_name: &str) {
+        let lines = vec!["foo", "bar", "baz"];
+        let tempdir = TempDir::new().unwrap();
+        let written = tempdir.path().join(file_name);
+        let misnamed = tempdir.path().join("misnamed.txt");
+
+        Io::default().write_lines(&written, lines.iter()).unwrap();
+        std::fs::rename(&written, &misnamed).unwrap();
+
+        let io = Io::builder().detect_compression(true).build();
+        assert_eq!(io.read_lines(&misnamed).unwrap(), lines);
+    }
+
+    #[test]
+    fn test_detecting_compression_ignores_extension() {
+        let tempdir = TempDir::new().unwrap();
+        let path = tempdir.path().join("not_really.gz");
+        std::fs::write(&path, "plain\ntext\n").unwrap();
+
+        let io = Io::builder().detect_compression(true).build();
+        assert_eq!(io.read_lines(&path).unwrap(), vec!["plain", "text"]);
+        assert!(Io::default().read_lines(&path).is_err());
+    }
+
+    #[test]
+    fn test_detecting_compression_of_empty_file() {
+        let tempdir = TempDir::new().unwrap();
+        let path = tempdir.path().join("empty.txt");
+        std::fs::write(&path, "").unwrap();
+
+        let io = Io::builder().detect_compression(true).build();
+        assert!(io.read_lines(&path).unwrap().is_empty());
+    }
+
+    #[rstest]
+    #[case(-5, false)]
+    #[case(1, false)]
+    #[case(19, false)]
+    #[case(3, true)]
+    fn test_writing_zstd_with_level(#[case] level: i32, #[case] ldm: bool) {
+        let lines: Vec<String> = (0..10_000).map(|i| format!("line {}", i % 100)).collect();
+        let tempdir = TempDir::new().unwrap();
+        let path = tempdir.path().join("lines.txt.zst");
+
+        let io = Io::builder().zstd_level(level).zstd_long_distance_matching(ldm).build();
+        io.write_lines(&path, &lines).unwrap();
+        assert_eq!(io.read_lines(&path).unwrap(), lines);
+    }
+
+    #[test]
+    fn test_higher_zstd_level_compresses_better() {
+        let lines: Vec<String> = (0..50_000).map(|i| format!("{}\t{}", i, i * 7 % 13)).collect();
+        let tempdir = TempDir::new().unwrap();
+        let fast = tempdir.path().join("fast.txt.zst");
+        let best = tempdir.path().join("best.txt.zst");
+
+        Io::builder().zstd_level(-5).build().write_lines(&fast, &lines).unwrap();
+        Io::builder().zstd_level(19).build().write_lines(&best, &lines).unwrap();
+        assert!(best.metadata().unwrap().len() < fast.metadata().unwrap().len());
+    }
+
+    #[cfg(feature = "gzp")]
+    #[rstest]
+    #[case("lines.txt.gz")]
+    #[case("lines.txt.bgz")]
+    fn test_writing_gzip_with_multiple_threads(#[case] file_name: &str) {
+        let lines: Vec<String> = (0..200_000).map(|i| format!("line\t{i}")).collect();
+        let tempdir = TempDir::new().unwrap();
+        let path = tempdir.path().join(file_name);
+
+        let io = Io::builder().threads(4).build();
+        io.write_lines(&path, &lines).unwrap();
+        assert_eq!(io.read_lines(&path).unwrap(), lines);
+
+        if Io::is_bgzf_path(&path) {
+            let mut reader = io.new_bgzf_reader(&path).unwrap();
+            let mut line = String::new();
+            reader.read_line(&mut line).unwrap();
+            assert_eq!(line, "line\t0\n");
+        }
+    }
+
+    #[test]
+    fn test_writing_zstd_with_multiple_threads() {
+        let lines: Vec<String> = (0..200_000).map(|i| format!("line\t{i}")).collect();
+        let tempdir = TempDir::new().unwrap();
+        let path = tempdir.path().join("lines.txt.zst");
+
+        let io = Io::builder().threads(4).zstd_level(3).build();
+        io.write_lines(&path, &lines).unwrap();
+        assert_eq!(io.read_lines(&path).unwrap(), lines);
+    }
+
+    #[test]
+    fn test_reading_and_writing_zstd_with_dictionary() {
+        let tempdir = TempDir::new().unwrap();
+        let io = Io::default();
+
+        // Write a set of small, similar sample files to train from
+        let samples: Vec<_> = (0..200)
+            .map(|i| {
+                let path = tempdir.path().join(format!("sample{i}.tsv"));
+                let lines = vec![
+                    "sample_name\tlibrary\tread_count\tmean_coverage".to_string(),
+                    format!("sample{i}\tlibrary{}\t{}\t{}.5", i % 7, i * 1000, i % 50),
+                ];
+                io.write_lines(&path, &lines).unwrap();
+                path
+            })
+            .collect();
+        let dictionary = io.train_zstd_dictionary(&samples, 4096).unwrap();
+        assert!(!dictionary.is_empty());
+
+        let lines = vec!["sample_name\tlibrary\tread_count\tmean_coverage", "sampleX\tlib\t1\t1"];
+        let with_dict = tempdir.path().join("with_dict.tsv.zst");
+        let without_dict = tempdir.path().join("without_dict.tsv.zst");
+        io.write_lines(&without_dict, &lines).unwrap();
+        let mut writer = io.new_zstd_writer_with_dictionary(&with_dict, &dictionary).unwrap();
+        for line in &lines {
+            writeln!(writer, "{line}").unwrap();
+        }
+        drop(writer);
+
+        let reader = io.new_zstd_reader_with_dictionary(&with_dict, &dictionary).unwrap();
+        let read: Vec<String> = reader.lines().map(|l| l.unwrap()).collect();
+        assert_eq!(read, lines);
+        assert!(with_dict.metadata().unwrap().len() < without_dict.metadata().unwrap().len());
+
+        // Reading without the dictionary fails
+        assert!(io.read_lines(&with_dict).is_err());
+    }
+
+    #[test]
+    fn test_zstd_dictionary_writers_respect_overwrite() {
+        let tempdir = TempDir::new().unwrap();
+        let path = tempdir.path().join("nested").join("out.zst");
+        let dictionary = b"sample_name\tlibrary";
+
+        let io = Io::builder().create_parent_dirs(true).overwrite(false).build();
+        let mut writer = io.new_zstd_writer_with_dictionary(&path, dictionary).unwrap();
+        writer.write_all(b"sample_name\n").unwrap();
+        writer.finish().unwrap();
+
+        let result = io.new_zstd_writer_with_dictionary(&path, dictionary);
+        assert!(matches!(result, Err(FgError::FileExists(_))));
+        let reader = io.new_zstd_reader_with_dictionary(&path, dictionary).unwrap();
+        assert_eq!(reader.lines().next().unwrap().unwrap(), "sample_name");
+    }
+
+    #[test]
+    #[should_panic(expected = "Number of threads cannot be zero!")]
+    fn test_zero_threads_panics() {
+        let _io = Io::builder().threads(0);
+    }
+
+    #[test]
+    #[should_panic(expected = "zstd level must be between")]
+    fn test_invalid_zstd_level_panics() {
+        let _io = Io::builder().zstd_level(1000);
+    }
+
+    #[cfg(feature = "xz")]
+    #[test]
+    #[should_panic(expected = "xz preset must be between 0 and 9")]
+    fn test_invalid_xz_preset_panics() {
+        let _io = Io::builder().xz_preset(10);
+    }
+
+    #[test]
+    fn test_reading_and_writing_empty_delim_file() {
+        let recs: Vec<Rec> = vec![];
+        let tmp = TempDir::new().unwrap();
+        let csv = tmp.path().join("recs.csv");
+        let tsv = tmp.path().join("recs.tsv.gz");
+
+        let df = DelimFile::default();
+        df.write_csv(&csv, &recs).unwrap();
+        df.write_tsv(&tsv, &recs).unwrap();
+        let from_csv: Vec<Rec> = df.read_csv(&csv).unwrap();
+        let from_tsv: Vec<Rec> = df.read_tsv(&tsv).unwrap();
+
+        assert_eq!(from_csv, recs);
+        assert_eq!(from_tsv, recs);
+    }
+
+    #[test]
+    fn test_reading_and_writing_delim_file() {
+        let recs: Vec<Rec> = vec![
+            Rec { s: "Hello".to_string(), i: 123, b: true, o: None },
+            Rec { s: "A,B,C".to_string(), i: 456, b: false, o: Some(123.45) },
+        ];
+        let tmp = TempDir::new().unwrap();
+        let csv = tmp.path().join("recs.csv");
+        let tsv = tmp.path().join("recs.tsv.gz");
+        let bz2 = tmp.path().join("recs.tsv.bz2");
+
+        let df = DelimFile::default();
+        df.write_csv(&csv, &recs).unwrap();
+        df.write_tsv(&tsv, &recs).unwrap();
+        df.write_tsv(&bz2, &recs).unwrap();
+        let from_csv: Vec<Rec> = df.read_csv(&csv).unwrap();
+        let from_tsv: Vec<Rec> = df.read_tsv(&tsv).unwrap();
+        let from_bz2: Vec<Rec> = df.read_tsv(&bz2).unwrap();
+
+        assert_eq!(from_csv, recs);
+        assert_eq!(from_tsv, recs);
+        assert_eq!(from_bz2, recs);
+    }
+
+    // ############################################################################################
+    // Tests compression_format() and the extension registry
+    // ############################################################################################
+
+    #[rstest]
+    #[case("reads.fq.gz", CompressionFormat::Gzip)]
+    #[case("reads.fq.bgz", CompressionFormat::Bgzf)]
+    #[case("reads.fq.zst", CompressionFormat::Zstd)]
+    #[cfg_attr(feature = "bzip2", case("reads.fq.bz2", CompressionFormat::Bzip2))]
+    #[cfg_attr(feature = "xz", case("reads.fq.xz", CompressionFormat::Xz))]
+    #[case("reads.fq", CompressionFormat::None)]
+    #[case(".gz", CompressionFormat::None)] // a hidden file, not an extension
+    #[case("reads.fq.GZ", CompressionFormat::None)] // extensions are case sensitive
+    fn test_default_compression_formats(
+        #[case] file_name: &str,
+        #[case] expected: CompressionFormat,
+    ) {
+        let dir = TempDir::new().unwrap();
+        assert_eq!(Io::default().compression_format(&dir.path().join(file_name)), expected);
+    }
+
+    #[rstest]
+    #[case("reads.bgzf", CompressionFormat::Bgzf)] // newly registered
+    #[case("notes.txt.gz2", CompressionFormat::Gzip)] // multi-part extension
+    #[case("notes.gz2", CompressionFormat::Zstd)] // shorter extension still matches
+    #[case("notes.txt.gz", CompressionFormat::Zstd)] // overridden default
+    #[cfg_attr(feature = "xz", case("notes.txt.xz", CompressionFormat::Xz))] // untouched default
+    fn test_registered_compression_formats(
+        #[case] file_name: &str,
+        #[case] expected: CompressionFormat,
+    ) {
+        let io = Io::builder()
+            .register_extension("bgzf", CompressionFormat::Bgzf)
+            .register_extension(".txt.gz2", CompressionFormat::Gzip)
+            .register_extension("gz2", CompressionFormat::Zstd)
+            .register_extension("gz", CompressionFormat::Zstd)
+            .build();
+        assert_eq!(io.compression_format(&file_name), expected);
+    }
+
+    #[test]
+    fn test_reading_and_writing_with_registered_extension() {
+        let lines = vec!["foo", "bar", "baz"];
+        let tempdir = TempDir::new().unwrap();
+        let path = tempdir.path().join("lines.txt.gz2");
+
+        let io = Io::builder().register_extension("gz2", CompressionFormat::Gzip).build();
+        io.write_lines(&path, &lines).unwrap();
+        assert_eq!(io.read_lines(&path).unwrap(), lines);
+
+        let bytes = std::fs::read(&path).unwrap();
+        assert_eq!(&bytes[0..2], &[0x1f, 0x8b]);
+    }
+
+    #[test]
+    fn test_clearing_extensions() {
+        let io = Io::builder().clear_extensions().build();
+        assert_eq!(io.compression_format(&"lines.txt.gz"), CompressionFormat::None);
+    }
+
+    #[test]
+    fn test_reading_and_writing_with_explicit_format() {
+        let lines = vec!["foo", "bar", "baz"];
+        let tempdir = TempDir::new().unwrap();
+        let path = tempdir.path().join("actually_zstd.txt");
+
+        let io = Io::default();
+        let mut writer = io.new_writer_with_format(&path, CompressionFormat::Zstd).unwrap();
+        for line in &lines {
+            writeln!(writer, "{line}").unwrap();
+        }
+        drop(writer);
+
+        let bytes = std::fs::read(&path).unwrap();
+        assert_eq!(&bytes[0..4], &[0x28, 0xb5, 0x2f, 0xfd]);
+
+        let reader = io.new_reader_with_format(&path, CompressionFormat::Zstd).unwrap();
+        let read: Vec<String> = reader.lines().map(|l| l.unwrap()).collect();
+        assert_eq!(read, lines);
+    }
+
+    #[rstest]
+    #[case("lines.txt")]
+    #[case("lines.txt.gz")]
+    #[case("lines.txt.bgz")]
+    #[case("lines.txt.zst")]
+    #[cfg_attr(feature = "bzip2", case("lines.txt.bz2"))]
+    #[cfg_attr(feature = "xz", case("lines.txt.xz"))]
+    fn test_appending_lines(#[case] file_name: &str) {
+        let tempdir = TempDir::new().unwrap();
+        let path = tempdir.path().join(file_name);
+
+        let io = Io::default();
+        io.append_lines(&path, ["foo", "bar"]).unwrap();
+        io.append_lines(&path, ["baz"]).unwrap();
+        assert_eq!(io.read_lines(&path).unwrap(), vec!["foo", "bar", "baz"]);
+
+        if Io::is_bgzf_path(&path) {
+            let reader = io.new_bgzf_reader(&path).unwrap();
+            let bgzf_lines: Vec<String> = reader.lines().map(|l| l.unwrap()).collect();
+            assert_eq!(bgzf_lines, vec!["foo", "bar", "baz"]);
+        }
+    }
+
+    #[test]
+    fn test_appending_to_delim_file() {
+        let recs = vec![
+            Rec { s: "Hello".to_string(), i: 123, b: true, o: None },
+            Rec { s: "World".to_string(), i: 456, b: false, o: Some(123.45) },
+        ];
+        let tmp = TempDir::new().unwrap();
+        let path = tmp.path().join("recs.tsv.gz");
+
+        let df = DelimFile::default();
+        df.append(&path, &recs[0..1], b'\t', true).unwrap();
+        df.append(&path, &recs[1..2], b'\t', true).unwrap();
+
+        let from_tsv: Vec<Rec> = df.read_tsv(&path).unwrap();
+        assert_eq!(from_tsv, recs);
+        assert_eq!(Io::default().read_lines(&path).unwrap().len(), 3);
+    }
+
+    #[test]
+    fn test_creating_parent_directories() {
+        let tempdir = TempDir::new().unwrap();
+        let path = tempdir.path().join("output").join("sample1").join("metrics.tsv.gz");
+
+        assert!(Io::default().write_lines(&path, ["foo"]).is_err());
+
+        let io = Io::builder().create_parent_dirs(true).build();
+        io.write_lines(&path, ["foo"]).unwrap();
+        io.append_lines(&path, ["bar"]).unwrap();
+        assert_eq!(io.read_lines(&path).unwrap(), vec!["foo", "bar"]);
+    }
+
+    #[test]
+    fn test_refusing_to_overwrite_files() {
+        let tempdir = TempDir::new().unwrap();
+        let path = tempdir.path().join("results.txt.gz");
+
+        let io = Io::builder().overwrite(false).build();
+        io.write_lines(&path, ["foo"]).unwrap();
+        let result = io.write_lines(&path, ["bar"]);
+        assert!(matches!(result, Err(FgError::FileExists(p)) if p == path));
+        assert_eq!(io.read_lines(&path).unwrap(), vec!["foo"]);
+
+        // Appending is still allowed, as is overwriting with the default Io
+        io.append_lines(&path, ["bar"]).unwrap();
+        assert_eq!(io.read_lines(&path).unwrap(), vec!["foo", "bar"]);
+        Io::default().write_lines(&path, ["baz"]).unwrap();
+        assert_eq!(io.read_lines(&path).unwrap(), vec!["baz"]);
+    }
+
+    #[cfg(feature = "checksum")]
+    #[rstest]
+    #[case(ChecksumAlgorithm::Md5)]
+    #[case(ChecksumAlgorithm::Sha256)]
+    fn test_writing_checksum_sidecars(#[case] algorithm: ChecksumAlgorithm) {
+        let tempdir = TempDir::new().unwrap();
+        let path = tempdir.path().join("results.tsv.gz");
+        let sidecar = algorithm.sidecar_path(&path);
+        let recs = vec![Rec { s: "Hello".to_string(), i: 123, b: true, o: None }];
+
+        let io = Io::builder().checksum(algorithm).build();
+        io.write_lines(&path, ["foo", "bar"]).unwrap();
+        let first = std::fs::read_to_string(&sidecar).unwrap();
+        assert!(first.ends_with("  results.tsv.gz\n"));
+
+        // Writing identical content produces an identical checksum
+        io.write_lines(&path, ["foo", "bar"]).unwrap();
+        assert_eq!(std::fs::read_to_string(&sidecar).unwrap(), first);
+
+        // Appending covers the whole file, and so matches writing the same content at once
+        let written = tempdir.path().join("written.txt");
+        let appended = tempdir.path().join("appended.txt");
+        io.write_lines(&written, ["foo", "bar"]).unwrap();
+        io.write_lines(&appended, ["foo"]).unwrap();
+        io.append_lines(&appended, ["bar"]).unwrap();
+        let written = std::fs::read_to_string(algorithm.sidecar_path(&written)).unwrap();
+        let appended = std::fs::read_to_string(algorithm.sidecar_path(&appended)).unwrap();
+        assert_eq!(written.split_whitespace().next(), appended.split_whitespace().next());
+
+        // DelimFile outputs get sidecars too
+        let df = DelimFile::new(io);
+        let tsv = tempdir.path().join("recs.tsv");
+        df.write_tsv(&tsv, &recs).unwrap();
+        assert!(algorithm.sidecar_path(&tsv).exists());
+    }
+
+    #[cfg(feature = "checksum")]
+    #[test]
+    fn test_checksum_sidecars_respect_overwrite() {
+        let tempdir = TempDir::new().unwrap();
+        let path = tempdir.path().join("out").join("lines.txt");
+        let sidecar = ChecksumAlgorithm::Md5.sidecar_path(&path);
+        let io = Io::builder()
+            .create_parent_dirs(true)
+            .overwrite(false)
+            .checksum(ChecksumAlgorithm::Md5)
+            .build();
+
+        let mut writer = io.new_writer(&path).unwrap();
+        writer.write_all(b"hello\n").unwrap();
+        writer.finish().unwrap();
+        assert_eq!(
+            std::fs::read_to_string(&sidecar).unwrap(),
+            "b1946ac92492d2347c6235b4d2611184  lines.txt\n"
+        );
+
+        // A stale sidecar is not overwritten, and no output is left without one
+        let other = tempdir.path().join("out").join("other.txt");
+        std::fs::write(ChecksumAlgorithm::Md5.sidecar_path(&other), "stale").unwrap();
+        let result = io.write_lines(&other, ["foo"]);
+        assert!(matches!(result, Err(FgError::FileExists(p)) if p.ends_with("other.txt.md5")));
+        assert!(!other.exists());
+
+        // Appending replaces the sidecar
+        io.append_lines(&path, ["bar"]).unwrap();
+        let expected = "4de3f11f11d1317b6f424e8a6936cb8e  lines.txt\n";
+        assert_eq!(std::fs::read_to_string(&sidecar).unwrap(), expected);
+    }
+
+    #[rstest]
+    #[case("lines.txt")]
+    #[case("lines.txt.gz")]
+    #[case("lines.txt.zst")]
+    fn test_counting_bytes_read_and_written(#[case] file_name: &str) {
+        let tempdir = TempDir::new().unwrap();
+        let path = tempdir.path().join(file_name);
+        let lines: Vec<String> = (0..1000).map(|i| format!("line {i}")).collect();
+        let expected_len: u64 = lines.iter().map(|l| l.len() as u64 + 1).sum();
+
+        let io = Io::default();
+        let (mut writer, written) = io.new_counting_writer(&path).unwrap();
+        for line in &lines {
+            writeln!(writer, "{line}").unwrap();
+        }
+        drop(writer);
+        let file_len = path.metadata().unwrap().len();
+        assert_eq!(written.uncompressed(), expected_len);
+        assert_eq!(written.compressed(), file_len);
+
+        let (reader, read) = io.new_counting_reader(&path).unwrap();
+        assert_eq!(reader.lines().count(), lines.len());
+        assert_eq!(read.uncompressed(), expected_len);
+        assert_eq!(read.compressed(), file_len);
+
+        if Io::is_gzip_path(&path) || Io::is_zstd_path(&path) {
+            assert!(file_len < expected_len);
+        }
+    }
+
+    #[test]
+    fn test_writing_with_tee_writer() {
+        let tempdir = TempDir::new().unwrap();
+        let paths = vec![
+            tempdir.path().join("out.txt"),
+            tempdir.path().join("out.txt.gz"),
+            tempdir.path().join("out.txt.zst"),
+        ];
+
+        let io = Io::default();
+        let mut writer = io.new_tee_writer(&paths).unwrap();
+        writeln!(writer, "foo").unwrap();
+        writeln!(writer, "bar").unwrap();
+        drop(writer);
+
+        for path in &paths {
+            assert_eq!(io.read_lines(path).unwrap(), vec!["foo", "bar"]);
+        }
+        assert_eq!(&std::fs::read(&paths[1]).unwrap()[0..2], &[0x1f, 0x8b]);
+    }
+
+    #[test]
+    fn test_reading_from_multiple_files() {
+        let tempdir = TempDir::new().unwrap();
+        let paths = vec![
+            tempdir.path().join("lane1.txt.gz"),
+            tempdir.path().join("lane2.txt"),
+            tempdir.path().join("lane3.txt.zst"),
+        ];
+
+        let io = Io::default();
+        io.write_lines(&paths[0], ["a", "b"]).unwrap();
+        io.write_lines(&paths[1], Vec::<String>::new()).unwrap();
+        io.write_lines(&paths[2], ["c"]).unwrap();
+
+        let reader = io.new_multi_reader(&paths).unwrap();
+        let lines: Vec<String> = reader.lines().map(|l| l.unwrap()).collect();
+        assert_eq!(lines, vec!["a", "b", "c"]);
+        assert_eq!(io.read_lines_multi(&paths).unwrap(), vec!["a", "b", "c"]);
+
+        // Missing files are reported when the reader is opened
+        let missing = vec![paths[0].clone(), tempdir.path().join("missing.txt")];
+        assert!(io.new_multi_reader(&missing).is_err());
+    }
+
+    #[test]
+    fn test_reading_lines_from_files_without_trailing_newline() {
+        let tempdir = TempDir::new().unwrap();
+        let paths = vec![tempdir.path().join("a.txt"), tempdir.path().join("b.txt")];
+        std::fs::write(&paths[0], "a1\na2").unwrap();
+        std::fs::write(&paths[1], "b1\n").unwrap();
+
+        let io = Io::default();
+        assert_eq!(io.read_lines_multi(&paths).unwrap(), vec!["a1", "a2", "b1"]);
+    }
+
+    #[rstest]
+    #[case("bytes.bin")]
+    #[case("bytes.bin.gz")]
+    #[case("bytes.bin.zst")]
+    #[cfg_attr(feature = "xz", case("bytes.bin.xz"))]
+    fn test_reading_and_writing_bytes(#[case] file_name: &str) {
+        let tempdir = TempDir::new().unwrap();
+        let path = tempdir.path().join(file_name);
+        let bytes: Vec<u8> = (0..=255u8).cycle().take(10_000).collect();
+
+        let io = Io::default();
+        io.write_bytes(&path, &bytes).unwrap();
+        assert_eq!(io.read_bytes(&path).unwrap(), bytes);
+
+        io.write_bytes(&path, &[]).unwrap();
+        assert!(io.read_bytes(&path).unwrap().is_empty());
+    }
+
+    #[rstest]
+    #[case("config.txt")]
+    #[case("config.txt.gz")]
+    #[cfg_attr(feature = "bzip2", case("config.txt.bz2"))]
+    fn test_reading_to_string(#[case] file_name: &str) {
+        let tempdir = TempDir::new().unwrap();
+        let path = tempdir.path().join(file_name);
+
+        let io = Io::default();
+        io.write_lines(&path, ["key=value", "", "other=1"]).unwrap();
+        assert_eq!(io.read_to_string(&path).unwrap(), "key=value\n\nother=1\n");
+
+        io.write_bytes(&path, &[b'a', 0xff, 0xfe]).unwrap();
+        assert!(io.read_to_string(&path).is_err());
+    }
+
+    #[cfg(feature = "mmap")]
+    #[rstest]
+    #[case("lines.txt")]
+    #[case("lines.txt.gz")]
+    fn test_reading_with_mmap_reader(#[case] file_name: &str) {
+        let tempdir = TempDir::new().unwrap();
+        let path = tempdir.path().join(file_name);
+        let lines: Vec<String> = (0..10_000).map(|i| format!("line {i}")).collect();
+
+        let io = Io::default();
+        io.write_lines(&path, &lines).unwrap();
+        // SAFETY: the files are not modified while they are read
+        #[allow(unsafe_code)]
+        let open = |p: &std::path::Path| unsafe { io.new_mmap_reader(&p) };
+        let reader = open(&path).unwrap();
+        assert_eq!(reader.lines().map(|l| l.unwrap()).collect::<Vec<_>>(), lines);
+
+        let empty = tempdir.path().join("empty.txt");
+        io.write_lines(&empty, Vec::<String>::new()).unwrap();
+        assert_eq!(open(&empty).unwrap().lines().count(), 0);
+
+        assert!(open("-".as_ref()).is_err());
+    }
+
+    #[rstest]
+    #[case("lines.txt")]
+    #[case("lines.txt.gz")]
+    #[case("lines.txt.zst")]
+    fn test_reading_with_threaded_reader(#[case] file_name: &str) {
+        let tempdir = TempDir::new().unwrap();
+        let path = tempdir.path().join(file_name);
+        let lines: Vec<String> = (0..10_000).map(|i| format!("line {i}")).collect();
+
+        let io = Io::builder().buffer_size(1024).build();
+        io.write_lines(&path, &lines).unwrap();
+        let reader = io.new_threaded_reader(&path).unwrap();
+        assert_eq!(reader.lines().map(|l| l.unwrap()).collect::<Vec<_>>(), lines);
+    }
+
+    #[rstest]
+    #[case("lines.txt")]
+    #[case("lines.txt.gz")]
+    #[case("lines.txt.zst")]
+    fn test_writing_with_threaded_writer(#[case] file_name: &str) {
+        let tempdir = TempDir::new().unwrap();
+        let path = tempdir.path().join(file_name);
+        let lines: Vec<String> = (0..10_000).map(|i| format!("line {i}")).collect();
+
+        let io = Io::builder().buffer_size(1024).build();
+        let mut writer = io.new_threaded_writer(&path).unwrap();
+        for line in &lines {
+            writeln!(writer, "{line}").unwrap();
+        }
+        writer.finish().unwrap();
+        assert_eq!(io.read_lines(&path).unwrap(), lines);
+    }
+
+    #[rstest]
+    #[case("lines.txt")]
+    #[case("lines.txt.gz")]
+    fn test_reading_lines_lazily(#[case] file_name: &str) {
+        let tempdir = TempDir::new().unwrap();
+        let path = tempdir.path().join(file_name);
+
+        let io = Io::default();
+        io.write_lines(&path, (0..1000).map(|i| format!("line {i}"))).unwrap();
+        let mut lines = io.read_lines_iter(&path).unwrap();
+        assert_eq!(lines.next().unwrap().unwrap(), "line 0");
+        assert_eq!(lines.last().unwrap().unwrap(), "line 999");
+        assert_eq!(io.read_lines_iter(&path).unwrap().count(), 1000);
+
+        assert!(io.read_lines_iter(&tempdir.path().join("missing.txt")).is_err());
+    }
+
+    #[rstest]
+    #[case("lines.txt")]
+    #[case("lines.txt.gz")]
+    fn test_reading_byte_lines(#[case] file_name: &str) {
+        let tempdir = TempDir::new().unwrap();
+        let path = tempdir.path().join(file_name);
+
+        let io = Io::default();
+        io.write_bytes(&path, b"caf\xe9\r\n\x00\xff\n\nlast").unwrap();
+        assert!(io.read_lines(&path).is_err());
+
+        let lines: Vec<Vec<u8>> = io.read_byte_lines(&path).unwrap().map(|l| l.unwrap()).collect();
+        assert_eq!(lines, vec![b"caf\xe9".to_vec(), vec![0x00, 0xff], vec![], b"last".to_vec()]);
+    }
+
+    #[rstest]
+    #[case("lines.txt", 4)]
+    #[case("lines.txt", 1024)]
+    #[case("lines.txt.gz", 1024)]
+    fn test_reading_first_and_last_n_lines(#[case] file_name: &str, #[case] buffer_size: usize) {
+        let tempdir = TempDir::new().unwrap();
+        let path = tempdir.path().join(file_name);
+        let lines: Vec<String> = (0..1000).map(|i| format!("line {i}")).collect();
+
+        let io = Io::builder().buffer_size(buffer_size).build();
+        io.write_lines(&path, &lines).unwrap();
+        for n in [0, 1, 3, 999, 1000, 5000] {
+            let expected = &lines[..n.min(1000)];
+            assert_eq!(io.read_first_n_lines(&path, n).unwrap(), expected);
+            let expected = &lines[1000 - n.min(1000)..];
+            assert_eq!(io.read_last_n_lines(&path, n).unwrap(), expected);
+        }
+
+        // Files without a trailing newline, with empty lines and with no lines at all
+        io.write_bytes(&path, b"a\r\n\nb\nc").unwrap();
+        assert_eq!(io.read_last_n_lines(&path, 3).unwrap(), vec!["", "b", "c"]);
+        assert_eq!(io.read_last_n_lines(&path, 10).unwrap(), vec!["a", "", "b", "c"]);
+        io.write_bytes(&path, b"\n\n").unwrap();
+        assert_eq!(io.read_last_n_lines(&path, 1).unwrap(), vec![""]);
+        io.write_bytes(&path, b"").unwrap();
+        assert!(io.read_last_n_lines(&path, 1).unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_following_files() {
+        let tempdir = TempDir::new().unwrap();
+        let path = tempdir.path().join("log.txt");
+        let interval = Duration::from_millis(5);
+        let timeout = Some(Duration::from_millis(50));
+
+        let io = Io::default();
+        io.write_lines(&path, ["a", "b"]).unwrap();
+        let reader = io.new_follow_reader(&path, interval, timeout).unwrap();
+        assert_eq!(reader.lines().map(|l| l.unwrap()).collect::<Vec<_>>(), vec!["a", "b"]);
+
+        let gzipped = tempdir.path().join("log.txt.gz");
+        io.write_lines(&gzipped, ["a"]).unwrap();
+        assert!(io.new_follow_reader(&gzipped, interval, timeout).is_err());
+    }
+
+    #[rstest]
+    #[case("lines.txt")]
+    #[case("lines.txt.gz")]
+    #[case("lines.txt.zst")]
+    fn test_counting_lines(#[case] file_name: &str) {
+        let tempdir = TempDir::new().unwrap();
+        let path = tempdir.path().join(file_name);
+
+        let io = Io::default();
+        io.write_lines(&path, (0..100_000).map(|i| format!("line {i}"))).unwrap();
+        assert_eq!(io.count_lines(&path).unwrap(), 100_000);
+
+        for (contents, expected) in [("", 0), ("\n", 1), ("a", 1), ("a\n\nb", 3), ("a\r\nb\r\n", 2)]
+        {
+            io.write_bytes(&path, contents.as_bytes()).unwrap();
+            assert_eq!(io.count_lines(&path).unwrap(), expected);
+            assert_eq!(io.read_lines(&path).unwrap().len() as u64, expected);
+        }
+    }
+
+    #[rstest]
+    #[case("lines.txt")]
+    #[case("lines.txt.gz")]
+    fn test_max_line_length(#[case] file_name: &str) {
+        let tempdir = TempDir::new().unwrap();
+        let path = tempdir.path().join(file_name);
+
+        let io = Io::builder().max_line_length(10).build();
+        io.write_lines(&path, ["short", "exactly 10"]).unwrap();
+        assert_eq!(io.read_lines(&path).unwrap(), vec!["short", "exactly 10"]);
+
+        io.write_lines(&path, ["short", &"x".repeat(1_000_000)]).unwrap();
+        let err = std::io::Error::from(io.read_lines(&path).unwrap_err());
+        assert!(err.to_string().contains("Line 2 exceeds the maximum line length"));
+        assert!(io.read_last_n_lines(&path, 1).is_err());
+        assert!(Io::default().read_lines(&path).is_ok());
+    }
+
+    #[rstest]
+    #[case("bomb.txt")]
+    #[case("bomb.txt.gz")]
+    #[case("bomb.txt.zst")]
+    fn test_max_decompressed_bytes(#[case] file_name: &str) {
+        let tempdir = TempDir::new().unwrap();
+        let path = tempdir.path().join(file_name);
+
+        let io = Io::builder().max_decompressed_bytes(1024 * 1024).build();
+        io.write_bytes(&path, &vec![b'a'; 1024 * 1024]).unwrap();
+        assert_eq!(io.read_bytes(&path).unwrap().len(), 1024 * 1024);
+
+        io.write_bytes(&path, &vec![b'a'; 1024 * 1024 + 1]).unwrap();
+        let err = std::io::Error::from(io.read_bytes(&path).unwrap_err());
+        assert!(err.to_string().contains("maximum of 1048576 decompressed bytes"));
+    }
+
+    #[test]
+    fn test_reading_lines_with_options() {
+        let tempdir = TempDir::new().unwrap();
+        let path = tempdir.path().join("samples.txt.gz");
+
+        let io = Io::default();
+        io.write_lines(&path, ["# samples", "  s1 ", "", "s2", "   ", "  # trailing"]).unwrap();
+        let opts = LineOptions::default().trim_whitespace(true).skip_empty(true).skip_prefix("#");
+        assert_eq!(io.read_lines_with_opts(&path, &opts).unwrap(), vec!["s1", "s2"]);
+        assert_eq!(io.read_lines_with_opts(&path, &LineOptions::default()).unwrap().len(), 6);
+    }
+
+    #[cfg(feature = "encoding")]
+    #[rstest]
+    #[case("utf-16le", "sheet.csv")]
+    #[case("utf-16be", "sheet.csv.gz")]
+    #[case("windows-1252", "sheet.csv")]
+    #[case("latin1", "sheet.csv.zst")]
+    fn test_reading_and_writing_with_encoding(#[case] label: &str, #[case] file_name: &str) {
+        use crate::io::Encoding;
+
+        let tempdir = TempDir::new().unwrap();
+        let path = tempdir.path().join(file_name);
+        let encoding = Encoding::for_label(label.as_bytes()).unwrap();
+        let lines = vec!["sample,gène", "s1,café"];
+
+        let io = Io::builder().encoding(encoding).build();
+        io.write_lines(&path, &lines).unwrap();
+        assert_eq!(io.read_lines(&path).unwrap(), lines);
+
+        // The raw bytes are in the requested encoding, and so can't be read as UTF-8
+        let (expected, _, _) = encoding.encode("s1,café\n");
+        let raw = Io::default().read_bytes(&path).unwrap();
+        if encoding.output_encoding() == encoding {
+            assert!(raw.ends_with(&expected));
+        }
+        assert!(Io::default().read_lines(&path).is_err());
+    }
+
+    #[rstest]
+    #[case("samples.csv")]
+    #[case("samples.csv.gz")]
+    fn test_stripping_byte_order_marks(#[case] file_name: &str) {
+        let tempdir = TempDir::new().unwrap();
+        let path = tempdir.path().join(file_name);
+        let io = Io::default();
+
+        io.write_bytes(&path, b"\xef\xbb\xbfsample_name,count\ns1,1\n").unwrap();
+        assert_eq!(io.read_lines(&path).unwrap(), vec!["sample_name,count", "s1,1"]);
+        assert_eq!(io.read_last_n_lines(&path, 2).unwrap(), vec!["sample_name,count", "s1,1"]);
+        let unstripped = Io::builder().strip_bom(false).build().read_lines(&path).unwrap();
+        assert_eq!(unstripped[0], "\u{feff}sample_name,count");
+        assert!(io.read_bytes(&path).unwrap().starts_with(b"\xef\xbb\xbf"));
+
+        let mut utf16 = vec![0xff, 0xfe];
+        utf16.extend("sample_name,count\r\ns1,1\r\n".encode_utf16().flat_map(u16::to_le_bytes));
+        io.write_bytes(&path, &utf16).unwrap();
+        assert_eq!(io.read_lines(&path).unwrap(), vec!["sample_name,count", "s1,1"]);
+        assert_eq!(io.read_last_n_lines(&path, 1).unwrap(), vec!["s1,1"]);
+    }
+
+    #[rstest]
+    #[case(LineTerminator::Lf, "a\nb\n")]
+    #[case(LineTerminator::CrLf, "a\r\nb\r\n")]
+    #[case(LineTerminator::Custom(b'\0'), "a\0b\0")]
+    fn test_writing_line_terminators(#[case] terminator: LineTerminator, #[case] expected: &str) {
+        let tempdir = TempDir::new().unwrap();
+        let path = tempdir.path().join("lines.txt.gz");
+
+        let io = Io::builder().line_terminator(terminator).build();
+        io.write_lines(&path, ["a", "b"]).unwrap();
+        assert_eq!(io.read_to_string(&path).unwrap(), expected);
+        if terminator != LineTerminator::Custom(b'\0') {
+            assert_eq!(io.read_lines(&path).unwrap(), vec!["a", "b"]);
+        }
+    }
+
+    #[test]
+    fn test_writing_delim_file_with_crlf() {
+        let tempdir = TempDir::new().unwrap();
+        let path = tempdir.path().join("recs.csv");
+        let recs = vec![Rec { s: "a".to_string(), i: 1, b: true, o: None }];
+
+        let delim = DelimFile::new(Io::builder().line_terminator(LineTerminator::CrLf).build());
+        delim.write_csv(&path, &recs).unwrap();
+        assert_eq!(std::fs::read_to_string(&path).unwrap(), "s,i,b,o\r\na,1,true,\r\n");
+        let read: Vec<Rec> = delim.read_csv(&path).unwrap();
+        assert_eq!(read, recs);
+    }
+
+    #[rstest]
+    #[case(1, 10)]
+    #[case(3, 4)]
+    #[case(10, 1)]
+    #[case(100, 1)]
+    fn test_reading_lines_in_chunks(#[case] chunk_size: usize, #[case] num_chunks: usize) {
+        let tempdir = TempDir::new().unwrap();
+        let path = tempdir.path().join("lines.txt.gz");
+        let lines: Vec<String> = (0..10).map(|i| format!("line {i}")).collect();
+
+        let io = Io::default();
+        io.write_lines(&path, &lines).unwrap();
+        let chunks: Vec<Vec<String>> =
+            io.read_lines_chunked(&path, chunk_size).unwrap().map(|c| c.unwrap()).collect();
+        assert_eq!(chunks.len(), num_chunks);
+        assert!(chunks.iter().all(|c| c.len() <= chunk_size));
+        assert_eq!(chunks.concat(), lines);
+
+        io.write_lines(&path, Vec::<String>::new()).unwrap();
+        assert_eq!(io.read_lines_chunked(&path, chunk_size).unwrap().count(), 0);
+    }
+
+    #[test]
+    fn test_reading_filtered_lines() {
+        let tempdir = TempDir::new().unwrap();
+        let path = tempdir.path().join("lines.txt.gz");
+
+        let io = Io::default();
+        io.write_bytes(&path, b"keep 1\r\ndrop 2\nkeep 3\n\nkeep 4").unwrap();
+        let kept = io.read_lines_filtered(&path, |l| l.starts_with("keep")).unwrap();
+        assert_eq!(kept, vec!["keep 1", "keep 3", "keep 4"]);
+
+        let mut seen = 0;
+        let mut iter = io
+            .read_lines_filtered_iter(&path, |l| {
+                seen += 1;
+                l.is_empty()
+            })
+            .unwrap();
+        assert_eq!(iter.next().unwrap().unwrap(), "");
+        assert!(iter.next().is_none());
+        drop(iter);
+        assert_eq!(seen, 5);
+    }
+
+    #[test]
+    fn test_reading_sampled_lines() {
+        let tempdir = TempDir::new().unwrap();
+        let path = tempdir.path().join("lines.txt.gz");
+        let lines: Vec<String> = (0..10_000).map(|i| format!("line {i}")).collect();
+
+        let io = Io::default();
+        io.write_lines(&path, &lines).unwrap();
+
+        let sampled = io.read_lines_sampled(&path, LineSampling::EveryNth(1000)).unwrap();
+        assert_eq!(sampled, lines.iter().step_by(1000).cloned().collect::<Vec<_>>());
+        let all = io.read_lines_sampled(&path, LineSampling::EveryNth(1)).unwrap();
+        assert_eq!(all, lines);
+    }
+
+    #[cfg(feature = "rand")]
+    #[test]
+    fn test_reading_lines_sampled_with_probability() {
+        let tempdir = TempDir::new().unwrap();
+        let path = tempdir.path().join("lines.txt.gz");
+        let lines: Vec<String> = (0..10_000).map(|i| format!("line {i}")).collect();
+
+        let io = Io::default();
+        io.write_lines(&path, &lines).unwrap();
+
+        let sampling = LineSampling::Probability { p: 0.1, seed: 42 };
+        let sampled = io.read_lines_sampled(&path, sampling).unwrap();
+        assert!(sampled.len() > 800 && sampled.len() < 1200);
+        assert_eq!(io.read_lines_sampled(&path, sampling).unwrap(), sampled);
+        let other = LineSampling::Probability { p: 0.1, seed: 7 };
+        assert_ne!(io.read_lines_sampled(&path, other).unwrap(), sampled);
+
+        let pinned = LineSampling::Probability { p: 0.001, seed: 42 };
+        let expected = [733, 1558, 2542, 2578, 3779, 4120, 6016, 6171, 6382, 7776, 8840, 8854];
+        let expected: Vec<String> = expected.iter().map(|i| format!("line {i}")).collect();
+        assert_eq!(io.read_lines_sampled(&path, pinned).unwrap(), expected);
+
+        let none = LineSampling::Probability { p: 0.0, seed: 42 };
+        assert!(io.read_lines_sampled(&path, none).unwrap().is_empty());
+    }
+
+    #[cfg(feature = "rand")]
+    #[test]
+    fn test_reservoir_sampling_lines() {
+        let tempdir = TempDir::new().unwrap();
+        let path = tempdir.path().join("lines.txt.gz");
+        let lines: Vec<String> = (0..1000).map(|i| format!("line {i}")).collect();
+
+        let io = Io::default();
+        io.write_lines(&path, &lines).unwrap();
+        let sample = io.reservoir_sample_lines(&path, 10, 42).unwrap();
+        assert_eq!(sample.len(), 10);
+        assert!(sample.iter().all(|l| lines.contains(l)));
+        assert_eq!(io.reservoir_sample_lines(&path, 10, 42).unwrap(), sample);
+        assert_eq!(io.reservoir_sample_lines(&path, 5000, 42).unwrap(), lines);
+    }
+
+    #[cfg(feature = "log")]
+    #[test]
+    fn test_reading_with_progress_reader() {
+        let tempdir = TempDir::new().unwrap();
+        let path = tempdir.path().join("lines.txt.gz");
+        let lines: Vec<String> = (0..1000).map(|i| format!("line {i}")).collect();
+
+        let io = Io::default();
+        io.write_lines(&path, &lines).unwrap();
+        let reader = io.new_progress_reader(&path, ProgressInterval::Bytes(1024)).unwrap();
+        assert_eq!(reader.lines().map(|l| l.unwrap()).collect::<Vec<_>>(), lines);
+    }
+
+    #[cfg(feature = "indicatif")]
+    #[rstest]
+    #[case("lines.txt", Some(6000))]
+    #[case("lines.txt.gz", None)]
+    fn test_progress_bars(#[case] name: &str, #[case] expected_len: Option<u64>) {
+        let tempdir = TempDir::new().unwrap();
+        let path = tempdir.path().join(name);
+        let lines = vec!["hello"; 1000];
+
+        let io = Io::default();
+        let (mut writer, bar) = io.new_writer_with_progress_bar(&path).unwrap();
+        for line in &lines {
+            writeln!(writer, "{line}").unwrap();
+        }
+        drop(writer);
+        assert_eq!(bar.position(), 6000);
+
+        let (reader, bar) = io.new_reader_with_progress_bar(&path).unwrap();
+        assert_eq!(bar.length(), expected_len);
+        assert_eq!(reader.lines().map(|l| l.unwrap()).collect::<Vec<_>>(), lines);
+        assert_eq!(bar.position(), 6000);
+    }
+
+    #[rstest]
+    #[case("in.tsv", "out.tsv.gz")]
+    #[case("in.tsv.gz", "out.tsv.zst")]
+    #[cfg_attr(all(feature = "bzip2", feature = "xz"), case("in.tsv.bz2", "out.tsv.xz"))]
+    #[case("in.tsv.zst", "out.tsv")]
+    fn test_copying_between_compression_formats(#[case] src: &str, #[case] dst: &str) {
+        let tempdir = TempDir::new().unwrap();
+        let src = tempdir.path().join(src);
+        let dst = tempdir.path().join(dst);
+        let lines: Vec<String> = (0..100).map(|i| format!("{i}\tline {i}")).collect();
+
+        let io = Io::default();
+        io.write_lines(&src, &lines).unwrap();
+        let copied = io.copy(&src, &dst).unwrap();
+        assert_eq!(copied, io.read_bytes(&src).unwrap().len() as u64);
+        assert_eq!(io.read_lines(&dst).unwrap(), lines);
+        assert_eq!(
+            io.compression_format(&dst),
+            CompressionFormat::from_magic(&std::fs::read(&dst).unwrap())
+        );
+
+        #[cfg(feature = "log")]
+        {
+            let dst2 = tempdir.path().join("progress.txt.gz");
+            assert_eq!(
+                io.copy_with_progress(&src, &dst2, ProgressInterval::Lines(10)).unwrap(),
+                copied
+            );
+            assert_eq!(io.read_lines(&dst2).unwrap(), lines);
+        }
+    }
+
+    #[rstest]
+    #[case(CompressionFormat::Gzip, "data.tsv.gz", false)]
+    #[case(CompressionFormat::Zstd, "data.tsv.zst", true)]
+    #[case(CompressionFormat::Bgzf, "data.tsv.bgz", true)]
+    fn test_compressing_and_decompressing_files(
+        #[case] format: CompressionFormat,
+        #[case] expected_name: &str,
+        #[case] remove_original: bool,
+    ) {
+        let tempdir = TempDir::new().unwrap();
+        let plain = tempdir.path().join("data.tsv");
+        let lines: Vec<String> = (0..100).map(|i| format!("{i}\tline {i}")).collect();
+
+        let io = Io::default();
+        io.write_lines(&plain, &lines).unwrap();
+        let compressed = io.compress_file(&plain, format, remove_original).unwrap();
+        assert_eq!(compressed, tempdir.path().join(expected_name));
+        assert_eq!(plain.exists(), !remove_original);
+        assert_eq!(io.read_lines(&compressed).unwrap(), lines);
+
+        let io = Io::builder().overwrite(false).build();
+        if remove_original {
+            let decompressed = io.decompress_file(&compressed, true).unwrap();
+            assert_eq!(decompressed, plain);
+            assert!(!compressed.exists());
+            assert_eq!(io.read_lines(&plain).unwrap(), lines);
+        } else {
+            let result = io.decompress_file(&compressed, true);
+            assert!(matches!(result, Err(FgError::FileExists(_))));
+            assert!(compressed.exists());
+        }
+
+        // No temporary files should be left behind
+        let names: Vec<_> = std::fs::read_dir(tempdir.path()).unwrap().collect();
+        assert_eq!(names.len(), if remove_original { 1 } else { 2 });
+    }
+
+    #[cfg(feature = "checksum")]
+    #[test]
+    fn test_compressing_files_with_checksums() {
+        let tempdir = TempDir::new().unwrap();
+        let plain = tempdir.path().join("data.tsv");
+        let io = Io::builder().checksum(ChecksumAlgorithm::Md5).build();
+        io.write_lines(&plain, ["a\tb"]).unwrap();
+
+        let compressed = io.compress_file(&plain, CompressionFormat::Gzip, true).unwrap();
+        let sidecar = std::fs::read_to_string(tempdir.path().join("data.tsv.gz.md5")).unwrap();
+        assert_eq!(compressed, tempdir.path().join("data.tsv.gz"));
+        assert_eq!(sidecar.split_whitespace().nth(1), Some("data.tsv.gz"));
+
+        // Only the compressed file and its sidecar should be left behind
+        let mut names: Vec<String> = std::fs::read_dir(tempdir.path())
+            .unwrap()
+            .map(|e| e.unwrap().file_name().to_string_lossy().into_owned())
+            .collect();
+        names.sort();
+        assert_eq!(names, ["data.tsv.gz", "data.tsv.gz.md5", "data.tsv.md5"]);
+    }
+
+    #[test]
+    fn test_compressing_and_decompressing_files_with_invalid_formats() {
+        let tempdir = TempDir::new().unwrap();
+        let plain = tempdir.path().join("data.tsv");
+        let io = Io::default();
+        io.write_lines(&plain, ["a"]).unwrap();
+        assert!(io.compress_file(&plain, CompressionFormat::None, false).is_err());
+        assert!(io.decompress_file(&plain, false).is_err());
+    }
+
+    #[rstest]
+    #[case("part.gz", "merged.gz", false)]
+    #[case("part.bgz", "merged.bgz", true)]
+    #[case("part.gz", "merged.bgz", true)]
+    fn test_concatenating_gzip_files(
+        #[case] part_name: &str,
+        #[case] output_name: &str,
+        #[case] expect_eof: bool,
+    ) {
+        let tempdir = TempDir::new().unwrap();
+        let io = Io::default();
+        let mut parts = vec![];
+        let mut expected = vec![];
+        for i in 0..3 {
+            let path = tempdir.path().join(format!("{i}.{part_name}"));
+            let lines: Vec<String> = (0..10).map(|j| format!("part {i} line {j}")).collect();
+            io.write_lines(&path, &lines).unwrap();
+            expected.extend(lines);
+            parts.push(path);
+        }
+
+        let output = tempdir.path().join(output_name);
+        let written = io.concat_gzip_files(&parts, &output).unwrap();
+        let bytes = std::fs::read(&output).unwrap();
+        assert_eq!(written, bytes.len() as u64);
+        assert_eq!(io.read_lines(&output).unwrap(), expected);
+        assert_eq!(bytes.ends_with(&BGZF_EOF), expect_eof);
+        // Only the final end-of-file marker should remain
+        let eof_count = bytes.windows(BGZF_EOF.len()).filter(|w| *w == BGZF_EOF).count();
+        assert_eq!(eof_count, usize::from(expect_eof));
+    }
+
+    #[test]
+    fn test_concatenating_non_gzip_files_fails() {
+        let tempdir = TempDir::new().unwrap();
+        let io = Io::default();
+        let gz = tempdir.path().join("a.txt.gz");
+        let plain = tempdir.path().join("b.txt");
+        io.write_lines(&gz, ["a"]).unwrap();
+        io.write_lines(&plain, ["b"]).unwrap();
+
+        let output = tempdir.path().join("merged.gz");
+        assert!(io.concat_gzip_files(&[&gz, &plain], &output).is_err());
+        assert!(!output.exists());
+    }
+
+    #[test]
+    fn test_reading_delim_file_iteratively() {
+        let tempdir = TempDir::new().unwrap();
+        let path = tempdir.path().join("recs.tsv.gz");
+        let recs: Vec<Rec> =
+            (0..1000).map(|i| Rec { s: format!("rec{i}"), i, b: i % 2 == 0, o: None }).collect();
+
+        let df = DelimFile::default();
+        df.write_tsv(&path, &recs).unwrap();
+        let mut reader = df.read_iter::<Rec, _>(&path, b'\t', true).unwrap();
+        assert_eq!(reader.next().unwrap().unwrap(), recs[0]);
+        let rest: Vec<Rec> = reader.map(|r| r.unwrap()).collect();
+        assert_eq!(rest, recs[1..]);
+    }
+
+    #[test]
+    fn test_writing_delim_file_incrementally() {
+        let tempdir = TempDir::new().unwrap();
+        let path = tempdir.path().join("recs.csv.gz");
+        let recs: Vec<Rec> = (0..100)
+            .map(|i| Rec { s: format!("rec{i}"), i, b: i % 2 == 0, o: Some(i as f64 / 2.0) })
+            .collect();
+
+        let df = DelimFile::default();
+        let mut writer = df.new_writer(&path, b',', true).unwrap();
+        for rec in &recs {
+            writer.write_record(rec).unwrap();
+        }
+        writer.finish().unwrap();
+        assert_eq!(df.read_csv::<Rec, _>(&path).unwrap(), recs);
+    }
+
+    #[test]
+    fn test_delim_file_builder_options() {
+        let tempdir = TempDir::new().unwrap();
+        let path = tempdir.path().join("recs.csv");
+        let recs = vec![
+            Rec { s: "it's".to_string(), i: 1, b: true, o: None },
+            Rec { s: "a,b".to_string(), i: 2, b: false, o: Some(1.5) },
+        ];
+
+        let df = DelimFile::builder()
+            .quote(b'\'')
+            .escape(Some(b'\\'))
+            .terminator(LineTerminator::CrLf)
+            .comment(Some(b'#'))
+            .trim(Trim::All)
+            .build();
+        df.write_csv(&path, &recs).unwrap();
+        assert_eq!(
+            std::fs::read_to_string(&path).unwrap(),
+            "s,i,b,o\r\n'it\\'s',1,true,\r\n'a,b',2,false,1.5\r\n"
+        );
+        assert_eq!(df.read_csv::<Rec, _>(&path).unwrap(), recs);
+
+        // Comments are skipped and whitespace trimmed when reading
+        std::fs::write(&path, "# comment\ns , i,b,o\n x ,1, true,\n# another\ny,2,false , 2\n")
+            .unwrap();
+        let expected = vec![
+            Rec { s: "x".to_string(), i: 1, b: true, o: None },
+            Rec { s: "y".to_string(), i: 2, b: false, o: Some(2.0) },
+        ];
+        assert_eq!(df.read_csv::<Rec, _>(&path).unwrap(), expected);
+    }
+
+    #[test]
+    fn test_delim_file_flexible_records() {
+        let tempdir = TempDir::new().unwrap();
+        let path = tempdir.path().join("recs.csv");
+        std::fs::write(&path, "s,i,b,o\nx,1,true\n").unwrap();
+
+        assert!(DelimFile::default().read_csv::<Rec, _>(&path).is_err());
+        let df = DelimFile::builder().flexible(true).build();
+        let recs: Vec<Rec> = df.read_csv(&path).unwrap();
+        assert_eq!(recs, vec![Rec { s: "x".to_string(), i: 1, b: true, o: None }]);
+    }
+
+    #[test]
+    fn test_reading_ragged_delim_file() {
+        #[derive(Debug, Deserialize, PartialEq)]
+        struct Padded {
+            s: String,
+            i: usize,
+            #[serde(default)]
+            b: bool,
+            o: Option<f64>,
+        }
+
+        let tempdir = TempDir::new().unwrap();
+        let path = tempdir.path().join("recs.csv");
+        std::fs::write(&path, "s,i,b,o\nx,1,true,0.5\ny,2\nz,3,true\n").unwrap();
+
+        let df = DelimFile::builder().ragged(true).build();
+        let mut reader = df.read_iter::<Padded, _>(&path, b',', true).unwrap();
+        let recs: Vec<Padded> = reader.by_ref().map(|r| r.unwrap()).collect();
+        assert_eq!(
+            recs,
+            vec![
+                Padded { s: "x".to_string(), i: 1, b: true, o: Some(0.5) },
+                Padded { s: "y".to_string(), i: 2, b: false, o: None },
+                Padded { s: "z".to_string(), i: 3, b: true, o: None },
+            ]
+        );
+        assert_eq!(reader.padded_records(), 2);
+
+        // Records with extra fields are still an error
+        std::fs::write(&path, "s,i,b,o\nx,1,true,0.5,extra\n").unwrap();
+        match df.read_csv::<Padded, _>(&path).unwrap_err() {
+            FgError::FieldCountMismatch { line, expected, found } => {
+                assert_eq!((line, expected, found), (2, 4, 5));
+            }
+            e => panic!("unexpected error: {e}"),
+        }
+    }
+
+    #[rstest]
+    #[case(1 << 20)]
+    #[case(100)]
+    fn test_sorting_delim_file(#[case] buffer_size: usize) {
+        let tempdir = TempDir::new().unwrap();
+        let input = tempdir.path().join("unsorted.tsv.gz");
+        let output = tempdir.path().join("sorted.tsv");
+        let mut lines = vec!["contig\tpos\tname".to_string()];
+        lines.extend((0..50).map(|i| format!("chr{}\t{}\tr{i}", i % 3, (i * 37) % 20)));
+        Io::default().write_lines(&input, &lines).unwrap();
+
+        let df = DelimFile::builder().sort_buffer_size(buffer_size).build();
+        let keys = [SortKey::new("contig"), SortKey::new("pos").numeric(true)];
+        assert_eq!(df.sort(&input, &output, b'\t', &keys).unwrap(), 50);
+
+        let sorted = Io::default().read_lines(&output).unwrap();
+        let mut expected = lines[1..].to_vec();
+        let key = |l: &String| {
+            let fields: Vec<&str> = l.split('\t').collect();
+            (fields[0].to_string(), fields[1].parse::<u32>().unwrap())
+        };
+        expected.sort_by_key(key);
+        assert_eq!(sorted[0], lines[0]);
+        assert_eq!(sorted[1..], expected);
+
+        let missing = df.sort(&input, &output, b'\t', &[SortKey::new("start")]);
+        assert!(matches!(missing, Err(FgError::HeaderMismatch { .. })));
+    }
+
+    #[test]
+    fn test_merging_sorted_delim_files() {
+        let tempdir = TempDir::new().unwrap();
+        let shards = [
+            vec!["contig\tpos", "chr1\t5", "chr1\t30", "chr2\t1"],
+            vec!["contig\tpos", "chr1\t5", "chr1\t10"],
+            vec!["contig\tpos"],
+            vec!["contig\tpos", "chr2\t0", "chr3\t7"],
+        ];
+        let inputs: Vec<_> = shards
+            .iter()
+            .enumerate()
+            .map(|(i, lines)| {
+                let path = tempdir.path().join(format!("shard{i}.tsv.gz"));
+                Io::default().write_lines(&path, lines).unwrap();
+                path
+            })
+            .collect();
+        let output = tempdir.path().join("merged.tsv");
+        let keys = [SortKey::new("contig"), SortKey::new("pos").numeric(true)];
+
+        let df = DelimFile::default();
+        assert_eq!(df.merge_sorted(&inputs, &output, b'\t', &keys).unwrap(), 7);
+        let merged = Io::default().read_lines(&output).unwrap();
+        let expected = [
+            "contig\tpos",
+            "chr1\t5",
+            "chr1\t5",
+            "chr1\t10",
+            "chr1\t30",
+            "chr2\t0",
+            "chr2\t1",
+            "chr3\t7",
+        ];
+        assert_eq!(merged, expected);
+
+        let other = tempdir.path().join("other.tsv");
+        Io::default().write_lines(&other, ["contig\tstart", "chr1\t1"]).unwrap();
+        let result = df.merge_sorted(&[&inputs[0], &other], &output, b'\t', &keys);
+        match result {
+            Err(FgError::HeaderMismatch { missing, unexpected }) => {
+                assert_eq!(missing, vec!["pos"]);
+                assert_eq!(unexpected, vec!["start"]);
+            }
+            _ => panic!("expected a header mismatch"),
+        }
+    }
+
+    #[rstest]
+    #[case(true, DuplicateKeys::KeepFirst, &["a\tb", "x\t1", "y\t3"])]
+    #[case(true, DuplicateKeys::KeepLast, &["a\tb", "x\t2", "y\t3"])]
+    #[case(false, DuplicateKeys::KeepFirst, &["a\tb", "x\t1", "y\t3"])]
+    #[case(false, DuplicateKeys::KeepLast, &["a\tb", "x\t2", "y\t3"])]
+    fn test_deduplicating_delim_file(
+        #[case] sorted: bool,
+        #[case] duplicates: DuplicateKeys,
+        #[case] expected: &[&str],
+    ) {
+        let tempdir = TempDir::new().unwrap();
+        let input = tempdir.path().join("input.tsv.gz");
+        let output = tempdir.path().join("output.tsv");
+        Io::default().write_lines(&input, ["a\tb", "x\t1", "x\t2", "y\t3"]).unwrap();
+
+        let df = DelimFile::default();
+        let dropped = df.dedup(&input, &output, b'\t', &["a"], duplicates, sorted).unwrap();
+        assert_eq!(dropped, 1);
+        assert_eq!(Io::default().read_lines(&output).unwrap(), expected);
+
+        let result = df.dedup(&input, &output, b'\t', &["a"], DuplicateKeys::Reject, sorted);
+        assert!(matches!(result, Err(FgError::DuplicateKey { line: 3, .. })));
+    }
+
+    #[test]
+    fn test_joining_delim_files() {
+        let tempdir = TempDir::new().unwrap();
+        let reads = tempdir.path().join("reads.tsv.gz");
+        let sheet = tempdir.path().join("sheet.tsv");
+        let output = tempdir.path().join("joined.tsv");
+        Io::default().write_lines(&reads, ["sample\treads", "s1\t10", "s2\t20", "s3\t30"]).unwrap();
+        Io::default().write_lines(&sheet, ["sample\tproject", "s2\tb", "s1\ta"]).unwrap();
+
+        let df = DelimFile::default();
+        let count = df.join(&reads, &sheet, &output, b'\t', &["sample"], JoinKind::Inner).unwrap();
+        assert_eq!(count, 2);
+        let joined = Io::default().read_lines(&output).unwrap();
+        assert_eq!(joined, ["sample\treads\tproject", "s1\t10\ta", "s2\t20\tb"]);
+
+        let count = df.join(&reads, &sheet, &output, b'\t', &["sample"], JoinKind::Left).unwrap();
+        assert_eq!(count, 3);
+        let joined = Io::default().read_lines(&output).unwrap();
+        assert_eq!(joined[3], "s3\t30\t");
+    }
+
+    #[test]
+    fn test_processing_delim_file() {
+        #[derive(Deserialize)]
+        struct Interval {
+            contig: String,
+            start: u64,
+            end: u64,
+        }
+
+        #[derive(Serialize)]
+        struct Length {
+            contig: String,
+            length: u64,
+        }
+
+        let tempdir = TempDir::new().unwrap();
+        let input = tempdir.path().join("intervals.tsv.gz");
+        let output = tempdir.path().join("lengths.tsv.zst");
+        let lines = ["contig\tstart\tend", "chr1\t10\t25", "chr2\t5\t5", "chr3\t0\t100"];
+        Io::default().write_lines(&input, lines).unwrap();
+
+        let written = DelimFile::default()
+            .process(&input, &output, b'\t', |i: Interval| {
+                (i.end > i.start).then(|| Length { contig: i.contig, length: i.end - i.start })
+            })
+            .unwrap();
+        assert_eq!(written, 2);
+        let lines = Io::default().read_lines(&output).unwrap();
+        assert_eq!(lines, ["contig\tlength", "chr1\t15", "chr3\t100"]);
+    }
+
+    #[rstest]
+    #[case("data.csv", Some(TableFormat::Csv))]
+    #[case("data.tsv.gz", Some(TableFormat::Tsv))]
+    #[case("data.PSV.zst", Some(TableFormat::Delimited(b'|')))]
+    #[case("data.gz", None)]
+    #[case("data.bam", None)]
+    fn test_table_format_of_path(#[case] path: &str, #[case] expected: Option<TableFormat>) {
+        assert_eq!(DelimFile::default().table_format(&path), expected);
+    }
+
+    #[test]
+    fn test_converting_csv_to_tsv() {
+        let tempdir = TempDir::new().unwrap();
+        let input = tempdir.path().join("input.csv.gz");
+        let output = tempdir.path().join("output.tsv.zst");
+        let lines = ["name,note", "a,\"x, y\"", "b,\"tab\there\""];
+        Io::default().write_lines(&input, lines).unwrap();
+
+        let df = DelimFile::default();
+        let (from, to) = (df.table_format(&input).unwrap(), df.table_format(&output).unwrap());
+        assert_eq!(df.convert(&input, from, &output, to).unwrap(), 2);
+        let converted = Io::default().read_lines(&output).unwrap();
+        assert_eq!(converted, ["name\tnote", "a\tx, y", "b\t\"tab\there\""]);
+    }
+
+    #[cfg(feature = "json")]
+    #[test]
+    fn test_converting_tsv_to_jsonl_and_back() {
+        let tempdir = TempDir::new().unwrap();
+        let tsv = tempdir.path().join("input.tsv");
+        let jsonl = tempdir.path().join("output.jsonl.gz");
+        let back = tempdir.path().join("back.csv");
+        Io::default().write_lines(&tsv, ["id\tvalue", "1\t", "2\tb"]).unwrap();
+
+        let df = DelimFile::default();
+        assert_eq!(df.convert(&tsv, TableFormat::Tsv, &jsonl, TableFormat::Jsonl).unwrap(), 2);
+        let lines = Io::default().read_lines(&jsonl).unwrap();
+        assert_eq!(lines, ["{\"id\":\"1\",\"value\":null}", "{\"id\":\"2\",\"value\":\"b\"}"]);
+
+        assert_eq!(df.convert(&jsonl, TableFormat::Jsonl, &back, TableFormat::Csv).unwrap(), 2);
+        assert_eq!(Io::default().read_lines(&back).unwrap(), ["id,value", "1,", "2,b"]);
+    }
+
+    #[test]
+    fn test_writing_records_to_multiple_files() {
+        #[derive(Serialize)]
+        struct Rec {
+            name: String,
+            count: u32,
+        }
+
+        let tempdir = TempDir::new().unwrap();
+        let tsv = tempdir.path().join("recs.tsv.gz");
+        let csv = tempdir.path().join("recs.csv");
+        let outputs = [(&tsv, TableFormat::Tsv), (&csv, TableFormat::Csv)];
+        let mut n = 0;
+        let recs = std::iter::from_fn(|| {
+            n += 1;
+            (n <= 2).then(|| Rec { name: format!("r{n}"), count: n * 10 })
+        });
+
+        assert_eq!(DelimFile::default().write_multi(&outputs, recs).unwrap(), 2);
+        let lines = Io::default().read_lines(&tsv).unwrap();
+        assert_eq!(lines, ["name\tcount", "r1\t10", "r2\t20"]);
+        let lines = Io::default().read_lines(&csv).unwrap();
+        assert_eq!(lines, ["name,count", "r1,10", "r2,20"]);
+    }
+
+    #[cfg(feature = "json")]
+    #[test]
+    fn test_writing_records_to_delimited_and_jsonl_files() {
+        #[derive(Serialize)]
+        struct Rec {
+            name: &'static str,
+            count: u32,
+        }
+
+        let tempdir = TempDir::new().unwrap();
+        let tsv = tempdir.path().join("recs.tsv");
+        let jsonl = tempdir.path().join("recs.jsonl.zst");
+        let df = DelimFile::default();
+        let mut writer =
+            df.new_multi_writer(&[(&tsv, TableFormat::Tsv), (&jsonl, TableFormat::Jsonl)]).unwrap();
+        writer.write_record(&Rec { name: "a", count: 1 }).unwrap();
+        writer.close().unwrap();
+
+        assert_eq!(Io::default().read_lines(&tsv).unwrap(), ["name\tcount", "a\t1"]);
+        assert_eq!(Io::default().read_lines(&jsonl).unwrap(), ["{\"name\":\"a\",\"count\":1}"]);
+    }
+
+    #[test]
+    fn test_overriding_io_settings_per_call() {
+        #[derive(Debug, Deserialize, Serialize, PartialEq)]
+        struct Count {
+            name: String,
+            count: u32,
+        }
+
+        let tempdir = TempDir::new().unwrap();
+        let fast = tempdir.path().join("fast.tsv.gz");
+        let small = tempdir.path().join("small.tsv.gz");
+        let recs: Vec<Count> =
+            (0..1000).map(|i| Count { name: format!("rec{}", i % 7), count: i % 13 }).collect();
+
+        let df = DelimFile::builder().io(Io::builder().compression(5).build()).build();
+        df.with_io(|io| io.compression(0)).write_tsv(&fast, &recs).unwrap();
+        df.with_io(|io| io.compression(9)).write_tsv(&small, &recs).unwrap();
+        let size = |p: &std::path::Path| std::fs::metadata(p).unwrap().len();
+        assert!(size(&small) < size(&fast));
+
+        let read: Vec<Count> = df.read_tsv(&fast).unwrap();
+        assert_eq!(read, recs);
+        assert_eq!(df.io.to_builder().build().compression.level(), 5);
+    }
+
+    #[rstest]
+    #[case(QuoteStyle::Necessary, b'"', "s,i\nx y,1\n\"a,b\",2\n")]
+    #[case(QuoteStyle::Always, b'"', "\"s\",\"i\"\n\"x y\",\"1\"\n\"a,b\",\"2\"\n")]
+    #[case(QuoteStyle::NonNumeric, b'"', "\"s\",\"i\"\n\"x y\",1\n\"a,b\",2\n")]
+    #[case(QuoteStyle::Always, b'\'', "'s','i'\n'x y','1'\n'a,b','2'\n")]
+    fn test_writing_delim_file_with_quote_style(
+        #[case] style: QuoteStyle,
+        #[case] quote: u8,
+        #[case] expected: &str,
+    ) {
+        #[derive(Serialize)]
+        struct Row {
+            s: &'static str,
+            i: u32,
+        }
+
+        let tempdir = TempDir::new().unwrap();
+        let path = tempdir.path().join("quoted.csv");
+        let df = DelimFile::builder().quote(quote).quote_style(style).build();
+        df.write(&path, [Row { s: "x y", i: 1 }, Row { s: "a,b", i: 2 }], b',', true).unwrap();
+        assert_eq!(std::fs::read_to_string(&path).unwrap(), expected);
+
+        df.write(&path, [Row { s: "a,b", i: 2 }], b',', false).unwrap();
+        assert_eq!(std::fs::read_to_string(&path).unwrap(), "s,i\na,b,2\n");
+    }
+
+    #[cfg(feature = "indexmap")]
+    #[test]
+    fn test_reading_and_writing_rows() {
+        let tempdir = TempDir::new().unwrap();
+        let input = tempdir.path().join("input.csv.gz");
+        let output = tempdir.path().join("output.tsv");
+        Io::default().write_lines(&input, ["sample,reads", "s1,10", "s2,20"]).unwrap();
+
+        let df = DelimFile::default();
+        let mut rows = df.read_rows(&input, b',', true).unwrap();
+        assert_eq!(rows.len(), 2);
+        assert_eq!(rows[1]["reads"], "20");
+        for row in &mut rows {
+            let reads: u32 = row["reads"].parse().unwrap();
+            row.insert("millions".to_string(), (reads as f64 / 1e6).to_string());
+            row.shift_remove("reads");
+        }
+        assert_eq!(df.write_rows(&output, &rows, b'\t', true).unwrap(), 2);
+        let lines = Io::default().read_lines(&output).unwrap();
+        assert_eq!(lines, ["sample\tmillions", "s1\t0.00001", "s2\t0.00002"]);
+
+        let df = DelimFile::builder().column_order(["millions", "lane"]).build();
+        df.write_rows(&output, rows, b'\t', true).unwrap();
+        let lines = Io::default().read_lines(&output).unwrap();
+        assert_eq!(lines, ["millions\tlane", "0.00001\t", "0.00002\t"]);
+    }
+
+    #[test]
+    fn test_reading_delim_files_into_reused_storage() {
+        let tempdir = TempDir::new().unwrap();
+        let first = tempdir.path().join("first.tsv");
+        let second = tempdir.path().join("second.tsv.gz");
+        Io::default()
+            .write_lines(&first, ["s\ti\tb\to", "a\t1\ttrue\t", "b\t2\tfalse\t3.5"])
+            .unwrap();
+        Io::default().write_lines(&second, ["o\tb\ti\ts\textra", "\ttrue\t3\tc\tx"]).unwrap();
+
+        let df = DelimFile::default();
+        let mut recs: Vec<Rec> = Vec::new();
+        assert_eq!(df.read_into(&first, b'\t', true, &mut recs).unwrap(), 2);
+        assert_eq!(df.read_into(&second, b'\t', true, &mut recs).unwrap(), 1);
+        assert_eq!(recs.iter().map(|r| r.i).collect::<Vec<_>>(), [1, 2, 3]);
+
+        let mut buffers = RecordBuffers::default();
+        for (path, expected) in [(&first, 2), (&second, 1), (&first, 2)] {
+            recs.clear();
+            let mut reader = df.read_iter_with_buffers(path, b'\t', true, buffers).unwrap();
+            assert_eq!(reader.read_into(&mut recs).unwrap(), expected);
+            buffers = reader.into_buffers();
+        }
+        assert_eq!(recs[1], Rec { s: "b".to_string(), i: 2, b: false, o: Some(3.5) });
+    }
+
+    #[test]
+    fn test_counting_delim_file_records() {
+        let tempdir = TempDir::new().unwrap();
+        let df = DelimFile::builder().comment(Some(b'#')).build();
+
+        let path = tempdir.path().join("recs.csv.gz");
+        let text = "#comment\ns,i,b,o\n\"multi\nline\",1,true,\nx,2,false,\n#comment\ny,3,true,1\n";
+        Io::default().write_lines(&path, [text.trim_end()]).unwrap();
+        assert_eq!(df.count(&path, b',').unwrap(), 3);
+        assert_eq!(df.read_csv::<Rec, _>(&path).unwrap().len(), 3);
+
+        let header_only = tempdir.path().join("header.csv");
+        std::fs::write(&header_only, "s,i,b,o\n").unwrap();
+        assert_eq!(df.count(&header_only, b',').unwrap(), 0);
+
+        let empty = tempdir.path().join("empty.csv");
+        std::fs::write(&empty, "").unwrap();
+        assert_eq!(df.count(&empty, b',').unwrap(), 0);
+    }
+
+    #[test]
+    fn test_reading_raw_delim_file_records() {
+        let tempdir = TempDir::new().unwrap();
+        let path = tempdir.path().join("recs.tsv.gz");
+        Io::default().write_lines(&path, ["name\tcount\tnote", "x\t1\t", "y\t2\tz"]).unwrap();
+
+        let reader = DelimFile::default().read_raw(&path, b'\t', false).unwrap();
+        assert_eq!(reader.headers(), vec!["name", "count", "note"]);
+        let recs: Vec<Vec<String>> =
+            reader.map(|r| r.unwrap().iter().map(String::from).collect()).collect();
+        assert_eq!(recs, vec![vec!["x", "1", ""], vec!["y", "2", "z"]]);
+    }
+
+    #[test]
+    fn test_reading_delim_file_into_map() {
+        let tempdir = TempDir::new().unwrap();
+        let path = tempdir.path().join("samples.tsv");
+        let lines = ["sample_name\ts\ti\tb", "s1\tx\t1\ttrue", "s2\ty\t2\tfalse"];
+        Io::default().write_lines(&path, lines).unwrap();
+
+        let df = DelimFile::default();
+        let map: HashMap<String, Rec> =
+            df.read_map(&path, b'\t', false, "sample_name", DuplicateKeys::Reject).unwrap();
+        assert_eq!(map.len(), 2);
+        assert_eq!(map["s2"], Rec { s: "y".to_string(), i: 2, b: false, o: None });
+    }
+
+    #[test]
+    fn test_reading_delim_file_in_chunks() {
+        let tempdir = TempDir::new().unwrap();
+        let path = tempdir.path().join("recs.tsv");
+        let recs: Vec<Rec> =
+            (0..10).map(|i| Rec { s: i.to_string(), i, b: i % 2 == 0, o: None }).collect();
+        let df = DelimFile::default();
+        df.write_tsv(&path, &recs).unwrap();
+
+        let chunks: Vec<Vec<Rec>> =
+            df.read_chunks(&path, b'\t', false, 4).unwrap().map(|c| c.unwrap()).collect();
+        assert_eq!(chunks.iter().map(Vec::len).collect::<Vec<_>>(), vec![4, 4, 2]);
+        assert_eq!(chunks.into_iter().flatten().collect::<Vec<_>>(), recs);
+    }
+
+    #[cfg(feature = "rayon")]
+    #[test]
+    fn test_reading_delim_file_in_parallel() {
+        let tempdir = TempDir::new().unwrap();
+        let path = tempdir.path().join("recs.tsv.gz");
+        let recs: Vec<Rec> = (0..3000)
+            .map(|i| Rec { s: format!("r{i}"), i, b: i % 3 == 0, o: Some(i as f64 / 2.0) })
+            .collect();
+        let df = DelimFile::builder().null_tokens(["NA"]).build();
+        df.write_tsv(&path, &recs).unwrap();
+        assert_eq!(df.read_parallel::<Rec, _>(&path, b'\t', false).unwrap(), recs);
+    }
 
-        let io = Io::default();
-        io.write_lines(&f1, &lines).unwrap();
-        let strings: Vec<String> = lines.iter().map(|l| l.to_string()).collect();
-        io.write_lines(&f2, &strings).unwrap();
+    #[rstest]
+    #[case("recs.tsv")]
+    #[case("recs.tsv.bgz")]
+    fn test_reading_delim_file_split_into_ranges(#[case] name: &str) {
+        let tempdir = TempDir::new().unwrap();
+        let path = tempdir.path().join(name);
+        let mut recs: Vec<Rec> = (0..40_000)
+            .map(|i| Rec { s: "x".repeat(i % 50), i, b: i % 3 == 0, o: Some(i as f64 / 4.0) })
+            .collect();
+        let df = DelimFile::builder().preamble(["# preamble"]).comment(Some(b'#')).build();
+        df.write(&path, &recs, b'\t', false).unwrap();
+        assert!(std::fs::metadata(&path).unwrap().len() > 1 << 17);
+        assert_eq!(df.read_split::<Rec, _>(&path, b'\t', false, 4).unwrap(), recs);
 
-        let r1 = io.read_lines(&f1).unwrap();
-        let r2 = io.read_lines(&f2).unwrap();
+        // Errors are reported with the line number in the file
+        recs[30_000].s = "bad\tline".to_string();
+        df.write(&path, &recs, b'\t', false).unwrap();
+        let df = DelimFile::builder().comment(Some(b'#')).strict(true).build();
+        match df.read_split::<Rec, _>(&path, b'\t', false, 4).unwrap_err() {
+            FgError::FieldCountMismatch { line, expected, found } => {
+                assert_eq!((line, expected, found), (30_003, 4, 5));
+            }
+            e => panic!("unexpected error: {e}"),
+        }
+    }
 
-        assert_eq!(r1, lines);
-        assert_eq!(r2, lines);
+    #[test]
+    fn test_reading_delim_file_split_reports_bad_values() {
+        let tempdir = TempDir::new().unwrap();
+        let path = tempdir.path().join("recs.tsv");
+        let mut text = "s\ti\tb\to\n".to_string();
+        for i in 0..20_000 {
+            let i = if i == 15_000 { "x".to_string() } else { i.to_string() };
+            text.push_str(&format!("name\t{i}\ttrue\t\n"));
+        }
+        std::fs::write(&path, text).unwrap();
+
+        match DelimFile::default().read_split::<Rec, _>(&path, b'\t', false, 8).unwrap_err() {
+            FgError::DelimError(e) => {
+                assert_eq!((e.line, e.column.as_deref()), (15_002, Some("i")));
+                assert_eq!(e.path.as_deref(), Some(path.as_path()));
+            }
+            e => panic!("unexpected error: {e}"),
+        }
     }
 
     #[test]
-    fn test_reading_and_writing_gzip_files() {
-        let lines = vec!["foo", "bar", "baz"];
+    fn test_reading_delim_file_split_rejects_gzip() {
         let tempdir = TempDir::new().unwrap();
-        let text = tempdir.path().join("text.txt");
-        let gzipped = tempdir.path().join("gzipped.txt.gz");
+        let path = tempdir.path().join("recs.tsv.gz");
+        Io::default().write_lines(&path, ["s\ti\tb\to"]).unwrap();
+        match DelimFile::default().read_split::<Rec, _>(&path, b'\t', false, 2) {
+            Err(FgError::IoError(e)) => assert_eq!(e.kind(), std::io::ErrorKind::Unsupported),
+            _ => panic!("expected an error"),
+        }
+    }
 
-        let io = Io::default();
-        io.write_lines(&text, &mut lines.iter()).unwrap();
-        io.write_lines(&gzipped, &mut lines.iter()).unwrap();
+    #[test]
+    fn test_reading_delim_file_split_with_text_handling() {
+        let tempdir = TempDir::new().unwrap();
+        let path = tempdir.path().join("recs.tsv");
+        std::fs::write(&path, "\u{feff}s\ti\tb\to\nx\t1\ttrue\t\n").unwrap();
+        let df = DelimFile::default();
+        let expected = vec![Rec { s: "x".to_string(), i: 1, b: true, o: None }];
+        assert_eq!(df.read_split::<Rec, _>(&path, b'\t', false, 2).unwrap(), expected);
 
-        let r1 = io.read_lines(&text).unwrap();
-        let r2 = io.read_lines(&gzipped).unwrap();
+        let limited = df.with_io(|io| io.max_decompressed_bytes(16));
+        match limited.read_split::<Rec, _>(&path, b'\t', false, 2) {
+            Err(FgError::IoError(e)) => assert_eq!(e.kind(), std::io::ErrorKind::InvalidData),
+            _ => panic!("expected an error"),
+        }
 
-        assert_eq!(r1, lines);
-        assert_eq!(r2, lines);
+        let mut utf16 = vec![0xff, 0xfe];
+        utf16.extend("s\ti\tb\to\nx\t1\ttrue\t\n".encode_utf16().flat_map(u16::to_le_bytes));
+        std::fs::write(&path, utf16).unwrap();
+        match df.read_split::<Rec, _>(&path, b'\t', false, 2) {
+            Err(FgError::IoError(e)) => assert_eq!(e.kind(), std::io::ErrorKind::Unsupported),
+            _ => panic!("expected an error"),
+        }
+    }
 
-        // Also check that we actually wrote gzipped data to the gzip file!
-        assert_ne!(text.metadata().unwrap().len(), gzipped.metadata().unwrap().len());
+    #[test]
+    fn test_reading_delim_file_split_with_quoted_fields() {
+        let tempdir = TempDir::new().unwrap();
+        let path = tempdir.path().join("recs.csv");
+        std::fs::write(&path, "s,i,b,o\n\"a, \"\"quoted\"\" name\",1,true,\n").unwrap();
+        let df = DelimFile::default();
+        let expected = vec![Rec { s: "a, \"quoted\" name".to_string(), i: 1, b: true, o: None }];
+        assert_eq!(df.read_split::<Rec, _>(&path, b',', true, 2).unwrap(), expected);
+
+        std::fs::write(&path, "s,i,b,o\n\"two\nlines\",1,true,\n").unwrap();
+        match df.read_split::<Rec, _>(&path, b',', true, 2) {
+            Err(FgError::IoError(e)) => {
+                assert_eq!(e.kind(), std::io::ErrorKind::InvalidData);
+                assert!(e.to_string().contains("quoted field contains a line terminator"));
+            }
+            _ => panic!("expected an error"),
+        }
     }
 
     #[test]
-    fn test_reading_and_writing_zstd_files() {
-        let lines = vec!["foo", "bar", "baz"];
+    fn test_inferring_delim_file_schema() {
         let tempdir = TempDir::new().unwrap();
-        let text = tempdir.path().join("text.txt");
-        let zstd_compressed = tempdir.path().join("zstd_compressed.txt.zst");
+        let path = tempdir.path().join("data.csv.gz");
+        let lines = [
+            "sample,count,fraction,passed,date,note",
+            "s1,10,0.5,true,2024-01-31,",
+            "s2,20,1,false,2024-02-01,NA",
+            "s3,30,NA,true,2024-02-02,\"a, b\"",
+            "s4,x,0.25,true,2024-02-03,c",
+        ];
+        Io::default().write_lines(&path, lines).unwrap();
 
-        assert_eq!(Io::is_zstd_path(&text), false);
-        assert_eq!(Io::is_zstd_path(&zstd_compressed), true);
+        let df = DelimFile::builder().null_tokens(["NA"]).build();
+        let columns = df.infer_schema(&path, b',', 3).unwrap();
+        let summary: Vec<(&str, ColumnType, bool)> =
+            columns.iter().map(|c| (c.name.as_str(), c.column_type, c.nullable)).collect();
+        assert_eq!(
+            summary,
+            vec![
+                ("sample", ColumnType::String, false),
+                ("count", ColumnType::Integer, false),
+                ("fraction", ColumnType::Float, true),
+                ("passed", ColumnType::Boolean, false),
+                ("date", ColumnType::Date, false),
+                ("note", ColumnType::String, true),
+            ]
+        );
+        assert_eq!(columns[0].examples, vec!["s1", "s2", "s3"]);
+        assert_eq!(columns[5].examples, vec!["a, b"]);
+        assert_eq!(columns[2].rust_type(), "Option<f64>");
+
+        // The whole file is sampled if it is smaller than the sample
+        let columns = df.infer_schema(&path, b',', 100).unwrap();
+        assert_eq!(columns[1].column_type, ColumnType::String);
+    }
+
+    #[test]
+    fn test_validating_delim_file_against_schema() {
+        let tempdir = TempDir::new().unwrap();
+        let path = tempdir.path().join("data.tsv");
+        let lines =
+            ["sample\tcount\tfraction\tstrand", "s1\t10\t0.5\t+", "s2\tx\t1.5\t.", "\t5\tNA\t-"];
+        Io::default().write_lines(&path, lines).unwrap();
+
+        let schema = Schema::new([
+            ColumnSpec::new("sample", ColumnType::String).required(true),
+            ColumnSpec::new("count", ColumnType::Integer),
+            ColumnSpec::new("fraction", ColumnType::Float).range(0.0, 1.0),
+            ColumnSpec::new("strand", ColumnType::String).allowed_values(["+", "-"]),
+            ColumnSpec::new("gene", ColumnType::String).required(true),
+            ColumnSpec::new("depth", ColumnType::Integer),
+        ]);
+        let df = DelimFile::builder().null_tokens(["NA"]).build();
+        let report = df.validate(&path, b'\t', &schema).unwrap();
+        assert!(!report.is_valid());
+        assert_eq!(report.records, 3);
+        let violations: Vec<(u64, &str, Option<&str>, ViolationKind)> = report
+            .violations
+            .iter()
+            .map(|v| (v.line, v.column.as_str(), v.value.as_deref(), v.kind))
+            .collect();
+        assert_eq!(
+            violations,
+            vec![
+                (1, "gene", None, ViolationKind::MissingColumn),
+                (3, "count", Some("x"), ViolationKind::InvalidType(ColumnType::Integer)),
+                (3, "fraction", Some("1.5"), ViolationKind::OutOfRange),
+                (3, "strand", Some("."), ViolationKind::NotAllowed),
+                (4, "sample", None, ViolationKind::MissingValue),
+            ]
+        );
+        assert_eq!(
+            report.violations[1].to_string(),
+            "Line 3, column 'count': \"x\" is not a valid integer"
+        );
 
+        let schema = Schema::new(schema.columns.into_iter().filter(|c| c.name == "count"));
+        let path = tempdir.path().join("valid.tsv");
+        Io::default().write_lines(&path, ["count", "1", "NA", "3"]).unwrap();
+        assert!(df.validate(&path, b'\t', &schema).unwrap().is_valid());
+    }
+
+    #[test]
+    fn test_delim_file_error_context() {
+        let tempdir = TempDir::new().unwrap();
+        let path = tempdir.path().join("recs.tsv");
+        std::fs::write(&path, "s\ti\tb\to\nx\t1\ttrue\t\ny\ttwo\tfalse\t\n").unwrap();
+
+        let err = DelimFile::default().read::<Rec, _>(&path, b'\t', false).unwrap_err();
+        let message = err.to_string();
+        match err {
+            FgError::DelimError(e) => {
+                assert_eq!(e.path.as_deref(), Some(path.as_path()));
+                assert_eq!(e.line, 3);
+                assert_eq!(e.column.as_deref(), Some("i"));
+                assert_eq!(e.field, Some(1));
+                assert_eq!(e.value.as_deref(), Some("two"));
+                assert_eq!(e.expected, Some("integer"));
+            }
+            e => panic!("unexpected error: {e}"),
+        }
+        let expected = format!(
+            "Failed to deserialize record on line 3 of {} in column 'i'; value \"two\" is not a \
+             valid integer: invalid digit found in string",
+            path.display()
+        );
+        assert_eq!(message, expected);
+
+        // Records read from memory have no path, and errors from missing fields have no value
+        let err = DelimFile::default().read_from_str::<Rec>("s,b\nx,true\n", b',', true);
+        assert_eq!(
+            err.unwrap_err().to_string(),
+            "Failed to deserialize record on line 2: missing field `i`"
+        );
+    }
+
+    #[test]
+    fn test_delim_file_strict_schema() {
+        let df = DelimFile::builder().flexible(true).strict(true).build();
+        let text = "s,i,b,o\nx,1,true,\ny,2,false\n";
+        match df.read_from_str::<Rec>(text, b',', true).unwrap_err() {
+            FgError::FieldCountMismatch { line, expected, found } => {
+                assert_eq!((line, expected, found), (3, 4, 3));
+            }
+            e => panic!("unexpected error: {e}"),
+        }
+
+        // Missing columns for optional fields and extra columns are rejected up front
+        for text in ["s,i,b\nx,1,true\n", "s,i,b,o,extra\nx,1,true,,e\n"] {
+            let result = df.read_from_str::<Rec>(text, b',', true);
+            assert!(matches!(result, Err(FgError::HeaderMismatch { .. })));
+            assert!(DelimFile::default().read_from_str::<Rec>(text, b',', true).is_ok());
+        }
+
+        let recs: Vec<Rec> = df.read_from_str("s,i,b,o\nx,1,true,\n", b',', true).unwrap();
+        assert_eq!(recs, vec![Rec { s: "x".to_string(), i: 1, b: true, o: None }]);
+    }
+
+    #[test]
+    fn test_reading_and_writing_delim_data_in_memory() {
+        let recs = vec![
+            Rec { s: "a b".to_string(), i: 1, b: true, o: None },
+            Rec { s: "c\td".to_string(), i: 2, b: false, o: Some(0.5) },
+        ];
+
+        let df = DelimFile::default();
+        let mut buf = vec![];
+        df.write_to(&mut buf, &recs, b'\t', true).unwrap();
+        assert_eq!(
+            String::from_utf8(buf.clone()).unwrap(),
+            "s\ti\tb\to\na b\t1\ttrue\t\n\"c\td\"\t2\tfalse\t0.5\n"
+        );
+        assert_eq!(df.read_from::<Rec, _>(buf.as_slice(), b'\t', true).unwrap(), recs);
+    }
+
+    #[test]
+    fn test_reading_and_writing_delim_strings() {
+        let recs = vec![
+            Rec { s: "x".to_string(), i: 1, b: true, o: Some(2.5) },
+            Rec { s: "y,z".to_string(), i: 2, b: false, o: None },
+        ];
+
+        let df = DelimFile::default();
+        let text = df.write_to_string(&recs, b',', true).unwrap();
+        assert_eq!(text, "s,i,b,o\nx,1,true,2.5\n\"y,z\",2,false,\n");
+        assert_eq!(df.read_from_str::<Rec>(&text, b',', true).unwrap(), recs);
+    }
+
+    #[test]
+    fn test_reading_and_writing_headerless_delim_files() {
+        let tempdir = TempDir::new().unwrap();
+        let path = tempdir.path().join("regions.bed.gz");
+        let regions = vec![("chr1".to_string(), 100u64, 200u64), ("chr2".to_string(), 5, 10)];
+
+        let df = DelimFile::default();
+        df.write_headerless(&path, &regions, b'\t', false).unwrap();
         let io = Io::default();
-        io.write_lines(&text, &mut lines.iter()).unwrap();
-        io.write_lines(&zstd_compressed, &mut lines.iter()).unwrap();
+        assert_eq!(io.read_lines(&path).unwrap(), vec!["chr1\t100\t200", "chr2\t5\t10"]);
+        assert_eq!(
+            df.read_headerless::<(String, u64, u64), _>(&path, b'\t', false).unwrap(),
+            regions
+        );
 
-        let r1 = io.read_lines(&text).unwrap();
-        let r2 = io.read_lines(&zstd_compressed).unwrap();
+        // Structs are deserialized by position
+        let path = tempdir.path().join("recs.tsv");
+        io.write_lines(&path, ["x\t1\ttrue\t", "y\t2\tfalse\t0.5"]).unwrap();
+        let recs: Vec<Rec> = df.read_headerless(&path, b'\t', true).unwrap();
+        assert_eq!(recs[0], Rec { s: "x".to_string(), i: 1, b: true, o: None });
+        assert_eq!(recs[1], Rec { s: "y".to_string(), i: 2, b: false, o: Some(0.5) });
+    }
 
-        assert_eq!(r1, lines);
-        assert_eq!(r2, lines);
+    #[test]
+    fn test_writing_delim_files_without_header() {
+        let tempdir = TempDir::new().unwrap();
+        let path = tempdir.path().join("recs.tsv");
+        let recs = vec![Rec { s: "x".to_string(), i: 1, b: true, o: None }];
 
-        // Check whether the two files are different
-        assert_ne!(text.metadata().unwrap().len(), zstd_compressed.metadata().unwrap().len());
+        let df = DelimFile::builder().write_header(false).build();
+        df.write_tsv(&path, &recs).unwrap();
+        df.append(&path, &recs, b'\t', true).unwrap();
+        assert_eq!(std::fs::read_to_string(&path).unwrap(), "x\t1\ttrue\t\nx\t1\ttrue\t\n");
+        assert_eq!(df.write_to_string(&recs, b',', true).unwrap(), "x,1,true,\n");
     }
 
     #[test]
-    fn test_reading_and_writing_empty_delim_file() {
-        let recs: Vec<Rec> = vec![];
-        let tmp = TempDir::new().unwrap();
-        let csv = tmp.path().join("recs.csv");
-        let tsv = tmp.path().join("recs.tsv.gz");
+    fn test_reading_delim_file_header() {
+        let tempdir = TempDir::new().unwrap();
+        let path = tempdir.path().join("recs.csv.gz");
+        let recs = vec![Rec { s: "x".to_string(), i: 1, b: true, o: None }];
 
         let df = DelimFile::default();
-        df.write_csv(&csv, &recs).unwrap();
-        df.write_tsv(&tsv, &recs).unwrap();
-        let from_csv: Vec<Rec> = df.read_csv(&csv).unwrap();
-        let from_tsv: Vec<Rec> = df.read_tsv(&tsv).unwrap();
+        df.write_csv(&path, &recs).unwrap();
+        assert_eq!(df.read_header(&path, b',').unwrap(), vec!["s", "i", "b", "o"]);
 
-        assert_eq!(from_csv, recs);
-        assert_eq!(from_tsv, recs);
+        let path = tempdir.path().join("quoted.tsv");
+        std::fs::write(&path, "\"first name\"\tage\nbob\t3\n").unwrap();
+        assert_eq!(df.read_header(&path, b'\t').unwrap(), vec!["first name", "age"]);
+
+        let path = tempdir.path().join("empty.tsv");
+        std::fs::write(&path, "").unwrap();
+        assert!(df.read_header(&path, b'\t').unwrap().is_empty());
     }
 
     #[test]
-    fn test_reading_and_writing_delim_file() {
-        let recs: Vec<Rec> = vec![
-            Rec { s: "Hello".to_string(), i: 123, b: true, o: None },
-            Rec { s: "A,B,C".to_string(), i: 456, b: false, o: Some(123.45) },
+    fn test_strict_header_validation() {
+        let tempdir = TempDir::new().unwrap();
+        let path = tempdir.path().join("recs.tsv");
+        std::fs::write(&path, "s\ti\tb\tother\nx\t1\ttrue\ty\n").unwrap();
+
+        let lax = DelimFile::default();
+        assert_eq!(lax.read_tsv::<Rec, _>(&path).unwrap().len(), 1);
+
+        let strict = DelimFile::builder().strict_headers(true).build();
+        let err = strict.read_tsv::<Rec, _>(&path).unwrap_err();
+        assert!(matches!(err, FgError::HeaderMismatch { .. }));
+        assert_eq!(
+            err.to_string(),
+            "Header does not match the expected columns; missing: [o], unexpected: [other]"
+        );
+    }
+
+    #[test]
+    fn test_reading_delim_file_with_renamed_headers() {
+        let tempdir = TempDir::new().unwrap();
+        let path = tempdir.path().join("recs.csv");
+        std::fs::write(&path, "Name,Index,b,o\nx,1,true,\n").unwrap();
+
+        let renames = HashMap::from([("Name", "s"), ("Index", "i")]);
+        let df = DelimFile::builder().strict_headers(true).build();
+        let recs: Vec<Rec> = df.read_with_renames(&path, b',', true, &renames).unwrap();
+        assert_eq!(recs, vec![Rec { s: "x".to_string(), i: 1, b: true, o: None }]);
+    }
+
+    #[test]
+    fn test_reading_subset_of_delim_file_columns() {
+        #[derive(Debug, Deserialize, PartialEq)]
+        struct Small {
+            i: usize,
+            s: String,
+        }
+
+        let tempdir = TempDir::new().unwrap();
+        let path = tempdir.path().join("recs.tsv");
+        let recs = vec![Rec { s: "x".to_string(), i: 1, b: true, o: Some(2.0) }];
+        let df = DelimFile::default();
+        df.write_tsv(&path, &recs).unwrap();
+
+        let small: Vec<Small> = df.read_tsv(&path).unwrap();
+        assert_eq!(small, vec![Small { i: 1, s: "x".to_string() }]);
+
+        let df = DelimFile::builder().extra_columns(ExtraColumns::Reject).build();
+        let err = df.read_tsv::<Small, _>(&path).unwrap_err();
+        assert!(
+            matches!(err, FgError::HeaderMismatch { unexpected, .. } if unexpected == ["b", "o"])
+        );
+        assert_eq!(df.read_tsv::<Rec, _>(&path).unwrap(), recs);
+    }
+
+    #[test]
+    fn test_reading_delim_file_with_duplicate_columns() {
+        let text = "s,i,b,o,s\nx,1,true,,y\n";
+        let err = DelimFile::default().read_from_str::<Rec>(text, b',', true).unwrap_err();
+        assert_eq!(err.to_string(), "Header contains more than one column named 's'");
+
+        let df = DelimFile::builder().duplicate_columns(DuplicateColumns::Rename).build();
+        let recs: Vec<Rec> = df.read_from_str(text, b',', true).unwrap();
+        assert_eq!(recs, vec![Rec { s: "x".to_string(), i: 1, b: true, o: None }]);
+    }
+
+    #[test]
+    fn test_writing_delim_file_columns_in_order() {
+        let recs = vec![
+            Rec { s: "x".to_string(), i: 1, b: true, o: None },
+            Rec { s: "y".to_string(), i: 2, b: false, o: Some(0.5) },
         ];
-        let tmp = TempDir::new().unwrap();
-        let csv = tmp.path().join("recs.csv");
-        let tsv = tmp.path().join("recs.tsv.gz");
+
+        let df = DelimFile::builder().column_order(["o", "s", "extra", "i"]).build();
+        let text = df.write_to_string(&recs, b'\t', true).unwrap();
+        assert_eq!(text, "o\ts\textra\ti\n\tx\t\t1\n0.5\ty\t\t2\n");
+    }
+
+    #[test]
+    fn test_reading_delim_file_with_comments() {
+        let tempdir = TempDir::new().unwrap();
+        let path = tempdir.path().join("recs.tsv.gz");
+        let io = Io::default();
+        io.write_lines(
+            &path,
+            ["## tool=x", "# date=today", "s\ti\tb\to", "# skipped", "x\t1\ttrue\t"],
+        )
+        .unwrap();
+
+        let df = DelimFile::builder().comment(Some(b'#')).build();
+        let (comments, recs) = df.read_with_comments::<Rec, _>(&path, b'\t', true).unwrap();
+        assert_eq!(comments, vec!["## tool=x", "# date=today"]);
+        assert_eq!(recs, vec![Rec { s: "x".to_string(), i: 1, b: true, o: None }]);
+        assert_eq!(df.read_tsv::<Rec, _>(&path).unwrap(), recs);
+
+        // Without a comment character nothing is captured
+        let path = tempdir.path().join("plain.tsv");
+        io.write_lines(&path, ["s\ti\tb\to", "x\t1\ttrue\t"]).unwrap();
+        let (comments, recs) =
+            DelimFile::default().read_with_comments::<Rec, _>(&path, b'\t', true).unwrap();
+        assert!(comments.is_empty());
+        assert_eq!(recs.len(), 1);
+    }
+
+    #[test]
+    fn test_writing_delim_file_with_preamble() {
+        let tempdir = TempDir::new().unwrap();
+        let path = tempdir.path().join("metrics.txt");
+        let recs = vec![Rec { s: "x".to_string(), i: 1, b: true, o: None }];
+
+        let df = DelimFile::builder()
+            .comment(Some(b'#'))
+            .preamble(["## generated-by=tool v1.2", "## date=2024-01-01"])
+            .build();
+        df.write_tsv(&path, &recs).unwrap();
+        df.append(&path, &recs, b'\t', true).unwrap();
+        assert_eq!(
+            std::fs::read_to_string(&path).unwrap(),
+            "## generated-by=tool v1.2\n## date=2024-01-01\ns\ti\tb\to\nx\t1\ttrue\t\nx\t1\ttrue\t\n"
+        );
+
+        let (comments, read) = df.read_with_comments::<Rec, _>(&path, b'\t', true).unwrap();
+        assert_eq!(comments, vec!["## generated-by=tool v1.2", "## date=2024-01-01"]);
+        assert_eq!(read.len(), 2);
+    }
+
+    #[test]
+    fn test_reading_delim_file_with_null_tokens() {
+        let text = "s\ti\tb\to\nNA\t1\ttrue\tNA\ny\t2\tfalse\t.\nz\t3\ttrue\tNaN\n";
+
+        assert!(DelimFile::default().read_from_str::<Rec>(text, b'\t', true).is_err());
+        let df = DelimFile::builder().null_tokens(["NA", "."]).build();
+        let recs: Vec<Rec> = df.read_from_str(text, b'\t', true).unwrap();
+        assert_eq!(recs[0], Rec { s: String::new(), i: 1, b: true, o: None });
+        assert_eq!(recs[1], Rec { s: "y".to_string(), i: 2, b: false, o: None });
+        // NaN is not a null token here, so is parsed as a float
+        assert!(recs[2].o.unwrap().is_nan());
+    }
+
+    #[test]
+    fn test_sniffing_delim_file_format() {
+        let tempdir = TempDir::new().unwrap();
+        let path = tempdir.path().join("data.txt.gz");
+        let lines = ["# exported 2024-01-31", "sample;count;fraction", "", "s1;10;0.5", "s2;20;1"];
+        Io::default().write_lines(&path, lines).unwrap();
+
+        let df = DelimFile::builder().comment(Some(b'#')).build();
+        let format = df.sniff(&path).unwrap();
+        assert_eq!(format, SniffedFormat { delimiter: b';', has_header: true, columns: 3 });
+        let header = df.read_header(&path, format.delimiter).unwrap();
+        assert_eq!(header, vec!["sample", "count", "fraction"]);
+    }
+
+    #[test]
+    fn test_reading_whitespace_delimited_file() {
+        let tempdir = TempDir::new().unwrap();
+        let path = tempdir.path().join("recs.txt.gz");
+        let lines = [
+            "# generated by an old tool",
+            "   s     i  b      o",
+            "   x     1  true   0.5  ",
+            "",
+            "   y    20  false\t\t2.25",
+        ];
+        Io::default().write_lines(&path, lines).unwrap();
+
+        let df = DelimFile::builder().comment(Some(b'#')).build();
+        let recs: Vec<Rec> = df.read_whitespace(&path).unwrap();
+        assert_eq!(
+            recs,
+            vec![
+                Rec { s: "x".to_string(), i: 1, b: true, o: Some(0.5) },
+                Rec { s: "y".to_string(), i: 20, b: false, o: Some(2.25) },
+            ]
+        );
+    }
+
+    #[test]
+    fn test_reading_delim_file_with_column_parsers() {
+        let text = "s\ti\tb\to\nx\t1,234\tyes\tNA\ny\t5\tno\t0.5\n";
+        let yes_no = |v: &str| if v == "yes" { "true".to_string() } else { "false".to_string() };
+        let df = DelimFile::builder()
+            .null_tokens(["NA"])
+            .column_parser("i", |v| v.replace(',', ""))
+            .column_parser("b", yes_no)
+            .column_parser("o", |v| panic!("parser called for null value {v}"))
+            .column_parser("o", str::to_string)
+            .column_parser("missing", |_| panic!("parser called for missing column"))
+            .build();
+        let recs: Vec<Rec> = df.read_from_str(text, b'\t', true).unwrap();
+        assert_eq!(recs[0], Rec { s: "x".to_string(), i: 1234, b: true, o: None });
+        assert_eq!(recs[1], Rec { s: "y".to_string(), i: 5, b: false, o: Some(0.5) });
+
+        // Parsers apply to fields by position in headerless files
+        let tempdir = TempDir::new().unwrap();
+        let path = tempdir.path().join("recs.tsv");
+        Io::default().write_lines(&path, ["z\t7,000\tno\t"]).unwrap();
+        let recs: Vec<Rec> = df.read_headerless(&path, b'\t', true).unwrap();
+        assert_eq!(recs, vec![Rec { s: "z".to_string(), i: 7000, b: false, o: None }]);
+    }
+
+    #[cfg(feature = "chrono")]
+    #[test]
+    fn test_reading_delim_file_with_date_formats() {
+        use crate::io::{dates, DateFormat};
+        use chrono::{NaiveDate, NaiveDateTime};
+
+        #[derive(Debug, Serialize, Deserialize, PartialEq)]
+        struct Run {
+            id: String,
+            started: NaiveDate,
+            finished: Option<NaiveDateTime>,
+            #[serde(with = "dates::excel_date")]
+            shipped: NaiveDate,
+        }
+
+        let text = "id,started,finished,shipped\nr1,1/31/2024,45322.75,45323\nr2,2/1/2024,,45324\n";
+        let df = DelimFile::builder()
+            .date_format("started", DateFormat::MonthDayYear)
+            .date_format("finished", DateFormat::ExcelSerial)
+            .build();
+        let runs: Vec<Run> = df.read_from_str(text, b',', true).unwrap();
+        let date = |m, d| NaiveDate::from_ymd_opt(2024, m, d).unwrap();
+        assert_eq!(
+            runs,
+            vec![
+                Run {
+                    id: "r1".to_string(),
+                    started: date(1, 31),
+                    finished: date(1, 31).and_hms_opt(18, 0, 0),
+                    shipped: date(2, 1),
+                },
+                Run {
+                    id: "r2".to_string(),
+                    started: date(2, 1),
+                    finished: None,
+                    shipped: date(2, 2)
+                },
+            ]
+        );
+
+        // Dates are written in ISO 8601 unless a serde helper is used
+        let text = df.write_to_string(&runs[1..], b',', true).unwrap();
+        assert_eq!(text, "id,started,finished,shipped\nr2,2024-02-01,,45324\n");
+
+        // Values not in the format are passed on unchanged and fail to deserialize
+        let text = "id,started,finished,shipped\nr1,31.1.2024,,45323\n";
+        let err = df.read_from_str::<Run>(text, b',', true).unwrap_err();
+        assert!(matches!(err, FgError::DelimError(e) if e.line == 2));
+    }
+
+    #[test]
+    fn test_writing_delim_file_with_none_token() {
+        let recs = vec![
+            Rec { s: String::new(), i: 1, b: true, o: None },
+            Rec { s: "x".to_string(), i: 2, b: false, o: Some(0.5) },
+        ];
+        let text = DelimFile::default().write_to_string(&recs, b'\t', true).unwrap();
+        assert_eq!(text, "s\ti\tb\to\n\t1\ttrue\t\nx\t2\tfalse\t0.5\n");
+
+        let df = DelimFile::builder().none_token("NA").build();
+        let text = df.write_to_string(&recs, b'\t', true).unwrap();
+        assert_eq!(text, "s\ti\tb\to\n\t1\ttrue\tNA\nx\t2\tfalse\t0.5\n");
+
+        // The token round trips when also configured as a null token for reading
+        let df = DelimFile::builder().none_token("NA").null_tokens(["NA"]).build();
+        let read: Vec<Rec> = df.read_from_str(&text, b'\t', true).unwrap();
+        assert_eq!(read, recs);
+
+        let df = DelimFile::builder().none_token(".").column_order(["o", "s"]).build();
+        let text = df.write_to_string(&recs, b',', true).unwrap();
+        assert_eq!(text, "o,s\n.,\n0.5,x\n");
+    }
+
+    #[test]
+    fn test_writing_delim_file_with_float_format() {
+        let recs = vec![
+            Rec { s: "x".to_string(), i: 1, b: true, o: Some(1.0 / 3.0) },
+            Rec { s: "y".to_string(), i: 2, b: false, o: Some(12.75) },
+        ];
+        let df = DelimFile::builder().float_format(FloatFormat::Decimals(4)).build();
+        let text = df.write_to_string(&recs, b',', true).unwrap();
+        assert_eq!(text, "s,i,b,o\nx,1,true,0.3333\ny,2,false,12.7500\n");
+
+        let df = DelimFile::builder().float_format(FloatFormat::SignificantDigits(2)).build();
+        let text = df.write_to_string(&recs, b',', true).unwrap();
+        assert_eq!(text, "s,i,b,o\nx,1,true,0.33\ny,2,false,13\n");
+    }
+
+    #[test]
+    #[should_panic(expected = "Number of significant digits cannot be zero!")]
+    fn test_zero_significant_digits_panics() {
+        let _df = DelimFile::builder().float_format(FloatFormat::SignificantDigits(0));
+    }
+
+    #[test]
+    fn test_delim_file_bools_as_ints() {
+        let recs = vec![
+            Rec { s: "x".to_string(), i: 1, b: true, o: None },
+            Rec { s: "y".to_string(), i: 0, b: false, o: Some(1.0) },
+        ];
+        let df = DelimFile::builder().bools_as_ints(true).build();
+        let text = df.write_to_string(&recs, b',', true).unwrap();
+        assert_eq!(text, "s,i,b,o\nx,1,1,\ny,0,0,1.0\n");
+        let read: Vec<Rec> = df.read_from_str(&text, b',', true).unwrap();
+        assert_eq!(read, recs);
+
+        // Either form is read, and only boolean fields are converted
+        let text = "b,s,i\ntrue,1,1\n0,0,0\n";
+        let read: Vec<Rec> = df.read_from_str(text, b',', true).unwrap();
+        assert_eq!(read[0], Rec { s: "1".to_string(), i: 1, b: true, o: None });
+        assert_eq!(read[1], Rec { s: "0".to_string(), i: 0, b: false, o: None });
+        assert!(DelimFile::default().read_from_str::<Rec>(text, b',', true).is_err());
+    }
+
+    #[test]
+    fn test_reading_delim_file_leniently() {
+        let tempdir = TempDir::new().unwrap();
+        let path = tempdir.path().join("recs.tsv.gz");
+        let text = "s\ti\tb\to\nx\t1\ttrue\t\ny\t-2\tfalse\t\nz\t3\tmaybe\t1.5\nw\t4\tfalse\t\n";
+        Io::default().write_lines(&path, text.lines()).unwrap();
 
         let df = DelimFile::default();
-        df.write_csv(&csv, &recs).unwrap();
-        df.write_tsv(&tsv, &recs).unwrap();
-        let from_csv: Vec<Rec> = df.read_csv(&csv).unwrap();
-        let from_tsv: Vec<Rec> = df.read_tsv(&tsv).unwrap();
+        assert!(df.read::<Rec, _>(&path, b'\t', false).is_err());
+        let (recs, bad): (Vec<Rec>, _) = df.read_lenient(&path, b'\t', false).unwrap();
+        assert_eq!(recs.iter().map(|r| r.s.as_str()).collect::<Vec<_>>(), vec!["x", "w"]);
+        assert_eq!(bad.iter().map(|b| b.line).collect::<Vec<_>>(), vec![3, 4]);
+        assert!(bad[1].message.starts_with("Failed to deserialize record on line 4 of "));
+        assert!(bad[1].message.ends_with(
+            "in column 'b'; value \"maybe\" is not a valid boolean: \
+             provided string was not `true` or `false`"
+        ));
+    }
 
-        assert_eq!(from_csv, recs);
-        assert_eq!(from_tsv, recs);
+    #[test]
+    fn test_writing_to_null_outputs() {
+        let io = Io::builder().overwrite(false);
+        #[cfg(feature = "checksum")]
+        let io = io.checksum(ChecksumAlgorithm::Md5);
+        let io = io.build();
+        io.write_lines(&"/dev/null", ["discarded"]).unwrap();
+        io.append_lines(&"/dev/null", ["discarded"]).unwrap();
+        assert!(io.read_lines(&"/dev/null").unwrap().is_empty());
+
+        let mut sink = io.sink();
+        writeln!(sink, "discarded").unwrap();
+        sink.flush().unwrap();
+    }
+
+    #[test]
+    fn test_writing_to_stdout() {
+        let io = Io::default();
+        let mut writer = io.new_writer(&"-").unwrap();
+        writer.flush().unwrap();
+    }
+
+    // ############################################################################################
+    // Tests is_stdin_path() and is_stdout_path()
+    // ############################################################################################
+
+    #[rstest]
+    #[case("-", true, true, false)]
+    #[case("/dev/stdin", true, false, false)]
+    #[case("/dev/stdout", false, true, false)]
+    #[case("/dev/null", false, false, true)]
+    #[case("./-", false, false, false)]
+    #[case("out.txt", false, false, false)]
+    fn test_is_stdio_path(
+        #[case] path: &str,
+        #[case] stdin: bool,
+        #[case] stdout: bool,
+        #[case] null: bool,
+    ) {
+        assert_eq!(Io::is_stdin_path(&path), stdin);
+        assert_eq!(Io::is_stdout_path(&path), stdout);
+        assert_eq!(Io::is_null_path(&path), null);
     }
 
     // ############################################################################################
@@ -416,6 +6334,21 @@ mod tests {
         assert_eq!(result, expected);
     }
 
+    // ############################################################################################
+    // Tests is_bgzf_path()
+    // ############################################################################################
+
+    #[rstest]
+    #[case("test_fastq.fq.gz", false)] // .fq.gz is not bgzf
+    #[case("test_fastq.fq.bgz", true)] // .fq.bgz is bgzf
+    #[case("test_fastq.fq", false)] // .fq is not bgzf
+    fn test_is_bgzf_path(#[case] file_name: &str, #[case] expected: bool) {
+        let dir = TempDir::new().unwrap();
+        let file_path = dir.path().join(file_name);
+        let result = Io::is_bgzf_path(&file_path);
+        assert_eq!(result, expected);
+    }
+
     // ############################################################################################
     // Tests is_zstd_path()
     // ############################################################################################
@@ -433,6 +6366,23 @@ mod tests {
         assert_eq!(result, expected);
     }
 
+    // ############################################################################################
+    // Tests is_bzip2_path()
+    // ############################################################################################
+
+    #[cfg(feature = "bzip2")]
+    #[rstest]
+    #[case("test_fastq.fq", false)] // .fq is invalid bzip2
+    #[case("test_fastq.fq.gz", false)] // .fq.gz is invalid bzip2
+    #[case("test_fastq.fq.zst", false)] // .fq.zst is invalid bzip2
+    #[case("test_fastq.fq.bz2", true)] // .fq.bz2 is valid bzip2
+    fn test_is_bzip2_path(#[case] file_name: &str, #[case] expected: bool) {
+        let dir = TempDir::new().unwrap();
+        let file_path = dir.path().join(file_name);
+        let result = Io::is_bzip2_path(&file_path);
+        assert_eq!(result, expected);
+    }
+
     // ############################################################################################
     // Tests is_fastq_path()
     // ############################################################################################
@@ -0,0 +1,67 @@
+//! A reader that presents several readers as a single continuous stream.
+use std::io::{self, BufRead, Read};
+
+/// Buffered reader that reads each of a sequence of readers to completion in turn, presenting
+/// their contents as one continuous stream.  No separators are inserted between readers.
+pub struct MultiReader<R: BufRead> {
+    readers: Vec<R>,
+    current: usize,
+}
+
+impl<R: BufRead> MultiReader<R> {
+    /// Creates a reader over the concatenation of the given readers.
+    pub fn new(readers: Vec<R>) -> Self {
+        Self { readers, current: 0 }
+    }
+}
+
+impl<R: BufRead> Read for MultiReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let available = self.fill_buf()?;
+        let n = available.len().min(buf.len());
+        buf[..n].copy_from_slice(&available[..n]);
+        self.consume(n);
+        Ok(n)
+    }
+}
+
+impl<R: BufRead> BufRead for MultiReader<R> {
+    fn fill_buf(&mut self) -> io::Result<&[u8]> {
+        // Advance past exhausted readers before borrowing the current reader's buffer
+        while self.current < self.readers.len() && self.readers[self.current].fill_buf()?.is_empty()
+        {
+            self.current += 1;
+        }
+
+        match self.readers.get_mut(self.current) {
+            Some(reader) => reader.fill_buf(),
+            None => Ok(&[]),
+        }
+    }
+
+    fn consume(&mut self, amt: usize) {
+        if let Some(reader) = self.readers.get_mut(self.current) {
+            reader.consume(amt);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_reading_across_readers() {
+        let readers: Vec<&[u8]> = vec![b"a\nb\n", b"", b"c\n", b"d"];
+        let reader = MultiReader::new(readers);
+        let lines: Vec<String> = reader.lines().map(|l| l.unwrap()).collect();
+        assert_eq!(lines, vec!["a", "b", "c", "d"]);
+    }
+
+    #[test]
+    fn test_reading_with_no_readers() {
+        let mut reader: MultiReader<&[u8]> = MultiReader::new(vec![]);
+        let mut buf = String::new();
+        assert_eq!(reader.read_to_string(&mut buf).unwrap(), 0);
+    }
+}
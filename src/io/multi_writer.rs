@@ -0,0 +1,93 @@
+//! A writer that writes each record to several tables, possibly in different formats.
+#[cfg(feature = "json")]
+use std::io::{BufWriter, Write};
+
+use serde::Serialize;
+
+use crate::io::DelimFileWriter;
+use crate::Result;
+#[cfg(feature = "json")]
+use crate::{io::FinishWrite, FgError};
+
+/// A destination of a [`MultiWriter`].
+enum Sink<S> {
+    Delim(Box<DelimFileWriter<S>>),
+    #[cfg(feature = "json")]
+    Jsonl(BufWriter<Box<dyn FinishWrite>>),
+}
+
+/// Writer returned by [`DelimFile::new_multi_writer`](crate::io::DelimFile::new_multi_writer)
+/// that serializes each record written to it once for every output, so that records from an
+/// iterator that can only be consumed once can be written to several files in one pass.
+pub struct MultiWriter<S> {
+    sinks: Vec<Sink<S>>,
+}
+
+impl<S: Serialize> MultiWriter<S> {
+    /// Creates a writer with no outputs.
+    pub(crate) fn new() -> Self {
+        Self { sinks: vec![] }
+    }
+
+    /// Adds a delimited output.
+    pub(crate) fn push_delim(&mut self, writer: DelimFileWriter<S>) {
+        self.sinks.push(Sink::Delim(Box::new(writer)));
+    }
+
+    /// Adds a JSON Lines output.
+    #[cfg(feature = "json")]
+    pub(crate) fn push_jsonl(&mut self, writer: BufWriter<Box<dyn FinishWrite>>) {
+        self.sinks.push(Sink::Jsonl(writer));
+    }
+
+    /// Writes a single record to every output.
+    pub fn write_record(&mut self, rec: &S) -> Result<()> {
+        for sink in &mut self.sinks {
+            match sink {
+                Sink::Delim(writer) => writer.write_record(rec)?,
+                #[cfg(feature = "json")]
+                Sink::Jsonl(writer) => {
+                    serde_json::to_writer(&mut *writer, rec)
+                        .map_err(|e| FgError::IoError(e.into()))?;
+                    writer.write_all(b"\n").map_err(FgError::IoError)?;
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Writes all the records from an iterable to every output, returning the number of records.
+    pub fn write_records(&mut self, recs: impl IntoIterator<Item = S>) -> Result<u64> {
+        let mut count = 0;
+        for rec in recs {
+            self.write_record(&rec)?;
+            count += 1;
+        }
+        Ok(count)
+    }
+
+    /// Flushes all records written so far to every output.
+    pub fn flush(&mut self) -> Result<()> {
+        for sink in &mut self.sinks {
+            match sink {
+                Sink::Delim(writer) => writer.flush()?,
+                #[cfg(feature = "json")]
+                Sink::Jsonl(writer) => writer.flush().map_err(FgError::IoError)?,
+            }
+        }
+        Ok(())
+    }
+
+    /// Flushes all records and finishes every output, such as writing the end of a compressed
+    /// stream, returning any error in doing so.
+    pub fn close(self) -> Result<()> {
+        for sink in self.sinks {
+            match sink {
+                Sink::Delim(writer) => (*writer).finish()?,
+                #[cfg(feature = "json")]
+                Sink::Jsonl(mut writer) => writer.finish().map_err(FgError::IoError)?,
+            }
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,151 @@
+//! A reader that periodically logs its progress.
+use std::io::{self, Read};
+use std::time::Instant;
+
+/// How often a [`ProgressReader`] logs its progress.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum ProgressInterval {
+    /// Log each time this many more lines have been read
+    Lines(u64),
+    /// Log each time this many more bytes have been read
+    Bytes(u64),
+}
+
+/// Reader that logs a heartbeat message at `info` level via the `log` crate each time a given
+/// number of lines or bytes have been read through it, reporting the totals read, the elapsed
+/// time and the rate of reading, and a final message when the end of the input is reached.
+/// This makes long-running reads visible in the same way as fgbio's `ProgressLogger`.
+///
+/// Lines are counted as newlines are read from the underlying reader, so when the reader is
+/// wrapped in a buffer the counts may run slightly ahead of the consumer.
+pub struct ProgressReader<R> {
+    inner: R,
+    name: String,
+    interval: ProgressInterval,
+    start: Instant,
+    lines: u64,
+    bytes: u64,
+    next_report: u64,
+    finished: bool,
+}
+
+impl<R> ProgressReader<R> {
+    /// Wraps a reader, logging progress every `interval`.  `name` identifies the input in log
+    /// messages, e.g. the path being read.
+    ///
+    /// # Panics
+    /// - panics if the interval is zero
+    pub fn new(inner: R, name: &str, interval: ProgressInterval) -> Self {
+        let (ProgressInterval::Lines(n) | ProgressInterval::Bytes(n)) = interval;
+        assert_ne!(n, 0, "Progress interval cannot be zero!");
+        Self {
+            inner,
+            name: name.to_string(),
+            interval,
+            start: Instant::now(),
+            lines: 0,
+            bytes: 0,
+            next_report: n,
+            finished: false,
+        }
+    }
+
+    /// Returns the number of lines read so far.
+    pub fn lines_read(&self) -> u64 {
+        self.lines
+    }
+
+    /// Returns the number of bytes read so far.
+    pub fn bytes_read(&self) -> u64 {
+        self.bytes
+    }
+
+    /// Consumes this wrapper, returning the underlying reader.
+    pub fn into_inner(self) -> R {
+        self.inner
+    }
+
+    /// Logs the current progress.
+    fn report(&self, verb: &str) {
+        let elapsed = self.start.elapsed().as_secs_f64();
+        let rate = |n: u64| if elapsed > 0.0 { n as f64 / elapsed } else { 0.0 };
+        log::info!(
+            "{} {} {} lines ({} bytes) in {:.1}s; {:.0} lines/s, {:.0} bytes/s",
+            self.name,
+            verb,
+            self.lines,
+            self.bytes,
+            elapsed,
+            rate(self.lines),
+            rate(self.bytes)
+        );
+    }
+}
+
+impl<R: Read> Read for ProgressReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let n = self.inner.read(buf)?;
+        if n == 0 && !buf.is_empty() {
+            if !self.finished {
+                self.finished = true;
+                self.report("finished reading");
+            }
+            return Ok(0);
+        }
+
+        self.bytes += n as u64;
+        self.lines += memchr::memchr_iter(b'\n', &buf[..n]).count() as u64;
+        let (count, step) = match self.interval {
+            ProgressInterval::Lines(step) => (self.lines, step),
+            ProgressInterval::Bytes(step) => (self.bytes, step),
+        };
+        if count >= self.next_report {
+            self.report("read");
+            self.next_report = (count / step + 1) * step;
+        }
+        Ok(n)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::Mutex;
+
+    /// A logger that records messages so that tests can inspect them.
+    struct TestLogger(Mutex<Vec<String>>);
+
+    impl log::Log for TestLogger {
+        fn enabled(&self, _: &log::Metadata) -> bool {
+            true
+        }
+
+        fn log(&self, record: &log::Record) {
+            self.0.lock().unwrap().push(record.args().to_string());
+        }
+
+        fn flush(&self) {}
+    }
+
+    static LOGGER: TestLogger = TestLogger(Mutex::new(Vec::new()));
+
+    #[test]
+    fn test_progress_is_logged() {
+        log::set_logger(&LOGGER).unwrap();
+        log::set_max_level(log::LevelFilter::Info);
+
+        let data = "line\n".repeat(1000);
+        let mut reader =
+            ProgressReader::new(data.as_bytes(), "test-input", ProgressInterval::Lines(100));
+        let mut buf = [0u8; 50];
+        while reader.read(&mut buf).unwrap() > 0 {}
+        assert_eq!(reader.lines_read(), 1000);
+        assert_eq!(reader.bytes_read(), 5000);
+
+        let messages = LOGGER.0.lock().unwrap();
+        let ours: Vec<&String> = messages.iter().filter(|m| m.starts_with("test-input")).collect();
+        assert_eq!(ours.len(), 11);
+        assert!(ours[0].starts_with("test-input read 100 lines (500 bytes)"));
+        assert!(ours[10].starts_with("test-input finished reading 1000 lines (5000 bytes)"));
+    }
+}
@@ -0,0 +1,190 @@
+//! A writer that splits its output across a series of numbered part files.
+use std::io::{self, BufWriter, Write};
+use std::path::{Path, PathBuf};
+
+use crate::io::{FinishWrite, Io};
+use crate::Result;
+
+/// The size at which a [`RollingWriter`] rolls over to a new part file.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum RollingLimit {
+    /// Roll over after this many lines have been written to a part
+    Lines(u64),
+    /// Roll over at the first line boundary once this many (uncompressed) bytes have been
+    /// written to a part
+    Bytes(u64),
+}
+
+/// Writer that splits line-oriented output across a series of part files, rolling over to a new
+/// part once the current part reaches a [`RollingLimit`].  Parts are named by inserting
+/// `.part-NNNN` before the extensions of the template path, so that `out.tsv.gz` is written as
+/// `out.part-0001.tsv.gz`, `out.part-0002.tsv.gz`, etc.  Each part is compressed according to
+/// its extension, and parts only roll over at line boundaries.
+///
+/// Parts are opened lazily, so no empty trailing part is created when the output ends exactly at
+/// a limit.  Each part is flushed and finalized as soon as the writer rolls past it.
+pub struct RollingWriter<'a> {
+    io: &'a Io,
+    template: PathBuf,
+    limit: RollingLimit,
+    current: Option<BufWriter<Box<dyn FinishWrite>>>,
+    lines_in_part: u64,
+    bytes_in_part: u64,
+    paths: Vec<PathBuf>,
+}
+
+impl<'a> RollingWriter<'a> {
+    /// Creates a new rolling writer that writes parts named after `template` using `io`.
+    ///
+    /// # Panics
+    /// - panics if the limit is zero
+    pub fn new<P: AsRef<Path>>(io: &'a Io, template: &P, limit: RollingLimit) -> Self {
+        let (RollingLimit::Lines(n) | RollingLimit::Bytes(n)) = limit;
+        assert_ne!(n, 0, "Rolling limit cannot be zero!");
+        Self {
+            io,
+            template: template.as_ref().to_path_buf(),
+            limit,
+            current: None,
+            lines_in_part: 0,
+            bytes_in_part: 0,
+            paths: vec![],
+        }
+    }
+
+    /// Returns the paths of the parts that have been created so far.
+    pub fn paths(&self) -> &[PathBuf] {
+        &self.paths
+    }
+
+    /// Flushes and finalizes the current part, returning the paths of all parts written.
+    pub fn finish(mut self) -> Result<Vec<PathBuf>> {
+        self.close_part()?;
+        Ok(std::mem::take(&mut self.paths))
+    }
+
+    /// Returns the path of the part with the given (one-based) number.
+    pub fn part_path(&self, part: usize) -> PathBuf {
+        let name = self.template.file_name().map(|n| n.to_string_lossy()).unwrap_or_default();
+        let split = name.char_indices().skip(1).find(|(_, c)| *c == '.').map(|(i, _)| i);
+        let part_name = match split {
+            Some(i) => format!("{}.part-{:04}{}", &name[..i], part, &name[i..]),
+            None => format!("{name}.part-{part:04}"),
+        };
+        self.template.with_file_name(part_name)
+    }
+
+    /// Flushes and drops the current part, if any, which finalizes its compression.
+    fn close_part(&mut self) -> io::Result<()> {
+        if let Some(mut writer) = self.current.take() {
+            writer.flush()?;
+        }
+        self.lines_in_part = 0;
+        self.bytes_in_part = 0;
+        Ok(())
+    }
+
+    /// Returns the current part, opening the next part if necessary.
+    fn current_part(&mut self) -> io::Result<&mut BufWriter<Box<dyn FinishWrite>>> {
+        if self.current.is_none() {
+            let path = self.part_path(self.paths.len() + 1);
+            self.current = Some(self.io.new_writer(&path)?);
+            self.paths.push(path);
+        }
+        Ok(self.current.as_mut().unwrap())
+    }
+
+    /// Returns true if the current part has reached its limit.
+    fn is_full(&self) -> bool {
+        match self.limit {
+            RollingLimit::Lines(n) => self.lines_in_part >= n,
+            RollingLimit::Bytes(n) => self.bytes_in_part >= n,
+        }
+    }
+}
+
+impl Write for RollingWriter<'_> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        // Write at most one line per call so that parts only roll over at line boundaries
+        let (len, ends_line) = match buf.iter().position(|b| *b == b'\n') {
+            Some(i) => (i + 1, true),
+            None => (buf.len(), false),
+        };
+
+        self.current_part()?.write_all(&buf[..len])?;
+        self.bytes_in_part += len as u64;
+        if ends_line {
+            self.lines_in_part += 1;
+            if self.is_full() {
+                self.close_part()?;
+            }
+        }
+        Ok(len)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        match self.current.as_mut() {
+            Some(writer) => writer.flush(),
+            None => Ok(()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    #[test]
+    fn test_part_paths() {
+        let io = Io::default();
+        let writer = RollingWriter::new(&io, &"/data/out.tsv.gz", RollingLimit::Lines(1));
+        assert_eq!(writer.part_path(1), Path::new("/data/out.part-0001.tsv.gz"));
+        assert_eq!(writer.part_path(12), Path::new("/data/out.part-0012.tsv.gz"));
+
+        let writer = RollingWriter::new(&io, &"out", RollingLimit::Lines(1));
+        assert_eq!(writer.part_path(2), Path::new("out.part-0002"));
+    }
+
+    #[test]
+    fn test_rolling_by_lines() {
+        let tempdir = TempDir::new().unwrap();
+        let io = Io::default();
+        let mut writer =
+            RollingWriter::new(&io, &tempdir.path().join("out.txt.gz"), RollingLimit::Lines(3));
+        for i in 0..7 {
+            writeln!(writer, "line {i}").unwrap();
+        }
+        let paths = writer.finish().unwrap();
+
+        assert_eq!(paths.len(), 3);
+        assert_eq!(io.read_lines(&paths[0]).unwrap(), vec!["line 0", "line 1", "line 2"]);
+        assert_eq!(io.read_lines(&paths[1]).unwrap(), vec!["line 3", "line 4", "line 5"]);
+        assert_eq!(io.read_lines(&paths[2]).unwrap(), vec!["line 6"]);
+    }
+
+    #[test]
+    fn test_rolling_by_bytes_respects_line_boundaries() {
+        let tempdir = TempDir::new().unwrap();
+        let io = Io::default();
+        let mut writer =
+            RollingWriter::new(&io, &tempdir.path().join("out.txt"), RollingLimit::Bytes(10));
+        // Write in one call to check that lines spanning the limit are kept intact
+        writer.write_all(b"aaaaaaa\nbbbbbbb\nccc\nddddddddddddd\n").unwrap();
+        let paths = writer.finish().unwrap();
+
+        let parts: Vec<String> =
+            paths.iter().map(|p| std::fs::read_to_string(p).unwrap()).collect();
+        assert_eq!(parts, vec!["aaaaaaa\nbbbbbbb\n", "ccc\nddddddddddddd\n"]);
+    }
+
+    #[test]
+    fn test_no_empty_trailing_part() {
+        let tempdir = TempDir::new().unwrap();
+        let io = Io::default();
+        let mut writer =
+            RollingWriter::new(&io, &tempdir.path().join("out.txt"), RollingLimit::Lines(2));
+        writer.write_all(b"1\n2\n3\n4\n").unwrap();
+        assert_eq!(writer.finish().unwrap().len(), 2);
+    }
+}
@@ -0,0 +1,152 @@
+//! Reading and writing the records of delimited files as ordered maps from column names to
+//! values, for tables whose columns are only known at runtime.
+use std::borrow::Borrow;
+use std::io::{BufRead, Read, Write};
+
+use csv::{ByteRecord, StringRecord};
+use indexmap::IndexMap;
+
+use crate::io::delim::dedupe_headers;
+use crate::io::DuplicateColumns;
+use crate::{FgError, Result};
+
+/// A record of a delimited file as a map from column names to values, in column order.
+pub type Row = IndexMap<String, String>;
+
+/// Iterator over the records of a delimited file as [`Row`]s, returned by
+/// [`DelimFile::read_rows_iter`](crate::io::DelimFile::read_rows_iter).  Every row has a value
+/// for every column, which is empty for fields missing from short records.
+pub struct RowReader<R = Box<dyn BufRead + Send>> {
+    reader: csv::Reader<R>,
+    columns: Vec<String>,
+    record: StringRecord,
+}
+
+impl<R: Read> RowReader<R> {
+    /// Creates a reader over a csv reader configured to expect a header line, handling columns
+    /// with the same name according to `duplicates`.
+    pub(crate) fn new(mut reader: csv::Reader<R>, duplicates: DuplicateColumns) -> Result<Self> {
+        let mut headers = reader.byte_headers()?.clone();
+        dedupe_headers(&mut headers, duplicates)?;
+        let columns = headers.iter().map(|h| String::from_utf8_lossy(h).into_owned()).collect();
+        Ok(Self { reader, columns, record: StringRecord::new() })
+    }
+
+    /// Returns the names of the columns, which are the keys of every row.
+    pub fn columns(&self) -> &[String] {
+        &self.columns
+    }
+}
+
+impl<R: Read> Iterator for RowReader<R> {
+    type Item = Result<Row>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        match self.reader.read_record(&mut self.record) {
+            Ok(true) => {
+                let values = self.columns.iter().enumerate().map(|(i, column)| {
+                    (column.clone(), self.record.get(i).unwrap_or_default().to_string())
+                });
+                Some(Ok(values.collect()))
+            }
+            Ok(false) => None,
+            Err(e) => Some(Err(FgError::ConversionError(e))),
+        }
+    }
+}
+
+/// Writes rows to a csv writer, with the values of `columns` in order, or of the keys of the
+/// first row if `columns` is `None`.  Missing values are written as empty fields.  When the
+/// columns come from the first row, a row with any other key is an error, while with explicit
+/// columns other keys are not written.  A header line is written before the first row if
+/// `header` is true, so nothing is written if there are no rows.  Returns the number of rows
+/// written.
+pub(crate) fn write_rows<W, B>(
+    writer: &mut csv::Writer<W>,
+    rows: impl IntoIterator<Item = B>,
+    columns: Option<&[String]>,
+    header: bool,
+) -> Result<u64>
+where
+    W: Write,
+    B: Borrow<Row>,
+{
+    let mut columns: Option<Vec<String>> = columns.map(|c| c.to_vec());
+    let explicit = columns.is_some();
+    let mut count = 0;
+    let mut record = ByteRecord::new();
+    for row in rows {
+        let row = row.borrow();
+        if columns.is_none() {
+            columns = Some(row.keys().cloned().collect());
+        }
+        let columns = columns.as_ref().expect("set from the first row");
+        if count == 0 && header {
+            writer.write_record(columns)?;
+        }
+        if !explicit && row.keys().any(|k| !columns.contains(k)) {
+            let unexpected = row.keys().filter(|k| !columns.contains(k)).cloned().collect();
+            return Err(FgError::HeaderMismatch { missing: vec![], unexpected });
+        }
+
+        record.clear();
+        for column in columns {
+            record.push_field(row.get(column).map_or("", String::as_str).as_bytes());
+        }
+        writer.write_byte_record(&record)?;
+        count += 1;
+    }
+    writer.flush().map_err(FgError::IoError)?;
+    Ok(count)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use csv::{ReaderBuilder, WriterBuilder};
+
+    fn row(pairs: &[(&str, &str)]) -> Row {
+        pairs.iter().map(|(k, v)| (k.to_string(), v.to_string())).collect()
+    }
+
+    #[test]
+    fn test_reading_rows() {
+        let data = "b,a,b\n1,2,3\n4,5\n";
+        let csv = ReaderBuilder::new().flexible(true).from_reader(data.as_bytes());
+        let reader = RowReader::new(csv, DuplicateColumns::Rename).unwrap();
+        assert_eq!(reader.columns(), ["b", "a", "b_2"]);
+        let rows: Vec<Row> = reader.map(|r| r.unwrap()).collect();
+        assert_eq!(
+            rows,
+            [
+                row(&[("b", "1"), ("a", "2"), ("b_2", "3")]),
+                row(&[("b", "4"), ("a", "5"), ("b_2", "")])
+            ]
+        );
+        assert_eq!(rows[0].keys().collect::<Vec<_>>(), ["b", "a", "b_2"]);
+
+        let csv = ReaderBuilder::new().from_reader(data.as_bytes());
+        let result = RowReader::new(csv, DuplicateColumns::Reject);
+        assert!(matches!(result, Err(FgError::DuplicateColumn { .. })));
+    }
+
+    #[test]
+    fn test_writing_rows() {
+        let rows = [row(&[("z", "1"), ("a", "x,y")]), row(&[("a", "2")])];
+        let mut writer = WriterBuilder::new().from_writer(vec![]);
+        assert_eq!(write_rows(&mut writer, &rows, None, true).unwrap(), 2);
+        assert_eq!(writer.get_ref(), b"z,a\n1,\"x,y\"\n,2\n");
+
+        let columns = ["a".to_string(), "b".to_string()];
+        let mut writer = WriterBuilder::new().from_writer(vec![]);
+        assert_eq!(write_rows(&mut writer, &rows, Some(&columns[..]), false).unwrap(), 2);
+        assert_eq!(writer.get_ref(), b"\"x,y\",\n2,\n");
+
+        let rows = [row(&[("a", "1")]), row(&[("a", "2"), ("b", "3")])];
+        let mut writer = WriterBuilder::new().from_writer(vec![]);
+        match write_rows(&mut writer, &rows, None, true) {
+            Err(FgError::HeaderMismatch { unexpected, .. }) => assert_eq!(unexpected, vec!["b"]),
+            _ => panic!("expected a header mismatch"),
+        }
+    }
+}
@@ -0,0 +1,372 @@
+//! Inference and validation of the types of the columns of delimited files.
+use std::fmt;
+
+/// The type of the values in a column of a delimited file.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub enum ColumnType {
+    /// `true` or `false`
+    Boolean,
+    /// Whole numbers that fit in an `i64`
+    Integer,
+    /// Numbers that parse as an `f64`, including integers, `NaN` and `inf`
+    Float,
+    /// Dates in ISO 8601 `YYYY-MM-DD` format
+    Date,
+    /// Any text
+    String,
+}
+
+impl ColumnType {
+    /// Returns the most specific type of a single non-empty value.
+    pub fn of(value: &str) -> ColumnType {
+        if value == "true" || value == "false" {
+            ColumnType::Boolean
+        } else if value.parse::<i64>().is_ok() {
+            ColumnType::Integer
+        } else if value.parse::<f64>().is_ok() {
+            ColumnType::Float
+        } else if is_date(value) {
+            ColumnType::Date
+        } else {
+            ColumnType::String
+        }
+    }
+
+    /// Returns true if a value is of this type.
+    pub fn matches(self, value: &str) -> bool {
+        match self {
+            ColumnType::Float => value.parse::<f64>().is_ok(),
+            ColumnType::String => true,
+            _ => ColumnType::of(value) == self,
+        }
+    }
+
+    /// Returns the most specific type that covers the values of both types, so that a column
+    /// with both integers and floats is a float column.
+    pub fn merge(self, other: ColumnType) -> ColumnType {
+        match (self, other) {
+            (a, b) if a == b => a,
+            (ColumnType::Integer, ColumnType::Float) | (ColumnType::Float, ColumnType::Integer) => {
+                ColumnType::Float
+            }
+            _ => ColumnType::String,
+        }
+    }
+
+    /// Returns the name of the Rust type used to hold values of this type.
+    pub fn rust_type(self) -> &'static str {
+        match self {
+            ColumnType::Boolean => "bool",
+            ColumnType::Integer => "i64",
+            ColumnType::Float => "f64",
+            ColumnType::Date | ColumnType::String => "String",
+        }
+    }
+}
+
+impl fmt::Display for ColumnType {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            ColumnType::Boolean => "boolean",
+            ColumnType::Integer => "integer",
+            ColumnType::Float => "float",
+            ColumnType::Date => "date",
+            ColumnType::String => "string",
+        };
+        f.write_str(name)
+    }
+}
+
+/// Returns true if a value is a date in `YYYY-MM-DD` format.
+fn is_date(value: &str) -> bool {
+    let bytes = value.as_bytes();
+    let digits = |range: std::ops::Range<usize>| bytes[range].iter().all(u8::is_ascii_digit);
+    if bytes.len() != 10 || bytes[4] != b'-' || bytes[7] != b'-' {
+        return false;
+    }
+    if !digits(0..4) || !digits(5..7) || !digits(8..10) {
+        return false;
+    }
+    let month: u32 = value[5..7].parse().unwrap_or_default();
+    let day: u32 = value[8..10].parse().unwrap_or_default();
+    (1..=12).contains(&month) && (1..=31).contains(&day)
+}
+
+/// The type and other properties of a column inferred by
+/// [`DelimFile::infer_schema`](crate::io::DelimFile::infer_schema) from a sample of values.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct InferredColumn {
+    /// The name of the column from the header
+    pub name: String,
+    /// The most specific type of all the values sampled, or [`ColumnType::String`] if no
+    /// values were present
+    pub column_type: ColumnType,
+    /// Whether any values sampled were missing
+    pub nullable: bool,
+    /// Up to three distinct values from the sample, in the order first seen
+    pub examples: Vec<String>,
+}
+
+impl InferredColumn {
+    /// The number of example values kept for each column.
+    const MAX_EXAMPLES: usize = 3;
+
+    /// Creates a column with the given name for which no values have been seen.
+    pub(crate) fn new(name: String) -> Self {
+        InferredColumn { name, column_type: ColumnType::String, nullable: false, examples: vec![] }
+    }
+
+    /// Updates the inferred properties of the column with a value, which is `None` if missing.
+    pub(crate) fn observe(&mut self, value: Option<&str>) {
+        let value = match value {
+            Some(value) => value,
+            None => {
+                self.nullable = true;
+                return;
+            }
+        };
+        let value_type = ColumnType::of(value);
+        self.column_type =
+            if self.examples.is_empty() { value_type } else { self.column_type.merge(value_type) };
+        if self.examples.len() < Self::MAX_EXAMPLES && !self.examples.iter().any(|e| e == value) {
+            self.examples.push(value.to_string());
+        }
+    }
+
+    /// Returns the name of the Rust type used to hold values of the column, which is an `Option`
+    /// if the column is nullable.
+    pub fn rust_type(&self) -> String {
+        if self.nullable {
+            format!("Option<{}>", self.column_type.rust_type())
+        } else {
+            self.column_type.rust_type().to_string()
+        }
+    }
+}
+
+/// The expected name, type and values of a column of a delimited file, used to validate files
+/// with [`DelimFile::validate`](crate::io::DelimFile::validate).
+///
+/// ```rust
+/// use fgoxide::io::{ColumnSpec, ColumnType};
+///
+/// let spec = ColumnSpec::new("fraction", ColumnType::Float).required(true).range(0.0, 1.0);
+/// ```
+#[derive(Clone, Debug, PartialEq)]
+pub struct ColumnSpec {
+    /// The name of the column in the header
+    pub name: String,
+    /// The type that all values in the column must be
+    pub column_type: ColumnType,
+    /// Whether the column must be present and all its values non-missing
+    pub required: bool,
+    /// The values allowed in the column, or `None` to allow any value of the type
+    pub allowed_values: Option<Vec<String>>,
+    /// The smallest value allowed in a numeric column, if any
+    pub min: Option<f64>,
+    /// The largest value allowed in a numeric column, if any
+    pub max: Option<f64>,
+}
+
+impl ColumnSpec {
+    /// Creates a spec for an optional column of the given type that allows any value.
+    pub fn new(name: impl Into<String>, column_type: ColumnType) -> ColumnSpec {
+        ColumnSpec {
+            name: name.into(),
+            column_type,
+            required: false,
+            allowed_values: None,
+            min: None,
+            max: None,
+        }
+    }
+
+    /// Sets whether the column must be present and all its values non-missing.
+    pub fn required(mut self, required: bool) -> ColumnSpec {
+        self.required = required;
+        self
+    }
+
+    /// Sets the values allowed in the column, e.g. the names of a set of categories.
+    pub fn allowed_values<I, V>(mut self, values: I) -> ColumnSpec
+    where
+        I: IntoIterator<Item = V>,
+        V: Into<String>,
+    {
+        self.allowed_values = Some(values.into_iter().map(Into::into).collect());
+        self
+    }
+
+    /// Sets the inclusive range of values allowed in an integer or float column.
+    pub fn range(mut self, min: f64, max: f64) -> ColumnSpec {
+        self.min = Some(min);
+        self.max = Some(max);
+        self
+    }
+
+    /// Checks a single non-missing value against the spec, returning the violation if any.
+    pub(crate) fn check(&self, value: &str) -> Option<ViolationKind> {
+        if !self.column_type.matches(value) {
+            return Some(ViolationKind::InvalidType(self.column_type));
+        }
+        if let Some(allowed) = &self.allowed_values {
+            if !allowed.iter().any(|a| a == value) {
+                return Some(ViolationKind::NotAllowed);
+            }
+        }
+        if matches!(self.column_type, ColumnType::Integer | ColumnType::Float) {
+            let number: f64 = value.parse().ok()?;
+            let bounded = self.min.is_some() || self.max.is_some();
+            let below = self.min.is_some_and(|min| number < min);
+            let above = self.max.is_some_and(|max| number > max);
+            if below || above || (bounded && number.is_nan()) {
+                return Some(ViolationKind::OutOfRange);
+            }
+        }
+        None
+    }
+}
+
+/// The expected columns of a delimited file, used to validate files with
+/// [`DelimFile::validate`](crate::io::DelimFile::validate).  Columns may appear in the file in
+/// any order, and columns of the file not in the schema are not checked.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Schema {
+    /// The specs of the columns to be checked
+    pub columns: Vec<ColumnSpec>,
+}
+
+impl Schema {
+    /// Creates a schema from the specs of its columns.
+    pub fn new(columns: impl IntoIterator<Item = ColumnSpec>) -> Schema {
+        Schema { columns: columns.into_iter().collect() }
+    }
+}
+
+/// The ways in which a delimited file can fail to match a [`Schema`].
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum ViolationKind {
+    /// A required column is not in the header
+    MissingColumn,
+    /// A required column has a missing value
+    MissingValue,
+    /// A value is not of the column's type
+    InvalidType(ColumnType),
+    /// A value is not one of the column's allowed values
+    NotAllowed,
+    /// A numeric value is outside the column's range
+    OutOfRange,
+}
+
+/// A single failure of a delimited file to match a [`Schema`].
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Violation {
+    /// The 1-based number of the line of the record, or of the header for a missing column
+    pub line: u64,
+    /// The name of the column
+    pub column: String,
+    /// The offending value, if there was one
+    pub value: Option<String>,
+    /// How the file failed to match the schema
+    pub kind: ViolationKind,
+}
+
+impl fmt::Display for Violation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Line {}, column '{}': ", self.line, self.column)?;
+        match (self.kind, &self.value) {
+            (ViolationKind::MissingColumn, _) => f.write_str("required column is missing"),
+            (ViolationKind::MissingValue, _) => f.write_str("required value is missing"),
+            (ViolationKind::InvalidType(t), Some(v)) => write!(f, "{v:?} is not a valid {t}"),
+            (ViolationKind::NotAllowed, Some(v)) => write!(f, "{v:?} is not an allowed value"),
+            (ViolationKind::OutOfRange, Some(v)) => write!(f, "{v:?} is out of range"),
+            (kind, None) => write!(f, "{kind:?}"),
+        }
+    }
+}
+
+/// The result of validating a delimited file against a [`Schema`] with
+/// [`DelimFile::validate`](crate::io::DelimFile::validate).
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct ValidationReport {
+    /// The number of records checked, not including the header
+    pub records: u64,
+    /// Every violation found, in the order they occur in the file
+    pub violations: Vec<Violation>,
+}
+
+impl ValidationReport {
+    /// Returns true if the file matched the schema.
+    pub fn is_valid(&self) -> bool {
+        self.violations.is_empty()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rstest::rstest;
+
+    #[rstest]
+    #[case("true", ColumnType::Boolean)]
+    #[case("1", ColumnType::Integer)]
+    #[case("-42", ColumnType::Integer)]
+    #[case("1.5", ColumnType::Float)]
+    #[case("1e-8", ColumnType::Float)]
+    #[case("NaN", ColumnType::Float)]
+    #[case("2024-02-29", ColumnType::Date)]
+    #[case("2024-13-01", ColumnType::String)]
+    #[case("2024/01/01", ColumnType::String)]
+    #[case("True", ColumnType::String)]
+    #[case("chr1", ColumnType::String)]
+    fn test_column_type_of_value(#[case] value: &str, #[case] expected: ColumnType) {
+        assert_eq!(ColumnType::of(value), expected);
+        assert!(expected.matches(value));
+    }
+
+    #[test]
+    fn test_merging_column_types() {
+        assert_eq!(ColumnType::Integer.merge(ColumnType::Integer), ColumnType::Integer);
+        assert_eq!(ColumnType::Integer.merge(ColumnType::Float), ColumnType::Float);
+        assert_eq!(ColumnType::Boolean.merge(ColumnType::Integer), ColumnType::String);
+        assert_eq!(ColumnType::Date.merge(ColumnType::Float), ColumnType::String);
+        assert!(ColumnType::Float.matches("3"));
+        assert!(!ColumnType::Integer.matches("3.0"));
+    }
+
+    #[test]
+    fn test_inferring_columns() {
+        let mut column = InferredColumn::new("n".to_string());
+        for value in [Some("1"), None, Some("2.5"), Some("1"), Some("3"), Some("4")] {
+            column.observe(value);
+        }
+        assert_eq!(column.column_type, ColumnType::Float);
+        assert!(column.nullable);
+        assert_eq!(column.examples, vec!["1", "2.5", "3"]);
+        assert_eq!(column.rust_type(), "Option<f64>");
+
+        let mut column = InferredColumn::new("empty".to_string());
+        column.observe(None);
+        assert_eq!(
+            (column.column_type, column.rust_type().as_str()),
+            (ColumnType::String, "Option<String>")
+        );
+    }
+
+    #[test]
+    fn test_checking_values_against_column_spec() {
+        let spec = ColumnSpec::new("q", ColumnType::Integer).range(0.0, 60.0);
+        assert_eq!(spec.check("30"), None);
+        assert_eq!(spec.check("0"), None);
+        assert_eq!(spec.check("61"), Some(ViolationKind::OutOfRange));
+        assert_eq!(spec.check("1.5"), Some(ViolationKind::InvalidType(ColumnType::Integer)));
+
+        let spec = ColumnSpec::new("strand", ColumnType::String).allowed_values(["+", "-"]);
+        assert_eq!(spec.check("+"), None);
+        assert_eq!(spec.check("."), Some(ViolationKind::NotAllowed));
+
+        let spec = ColumnSpec::new("f", ColumnType::Float).range(0.0, 1.0);
+        assert_eq!(spec.check("NaN"), Some(ViolationKind::OutOfRange));
+        assert_eq!(ColumnSpec::new("f", ColumnType::Float).check("NaN"), None);
+    }
+}
@@ -0,0 +1,310 @@
+//! A writer that routes lines to one of several output files based on a key.
+use std::collections::HashMap;
+use std::hash::{Hash, Hasher};
+use std::io::{BufWriter, ErrorKind, Write};
+use std::path::{Path, PathBuf};
+
+use crate::io::{FinishWrite, Io};
+use crate::{FgError, Result};
+
+/// How keys are mapped to the outputs of a [`ShardedWriter`].
+enum Routing<K> {
+    /// Keys are hashed with [`Fnv1aHasher`] to pick one of the outputs
+    Hash,
+    /// Keys are looked up to find the index of their output, with an optional fallback output
+    Map(HashMap<K, usize>, Option<usize>),
+}
+
+/// Writer that routes each line it is given to one of a number of output files, using a
+/// caller-supplied function to extract a key from each line.  Keys are either hashed across a
+/// fixed set of outputs ([`ShardedWriter::hashed`]) or looked up in an explicit mapping
+/// ([`ShardedWriter::mapped`]).  Outputs are opened lazily on first use, compressed according to
+/// their extensions, and finalized when the writer is finished or dropped.
+///
+/// ```rust
+/// use std::collections::HashMap;
+/// use fgoxide::io::{Io, ShardedWriter};
+/// use tempfile::TempDir;
+///
+/// let dir = TempDir::new().unwrap();
+/// let mapping = HashMap::from([
+///     ("s1".to_string(), dir.path().join("s1.txt.gz")),
+///     ("s2".to_string(), dir.path().join("s2.txt.gz")),
+/// ]);
+///
+/// let io = Io::default();
+/// let key = |line: &str| line.split('\t').next().unwrap_or_default().to_string();
+/// let mut writer = ShardedWriter::mapped(&io, mapping, None, key);
+/// writer.write_line("s1\tACGT").unwrap();
+/// writer.write_line("s2\tTTTT").unwrap();
+/// writer.finish().unwrap();
+///
+/// assert_eq!(io.read_lines(&dir.path().join("s2.txt.gz")).unwrap(), vec!["s2\tTTTT"]);
+/// ```
+pub struct ShardedWriter<'a, K, F> {
+    io: &'a Io,
+    paths: Vec<PathBuf>,
+    writers: Vec<Option<BufWriter<Box<dyn FinishWrite>>>>,
+    routing: Routing<K>,
+    key: F,
+}
+
+impl<'a, K, F> ShardedWriter<'a, K, F>
+where
+    K: Hash + Eq,
+    F: Fn(&str) -> K,
+{
+    /// Creates a writer that distributes lines across `paths` by hashing their keys, so that
+    /// all lines with the same key are written to the same output.  Keys are hashed with the
+    /// 64-bit FNV-1a hash, so a key is always assigned to the same output, across runs and
+    /// platforms, for the same number of paths.
+    ///
+    /// # Panics
+    /// - panics if `paths` is empty
+    pub fn hashed<P: AsRef<Path>>(io: &'a Io, paths: &[P], key: F) -> Self {
+        assert!(!paths.is_empty(), "Must provide at least one output path!");
+        let paths: Vec<PathBuf> = paths.iter().map(|p| p.as_ref().to_path_buf()).collect();
+        Self::new(io, paths, Routing::Hash, key)
+    }
+
+    /// Creates a writer that sends lines to the output mapped to their key, or to `default` if
+    /// their key is not in the mapping.  Without a default, writing a line with an unmapped key
+    /// is an error.  Several keys may map to the same path.
+    pub fn mapped<P: AsRef<Path>>(
+        io: &'a Io,
+        mapping: HashMap<K, P>,
+        default: Option<P>,
+        key: F,
+    ) -> Self {
+        let mut paths: Vec<PathBuf> = vec![];
+        let mut index_of = |p: &P| match paths.iter().position(|x| x == p.as_ref()) {
+            Some(i) => i,
+            None => {
+                paths.push(p.as_ref().to_path_buf());
+                paths.len() - 1
+            }
+        };
+
+        let indices = mapping.into_iter().map(|(k, p)| (k, index_of(&p))).collect();
+        let default = default.map(|p| index_of(&p));
+        Self::new(io, paths, Routing::Map(indices, default), key)
+    }
+
+    fn new(io: &'a Io, paths: Vec<PathBuf>, routing: Routing<K>, key: F) -> Self {
+        let writers = paths.iter().map(|_| None).collect();
+        Self { io, paths, writers, routing, key }
+    }
+
+    /// Returns the paths of all the outputs this writer may write to.
+    pub fn paths(&self) -> &[PathBuf] {
+        &self.paths
+    }
+
+    /// Writes a line, followed by the configured line terminator, to the output selected by the
+    /// line's key.
+    pub fn write_line(&mut self, line: &str) -> Result<()> {
+        let key = (self.key)(line);
+        let terminator = self.io.line_terminator;
+        let writer = self.writer_for(&key)?;
+        writer.write_all(line.as_bytes()).map_err(FgError::IoError)?;
+        writer.write_all(terminator.as_bytes()).map_err(FgError::IoError)
+    }
+
+    /// Returns the output for the given key, opening it if necessary.  This allows callers to
+    /// write arbitrary data (e.g. serialized records) to the output chosen for a key.
+    pub fn writer_for(&mut self, key: &K) -> Result<&mut BufWriter<Box<dyn FinishWrite>>> {
+        let index = match &self.routing {
+            Routing::Hash => {
+                let mut hasher = Fnv1aHasher::default();
+                key.hash(&mut hasher);
+                (hasher.finish() % self.paths.len() as u64) as usize
+            }
+            Routing::Map(indices, default) => match indices.get(key).or(default.as_ref()) {
+                Some(i) => *i,
+                None => {
+                    let msg = "No output is mapped to key and no default output was given";
+                    return Err(FgError::IoError(std::io::Error::new(
+                        ErrorKind::InvalidInput,
+                        msg,
+                    )));
+                }
+            },
+        };
+
+        if self.writers[index].is_none() {
+            self.writers[index] = Some(self.io.new_writer(&self.paths[index])?);
+        }
+        Ok(self.writers[index].as_mut().unwrap())
+    }
+
+    /// Flushes and closes all outputs, returning the paths of the outputs that were written to.
+    pub fn finish(mut self) -> Result<Vec<PathBuf>> {
+        let mut written = vec![];
+        for (writer, path) in self.writers.iter_mut().zip(&self.paths) {
+            if let Some(mut writer) = writer.take() {
+                writer.flush().map_err(FgError::IoError)?;
+                written.push(path.clone());
+            }
+        }
+        Ok(written)
+    }
+}
+
+/// The 64-bit FNV-1a hash, used to assign keys to shards.  Unlike the standard library's default
+/// hasher its algorithm is fixed, and integers are hashed as their little-endian bytes, so the
+/// same key is written to the same shard by every build on every platform.
+struct Fnv1aHasher(u64);
+
+impl Fnv1aHasher {
+    const OFFSET_BASIS: u64 = 0xcbf2_9ce4_8422_2325;
+    const PRIME: u64 = 0x0000_0100_0000_01b3;
+}
+
+impl Default for Fnv1aHasher {
+    fn default() -> Self {
+        Self(Self::OFFSET_BASIS)
+    }
+}
+
+impl Hasher for Fnv1aHasher {
+    fn finish(&self) -> u64 {
+        self.0
+    }
+
+    fn write(&mut self, bytes: &[u8]) {
+        for &b in bytes {
+            self.0 = (self.0 ^ u64::from(b)).wrapping_mul(Self::PRIME);
+        }
+    }
+
+    fn write_u16(&mut self, i: u16) {
+        self.write(&i.to_le_bytes());
+    }
+
+    fn write_u32(&mut self, i: u32) {
+        self.write(&i.to_le_bytes());
+    }
+
+    fn write_u64(&mut self, i: u64) {
+        self.write(&i.to_le_bytes());
+    }
+
+    fn write_u128(&mut self, i: u128) {
+        self.write(&i.to_le_bytes());
+    }
+
+    fn write_usize(&mut self, i: usize) {
+        self.write_u64(i as u64);
+    }
+
+    fn write_i16(&mut self, i: i16) {
+        self.write_u16(i as u16);
+    }
+
+    fn write_i32(&mut self, i: i32) {
+        self.write_u32(i as u32);
+    }
+
+    fn write_i64(&mut self, i: i64) {
+        self.write_u64(i as u64);
+    }
+
+    fn write_i128(&mut self, i: i128) {
+        self.write_u128(i as u128);
+    }
+
+    fn write_isize(&mut self, i: isize) {
+        self.write_u64(i as u64);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    fn first_field(line: &str) -> String {
+        line.split(',').next().unwrap().to_string()
+    }
+
+    #[test]
+    fn test_hashed_sharding_keeps_keys_together() {
+        let tempdir = TempDir::new().unwrap();
+        let paths: Vec<PathBuf> =
+            (0..4).map(|i| tempdir.path().join(format!("shard{i}.txt.gz"))).collect();
+
+        let io = Io::default();
+        let mut writer = ShardedWriter::hashed(&io, &paths, first_field);
+        for i in 0..1000 {
+            writer.write_line(&format!("key{},{}", i % 20, i)).unwrap();
+        }
+        let written = writer.finish().unwrap();
+        assert!(written.len() > 1);
+
+        let mut total = 0;
+        for path in &written {
+            let lines = io.read_lines(path).unwrap();
+            total += lines.len();
+            // Every key must be found in exactly one shard
+            for other in written.iter().filter(|p| *p != path) {
+                let other_keys: Vec<String> =
+                    io.read_lines(other).unwrap().iter().map(|l| first_field(l)).collect();
+                assert!(lines.iter().all(|l| !other_keys.contains(&first_field(l))));
+            }
+        }
+        assert_eq!(total, 1000);
+    }
+
+    #[test]
+    fn test_hashed_sharding_is_reproducible() {
+        let tempdir = TempDir::new().unwrap();
+        let paths: Vec<PathBuf> =
+            (0..4).map(|i| tempdir.path().join(format!("shard{i}.txt"))).collect();
+
+        let io = Io::default();
+        let mut writer = ShardedWriter::hashed(&io, &paths, first_field);
+        for line in ["s1,1", "s2,2", "s3,3", "s4,4"] {
+            writer.write_line(line).unwrap();
+        }
+        writer.finish().unwrap();
+
+        for (path, expected) in paths.iter().zip(["s3,3", "s2,2", "s1,1", "s4,4"]) {
+            assert_eq!(io.read_lines(path).unwrap(), vec![expected]);
+        }
+    }
+
+    #[test]
+    fn test_mapped_sharding() {
+        let tempdir = TempDir::new().unwrap();
+        let a = tempdir.path().join("a.txt");
+        let b = tempdir.path().join("b.txt");
+        let other = tempdir.path().join("other.txt");
+        let mapping = HashMap::from([
+            ("a1".to_string(), a.clone()),
+            ("a2".to_string(), a.clone()),
+            ("b".to_string(), b.clone()),
+        ]);
+
+        let io = Io::default();
+        let mut writer = ShardedWriter::mapped(&io, mapping, Some(other.clone()), first_field);
+        for line in ["a1,1", "b,2", "a2,3", "c,4"] {
+            writer.write_line(line).unwrap();
+        }
+        writer.finish().unwrap();
+
+        assert_eq!(io.read_lines(&a).unwrap(), vec!["a1,1", "a2,3"]);
+        assert_eq!(io.read_lines(&b).unwrap(), vec!["b,2"]);
+        assert_eq!(io.read_lines(&other).unwrap(), vec!["c,4"]);
+    }
+
+    #[test]
+    fn test_unmapped_key_without_default_fails() {
+        let tempdir = TempDir::new().unwrap();
+        let mapping = HashMap::from([("a".to_string(), tempdir.path().join("a.txt"))]);
+
+        let io = Io::default();
+        let mut writer = ShardedWriter::mapped(&io, mapping, None, first_field);
+        writer.write_line("a,1").unwrap();
+        assert!(writer.write_line("b,2").is_err());
+    }
+}
@@ -0,0 +1,144 @@
+//! Guessing the delimiter of a delimited file and whether it has a header line.
+use csv::{ReaderBuilder, StringRecord};
+
+use crate::io::ColumnType;
+
+/// The number of lines of a file inspected by
+/// [`DelimFile::sniff`](crate::io::DelimFile::sniff).
+pub(crate) const SAMPLE_LINES: usize = 50;
+
+/// The delimiters that are tried, in order of preference when they fit equally well.
+const DELIMITERS: [u8; 4] = [b'\t', b',', b';', b'|'];
+
+/// The format of a delimited file as guessed by
+/// [`DelimFile::sniff`](crate::io::DelimFile::sniff).
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct SniffedFormat {
+    /// The byte that separates fields, one of tab, `,`, `;` or `|`
+    pub delimiter: u8,
+    /// Whether the first line appears to be a header naming the columns
+    pub has_header: bool,
+    /// The number of fields in most lines
+    pub columns: usize,
+}
+
+impl SniffedFormat {
+    /// Guesses the format of the given lines, which should not include comments or blank
+    /// lines.  The delimiter chosen is the one that splits the most lines into the same number
+    /// of fields, preferring more fields when lines are split equally consistently.  Lines that
+    /// cannot be split by any delimiter are taken to be tab-delimited with a single column.
+    pub(crate) fn from_lines(lines: &[&str], quote: u8) -> SniffedFormat {
+        let text = lines.join("\n");
+        let mut best: Option<(usize, usize, u8, Vec<StringRecord>)> = None;
+        for delimiter in DELIMITERS {
+            let records: Vec<StringRecord> = ReaderBuilder::new()
+                .delimiter(delimiter)
+                .quote(quote)
+                .has_headers(false)
+                .flexible(true)
+                .from_reader(text.as_bytes())
+                .records()
+                .filter_map(|r| r.ok())
+                .collect();
+            let (columns, count) = modal_field_count(&records);
+            let better = match &best {
+                Some((best_count, best_columns, ..)) => {
+                    (count, columns) > (*best_count, *best_columns)
+                }
+                None => true,
+            };
+            if columns > 1 && better {
+                best = Some((count, columns, delimiter, records));
+            }
+        }
+
+        match best {
+            Some((_, columns, delimiter, records)) => {
+                SniffedFormat { delimiter, has_header: has_header(&records), columns }
+            }
+            None => {
+                let records: Vec<StringRecord> =
+                    lines.iter().map(|l| StringRecord::from(vec![*l])).collect();
+                SniffedFormat { delimiter: b'\t', has_header: has_header(&records), columns: 1 }
+            }
+        }
+    }
+}
+
+/// Returns the most common number of fields in the records, and the number of records with
+/// that many fields.
+fn modal_field_count(records: &[StringRecord]) -> (usize, usize) {
+    let mut counts: Vec<(usize, usize)> = vec![];
+    for record in records {
+        match counts.iter_mut().find(|(len, _)| *len == record.len()) {
+            Some((_, count)) => *count += 1,
+            None => counts.push((record.len(), 1)),
+        }
+    }
+    counts.into_iter().max_by_key(|&(len, count)| (count, len)).unwrap_or((0, 0))
+}
+
+/// Guesses whether the first record is a header.  Each column votes for a header if the first
+/// value does not fit the type or length shared by the rest of the column's values, and against
+/// if it does.  If no column votes either way, the first record is taken to be a header if none
+/// of its values appear again in their columns.
+fn has_header(records: &[StringRecord]) -> bool {
+    let (first, rest) = match records.split_first() {
+        Some(split) => split,
+        None => return false,
+    };
+    if rest.is_empty() {
+        return first.iter().all(|v| ColumnType::of(v) == ColumnType::String);
+    }
+
+    let mut votes = 0i64;
+    for (i, name) in first.iter().enumerate() {
+        if name.is_empty() {
+            votes -= 1;
+            continue;
+        }
+        let values: Vec<&str> =
+            rest.iter().filter_map(|r| r.get(i)).filter(|v| !v.is_empty()).collect();
+        let column_type = values.iter().map(|v| ColumnType::of(v)).reduce(ColumnType::merge);
+        match column_type {
+            Some(ColumnType::String) => {
+                let len = values[0].chars().count();
+                if values.iter().all(|v| v.chars().count() == len) {
+                    votes += if name.chars().count() == len { -1 } else { 1 };
+                }
+            }
+            Some(column_type) => votes += if column_type.matches(name) { -1 } else { 1 },
+            None => {}
+        }
+    }
+
+    match votes {
+        0 => first.iter().enumerate().all(|(i, name)| rest.iter().all(|r| r.get(i) != Some(name))),
+        votes => votes > 0,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rstest::rstest;
+
+    #[rstest]
+    #[case(&["name\tcount", "a\t1", "b\t2"], b'\t', true, 2)]
+    #[case(&["name,count,note", "a,1,\"x;y\"", "b,2,z"], b',', true, 3)]
+    #[case(&["a;1;0.5", "b;2;0.25"], b';', false, 3)]
+    #[case(&["chr1|100|200", "chr2|300|400"], b'|', false, 3)]
+    #[case(&["sample,barcode", "s1,ACGT", "s2,TTGA"], b',', true, 2)]
+    #[case(&["ACGT,s1", "TTGA,s2"], b',', false, 2)]
+    #[case(&["name", "a", "b"], b'\t', true, 1)]
+    #[case(&["1", "2"], b'\t', false, 1)]
+    fn test_sniffing_format(
+        #[case] lines: &[&str],
+        #[case] delimiter: u8,
+        #[case] has_header: bool,
+        #[case] columns: usize,
+    ) {
+        let format = SniffedFormat::from_lines(lines, b'"');
+        assert_eq!(format, SniffedFormat { delimiter, has_header, columns });
+    }
+}
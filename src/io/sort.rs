@@ -0,0 +1,351 @@
+//! Sorting and merging the records of delimited files by the values of their columns.
+use std::cmp::Ordering;
+use std::collections::BinaryHeap;
+use std::fs;
+use std::io::{self, BufRead, BufWriter, ErrorKind, Read, Write};
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicUsize, Ordering as AtomicOrdering};
+
+use csv::{ByteRecord, ReaderBuilder, StringRecord, WriterBuilder};
+
+use crate::io::delim::column_indices;
+use crate::io::{FinishWrite, Io};
+use crate::{FgError, Result};
+
+/// A column by which records are sorted with
+/// [`DelimFile::sort`](crate::io::DelimFile::sort).
+///
+/// ```rust
+/// use fgoxide::io::SortKey;
+///
+/// let keys = [SortKey::new("contig"), SortKey::new("position").numeric(true)];
+/// ```
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct SortKey {
+    /// The name of the column in the header
+    pub column: String,
+    /// Whether values are compared as numbers rather than as text.  Values that are not
+    /// numbers are sorted after all numbers.
+    pub numeric: bool,
+    /// Whether records are sorted from the largest value to the smallest
+    pub descending: bool,
+}
+
+impl SortKey {
+    /// Creates a key that sorts records by the text of a column in ascending order.
+    pub fn new(column: impl Into<String>) -> SortKey {
+        SortKey { column: column.into(), numeric: false, descending: false }
+    }
+
+    /// Sets whether values are compared as numbers rather than as text.
+    pub fn numeric(mut self, numeric: bool) -> SortKey {
+        self.numeric = numeric;
+        self
+    }
+
+    /// Sets whether records are sorted from the largest value to the smallest.
+    pub fn descending(mut self, descending: bool) -> SortKey {
+        self.descending = descending;
+        self
+    }
+}
+
+/// A [`SortKey`] with the index of its column in a particular header.
+pub(crate) struct ResolvedKey {
+    index: usize,
+    numeric: bool,
+    descending: bool,
+}
+
+/// Finds the columns of the sort keys in a header, returning a [`FgError::HeaderMismatch`]
+/// listing any that are missing.
+pub(crate) fn resolve_keys(keys: &[SortKey], headers: &StringRecord) -> Result<Vec<ResolvedKey>> {
+    let columns: Vec<&str> = keys.iter().map(|key| key.column.as_str()).collect();
+    let indices = column_indices(&columns, headers)?;
+    let resolved = keys.iter().zip(indices).map(|(key, index)| ResolvedKey {
+        index,
+        numeric: key.numeric,
+        descending: key.descending,
+    });
+    Ok(resolved.collect())
+}
+
+/// Compares two records by the given keys.
+pub(crate) fn compare(keys: &[ResolvedKey], a: &ByteRecord, b: &ByteRecord) -> Ordering {
+    for key in keys {
+        let (x, y) = (a.get(key.index).unwrap_or_default(), b.get(key.index).unwrap_or_default());
+        let ordering = if key.numeric {
+            let parse = |v: &[u8]| std::str::from_utf8(v).ok()?.trim().parse::<f64>().ok();
+            match (parse(x), parse(y)) {
+                (Some(x), Some(y)) => x.total_cmp(&y),
+                (Some(_), None) => Ordering::Less,
+                (None, Some(_)) => Ordering::Greater,
+                (None, None) => x.cmp(y),
+            }
+        } else {
+            x.cmp(y)
+        };
+        let ordering = if key.descending { ordering.reverse() } else { ordering };
+        if ordering != Ordering::Equal {
+            return ordering;
+        }
+    }
+    Ordering::Equal
+}
+
+/// The next record from one of the sources being merged, ordered so that a [`BinaryHeap`]
+/// yields the smallest record first, and records that compare equal in source order.
+struct HeapEntry<'a> {
+    record: ByteRecord,
+    source: usize,
+    keys: &'a [ResolvedKey],
+}
+
+impl Ord for HeapEntry<'_> {
+    fn cmp(&self, other: &Self) -> Ordering {
+        compare(self.keys, &self.record, &other.record)
+            .then(self.source.cmp(&other.source))
+            .reverse()
+    }
+}
+
+impl PartialOrd for HeapEntry<'_> {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl PartialEq for HeapEntry<'_> {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other) == Ordering::Equal
+    }
+}
+
+impl Eq for HeapEntry<'_> {}
+
+/// Merges records from sources that are each sorted by the keys, writing them in sorted order.
+/// Records that compare equal are written in the order of their sources.  Returns the number
+/// of records written, or an error if a source is found not to be sorted.
+pub(crate) fn merge<R: Read, W: Write>(
+    sources: &mut [csv::Reader<R>],
+    keys: &[ResolvedKey],
+    writer: &mut csv::Writer<W>,
+) -> Result<u64> {
+    let mut heap = BinaryHeap::with_capacity(sources.len());
+    for (source, reader) in sources.iter_mut().enumerate() {
+        let mut record = ByteRecord::new();
+        if reader.read_byte_record(&mut record)? {
+            heap.push(HeapEntry { record, source, keys });
+        }
+    }
+
+    let mut count = 0;
+    let mut next = ByteRecord::new();
+    while let Some(mut entry) = heap.pop() {
+        writer.write_byte_record(&entry.record)?;
+        count += 1;
+        if sources[entry.source].read_byte_record(&mut next)? {
+            if compare(keys, &entry.record, &next) == Ordering::Greater {
+                let msg = format!("Input {} is not sorted by the merge keys", entry.source + 1);
+                return Err(FgError::IoError(io::Error::new(ErrorKind::InvalidData, msg)));
+            }
+            std::mem::swap(&mut entry.record, &mut next);
+            heap.push(entry);
+        }
+    }
+    writer.flush().map_err(FgError::IoError)?;
+    Ok(count)
+}
+
+/// The most chunks that [`SpillDir::merge_into`] merges at once, which bounds the number of files
+/// open while sorting.
+pub(crate) const MAX_MERGE_FAN_IN: usize = 64;
+
+/// A writer of the records of a chunk file.
+type ChunkWriter = csv::Writer<BufWriter<Box<dyn FinishWrite>>>;
+
+/// A temporary directory holding sorted chunks of records, which is removed when dropped.
+pub(crate) struct SpillDir {
+    path: PathBuf,
+    io: Io,
+    delimiter: u8,
+    chunks: Vec<PathBuf>,
+    /// The number of chunk files created so far, used to name new chunks
+    created: usize,
+}
+
+impl SpillDir {
+    /// Creates a new, uniquely named directory in the system's temporary directory.
+    pub(crate) fn new(delimiter: u8) -> Result<Self> {
+        static COUNTER: AtomicUsize = AtomicUsize::new(0);
+        let name = format!(
+            "fgoxide-sort-{}-{}",
+            std::process::id(),
+            COUNTER.fetch_add(1, AtomicOrdering::Relaxed)
+        );
+        let path = std::env::temp_dir().join(name);
+        fs::create_dir_all(&path).map_err(FgError::IoError)?;
+        // Chunks are compressed quickly, as they are only read back once
+        let io = Io::builder().compression(1).build();
+        Ok(Self { path, io, delimiter, chunks: vec![], created: 0 })
+    }
+
+    /// Writes a chunk of sorted records to a new compressed file.
+    pub(crate) fn spill(&mut self, records: &[ByteRecord]) -> Result<()> {
+        let (path, mut writer) = self.new_chunk()?;
+        for record in records {
+            writer.write_byte_record(record)?;
+        }
+        writer.flush().map_err(FgError::IoError)?;
+        self.chunks.push(path);
+        Ok(())
+    }
+
+    /// Merges all the chunks written and writes their records in sorted order, returning the
+    /// number of records written.  While there are more than `fan_in` chunks, runs of `fan_in`
+    /// consecutive chunks are first merged into single chunks, so that no more than `fan_in`
+    /// files are open at once.  Merging consecutive chunks keeps the merge stable.
+    ///
+    /// # Panics
+    ///
+    /// - panics if `fan_in` is less than two
+    pub(crate) fn merge_into<W: Write>(
+        &mut self,
+        keys: &[ResolvedKey],
+        writer: &mut csv::Writer<W>,
+        fan_in: usize,
+    ) -> Result<u64> {
+        assert!(fan_in >= 2, "Merge fan-in must be at least two!");
+        while self.chunks.len() > fan_in {
+            let chunks = std::mem::take(&mut self.chunks);
+            for run in chunks.chunks(fan_in) {
+                let (path, mut chunk_writer) = self.new_chunk()?;
+                merge(&mut self.readers(run)?, keys, &mut chunk_writer)?;
+                drop(chunk_writer);
+                for merged in run {
+                    fs::remove_file(merged).map_err(FgError::IoError)?;
+                }
+                self.chunks.push(path);
+            }
+        }
+        merge(&mut self.readers(&self.chunks)?, keys, writer)
+    }
+
+    /// Creates a writer over a new, empty chunk file, returning its path and the writer.
+    fn new_chunk(&mut self) -> Result<(PathBuf, ChunkWriter)> {
+        let path = self.path.join(format!("chunk{}.csv.gz", self.created));
+        self.created += 1;
+        let writer = WriterBuilder::new()
+            .delimiter(self.delimiter)
+            .has_headers(false)
+            .flexible(true)
+            .from_writer(self.io.new_writer(&path)?);
+        Ok((path, writer))
+    }
+
+    /// Opens readers over the given chunks, in order.
+    fn readers(&self, chunks: &[PathBuf]) -> Result<Vec<csv::Reader<Box<dyn BufRead + Send>>>> {
+        chunks
+            .iter()
+            .map(|path| {
+                Ok(ReaderBuilder::new()
+                    .delimiter(self.delimiter)
+                    .has_headers(false)
+                    .flexible(true)
+                    .from_reader(self.io.new_reader(path)?))
+            })
+            .collect()
+    }
+
+    /// Returns the path of the directory.
+    #[cfg(test)]
+    fn path(&self) -> &std::path::Path {
+        &self.path
+    }
+}
+
+impl Drop for SpillDir {
+    fn drop(&mut self) {
+        let _ = fs::remove_dir_all(&self.path);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn records(rows: &[&[&str]]) -> Vec<ByteRecord> {
+        rows.iter().map(|r| ByteRecord::from(r.to_vec())).collect()
+    }
+
+    #[test]
+    fn test_comparing_records() {
+        let headers = StringRecord::from(vec!["contig", "pos", "name"]);
+        let keys = [SortKey::new("contig"), SortKey::new("pos").numeric(true)];
+        let keys = resolve_keys(&keys, &headers).unwrap();
+        let mut recs = records(&[
+            &["chr2", "5", "a"],
+            &["chr1", "100", "b"],
+            &["chr1", "20", "c"],
+            &["chr1", "NA", "d"],
+            &["chr1", "20", "e"],
+        ]);
+        recs.sort_by(|a, b| compare(&keys, a, b));
+        let names: Vec<&[u8]> = recs.iter().map(|r| &r[2]).collect();
+        assert_eq!(names, vec![b"c", b"e", b"b", b"d", b"a"]);
+
+        let keys = resolve_keys(&[SortKey::new("name").descending(true)], &headers).unwrap();
+        recs.sort_by(|a, b| compare(&keys, a, b));
+        assert_eq!(&recs[0][2], b"e");
+
+        match resolve_keys(&[SortKey::new("start")], &headers) {
+            Err(FgError::HeaderMismatch { missing, .. }) => assert_eq!(missing, vec!["start"]),
+            _ => panic!("expected a missing column"),
+        }
+    }
+
+    #[test]
+    fn test_spilling_and_merging_chunks() {
+        let headers = StringRecord::from(vec!["n"]);
+        let keys = resolve_keys(&[SortKey::new("n").numeric(true)], &headers).unwrap();
+        let mut spill = SpillDir::new(b'\t').unwrap();
+        spill.spill(&records(&[&["1"], &["4"], &["9"]])).unwrap();
+        spill.spill(&records(&[&["2"], &["3"], &["10"]])).unwrap();
+        spill.spill(&records(&[])).unwrap();
+
+        let mut writer = WriterBuilder::new().from_writer(vec![]);
+        let count = spill.merge_into(&keys, &mut writer, MAX_MERGE_FAN_IN).unwrap();
+        assert_eq!(count, 6);
+        assert_eq!(writer.get_ref(), b"1\n2\n3\n4\n9\n10\n");
+
+        let mut unsorted = SpillDir::new(b'\t').unwrap();
+        unsorted.spill(&records(&[&["1"], &["5"]])).unwrap();
+        unsorted.spill(&records(&[&["3"], &["2"]])).unwrap();
+        let mut writer = WriterBuilder::new().from_writer(vec![]);
+        let result = unsorted.merge_into(&keys, &mut writer, MAX_MERGE_FAN_IN);
+        assert!(matches!(result, Err(FgError::IoError(e)) if e.kind() == ErrorKind::InvalidData));
+
+        let path = spill.path().to_path_buf();
+        assert!(path.exists());
+        drop(spill);
+        assert!(!path.exists());
+    }
+
+    #[test]
+    fn test_merging_chunks_in_passes() {
+        let headers = StringRecord::from(vec!["n", "source"]);
+        let keys = resolve_keys(&[SortKey::new("n").numeric(true)], &headers).unwrap();
+        let mut spill = SpillDir::new(b'\t').unwrap();
+        for source in ["a", "b", "c", "d", "e", "f", "g"] {
+            spill.spill(&records(&[&["1", source], &["2", source]])).unwrap();
+        }
+
+        let mut writer = WriterBuilder::new().delimiter(b'\t').from_writer(vec![]);
+        assert_eq!(spill.merge_into(&keys, &mut writer, 2).unwrap(), 14);
+        let expected = "1\ta\n1\tb\n1\tc\n1\td\n1\te\n1\tf\n1\tg\n\
+                        2\ta\n2\tb\n2\tc\n2\td\n2\te\n2\tf\n2\tg\n";
+        assert_eq!(String::from_utf8_lossy(writer.get_ref()), expected);
+        assert_eq!(spill.chunks.len(), 2);
+        assert_eq!(fs::read_dir(spill.path()).unwrap().count(), 2);
+    }
+}
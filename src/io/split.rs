@@ -0,0 +1,478 @@
+//! Division of uncompressed and BGZF files into ranges that can be read independently, so that
+//! large files can be parsed on multiple threads.
+use std::fs::File;
+use std::io::{self, BufReader, Read, Seek, SeekFrom};
+use std::path::Path;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Mutex;
+
+use crate::io::bgzf::{read_block_size, BgzfReader, VirtualOffset};
+use crate::{FgError, Result};
+
+/// How the data of a file being split is laid out.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub(crate) enum Layout {
+    /// Uncompressed data, which can be split at any byte
+    Plain,
+    /// BGZF compressed data, which can be split between blocks
+    Bgzf,
+}
+
+/// A range of a file that can be read and decompressed independently of the rest of the file.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub(crate) struct DataRange {
+    /// The offset in the file of the first byte of the range
+    pub(crate) start: u64,
+    /// The offset in the file of the byte after the end of the range
+    pub(crate) end: u64,
+    /// The number of bytes to discard from the start of the range's data once decompressed
+    pub(crate) skip: usize,
+}
+
+impl Layout {
+    /// Determines the layout of a file, returning `None` if it is compressed in a way that does
+    /// not allow it to be split.  `compressed` is whether the file is expected to be compressed.
+    pub(crate) fn detect(path: &Path, compressed: bool) -> io::Result<Option<Layout>> {
+        if !compressed {
+            return Ok(Some(Layout::Plain));
+        }
+        let mut reader = BufReader::new(File::open(path)?);
+        match read_block_size(&mut reader) {
+            Ok(_) => Ok(Some(Layout::Bgzf)),
+            Err(e) if e.kind() == io::ErrorKind::InvalidData => Ok(None),
+            Err(e) => Err(e),
+        }
+    }
+
+    /// Opens a reader over the decompressed data of a file.
+    pub(crate) fn open(self, path: &Path) -> io::Result<LayoutReader> {
+        let file = BufReader::new(File::open(path)?);
+        Ok(match self {
+            Layout::Plain => LayoutReader::Plain(file, 0),
+            Layout::Bgzf => LayoutReader::Bgzf(BgzfReader::new(file)),
+        })
+    }
+
+    /// Divides the data of a file, from the given position as returned by
+    /// [`LayoutReader::position`] to the end of the file, into ranges of approximately
+    /// `target_size` bytes of the file each.
+    pub(crate) fn split(
+        self,
+        path: &Path,
+        position: u64,
+        target_size: u64,
+    ) -> io::Result<Vec<DataRange>> {
+        let target_size = target_size.max(1);
+        let file = File::open(path)?;
+        let mut ranges = vec![];
+        match self {
+            Layout::Plain => {
+                let len = file.metadata()?.len();
+                let mut start = position;
+                while start < len {
+                    let end = (start + target_size).min(len);
+                    ranges.push(DataRange { start, end, skip: 0 });
+                    start = end;
+                }
+            }
+            Layout::Bgzf => {
+                let position = VirtualOffset::from(position);
+                let mut range = DataRange {
+                    start: position.coffset(),
+                    end: position.coffset(),
+                    skip: usize::from(position.uoffset()),
+                };
+                let mut reader = BufReader::new(file);
+                reader.seek(SeekFrom::Start(range.end))?;
+                while let Some(size) = read_block_size(&mut reader)? {
+                    range.end += size;
+                    reader.seek(SeekFrom::Start(range.end))?;
+                    if range.end - range.start >= target_size {
+                        let start = range.end;
+                        ranges.push(std::mem::replace(
+                            &mut range,
+                            DataRange { start, end: start, skip: 0 },
+                        ));
+                    }
+                }
+                if range.end > range.start {
+                    ranges.push(range);
+                }
+            }
+        }
+        Ok(ranges)
+    }
+
+    /// Reads and decompresses the data in a range of a file.
+    pub(crate) fn read_range(self, path: &Path, range: &DataRange) -> io::Result<Vec<u8>> {
+        let mut file = File::open(path)?;
+        file.seek(SeekFrom::Start(range.start))?;
+        let mut bytes = Vec::with_capacity((range.end - range.start) as usize);
+        file.take(range.end - range.start).read_to_end(&mut bytes)?;
+        if self == Layout::Bgzf {
+            let mut data = vec![];
+            BgzfReader::new(bytes.as_slice()).read_to_end(&mut data)?;
+            bytes = data;
+        }
+        if range.skip > bytes.len() {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                "Range is shorter than expected",
+            ));
+        }
+        bytes.drain(..range.skip);
+        Ok(bytes)
+    }
+}
+
+/// Reader over the decompressed data of a file that can report its position in the file.
+pub(crate) enum LayoutReader {
+    Plain(BufReader<File>, u64),
+    Bgzf(BgzfReader<BufReader<File>>),
+}
+
+impl LayoutReader {
+    /// Returns the position of the next byte to be read, as a byte offset for uncompressed
+    /// files or a virtual offset for BGZF files.
+    pub(crate) fn position(&self) -> u64 {
+        match self {
+            LayoutReader::Plain(_, offset) => *offset,
+            LayoutReader::Bgzf(reader) => u64::from(reader.virtual_offset()),
+        }
+    }
+}
+
+impl Read for LayoutReader {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let data = io::BufRead::fill_buf(self)?;
+        let n = data.len().min(buf.len());
+        buf[..n].copy_from_slice(&data[..n]);
+        io::BufRead::consume(self, n);
+        Ok(n)
+    }
+}
+
+impl io::BufRead for LayoutReader {
+    fn fill_buf(&mut self) -> io::Result<&[u8]> {
+        match self {
+            LayoutReader::Plain(reader, _) => reader.fill_buf(),
+            LayoutReader::Bgzf(reader) => reader.fill_buf(),
+        }
+    }
+
+    fn consume(&mut self, amt: usize) {
+        match self {
+            LayoutReader::Plain(reader, offset) => {
+                reader.consume(amt);
+                *offset += amt as u64;
+            }
+            LayoutReader::Bgzf(reader) => reader.consume(amt),
+        }
+    }
+}
+
+/// Options controlling how a file is divided and read by [`read_split`].
+#[derive(Copy, Clone, Debug)]
+pub(crate) struct SplitOptions {
+    /// The maximum number of threads on which to read ranges
+    pub(crate) threads: usize,
+    /// The approximate size of each range in bytes of the file
+    pub(crate) range_size: u64,
+    /// The byte that ends each line
+    pub(crate) terminator: u8,
+}
+
+/// Reads records from a file, starting at the given position as returned by
+/// [`LayoutReader::position`], by dividing the file into ranges that are parsed on multiple
+/// threads.  Records are returned in the order they appear in the file.
+///
+/// Each range is split into lines and `parse` is called with runs of whole lines.  Lines that
+/// span two or more ranges are reassembled and parsed once the ranges have been read.  The line
+/// numbers in errors from `parse` are expected to be relative to a first line numbered two, as
+/// if following a header line, and are corrected to line numbers in the file given that the
+/// line at `position` is `first_line`.
+pub(crate) fn read_split<D, F>(
+    layout: Layout,
+    path: &Path,
+    position: u64,
+    first_line: u64,
+    options: &SplitOptions,
+    parse: F,
+) -> Result<Vec<D>>
+where
+    D: Send,
+    F: Fn(&[u8]) -> Result<Vec<D>> + Sync,
+{
+    let SplitOptions { threads, range_size, terminator } = *options;
+    let ranges = layout.split(path, position, range_size).map_err(FgError::IoError)?;
+    let next = AtomicUsize::new(0);
+    let pieces = Mutex::new(Vec::with_capacity(ranges.len()));
+    std::thread::scope(|scope| {
+        for _ in 0..threads.clamp(1, ranges.len().max(1)) {
+            scope.spawn(|| loop {
+                let i = next.fetch_add(1, Ordering::Relaxed);
+                let Some(range) = ranges.get(i) else { break };
+                let result = layout
+                    .read_range(path, range)
+                    .map(|data| Pieces::new(data, i == 0, terminator, &parse));
+                pieces.lock().expect("lock poisoned").push((i, result));
+            });
+        }
+    });
+    let mut pieces = pieces.into_inner().expect("lock poisoned");
+    pieces.sort_by_key(|(i, _)| *i);
+
+    let mut recs = vec![];
+    let mut carry = vec![];
+    let mut line = first_line;
+    for (_, result) in pieces {
+        let pieces = result.map_err(FgError::IoError)?;
+        carry.extend_from_slice(&pieces.head);
+        if let Some((body, lines)) = pieces.body {
+            // The carried over data is now a single whole line
+            if !carry.is_empty() {
+                recs.extend(parse(&carry).map_err(|e| shift_line(e, line - 2))?);
+                line += 1;
+            }
+            recs.extend(body.map_err(|e| shift_line(e, line - 2))?);
+            line += lines;
+            carry = pieces.tail;
+        }
+    }
+    if !carry.is_empty() {
+        recs.extend(parse(&carry).map_err(|e| shift_line(e, line - 2))?);
+    }
+    Ok(recs)
+}
+
+/// The data of a range divided at line boundaries, with the whole lines parsed.
+struct Pieces<D> {
+    /// The data before the first line terminator, which completes a line started in an
+    /// earlier range
+    head: Vec<u8>,
+    /// The records parsed from the whole lines of the range and the number of lines, or `None`
+    /// if the range contains no line terminators and so is entirely part of a longer line
+    body: Option<(Result<Vec<D>>, u64)>,
+    /// The data after the last line terminator, which starts a line completed in a later range
+    tail: Vec<u8>,
+}
+
+impl<D> Pieces<D> {
+    /// Divides the data of a range into pieces, parsing the whole lines.  The first range starts
+    /// with a whole line.
+    fn new<F>(data: Vec<u8>, first: bool, terminator: u8, parse: F) -> Self
+    where
+        F: Fn(&[u8]) -> Result<Vec<D>>,
+    {
+        let body_start = if first {
+            0
+        } else {
+            match memchr::memchr(terminator, &data) {
+                Some(i) => i + 1,
+                None => return Pieces { head: data, body: None, tail: vec![] },
+            }
+        };
+        let body_end = memchr::memrchr(terminator, &data[body_start..])
+            .map_or(body_start, |i| body_start + i + 1);
+        let body = &data[body_start..body_end];
+        let lines = memchr::memchr_iter(terminator, body).count() as u64;
+        Pieces {
+            head: data[..body_start].to_vec(),
+            body: Some((parse(body), lines)),
+            tail: data[body_end..].to_vec(),
+        }
+    }
+}
+
+/// The characters that determine where the quoted fields of delimited lines start and end.
+pub(crate) struct Quoting {
+    pub(crate) delimiter: u8,
+    pub(crate) quote: u8,
+    pub(crate) escape: Option<u8>,
+    pub(crate) comment: Option<u8>,
+    pub(crate) terminator: u8,
+}
+
+impl Quoting {
+    /// Returns true if a quoted field in the given lines contains a line terminator, or is not
+    /// closed by the end of the data, in which case dividing the data at line terminators
+    /// would cut the field in two.  A quote only starts a quoted field at the start of a field,
+    /// and within one is escaped with the escape character if there is one, or otherwise by
+    /// doubling it.  Comment lines are skipped.
+    pub(crate) fn spans_lines(&self, data: &[u8]) -> bool {
+        let (mut quoted, mut closed) = (false, false);
+        let (mut line_start, mut field_start) = (true, true);
+        let mut bytes = data.iter();
+        while let Some(&b) = bytes.next() {
+            if quoted {
+                if Some(b) == self.escape {
+                    bytes.next();
+                } else if b == self.quote {
+                    (quoted, closed) = (false, true);
+                } else if b == self.terminator {
+                    return true;
+                }
+            } else if line_start && Some(b) == self.comment {
+                for &c in bytes.by_ref() {
+                    if c == self.terminator {
+                        break;
+                    }
+                }
+            } else if b == self.quote && (field_start || (closed && self.escape.is_none())) {
+                // Starts a quoted field, or continues one after a doubled quote
+                (quoted, closed, line_start, field_start) = (true, false, false, false);
+            } else {
+                line_start = b == self.terminator;
+                field_start = line_start || b == self.delimiter;
+                closed = false;
+            }
+        }
+        quoted
+    }
+}
+
+/// Adds `delta` to the line number in an error, if it has one.
+fn shift_line(e: FgError, delta: u64) -> FgError {
+    match e {
+        FgError::DelimError(mut e) => {
+            e.line += delta;
+            FgError::DelimError(e)
+        }
+        FgError::FieldCountMismatch { line, expected, found } => {
+            FgError::FieldCountMismatch { line: line + delta, expected, found }
+        }
+        e => e,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::io::BgzfWriter;
+    use flate2::Compression;
+    use rstest::rstest;
+    use std::io::{BufRead, Write};
+    use tempfile::TempDir;
+
+    /// Reads all the ranges of a file after its first line and concatenates their data.
+    fn read_after_first_line(layout: Layout, path: &Path, target_size: u64) -> (usize, Vec<u8>) {
+        let mut reader = layout.open(path).unwrap();
+        reader.read_until(b'\n', &mut vec![]).unwrap();
+        let ranges = layout.split(path, reader.position(), target_size).unwrap();
+        let data = ranges.iter().flat_map(|r| layout.read_range(path, r).unwrap()).collect();
+        (ranges.len(), data)
+    }
+
+    #[rstest]
+    #[case(b"a,b\n\"c,d\",e\n", None, false)]
+    #[case(b"a,\"say \"\"hi\"\"\"\n", None, false)] // doubled quotes
+    #[case(b"a,\"say \\\"hi\\\"\"\n", Some(b'\\'), false)] // escaped quotes
+    #[case(b"a,b\"c\n", None, false)] // a quote within an unquoted field
+    #[case(b"#\"a\nb,c\n", None, false)] // a quote within a comment
+    #[case(b"a,\"two\nlines\"\n", None, true)]
+    #[case(b"a,\"\"\"\n\"\n", None, true)] // a doubled quote then a line terminator
+    #[case(b"a,\"unclosed", None, true)]
+    fn test_finding_quoted_fields_that_span_lines(
+        #[case] data: &[u8],
+        #[case] escape: Option<u8>,
+        #[case] expected: bool,
+    ) {
+        let quoting = Quoting {
+            delimiter: b',',
+            quote: b'"',
+            escape,
+            comment: Some(b'#'),
+            terminator: b'\n',
+        };
+        assert_eq!(quoting.spans_lines(data), expected);
+    }
+
+    #[test]
+    fn test_splitting_plain_files() {
+        let tempdir = TempDir::new().unwrap();
+        let path = tempdir.path().join("data.txt");
+        std::fs::write(&path, "header\n0123456789\nabcdefghij\n").unwrap();
+
+        assert_eq!(Layout::detect(&path, false).unwrap(), Some(Layout::Plain));
+        let (n, data) = read_after_first_line(Layout::Plain, &path, 5);
+        assert_eq!(n, 5);
+        assert_eq!(data, b"0123456789\nabcdefghij\n");
+        assert_eq!(read_after_first_line(Layout::Plain, &path, 100).0, 1);
+    }
+
+    /// Parses lines of `name,number` after a header line, failing on lines that are not numbers.
+    fn parse(bytes: &[u8]) -> Result<Vec<u64>> {
+        let text = std::str::from_utf8(bytes).unwrap();
+        let mut values = vec![];
+        for (i, line) in text.lines().enumerate() {
+            let field = line.split(',').nth(1).unwrap_or_default();
+            match field.parse() {
+                Ok(value) => values.push(value),
+                Err(_) => {
+                    let line = i as u64 + 2;
+                    return Err(FgError::FieldCountMismatch { line, expected: 2, found: 1 });
+                }
+            }
+        }
+        Ok(values)
+    }
+
+    #[test]
+    fn test_reading_split_ranges() {
+        let tempdir = TempDir::new().unwrap();
+        let path = tempdir.path().join("data.csv");
+        let header = "# comment\nname,n\n";
+        let position = header.len() as u64;
+        let mut text = header.to_string();
+        for i in 0..1000u64 {
+            text.push_str(&format!("{},{}\n", "x".repeat(i as usize % 40), i));
+        }
+        std::fs::write(&path, &text).unwrap();
+
+        for range_size in [1, 7, 64, 1000, 100_000] {
+            for threads in [1, 3] {
+                let options = SplitOptions { threads, range_size, terminator: b'\n' };
+                let values =
+                    read_split(Layout::Plain, &path, position, 3, &options, parse).unwrap();
+                assert_eq!(values, (0..1000).collect::<Vec<_>>(), "range size {range_size}");
+            }
+        }
+
+        // Errors are reported with line numbers in the file
+        std::fs::write(&path, text.replace(",500\n", ",x\n")).unwrap();
+        for range_size in [1, 64, 100_000] {
+            let options = SplitOptions { threads: 2, range_size, terminator: b'\n' };
+            match read_split(Layout::Plain, &path, position, 3, &options, parse) {
+                Err(FgError::FieldCountMismatch { line, .. }) => assert_eq!(line, 503),
+                _ => panic!("expected an error"),
+            }
+        }
+    }
+
+    #[test]
+    fn test_splitting_bgzf_files() {
+        let tempdir = TempDir::new().unwrap();
+        let path = tempdir.path().join("data.txt.gz");
+        let mut expected = vec![];
+        let mut writer = BgzfWriter::new(File::create(&path).unwrap(), Compression::fast());
+        writer.write_all(b"header\n").unwrap();
+        for i in 0..50_000 {
+            let line = format!("{i}\t{}\n", i * 7919 % 10007);
+            writer.write_all(line.as_bytes()).unwrap();
+            expected.extend_from_slice(line.as_bytes());
+        }
+        writer.finish().unwrap();
+
+        assert_eq!(Layout::detect(&path, true).unwrap(), Some(Layout::Bgzf));
+        let (n, data) = read_after_first_line(Layout::Bgzf, &path, 50_000);
+        assert!(n > 2, "only {n} ranges");
+        assert_eq!(data, expected);
+
+        let gzip = tempdir.path().join("data.gz");
+        let mut writer =
+            flate2::write::GzEncoder::new(File::create(&gzip).unwrap(), Compression::fast());
+        writer.write_all(b"header\n").unwrap();
+        writer.finish().unwrap();
+        assert_eq!(Layout::detect(&gzip, true).unwrap(), None);
+    }
+}
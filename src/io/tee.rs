@@ -0,0 +1,68 @@
+//! A writer that duplicates its output to several destinations.
+use std::io::{self, Write};
+
+use crate::io::FinishWrite;
+
+/// Writer that writes every byte it receives to each of a set of underlying writers.
+pub struct TeeWriter<W: Write> {
+    writers: Vec<W>,
+}
+
+impl<W: Write> TeeWriter<W> {
+    /// Creates a new writer that fans output out to all of the given writers.
+    pub fn new(writers: Vec<W>) -> Self {
+        Self { writers }
+    }
+
+    /// Consumes this writer, returning the underlying writers.
+    pub fn into_inner(self) -> Vec<W> {
+        self.writers
+    }
+}
+
+impl<W: Write> Write for TeeWriter<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        for writer in &mut self.writers {
+            writer.write_all(buf)?;
+        }
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        for writer in &mut self.writers {
+            writer.flush()?;
+        }
+        Ok(())
+    }
+}
+
+impl<W: FinishWrite> FinishWrite for TeeWriter<W> {
+    fn finish(&mut self) -> io::Result<()> {
+        for writer in &mut self.writers {
+            writer.finish()?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_tee_writer_duplicates_output() {
+        let mut tee = TeeWriter::new(vec![vec![], vec![], vec![]]);
+        tee.write_all(b"hello ").unwrap();
+        tee.write_all(b"world").unwrap();
+        tee.flush().unwrap();
+        for out in tee.into_inner() {
+            assert_eq!(out, b"hello world");
+        }
+    }
+
+    #[test]
+    fn test_tee_writer_with_no_writers() {
+        let mut tee: TeeWriter<Vec<u8>> = TeeWriter::new(vec![]);
+        assert_eq!(tee.write(b"discarded").unwrap(), 9);
+    }
+}
@@ -0,0 +1,337 @@
+//! Readers and writers that perform reading and writing on background threads.
+use std::io::{self, BufRead, ErrorKind, Read, Write};
+use std::sync::mpsc::{channel, sync_channel, Receiver, SyncSender};
+use std::thread::{self, JoinHandle};
+
+/// Reader that reads from an underlying reader on a dedicated thread, handing chunks of data
+/// back over a bounded channel.  This allows CPU-bound consumers to run concurrently with
+/// decompression rather than alternating with it.  At most `capacity` chunks of `chunk_size`
+/// bytes are read ahead of the consumer.
+///
+/// If the underlying reader returns an error it is passed on to the consumer, after which the
+/// reader reports end of file.  Dropping the reader stops the background thread after its current
+/// read completes.
+pub struct ThreadedReader {
+    receiver: Receiver<io::Result<Vec<u8>>>,
+    chunk: Vec<u8>,
+    pos: usize,
+    done: bool,
+}
+
+impl ThreadedReader {
+    /// Starts reading from `inner` on a new thread.
+    ///
+    /// # Panics
+    /// - panics if `chunk_size` or `capacity` is zero
+    pub fn new<R: Read + Send + 'static>(inner: R, chunk_size: usize, capacity: usize) -> Self {
+        assert!(chunk_size > 0, "Chunk size must be greater than zero!");
+        assert!(capacity > 0, "Capacity must be greater than zero!");
+
+        let (sender, receiver) = sync_channel(capacity);
+        thread::spawn(move || {
+            let mut inner = inner;
+            loop {
+                let (chunk, error) = Self::read_chunk(&mut inner, chunk_size);
+                let eof = chunk.is_empty();
+                if !eof && sender.send(Ok(chunk)).is_err() {
+                    break;
+                }
+                if let Some(e) = error {
+                    let _ = sender.send(Err(e));
+                    break;
+                }
+                if eof {
+                    break;
+                }
+            }
+        });
+
+        Self { receiver, chunk: Vec::new(), pos: 0, done: false }
+    }
+
+    /// Reads until `chunk_size` bytes have been read, the end of the input is reached or an
+    /// error occurs, returning the bytes read along with any error.
+    fn read_chunk<R: Read>(inner: &mut R, chunk_size: usize) -> (Vec<u8>, Option<io::Error>) {
+        let mut chunk = vec![0u8; chunk_size];
+        let mut filled = 0;
+        let mut error = None;
+        while filled < chunk_size {
+            match inner.read(&mut chunk[filled..]) {
+                Ok(0) => break,
+                Ok(n) => filled += n,
+                Err(e) if e.kind() == ErrorKind::Interrupted => {}
+                Err(e) => {
+                    error = Some(e);
+                    break;
+                }
+            }
+        }
+        chunk.truncate(filled);
+        (chunk, error)
+    }
+}
+
+impl Read for ThreadedReader {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let available = self.fill_buf()?;
+        let n = available.len().min(buf.len());
+        buf[..n].copy_from_slice(&available[..n]);
+        self.consume(n);
+        Ok(n)
+    }
+}
+
+impl BufRead for ThreadedReader {
+    fn fill_buf(&mut self) -> io::Result<&[u8]> {
+        while self.pos >= self.chunk.len() && !self.done {
+            match self.receiver.recv() {
+                Ok(Ok(chunk)) => {
+                    self.chunk = chunk;
+                    self.pos = 0;
+                }
+                Ok(Err(e)) => {
+                    self.done = true;
+                    return Err(e);
+                }
+                Err(_) => self.done = true,
+            }
+        }
+
+        if self.pos >= self.chunk.len() {
+            Ok(&[])
+        } else {
+            Ok(&self.chunk[self.pos..])
+        }
+    }
+
+    fn consume(&mut self, amt: usize) {
+        self.pos = (self.pos + amt).min(self.chunk.len());
+    }
+}
+
+/// The messages sent from a [`ThreadedWriter`] to its background thread.
+enum Message {
+    Data(Vec<u8>),
+    Flush,
+}
+
+/// Writer that buffers data and hands it in chunks over a bounded channel to a dedicated thread,
+/// which writes it to an underlying writer.  This moves compression and disk writes off the
+/// calling thread, so that hot loops are not stalled by compression latency.  At most `capacity`
+/// chunks of `chunk_size` bytes are queued before writes block.
+///
+/// Errors from the underlying writer are reported by the first write or flush after they occur.
+/// Dropping the writer waits for all queued data to be written but discards any errors, so
+/// [`ThreadedWriter::finish`] should be used where errors must be detected.
+pub struct ThreadedWriter<W: Write + Send + 'static> {
+    sender: Option<SyncSender<Message>>,
+    acks: Receiver<io::Result<()>>,
+    handle: Option<JoinHandle<io::Result<W>>>,
+    buffer: Vec<u8>,
+    chunk_size: usize,
+}
+
+impl<W: Write + Send + 'static> ThreadedWriter<W> {
+    /// Starts a background thread that writes to `inner`.
+    ///
+    /// # Panics
+    /// - panics if `chunk_size` or `capacity` is zero
+    pub fn new(inner: W, chunk_size: usize, capacity: usize) -> Self {
+        assert!(chunk_size > 0, "Chunk size must be greater than zero!");
+        assert!(capacity > 0, "Capacity must be greater than zero!");
+
+        let (sender, receiver) = sync_channel(capacity);
+        let (ack_sender, acks) = channel();
+        let handle = thread::spawn(move || {
+            let mut inner = inner;
+            for message in receiver {
+                match message {
+                    Message::Data(data) => inner.write_all(&data)?,
+                    Message::Flush => {
+                        let _ = ack_sender.send(inner.flush());
+                    }
+                }
+            }
+            inner.flush()?;
+            Ok(inner)
+        });
+
+        Self {
+            sender: Some(sender),
+            acks,
+            handle: Some(handle),
+            buffer: Vec::with_capacity(chunk_size),
+            chunk_size,
+        }
+    }
+
+    /// Sends a message to the background thread, returning its error if it has stopped.
+    fn send(&mut self, message: Message) -> io::Result<()> {
+        match self.sender.as_ref().map(|s| s.send(message)) {
+            Some(Ok(())) => Ok(()),
+            _ => Err(self.worker_error()),
+        }
+    }
+
+    /// Sends any buffered data to the background thread.
+    fn send_buffer(&mut self) -> io::Result<()> {
+        if self.buffer.is_empty() {
+            return Ok(());
+        }
+        let data = std::mem::replace(&mut self.buffer, Vec::with_capacity(self.chunk_size));
+        self.send(Message::Data(data))
+    }
+
+    /// Waits for the background thread, which has stopped early, and returns the reason.
+    fn worker_error(&mut self) -> io::Error {
+        self.sender = None;
+        match self.handle.take().map(|h| h.join()) {
+            Some(Ok(Err(e))) => e,
+            Some(Err(_)) => io::Error::new(ErrorKind::BrokenPipe, "Writer thread panicked"),
+            _ => io::Error::new(ErrorKind::BrokenPipe, "Writer thread is no longer running"),
+        }
+    }
+
+    /// Writes all buffered data, waits for the background thread to finish and returns the
+    /// underlying writer.
+    pub fn finish(mut self) -> io::Result<W> {
+        self.send_buffer()?;
+        self.sender = None;
+        match self.handle.take().map(|h| h.join()) {
+            Some(Ok(result)) => result,
+            _ => Err(io::Error::new(ErrorKind::BrokenPipe, "Writer thread panicked")),
+        }
+    }
+}
+
+impl<W: Write + Send + 'static> Write for ThreadedWriter<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        if self.sender.is_none() {
+            return Err(self.worker_error());
+        }
+        self.buffer.extend_from_slice(buf);
+        if self.buffer.len() >= self.chunk_size {
+            self.send_buffer()?;
+        }
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.send_buffer()?;
+        self.send(Message::Flush)?;
+        match self.acks.recv() {
+            Ok(result) => result,
+            Err(_) => Err(self.worker_error()),
+        }
+    }
+}
+
+impl<W: Write + Send + 'static> Drop for ThreadedWriter<W> {
+    fn drop(&mut self) {
+        let _ = self.send_buffer();
+        self.sender = None;
+        if let Some(handle) = self.handle.take() {
+            let _ = handle.join();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A reader that fails after returning some data.
+    struct FailingReader(bool);
+
+    impl Read for FailingReader {
+        fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+            if self.0 {
+                Err(io::Error::new(ErrorKind::InvalidData, "bad data"))
+            } else {
+                self.0 = true;
+                buf[..3].copy_from_slice(b"abc");
+                Ok(3)
+            }
+        }
+    }
+
+    #[test]
+    fn test_reading_in_chunks() {
+        let data: Vec<u8> = (0..100_000u32).flat_map(|i| i.to_le_bytes()).collect();
+        for chunk_size in [1, 7, 4096, 1_000_000] {
+            let mut reader = ThreadedReader::new(std::io::Cursor::new(data.clone()), chunk_size, 2);
+            let mut out = Vec::new();
+            reader.read_to_end(&mut out).unwrap();
+            assert_eq!(out, data);
+        }
+    }
+
+    #[test]
+    fn test_reading_lines() {
+        let reader = ThreadedReader::new(&b"a\nbb\nccc\n"[..], 2, 1);
+        let lines: Vec<String> = reader.lines().map(|l| l.unwrap()).collect();
+        assert_eq!(lines, vec!["a", "bb", "ccc"]);
+    }
+
+    #[test]
+    fn test_reading_empty_input() {
+        let mut reader = ThreadedReader::new(std::io::empty(), 16, 1);
+        assert!(reader.fill_buf().unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_errors_are_passed_to_consumer() {
+        let mut reader = ThreadedReader::new(FailingReader(false), 16, 1);
+        let mut out = Vec::new();
+        let err = reader.read_to_end(&mut out).unwrap_err();
+        assert_eq!(err.kind(), ErrorKind::InvalidData);
+        assert_eq!(out, b"abc");
+    }
+
+    /// A writer that fails once more than a fixed number of bytes have been written.
+    #[derive(Debug)]
+    struct LimitedWriter(usize);
+
+    impl Write for LimitedWriter {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            if buf.len() > self.0 {
+                return Err(io::Error::new(ErrorKind::WriteZero, "out of space"));
+            }
+            self.0 -= buf.len();
+            Ok(buf.len())
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn test_writing_in_chunks() {
+        let data: Vec<u8> = (0..100_000u32).flat_map(|i| i.to_le_bytes()).collect();
+        for chunk_size in [1, 7, 4096, 1_000_000] {
+            let mut writer = ThreadedWriter::new(Vec::new(), chunk_size, 2);
+            for piece in data.chunks(1000) {
+                writer.write_all(piece).unwrap();
+            }
+            assert_eq!(writer.finish().unwrap(), data);
+        }
+    }
+
+    #[test]
+    fn test_flush_waits_for_data_to_be_written() {
+        let mut writer = ThreadedWriter::new(LimitedWriter(10), 1024, 1);
+        writer.write_all(b"0123456789").unwrap();
+        writer.flush().unwrap();
+        writer.write_all(b"x").unwrap();
+        assert_eq!(writer.flush().unwrap_err().kind(), ErrorKind::WriteZero);
+        assert!(writer.write_all(b"y").is_err());
+    }
+
+    #[test]
+    fn test_finish_reports_errors() {
+        let mut writer = ThreadedWriter::new(LimitedWriter(10), 4, 1);
+        writer.write_all(b"0123456789abcdef").unwrap_or_default();
+        assert_eq!(writer.finish().unwrap_err().kind(), ErrorKind::WriteZero);
+    }
+}
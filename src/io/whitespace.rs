@@ -0,0 +1,107 @@
+//! A reader that converts whitespace-aligned columns into tab-delimited text.
+use std::io::{self, BufRead, Read};
+
+/// Reader over text whose fields are separated by runs of whitespace, such as the space-aligned
+/// tables written by many older tools, that yields the same text with each run of spaces and
+/// tabs between fields replaced by a single tab, and leading and trailing whitespace removed.
+/// This splits lines as `awk` does, so that the output can be parsed as tab-delimited.  Lines
+/// are separated by `\n`, optionally preceded by `\r`, and lines that are entirely whitespace
+/// become empty lines.
+pub struct WhitespaceReader<R> {
+    inner: R,
+    /// The raw bytes of the line being converted
+    line: Vec<u8>,
+    /// The converted line waiting to be returned to the caller
+    out: Vec<u8>,
+    out_pos: usize,
+}
+
+impl<R> WhitespaceReader<R> {
+    /// Wraps a reader, which should be positioned at the start of a line.
+    pub fn new(inner: R) -> Self {
+        Self { inner, line: Vec::new(), out: Vec::new(), out_pos: 0 }
+    }
+
+    /// Consumes this wrapper, returning the underlying reader.
+    pub fn into_inner(self) -> R {
+        self.inner
+    }
+}
+
+impl<R: BufRead> WhitespaceReader<R> {
+    /// Reads and converts the next line into `out`, leaving `out` empty at the end of input.
+    fn next_line(&mut self) -> io::Result<()> {
+        self.line.clear();
+        self.out.clear();
+        self.out_pos = 0;
+        if self.inner.read_until(b'\n', &mut self.line)? == 0 {
+            return Ok(());
+        }
+        for field in self.line.split(|b| matches!(b, b' ' | b'\t' | b'\r' | b'\n')) {
+            if field.is_empty() {
+                continue;
+            }
+            if !self.out.is_empty() {
+                self.out.push(b'\t');
+            }
+            self.out.extend_from_slice(field);
+        }
+        self.out.push(b'\n');
+        Ok(())
+    }
+}
+
+impl<R: BufRead> Read for WhitespaceReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let available = self.fill_buf()?;
+        let n = available.len().min(buf.len());
+        buf[..n].copy_from_slice(&available[..n]);
+        self.consume(n);
+        Ok(n)
+    }
+}
+
+impl<R: BufRead> BufRead for WhitespaceReader<R> {
+    fn fill_buf(&mut self) -> io::Result<&[u8]> {
+        if self.out_pos >= self.out.len() {
+            self.next_line()?;
+        }
+        Ok(&self.out[self.out_pos..])
+    }
+
+    fn consume(&mut self, amt: usize) {
+        self.out_pos = (self.out_pos + amt).min(self.out.len());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rstest::rstest;
+
+    #[rstest]
+    #[case("a b  c\n", "a\tb\tc\n")]
+    #[case("  chr1   100\t 200  \r\n", "chr1\t100\t200\n")]
+    #[case("a b\n   \nc d", "a\tb\n\nc\td\n")]
+    #[case("", "")]
+    fn test_converting_whitespace_to_tabs(#[case] input: &str, #[case] expected: &str) {
+        let mut output = String::new();
+        WhitespaceReader::new(input.as_bytes()).read_to_string(&mut output).unwrap();
+        assert_eq!(output, expected);
+    }
+
+    #[test]
+    fn test_reading_converted_lines_in_small_pieces() {
+        let mut reader = WhitespaceReader::new("name   value\nx      1\n".as_bytes());
+        let mut output = vec![];
+        let mut buf = [0u8; 3];
+        loop {
+            let n = reader.read(&mut buf).unwrap();
+            if n == 0 {
+                break;
+            }
+            output.extend_from_slice(&buf[..n]);
+        }
+        assert_eq!(output, b"name\tvalue\nx\t1\n");
+    }
+}
@@ -1,3 +1,7 @@
+#[cfg(feature = "rand")]
+use rand::{Rng, SeedableRng};
+#[cfg(feature = "rand")]
+use rand_chacha::ChaCha8Rng;
 use std::any::Any;
 use std::panic::{catch_unwind, resume_unwind, AssertUnwindSafe};
 use std::sync::mpsc::{sync_channel, Receiver};
@@ -182,6 +186,60 @@ where
     }
 }
 
+/// Selects `k` items uniformly at random from an iterator of unknown length in a single pass,
+/// holding at most `k` items in memory (reservoir sampling).  If the iterator yields fewer than
+/// `k` items, all of them are returned.  The sampled items are returned in the order in which
+/// they were yielded by the iterator, and the same `seed` always produces the same sample, on
+/// every platform.
+///
+/// ```
+/// use fgoxide::iter::reservoir_sample;
+///
+/// let sample = reservoir_sample(0..1000, 10, 42);
+/// assert_eq!(sample.len(), 10);
+/// assert!(sample.windows(2).all(|w| w[0] < w[1]));
+/// ```
+#[cfg(feature = "rand")]
+pub fn reservoir_sample<T, I>(iter: I, k: usize, seed: u64) -> Vec<T>
+where
+    I: IntoIterator<Item = T>,
+{
+    let result: Result<Vec<T>, std::convert::Infallible> =
+        try_reservoir_sample(iter.into_iter().map(Ok), k, seed);
+    match result {
+        Ok(sample) => sample,
+        Err(e) => match e {},
+    }
+}
+
+/// Selects `k` items uniformly at random from an iterator of results, as with
+/// [`reservoir_sample`], returning the first error encountered if any.  This allows sampling
+/// directly from fallible iterators such as those returned by
+/// [`Io::read_lines_iter`](crate::io::Io::read_lines_iter).
+#[cfg(feature = "rand")]
+pub fn try_reservoir_sample<T, E, I>(iter: I, k: usize, seed: u64) -> Result<Vec<T>, E>
+where
+    I: IntoIterator<Item = Result<T, E>>,
+{
+    let mut rng = ChaCha8Rng::seed_from_u64(seed);
+    let mut reservoir: Vec<(usize, T)> = Vec::with_capacity(k);
+    for (i, item) in iter.into_iter().enumerate() {
+        let item = item?;
+        if reservoir.len() < k {
+            reservoir.push((i, item));
+        } else {
+            // Sampled as a u64 so that the sample doesn't depend on the platform's pointer width
+            let j = rng.gen_range(0..=i as u64);
+            if j < k as u64 {
+                reservoir[j as usize] = (i, item);
+            }
+        }
+    }
+
+    reservoir.sort_by_key(|(i, _)| *i);
+    Ok(reservoir.into_iter().map(|(_, item)| item).collect())
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -430,4 +488,45 @@ mod tests {
             assert_eq!(test_iter.next(), None);
         }
     }
+
+    #[cfg(feature = "rand")]
+    #[rstest]
+    #[case(0, 0)]
+    #[case(10, 5)]
+    #[case(10, 10)]
+    #[case(10, 20)]
+    fn test_reservoir_sample_size(#[case] len: usize, #[case] k: usize) {
+        let sample = reservoir_sample(0..len, k, 1);
+        assert_eq!(sample.len(), k.min(len));
+        assert!(sample.windows(2).all(|w| w[0] < w[1]));
+    }
+
+    #[cfg(feature = "rand")]
+    #[test]
+    fn test_reservoir_sample_is_reproducible_and_uniform() {
+        assert_eq!(reservoir_sample(0..1000, 10, 7), reservoir_sample(0..1000, 10, 7));
+        assert_ne!(reservoir_sample(0..1000, 10, 7), reservoir_sample(0..1000, 10, 8));
+
+        // Each of 10 items should be picked about half the time when sampling 5 of them
+        let mut counts = [0; 10];
+        for seed in 0..2000 {
+            for i in reservoir_sample(0..10, 5, seed) {
+                counts[i] += 1;
+            }
+        }
+        assert!(counts.iter().all(|c| (800..1200).contains(c)), "{counts:?}");
+    }
+
+    #[cfg(feature = "rand")]
+    #[test]
+    fn test_reservoir_sample_is_pinned_for_a_seed() {
+        assert_eq!(reservoir_sample(0..1000, 5, 42), vec![45, 240, 523, 806, 996]);
+    }
+
+    #[cfg(feature = "rand")]
+    #[test]
+    fn test_try_reservoir_sample_returns_errors() {
+        let items: Vec<Result<usize, String>> = vec![Ok(1), Err("bad".to_string()), Ok(3)];
+        assert_eq!(try_reservoir_sample(items, 2, 1), Err("bad".to_string()));
+    }
 }
@@ -1,11 +1,16 @@
 //! There are many helper functions that are used repeatedly across projects, such as serializing an
 //! iterator of `Serializable` objects to a file. This crate aims to collect those usage patterns,
 //! refine the APIs around them, and provide well tested code to be used across projects.
-#![forbid(unsafe_code)]
+// Memory mapping a file cannot be done safely, so with the `mmap` feature unsafe code is denied
+// rather than forbidden, and allowed only in `Io::new_mmap_reader`, which is itself unsafe.
+#![cfg_attr(not(feature = "mmap"), forbid(unsafe_code))]
+#![cfg_attr(feature = "mmap", deny(unsafe_code))]
 
 pub mod io;
 pub mod iter;
 
+use std::fmt;
+use std::path::PathBuf;
 use thiserror::Error;
 
 /// Error types for `fgoxide`
@@ -16,6 +21,95 @@ pub enum FgError {
 
     #[error("Error parsing/formatting delimited data.")]
     ConversionError(#[from] csv::Error),
+
+    #[error("Refusing to overwrite existing file: {}", .0.display())]
+    FileExists(PathBuf),
+
+    #[error(
+        "Header does not match the expected columns; missing: [{}], unexpected: [{}]",
+        .missing.join(", "),
+        .unexpected.join(", ")
+    )]
+    HeaderMismatch { missing: Vec<String>, unexpected: Vec<String> },
+
+    #[error("Record on line {line} has {found} fields but {expected} were expected")]
+    FieldCountMismatch { line: u64, expected: u64, found: u64 },
+
+    #[error(transparent)]
+    DelimError(Box<DelimError>),
+
+    #[error("Duplicate key '{key}' on line {line}")]
+    DuplicateKey { key: String, line: u64 },
+
+    #[error("Header contains more than one column named '{column}'")]
+    DuplicateColumn { column: String },
+}
+
+/// Describes a record in a delimited file that could not be deserialized, locating the
+/// offending field so that bad input can be found and fixed.
+#[derive(Debug)]
+pub struct DelimError {
+    /// The path of the file being read, if reading from a file
+    pub path: Option<PathBuf>,
+    /// The 1-based number of the line on which the record starts
+    pub line: u64,
+    /// The name of the column containing the offending field, if the file has a header
+    pub column: Option<String>,
+    /// The 0-based index of the offending field within the record, if known
+    pub field: Option<u64>,
+    /// The value of the offending field, if known
+    pub value: Option<String>,
+    /// The type the offending field was expected to be, if known
+    pub expected: Option<&'static str>,
+    /// The underlying error from deserializing the record
+    pub error: csv::Error,
+}
+
+impl fmt::Display for DelimError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Failed to deserialize record on line {}", self.line)?;
+        if let Some(path) = &self.path {
+            write!(f, " of {}", path.display())?;
+        }
+        match (&self.column, self.field) {
+            (Some(column), _) => write!(f, " in column '{column}'")?,
+            (None, Some(field)) => write!(f, " in field {}", field + 1)?,
+            (None, None) => {}
+        }
+        if let Some(value) = &self.value {
+            write!(f, "; value {value:?}")?;
+            if let Some(expected) = self.expected {
+                write!(f, " is not a valid {expected}")?;
+            }
+        }
+        match self.error.kind() {
+            csv::ErrorKind::Deserialize { err, .. } => write!(f, ": {}", err.kind()),
+            _ => write!(f, ": {}", self.error),
+        }
+    }
+}
+
+impl std::error::Error for DelimError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        Some(&self.error)
+    }
+}
+
+/// Converts errors back into `std::io::Error`s for use within `Read` and `Write` implementations.
+impl From<FgError> for std::io::Error {
+    fn from(e: FgError) -> Self {
+        use std::io::ErrorKind;
+        match e {
+            FgError::IoError(e) => e,
+            FgError::FileExists(_) => std::io::Error::new(ErrorKind::AlreadyExists, e),
+            FgError::ConversionError(_)
+            | FgError::HeaderMismatch { .. }
+            | FgError::FieldCountMismatch { .. }
+            | FgError::DelimError(_)
+            | FgError::DuplicateKey { .. }
+            | FgError::DuplicateColumn { .. } => std::io::Error::new(ErrorKind::InvalidData, e),
+        }
+    }
 }
 
 /// Result type that should be used everywhere